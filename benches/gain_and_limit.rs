@@ -0,0 +1,118 @@
+// Throughput baseline for the per-sample gain/limit loop: `cargo bench --bench gain_and_limit`.
+//
+// A plain timing harness (`harness = false`) instead of criterion: each case runs in batches for
+// about half a second and reports the median batch, which is stable enough to compare an
+// optimisation against, without criterion's dependency tree. Inputs are 1 s of 48 kHz audio.
+// A counting global allocator reports heap allocations per call, so the in-place variants can be
+// seen to allocate nothing.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use adaptive_vol::adaptive_gain::{
+    apply_gain_and_limit, apply_gain_and_limit_in_place, apply_gain_and_limit_with, process_chunk,
+    process_chunk_in_place, Dither, Limiter, CHUNK_SAMPLES, SAMPLE_RATE,
+};
+use adaptive_vol::dynamics::LookaheadLimiter;
+use adaptive_vol::soft_limit;
+
+const RUN_TIME: Duration = Duration::from_millis(500);
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Time `f` (which processes `samples` samples per call) and print the median throughput and the
+/// heap allocations per call
+fn bench(name: &str, samples: usize, mut f: impl FnMut()) {
+    f(); // warm-up
+    let mut batches = Vec::with_capacity(1 << 16);
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    while start.elapsed() < RUN_TIME || batches.len() < 5 {
+        let t = Instant::now();
+        f();
+        batches.push(t.elapsed());
+    }
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations_before) as f64 / batches.len() as f64;
+    batches.sort();
+    let median = batches[batches.len() / 2];
+    let rate = samples as f64 / median.as_secs_f64();
+    println!("{:<46} {:>10.2?}/iter {:>10.1} Msamples/s {:>6.2} allocs/iter", name, median, rate / 1e6, allocations);
+}
+
+fn main() {
+    let pcm: Vec<i16> = (0..SAMPLE_RATE)
+        .map(|n| ((n as f32 * 2.0 * std::f32::consts::PI * 440.0 / SAMPLE_RATE as f32).sin() * 20_000.0) as i16)
+        .collect();
+    let float: Vec<f32> = pcm.iter().map(|&s| s as f32 / i16::MAX as f32).collect();
+
+    // 0.5 stays linear, 1.5 and 4.0 drive the limiter progressively harder
+    for gain in [0.5f32, 1.0, 1.5, 4.0] {
+        bench(&format!("apply_gain_and_limit gain {}", gain), pcm.len(), || {
+            black_box(apply_gain_and_limit(black_box(&pcm), gain));
+        });
+    }
+    // steady-state chunk loop: one 10 ms chunk per call, allocating vs in place
+    let chunk = &pcm[..CHUNK_SAMPLES];
+    bench("apply_gain_and_limit, 10 ms chunk", CHUNK_SAMPLES, || {
+        black_box(apply_gain_and_limit(black_box(chunk), 1.5));
+    });
+    let mut buf = chunk.to_vec();
+    bench("apply_gain_and_limit_in_place, 10 ms chunk", CHUNK_SAMPLES, || {
+        buf.copy_from_slice(chunk);
+        apply_gain_and_limit_in_place(black_box(&mut buf), 1.5);
+    });
+    let mut limiter = Limiter::for_sample_rate(SAMPLE_RATE as f32);
+    let float_chunk = &float[..CHUNK_SAMPLES];
+    bench("process_chunk, 10 ms chunk", CHUNK_SAMPLES, || {
+        black_box(process_chunk(black_box(float_chunk), 1.5, &mut limiter));
+    });
+    let mut float_buf = float_chunk.to_vec();
+    bench("process_chunk_in_place, 10 ms chunk", CHUNK_SAMPLES, || {
+        float_buf.copy_from_slice(float_chunk);
+        process_chunk_in_place(black_box(&mut float_buf), 1.5, &mut limiter);
+    });
+
+    let mut limiter = Limiter::for_sample_rate(SAMPLE_RATE as f32);
+    bench("apply_gain_and_limit_with Limiter, gain 1.5", pcm.len(), || {
+        black_box(apply_gain_and_limit_with(black_box(&pcm), 1.5, Some(&mut limiter), Dither::None));
+    });
+
+    // the two limiters on their own, over float samples driven 6 dB over full scale
+    bench("soft_limit (memoryless)", float.len(), || {
+        for &s in black_box(&float) {
+            black_box(soft_limit(s * 2.0, 0.98));
+        }
+    });
+    let mut limiter = Limiter::for_sample_rate(SAMPLE_RATE as f32);
+    bench("Limiter::process (stateful)", float.len(), || {
+        for &s in black_box(&float) {
+            black_box(limiter.process(s * 2.0));
+        }
+    });
+    // the stream renderer's limiter: stereo frames, 1 ms lookahead
+    let mut limiter = LookaheadLimiter::new(0.99, SAMPLE_RATE / 1000, 2, 100.0, SAMPLE_RATE as f32);
+    bench("LookaheadLimiter::process_frame (stereo)", float.len(), || {
+        for pair in black_box(&float).chunks_exact(2) {
+            let mut frame = [pair[0] * 2.0, pair[1] * 2.0];
+            limiter.process_frame(&mut frame);
+            black_box(frame);
+        }
+    });
+}
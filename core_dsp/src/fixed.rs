@@ -0,0 +1,190 @@
+//! Fixed-point block math for the embedded app (`src/test.rs`): ADC RMS around a tracked DC bias,
+//! and dB conversions in Q16.16 so the control loop needs no float calls.
+
+/// Fractional bits of the Q16.16 values used throughout
+pub const Q_BITS: u32 = 16;
+/// 1.0 in Q16.16
+pub const Q_ONE: i32 = 1 << Q_BITS;
+/// Midpoint of the 12-bit ADC, in counts
+pub const ADC_MIDPOINT: u16 = 2048;
+/// Per-block weight of the newest block mean in the DC estimate. Slow enough that a bass note
+/// doesn't drag the center around; at 256-sample blocks it settles within a few hundred blocks.
+pub const DC_TRACK_ALPHA: f32 = 0.02;
+
+// log2(1 + i/32) in Q16.16, i = 0..=32
+const LOG2_LUT: [i32; 33] = [
+    0, 2909, 5732, 8473, 11136, 13727, 16248, 18704, 21098, 23433, 25711, 27936, 30109, 32234, 34312, 36346, 38336,
+    40286, 42196, 44068, 45904, 47705, 49472, 51207, 52911, 54584, 56229, 57845, 59434, 60997, 62534, 64047, 65536,
+];
+// 2^(i/32) in Q16.16, i = 0..=32
+const EXP2_LUT: [i32; 33] = [
+    65536, 66971, 68438, 69936, 71468, 73032, 74632, 76266, 77936, 79642, 81386, 83169, 84990, 86851, 88752, 90696,
+    92682, 94711, 96785, 98905, 101070, 103283, 105545, 107856, 110218, 112631, 115098, 117618, 120194, 122825,
+    125515, 128263, 131072,
+];
+// 20 * log10(2) in Q16.16: dB per doubling
+const DB_PER_OCTAVE_Q16: i64 = 394_566;
+// log2(10) / 20 in Q0.32: doublings per dB
+const OCTAVES_PER_DB_Q32: i64 = 713_378_626;
+// LUT segments: the top 5 fraction bits pick the entry, the low 11 interpolate
+const LUT_SHIFT: u32 = Q_BITS - 5;
+
+/// RMS of a block of 12-bit ADC samples around `ADC_MIDPOINT`, in ADC counts as Q16.16
+pub fn rms_u16_block_fixed(buf: &[u16]) -> i32 {
+    rms_u16_block_centered(buf, ADC_MIDPOINT as f32)
+}
+
+/// RMS of a block of ADC samples around `center` (counts, e.g. the `update_dc_estimate` output),
+/// in ADC counts as Q16.16
+pub fn rms_u16_block_centered(buf: &[u16], center: f32) -> i32 {
+    if buf.is_empty() {
+        return 0;
+    }
+    let center_q8 = (center * 256.0) as i64;
+    // deviations in Q8 keep the sum of squares within i64: (2^20)^2 per sample
+    let sum_sq: i64 = buf
+        .iter()
+        .map(|&s| {
+            let d = ((s as i64) << 8) - center_q8;
+            d * d
+        })
+        .sum();
+    let mean_sq_q16 = (sum_sq / buf.len() as i64) as u64;
+    isqrt_u64(mean_sq_q16 << Q_BITS).min(i32::MAX as u64) as i32
+}
+
+/// Leaky-integrator update of the ADC bias estimate from one block: the mic bias drifts with the
+/// supply and the coupling, so the center subtracted before the RMS follows it instead of assuming 2048
+pub fn update_dc_estimate(dc_estimate: f32, buf: &[u16]) -> f32 {
+    if buf.is_empty() {
+        return dc_estimate;
+    }
+    let sum: u32 = buf.iter().map(|&s| s as u32).sum();
+    let mean = sum as f32 / buf.len() as f32;
+    dc_estimate + DC_TRACK_ALPHA * (mean - dc_estimate)
+}
+
+/// 20*log10(lin) for a positive Q16.16 value, as Q16.16 dB. Non-positive input saturates to
+/// `i32::MIN` (minus infinity dB).
+pub fn lin_to_db_q(lin: i32) -> i32 {
+    if lin <= 0 {
+        return i32::MIN;
+    }
+    ((log2_q(lin) as i64 * DB_PER_OCTAVE_Q16) >> Q_BITS) as i32
+}
+
+/// 10^(db/20) for a Q16.16 dB value, as Q16.16. Saturates at `i32::MAX` (about +90 dB) and
+/// flushes to 0 below about -96 dB.
+pub fn db_to_lin_q(db: i32) -> i32 {
+    let octaves = (db as i64 * OCTAVES_PER_DB_Q32) >> 32; // Q16.16
+    let whole = octaves >> Q_BITS;
+    let mantissa = exp2_frac_q((octaves & (Q_ONE as i64 - 1)) as i32) as i64; // [1, 2) in Q16.16
+    if whole >= 15 {
+        return i32::MAX;
+    }
+    if whole <= -32 {
+        return 0;
+    }
+    let lin = if whole >= 0 { mantissa << whole } else { mantissa >> -whole };
+    lin.min(i32::MAX as i64) as i32
+}
+
+/// log2 of a positive Q16.16 value, as Q16.16
+fn log2_q(x: i32) -> i32 {
+    let msb = 31 - x.leading_zeros() as i32;
+    let whole = msb - Q_BITS as i32;
+    // normalise the mantissa to [1, 2) in Q16.16
+    let m = if whole >= 0 { x >> whole } else { x << -whole };
+    let frac = m - Q_ONE;
+    (whole << Q_BITS) + interpolate(&LOG2_LUT, frac)
+}
+
+/// 2^frac for `frac` in [0, 1) as Q16.16
+fn exp2_frac_q(frac: i32) -> i32 {
+    interpolate(&EXP2_LUT, frac)
+}
+
+// Linear interpolation in a 33-entry table over a Q16.16 fraction in [0, 1)
+fn interpolate(lut: &[i32; 33], frac: i32) -> i32 {
+    let i = (frac >> LUT_SHIFT) as usize;
+    let t = (frac & ((1 << LUT_SHIFT) - 1)) as i64;
+    let (a, b) = (lut[i] as i64, lut[i + 1] as i64);
+    (a + (((b - a) * t) >> LUT_SHIFT)) as i32
+}
+
+// Integer square root (floor), bit by bit
+fn isqrt_u64(n: u64) -> u64 {
+    let mut rem = n;
+    let mut root = 0u64;
+    let mut bit = 1u64 << 62;
+    while bit > n {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rem >= root + bit {
+            rem -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_f32(q: i32) -> f32 {
+        q as f32 / Q_ONE as f32
+    }
+
+    #[test]
+    fn test_fixed_rms_matches_float() {
+        let tone: [u16; 256] = core::array::from_fn(|i| (2048.0 + 900.0 * (i as f32 * 0.3).sin()).round() as u16);
+        let float_rms = (tone.iter().map(|&s| (s as f32 - 2048.0).powi(2)).sum::<f32>() / tone.len() as f32).sqrt();
+        let fixed = to_f32(rms_u16_block_fixed(&tone));
+        assert!((fixed - float_rms).abs() < 0.01, "fixed {} vs float {}", fixed, float_rms);
+        assert_eq!(rms_u16_block_fixed(&[ADC_MIDPOINT; 64]), 0);
+        assert_eq!(rms_u16_block_fixed(&[]), 0);
+        assert_eq!(rms_u16_block_fixed(&[4095, 1]), 2047 << Q_BITS, "full-scale square wave");
+    }
+
+    #[test]
+    fn test_fixed_db_conversions_match_float() {
+        for lin in [0.001f32, 0.1, 0.5, 1.0, 1.7, 10.0, 640.0, 2047.0] {
+            let q = (lin * Q_ONE as f32) as i32;
+            let db = to_f32(lin_to_db_q(q));
+            // compare against the quantised input: 0.001 is only 65 in Q16.16
+            assert!((db - 20.0 * to_f32(q).log10()).abs() < 0.01, "{} -> {} dB", lin, db);
+        }
+        for db in [-60.0f32, -20.0, -6.0, -0.5, 0.0, 3.0, 12.0, 40.0] {
+            let lin = to_f32(db_to_lin_q((db * Q_ONE as f32) as i32));
+            let expected = 10f32.powf(db / 20.0);
+            assert!((lin - expected).abs() <= expected * 1e-3 + 2.0 / Q_ONE as f32, "{} dB -> {} ({})", db, lin, expected);
+        }
+        assert_eq!(lin_to_db_q(0), i32::MIN);
+        assert_eq!(db_to_lin_q(120 << Q_BITS), i32::MAX);
+        assert_eq!(db_to_lin_q(-200 << Q_BITS), 0);
+    }
+
+    #[test]
+    fn test_dc_estimate_converges_to_the_bias() {
+        // 400-count tone with a 32-sample period (whole cycles per block) on a 2300-count bias
+        let block = |bias: f32| -> [u16; 256] {
+            core::array::from_fn(|i| (bias + 400.0 * (2.0 * core::f32::consts::PI * i as f32 / 32.0).sin()).round() as u16)
+        };
+        let tone_only_rms = to_f32(rms_u16_block_centered(&block(2048.0), 2048.0));
+        let offset = block(2300.0);
+        assert!(to_f32(rms_u16_block_fixed(&offset)) > tone_only_rms + 50.0, "fixed 2048 center picks up the bias");
+
+        let mut dc = ADC_MIDPOINT as f32;
+        for _ in 0..500 {
+            dc = update_dc_estimate(dc, &offset);
+        }
+        assert!((dc - 2300.0).abs() < 0.1, "estimate {}", dc);
+        let rms = to_f32(rms_u16_block_centered(&offset, dc));
+        assert!((rms - tone_only_rms).abs() < 0.05, "{} vs tone-only {}", rms, tone_only_rms);
+    }
+}
@@ -0,0 +1,212 @@
+//! Level and gain math shared by the host binaries (`adaptive_vol`) and the embedded app
+//! (`src/test.rs`), so both compute dB, RMS, smoothing and limiting the same way.
+//!
+//! `no_std` unless the default `std` feature is on; without it, build with the `no_std` feature to
+//! take the float functions from libm. Nothing here allocates.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod fixed;
+mod math;
+
+/// Range `db_to_lin` clamps its input to: below is silence for any practical purpose, above is no
+/// sane gain or level
+pub const MIN_DB: f32 = -180.0;
+pub const MAX_DB: f32 = 60.0;
+
+/// dB -> linear amplitude, for dB clamped to `MIN_DB..=MAX_DB`; NaN reads as `MIN_DB` so it can't
+/// poison whatever the result multiplies
+pub fn db_to_lin(db: f32) -> f32 {
+    let db = if db.is_nan() { MIN_DB } else { db.clamp(MIN_DB, MAX_DB) };
+    math::powf(10.0, db / 20.0)
+}
+
+/// Linear amplitude -> dB; amplitudes below 1e-9 read as -180 dB instead of minus infinity
+pub fn lin_to_db(lin: f32) -> f32 {
+    20.0 * math::log10(lin.max(1e-9))
+}
+
+/// Root mean square of a block of samples (0 for an empty block)
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sumsq: f32 = samples.iter().map(|s| s * s).sum();
+    math::sqrt(sumsq / samples.len() as f32)
+}
+
+/// What `rms_to_db` and `RmsEstimate::db` return when there is nothing to measure (an empty block,
+/// or nothing but zeros or clipped samples): far below any real level but finite, so it can't turn
+/// into a NaN further on. Callers check for it with `is_measurement`.
+pub const NO_MEASUREMENT_DB: f32 = MIN_DB;
+
+/// Whether `db` is a real level rather than `NO_MEASUREMENT_DB` (or a non-finite value)
+pub fn is_measurement(db: f32) -> bool {
+    db.is_finite() && db > NO_MEASUREMENT_DB
+}
+
+/// RMS of a block in dB, plus `calibration_db` (e.g. dBFS -> dB SPL for a mic). `NO_MEASUREMENT_DB`
+/// for an empty or all-zero block, whatever the calibration.
+pub fn rms_to_db(samples: &[f32], calibration_db: f32) -> f32 {
+    let rms = rms(samples);
+    if rms <= 0.0 {
+        return NO_MEASUREMENT_DB;
+    }
+    lin_to_db(rms) + calibration_db
+}
+
+/// Samples at or above this magnitude (of full scale 1.0) count as clipped
+pub const CLIP_LEVEL: f32 = 0.999;
+/// Above this fraction of clipped samples a block's level is not trusted
+pub const MAX_CLIPPED_FRACTION: f32 = 0.05;
+
+/// RMS of the unclipped samples of a block, and how much of the block was clipped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RmsEstimate {
+    pub rms: f32,
+    /// Fraction of samples at or beyond `CLIP_LEVEL` (0..1; 0 for an empty block)
+    pub clipped_fraction: f32,
+}
+
+impl RmsEstimate {
+    /// RMS in dB plus `calibration_db`, as `rms_to_db` (`NO_MEASUREMENT_DB` with nothing to measure)
+    pub fn db(&self, calibration_db: f32) -> f32 {
+        if self.rms <= 0.0 {
+            return NO_MEASUREMENT_DB;
+        }
+        lin_to_db(self.rms) + calibration_db
+    }
+
+    /// Too much of the block clipped: the true level is unknown (and above what `rms` says)
+    pub fn is_reliable(&self) -> bool {
+        self.clipped_fraction <= MAX_CLIPPED_FRACTION
+    }
+}
+
+/// `rms` over the samples below `clip_level` only: clipped samples would inflate the mean by an
+/// amount that says nothing about the real level. A fully clipped block reads 0.
+pub fn clip_aware_rms(samples: &[f32], clip_level: f32) -> RmsEstimate {
+    let mut sumsq = 0.0f32;
+    let mut clipped = 0usize;
+    for &s in samples {
+        if s.abs() >= clip_level {
+            clipped += 1;
+        } else {
+            sumsq += s * s;
+        }
+    }
+    let kept = samples.len() - clipped;
+    RmsEstimate {
+        rms: if kept == 0 { 0.0 } else { math::sqrt(sumsq / kept as f32) },
+        clipped_fraction: if samples.is_empty() { 0.0 } else { clipped as f32 / samples.len() as f32 },
+    }
+}
+
+/// Change to apply to a one-pole smoother at `value` heading for `target` over `dt` seconds:
+/// `tau_attack` when rising, `tau_release` when falling. Zero for a non-positive `dt`.
+pub fn attack_release_delta(value: f32, target: f32, tau_attack: f32, tau_release: f32, dt: f32) -> f32 {
+    if dt <= 0.0 {
+        return 0.0;
+    }
+    let tau = if target > value { tau_attack } else { tau_release };
+    let alpha = 1.0 - math::exp(-dt / tau);
+    alpha * (target - value)
+}
+
+/// Shape of the curve `soft_limit_with` applies above the threshold
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Saturation {
+    /// exceeded / (1 + exceeded): gentle, approaches threshold + 1 (the original `soft_limit` curve)
+    #[default]
+    Sqrt,
+    /// tanh of the excess: same unit slope at the threshold, saturates faster
+    Tanh,
+    /// Clip at the threshold
+    Hard,
+}
+
+// Simple soft limiter: if |sample| > threshold => compress to avoid clip
+pub fn soft_limit(sample: f32, threshold: f32) -> f32 {
+    soft_limit_with(sample, threshold, Saturation::Sqrt)
+}
+
+/// Pass samples below `threshold` unchanged and shape the excess above it with `kind`
+pub fn soft_limit_with(sample: f32, threshold: f32, kind: Saturation) -> f32 {
+    let abs = sample.abs();
+    if abs <= threshold { sample }
+    else {
+        let sign = sample.signum();
+        let excess = abs - threshold;
+        let shaped = match kind {
+            // gentle compression beyond threshold (e.g., sqrt curve)
+            Saturation::Sqrt => excess / (1.0 + excess),
+            Saturation::Tanh => math::tanh(excess),
+            Saturation::Hard => 0.0,
+        };
+        sign * (threshold + shaped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_conversions() {
+        for db in [-40.0, -6.0, 0.0, 12.0] {
+            assert!((lin_to_db(db_to_lin(db)) - db).abs() < 1e-4, "{} dB", db);
+        }
+        assert_eq!(lin_to_db(0.0), -180.0);
+        for poison in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert!(db_to_lin(poison).is_finite(), "{}", poison);
+        }
+        assert_eq!(db_to_lin(f32::NEG_INFINITY), db_to_lin(MIN_DB));
+        assert_eq!(db_to_lin(1000.0), db_to_lin(MAX_DB));
+        let square = [0.5, -0.5, 0.5, -0.5];
+        assert!((rms(&square) - 0.5).abs() < 1e-7);
+        assert!((rms_to_db(&square, 94.0) - (94.0 - 6.0206)).abs() < 1e-3);
+        assert_eq!(rms(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_empty_block_reads_as_no_measurement() {
+        // the controller can run before the mic has delivered anything
+        let db = rms_to_db(&[], 94.0);
+        assert!(db.is_finite(), "{}", db);
+        assert_eq!(db, NO_MEASUREMENT_DB);
+        assert!(!is_measurement(db));
+        assert_eq!(rms_to_db(&[0.0; 16], 94.0), NO_MEASUREMENT_DB, "silence is no measurement either");
+        assert_eq!(clip_aware_rms(&[], CLIP_LEVEL).db(94.0), NO_MEASUREMENT_DB);
+        assert!(is_measurement(rms_to_db(&[1e-6, -1e-6], 0.0)), "-120 dBFS is still a level");
+        assert!(!is_measurement(f32::NAN));
+    }
+
+    #[test]
+    fn test_clip_aware_rms_ignores_clipped_samples() {
+        // first half a -20 dBFS square wave, second half slammed to full scale
+        let mut block = [0.0f32; 64];
+        for (i, s) in block.iter_mut().enumerate() {
+            let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+            *s = sign * if i < 32 { 0.1 } else { 1.0 };
+        }
+        let estimate = clip_aware_rms(&block, CLIP_LEVEL);
+        assert!((estimate.rms - 0.1).abs() < 1e-6, "{}", estimate.rms);
+        assert_eq!(estimate.clipped_fraction, 0.5);
+        assert!(!estimate.is_reliable());
+        assert!(rms(&block) > 0.7, "plain RMS is dominated by the clipped half");
+
+        let clean = clip_aware_rms(&block[..32], CLIP_LEVEL);
+        assert!(clean.is_reliable() && (clean.db(0.0) - rms_to_db(&block[..32], 0.0)).abs() < 1e-6);
+        assert_eq!(clip_aware_rms(&[1.0, -1.0], CLIP_LEVEL).rms, 0.0);
+        assert_eq!(clip_aware_rms(&[], CLIP_LEVEL).clipped_fraction, 0.0);
+    }
+
+    #[test]
+    fn test_attack_release_delta() {
+        let rise = attack_release_delta(0.0, 10.0, 0.1, 1.0, 0.1);
+        let fall = attack_release_delta(10.0, 0.0, 0.1, 1.0, 0.1);
+        assert!(rise > 6.0 && rise < 10.0, "attack moves most of the way: {}", rise);
+        assert!(-fall < 1.0, "release is slower: {}", fall);
+        assert_eq!(attack_release_delta(0.0, 10.0, 0.1, 1.0, 0.0), 0.0);
+    }
+}
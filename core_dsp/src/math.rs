@@ -0,0 +1,30 @@
+//! The few float functions `core` lacks, from std on the host and libm on the target
+
+#[cfg(feature = "std")]
+mod imp {
+    pub fn powf(x: f32, y: f32) -> f32 {
+        x.powf(y)
+    }
+    pub fn exp(x: f32) -> f32 {
+        x.exp()
+    }
+    pub fn log10(x: f32) -> f32 {
+        x.log10()
+    }
+    pub fn sqrt(x: f32) -> f32 {
+        x.sqrt()
+    }
+    pub fn tanh(x: f32) -> f32 {
+        x.tanh()
+    }
+}
+
+#[cfg(all(not(feature = "std"), feature = "no_std"))]
+mod imp {
+    pub use libm::{expf as exp, log10f as log10, powf, sqrtf as sqrt, tanhf as tanh};
+}
+
+#[cfg(not(any(feature = "std", feature = "no_std")))]
+compile_error!("core_dsp needs either the `std` or the `no_std` (libm) feature for its float math");
+
+pub use imp::*;
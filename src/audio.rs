@@ -1,30 +1,44 @@
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Sample, SampleFormat};
-use crate::gain::AdaptiveGain;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{FromSample, Sample, SampleFormat};
+use adaptive_vol::adaptive_gain::{downmix_to_mono, mock_get_cabin_noise_db, NoiseCombine};
+use adaptive_vol::device::{default_input_device, default_output_device};
+use adaptive_vol::gain::AdaptiveGain;
+use adaptive_vol::util::install_ctrlc_handler;
+use anyhow::bail;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Simulated speed (sine) at `t` seconds
+fn simulated_speed(t: f32) -> f32 {
+    60.0 + 20.0 * (t * 0.05).sin()
+}
 
 pub fn run_audio_loop() -> anyhow::Result<()> {
     let host = cpal::default_host();
 
-    let input_device = host.default_input_device().expect("no input device available");
-    let output_device = host.default_output_device().expect("no output device available");
+    let output_device = default_output_device(&host)?;
+    println!("Output: {:?}", output_device.name()?);
+
+    let mut adaptive = AdaptiveGain::default();
+    adaptive.set_noise_combine(NoiseCombine::from_env());
+    let shared_gain = Arc::new(Mutex::new(adaptive));
 
+    // No microphone: keep the gain loop running on a simulated cabin noise level instead
+    let Some(input_device) = default_input_device(&host) else {
+        println!("Input: none found, using simulated cabin noise");
+        return run_simulated_noise(shared_gain);
+    };
     println!("Input: {:?}", input_device.name()?);
-    println!("Output: {:?}", output_device.name()?);
 
     let config = input_device.default_input_config()?;
     let sample_rate = config.sample_rate().0 as f32;
 
-    let shared_gain = Arc::new(Mutex::new(AdaptiveGain::new(75.0, 0.1, 1.0, 0.0)));
-    let output_gain = shared_gain.clone();
-
-    // Simulate speed (sine)
-    let mut speed = 0.0f32;
-
-    let input_stream = match config.sample_format() {
-        SampleFormat::F32 => build_stream::<f32>(&input_device, &output_device, sample_rate, shared_gain, &mut speed)?,
-        SampleFormat::I16 => build_stream::<i16>(&input_device, &output_device, sample_rate, shared_gain, &mut speed)?,
-        SampleFormat::U16 => build_stream::<u16>(&input_device, &output_device, sample_rate, shared_gain, &mut speed)?,
+    let (input_stream, _output_stream) = match config.sample_format() {
+        SampleFormat::F32 => build_stream::<f32>(&input_device, &output_device, sample_rate, shared_gain)?,
+        SampleFormat::I16 => build_stream::<i16>(&input_device, &output_device, sample_rate, shared_gain)?,
+        SampleFormat::U16 => build_stream::<u16>(&input_device, &output_device, sample_rate, shared_gain)?,
+        format => bail!("input device uses unsupported sample format {:?}", format),
     };
 
     input_stream.play()?;
@@ -32,52 +46,72 @@ pub fn run_audio_loop() -> anyhow::Result<()> {
     Ok(())
 }
 
+// Same gain printout as the mic callback, driven by the mock cabin noise at ~10 Hz until Ctrl-C
+fn run_simulated_noise(gain_ref: Arc<Mutex<AdaptiveGain>>) -> anyhow::Result<()> {
+    let stop = install_ctrlc_handler();
+    let started = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        let t = started.elapsed().as_secs_f32();
+        let cabin_db = mock_get_cabin_noise_db(t);
+        let speed = simulated_speed(t);
+        let (gain_db, _) = gain_ref.lock().unwrap().compute_gain(cabin_db, speed);
+        println!("Cabin (simulated): {:.1} dB | Speed: {:.1} | Gain: {:.2} dB", cabin_db, speed, gain_db);
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Ok(())
+}
+
+// Returns the input stream and the (silent) output stream, which must be kept alive alongside it
 fn build_stream<T>(
     input_device: &cpal::Device,
     output_device: &cpal::Device,
     sample_rate: f32,
     gain_ref: Arc<Mutex<AdaptiveGain>>,
-    speed_ref: &mut f32,
-) -> anyhow::Result<cpal::Stream>
+) -> anyhow::Result<(cpal::Stream, cpal::Stream)>
 where
-    T: Sample + cpal::FromSample<f32> + cpal::SizedSample,
+    T: Sample + cpal::SizedSample,
+    f32: FromSample<T>,
 {
     let config = input_device.default_input_config()?.config();
     let channels = config.channels as usize;
 
-    let mut output_stream = output_device.build_output_stream_raw(
+    let output_stream = output_device.build_output_stream_raw(
         &config,
         SampleFormat::F32,
         move |data, _: &cpal::OutputCallbackInfo| {
-            let buffer = data.as_slice::<f32>().unwrap();
+            let buffer = data.as_slice_mut::<f32>().unwrap();
             for s in buffer.iter_mut() {
                 *s = 0.0;
             }
         },
         move |err| eprintln!("output err: {err:?}"),
+        None,
     )?;
     output_stream.play()?;
 
     let mut frame_count = 0u64;
+    let mut frame_f32 = Vec::<f32>::with_capacity(channels);
 
     let stream = input_device.build_input_stream(
         &config,
         move |data: &[T], _: &cpal::InputCallbackInfo| {
             let mut rms = 0.0f32;
-            for sample in data.iter().step_by(channels) {
-                let v = sample.to_f32();
+            for frame in data.chunks(channels) {
+                frame_f32.clear();
+                frame_f32.extend(frame.iter().map(|&s| f32::from_sample(s)));
+                let v = downmix_to_mono(&frame_f32);
                 rms += v * v;
             }
             rms = (rms / (data.len() as f32 / channels as f32)).sqrt();
             let cabin_db = 20.0 * rms.max(1e-6).log10() + 94.0;
 
-            *speed_ref = 60.0 + 20.0 * ((frame_count as f32 / sample_rate) * 0.05).sin();
+            let speed = simulated_speed(frame_count as f32 / sample_rate);
             frame_count += data.len() as u64 / channels as u64;
 
             let mut gain = gain_ref.lock().unwrap();
-            let (gain_db, gain_lin) = gain.compute_gain(cabin_db, *speed_ref);
+            let (gain_db, _gain_lin) = gain.compute_gain(cabin_db, speed);
 
-            println!("Cabin: {:.1} dB | Speed: {:.1} | Gain: {:.2} dB", cabin_db, *speed_ref, gain_db);
+            println!("Cabin: {:.1} dB | Speed: {:.1} | Gain: {:.2} dB", cabin_db, speed, gain_db);
 
             // Normally apply gain to playback buffer here (loopback / file)
             // For demo, we just print gain values.
@@ -86,5 +120,5 @@ where
         move |err| eprintln!("input err: {err:?}"),
         None,
     )?;
-    Ok(stream)
+    Ok((stream, output_stream))
 }
@@ -0,0 +1,43 @@
+// `analyze`: print a WAV file's integrated loudness and peak levels. Also the first pass of
+// `--target-lufs` normalization for `play` and `process`.
+use anyhow::{bail, Result};
+
+use adaptive_vol::offline::{analyze_wav, normalization_gain, DEFAULT_PEAK_CEILING_DB};
+
+use crate::args::AnalyzeArgs;
+
+pub fn run(args: &AnalyzeArgs) -> Result<()> {
+    let analysis = analyze_wav(&args.wav)?;
+    println!("File:                {}", args.wav);
+    println!("Duration:            {:.2} s", analysis.duration_s);
+    match analysis.integrated_lufs {
+        Some(lufs) => println!("Integrated loudness: {:.1} LUFS", lufs),
+        None => println!("Integrated loudness: silent (below the -70 LUFS gate)"),
+    }
+    println!("Sample peak:         {:.1} dBFS", analysis.sample_peak_dbfs);
+    println!("True peak:           {:.1} dBTP", analysis.true_peak_dbtp);
+    Ok(())
+}
+
+/// Measure `wav` and return the linear gain that normalizes it to `target_lufs` without pushing its
+/// true peak above `DEFAULT_PEAK_CEILING_DB`, reporting what was applied
+pub fn loudness_baseline(wav: &str, target_lufs: f32) -> Result<f32> {
+    let analysis = analyze_wav(wav)?;
+    let Some(normalization) = normalization_gain(&analysis, target_lufs, DEFAULT_PEAK_CEILING_DB) else {
+        bail!("'{}' is silent (below the -70 LUFS gate); nothing to normalize", wav);
+    };
+    let source_lufs = analysis.integrated_lufs.unwrap_or(f32::NEG_INFINITY);
+    println!(
+        "Loudness normalization: {:.1} LUFS -> {:.1} LUFS, gain {:+.2} dB",
+        source_lufs,
+        source_lufs + normalization.gain_db,
+        normalization.gain_db
+    );
+    if normalization.peak_limited {
+        println!(
+            "  limited by the {:.1} dBTP true-peak ceiling (target was {:.1} LUFS)",
+            DEFAULT_PEAK_CEILING_DB, target_lufs
+        );
+    }
+    Ok(normalization.gain_lin())
+}
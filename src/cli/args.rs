@@ -0,0 +1,842 @@
+//! Command-line parsing for the `adaptive_vol` binary. Kept std-only: a handful of flags per
+//! subcommand doesn't justify an argument-parsing dependency.
+
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use log::LevelFilter;
+
+use adaptive_vol::controller::ControllerKind;
+use adaptive_vol::dropout::DropoutHold;
+use adaptive_vol::dynamics::{Ducker, MicAgc};
+use adaptive_vol::filters::DEFAULT_MIC_HIGHPASS_HZ;
+use adaptive_vol::kalman::{DEFAULT_MEASUREMENT_NOISE_DB2, DEFAULT_PROCESS_NOISE_DB2};
+use adaptive_vol::spectral::DEFAULT_BAND_HZ as SPECTRAL_BAND_HZ;
+use adaptive_vol::speed::SpeedUnit;
+use adaptive_vol::spl::Weighting;
+use adaptive_vol::vad::VadThresholds;
+use adaptive_vol::Preset;
+
+pub const USAGE: &str = "\
+Adaptive in-car volume control
+
+Usage: adaptive_vol [OPTIONS] <COMMAND>
+
+Commands:
+  simulate                       Mocked speed/noise driving a sine, no audio device
+      --trace <csv>              Replay t,cabin_db,speed_kmh rows instead of the mocks
+      --seed <n>                 Seeded random cabin noise (wander and bumps), same run per seed
+  play [wav] [--auto]            Play a WAV through rodio; --auto mocks speed/noise,
+                                 otherwise they are polled from SPEED_UI_URL
+      --trace <csv>              Replay a recorded trace (implies --auto)
+      --output-device <idx|name> Output device (index or name substring)
+      --eq <json>                Parametric EQ preset applied after the gain
+      --target-lufs <LUFS>       Normalize the file's loudness first (two-pass, peak-safe)
+  stream [wav] [speed-url]       Live cpal output with mic noise and a speed source
+      --loop                     Restart the WAV when it ends
+      --speed-unit <kmh|mph>     Unit the speed server reports in
+      --obd <tty>                Read speed from an ELM327 OBD-II adapter (OBD_BAUD)
+      --nmea <tty|tcp://h:p>     Read speed from an NMEA GPS receiver (NMEA_BAUD)
+      --record <csv>             Log every controller step (replayable with --trace)
+      --input-device <idx|name>  Microphone (index or name substring)
+      --output-device <idx|name> Output device (index or name substring)
+      --eq <json>                Parametric EQ preset applied after the gain
+      --loudness-comp            Bass/treble shelves that follow the playback level
+      --controller <direct|pid>  Gain controller (default direct: target - noise, smoothed)
+      --control <addr:port>      HTTP knob: POST /offset {\"db\": 3.0}, GET /status
+      --nav <wav>                Navigation prompt mixed over the music, which ducks under it
+      --nav-repeat-s <s>         Pause between repeats of the prompt (default 15)
+      --duck-threshold-db <dBFS> Prompt level above which the music ducks (default -45)
+      --duck-depth-db <dB>       How far the music ducks (default 12)
+      --duck-attack-ms <ms>      Fade down over (default 50)
+      --duck-release-ms <ms>     Fade back up over once the prompt ends (default 600)
+      --metrics-port <port>      Prometheus metrics on /metrics (needs the `metrics` feature)
+      --mic-channels <i,j,..>    Input channels to measure as separate mics (0-based), power-averaged
+      --mic-outlier-db <dB>      Leave out a mic this far from the median of the others
+      --mic-weighting <a|z>      Frequency weighting of the mic level (default z, flat)
+      --mic-calibration-db <dB>  Added to the mic dBFS to get dB SPL (default 94, or the profile's)
+      --mic-highpass-hz <Hz>     Mic high-pass corner ahead of the level (default 20, 0 = off)
+      --mic-agc <on|dBFS,s>      Normalize the mic's level (target, calibration window at startup)
+      --mic-noise-band <road|lo-hi>  Cabin level from this band of the mic spectrum (Hz)
+      --multiband                Per-band output gain from the mic spectrum
+      --cabin-kalman <on|q,r>    Kalman-filter the cabin level (process, measurement noise dB^2)
+      --spl-weighting <fast|slow|impulse>  Meter time weighting instead of per-window RMS
+      --self-masking-coupling-db <dB>  Take our own playback back out of the mic level
+      --vad                      Hold the gain while the mic picks up speech
+      --vad-onset-db <dB>        Voice-band rise over its floor that counts as speech (default 6)
+      --vad-band-ratio <0..1>    Share of the energy in the voice band it needs (default 0.05)
+      --vad-hangover-blocks <n>  50 ms blocks still reported as speech after it stops (default 6)
+      --dropout-timeout-s <s>    Hold through a dead mic or stale speed, then 0 dB (default 10)
+      --no-clip-backoff          Leave a clipping gain to the limiter alone
+  process <in.wav> <out.wav>     Write a gain-adjusted copy of a WAV
+      --gain <linear>            Fixed gain to apply (default 1.5)
+      --auto                     Adaptive gain following the mocked speed/noise instead
+      --trace <csv>              Adaptive gain following a recorded trace
+      --controller <direct|pid>  Gain controller for --auto/--trace (default direct)
+      --target-lufs <LUFS>       Normalize the file's loudness (replaces --gain; the adaptive
+                                 gain of --auto/--trace applies on top)
+  analyze <wav>                  Print a WAV's integrated loudness (LUFS), sample and true peak
+  telemetry <tty>                Print the embedded board's gain frames as CSV (TELEMETRY_BAUD)
+
+Options (any position):
+  --preset <quiet|normal|loud>   Target level and offset together (before --target-db/--offset-db)
+  --target-db <dB>               Target playback level (overrides config.toml)
+  --offset-db <dB>               User volume offset (overrides config.toml)
+  --profile <path>               Vehicle noise profile (JSON)
+  --config <path>                Tuning file (default ./config.toml)
+  --night                        Night mode: target -6 dB, gain capped at +6 dB, and a gentle
+                                 compressor on play/stream output
+  --log-level <level>            off, error, warn, info (default), debug or trace; RUST_LOG also
+                                 takes per-module levels (adaptive_vol::speed_source=debug)
+  --list-devices                 List audio devices with their indices and configs, then exit
+  -h, --help                     Print this help
+";
+
+/// Default pause between repeats of the --nav prompt (s)
+pub const DEFAULT_NAV_REPEAT_S: f32 = 15.0;
+
+/// Flags accepted before or after the subcommand
+const GLOBAL_FLAGS: [&str; 6] = ["--preset", "--target-db", "--offset-db", "--profile", "--config", "--log-level"];
+/// Switches accepted before or after the subcommand
+const GLOBAL_SWITCHES: [&str; 1] = ["--night"];
+
+#[derive(Debug, Default, PartialEq)]
+pub struct GlobalArgs {
+    pub preset: Option<Preset>,
+    pub target_db: Option<f32>,
+    pub offset_db: Option<f32>,
+    pub profile: Option<String>,
+    pub config: Option<String>,
+    pub night: bool,
+    /// `--log-level`: overrides the default level from RUST_LOG
+    pub log_level: Option<LevelFilter>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SimulateArgs {
+    pub trace: Option<String>,
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PlayArgs {
+    pub wav: String,
+    pub auto: bool,
+    pub trace: Option<String>,
+    pub output_device: Option<String>,
+    pub eq: Option<String>,
+    pub target_lufs: Option<f32>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StreamArgs {
+    pub wav: String,
+    pub speed_url: String,
+    pub loop_playback: bool,
+    pub speed_unit: SpeedUnit,
+    pub obd: Option<String>,
+    pub nmea: Option<String>,
+    pub record: Option<String>,
+    pub input_device: Option<String>,
+    pub output_device: Option<String>,
+    pub eq: Option<String>,
+    pub loudness_comp: bool,
+    pub controller: ControllerKind,
+    pub control: Option<String>,
+    pub nav: Option<NavOptions>,
+    pub metrics_port: Option<u16>,
+    pub mic: MicOptions,
+}
+
+/// `stream --nav`: the prompt, how often it repeats and how the music ducks under it (see `Ducker`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct NavOptions {
+    pub path: String,
+    pub repeat: Duration,
+    pub duck_threshold_db: f32,
+    pub duck_depth_db: f32,
+    pub duck_attack_ms: f32,
+    pub duck_release_ms: f32,
+}
+
+/// `stream` mic measurement and controller options; `Default` is what the flags leave unset
+#[derive(Clone, Debug, PartialEq)]
+pub struct MicOptions {
+    /// Input channels measured as separate mics and averaged (see `MicArray`); None downmixes every
+    /// channel into one mic
+    pub channels: Option<Vec<usize>>,
+    /// Leave out a mic this far (dB) from the median of the others
+    pub outlier_db: Option<f32>,
+    /// `--mic-weighting a`: A-weight the mic signal before its level is measured
+    pub a_weighting: bool,
+    /// Offset mapping mic dBFS to dB SPL; None takes the profile's, else the built-in default
+    pub calibration_db: Option<f32>,
+    /// Corner of the mic high-pass (Hz); None measures the signal as captured
+    pub highpass_hz: Option<f32>,
+    /// Mic AGC target (dBFS) and calibration window (s; see `MicAgc`)
+    pub agc: Option<(f32, f32)>,
+    /// Estimate cabin noise from this band of the mic spectrum instead of broadband RMS
+    pub noise_band_hz: Option<(f32, f32)>,
+    /// Per-band gain from the mic spectrum on top of the broadband gain (see `MultibandGain`)
+    pub multiband: bool,
+    /// Kalman-filter the per-window cabin level (process, measurement noise in dB²; see `KalmanLevel`)
+    pub kalman: Option<(f32, f32)>,
+    /// Read the broadband level through a sound level meter time weighting
+    pub spl_weighting: Option<Weighting>,
+    /// Hold the gain while the mic picks up speech (see `Vad`)
+    pub vad: Option<VadThresholds>,
+    /// Output dBFS to mic level coupling, from `SelfMaskingCompensator::calibrate`
+    pub self_masking_coupling_db: Option<f32>,
+    /// How long the gain is held through a dropout before the safe gain (s; see `DropoutHold`)
+    pub dropout_timeout_s: f32,
+    /// Trim the gain while the output keeps hitting the limiter (see `ClipBackoff`)
+    pub clip_backoff: bool,
+}
+
+impl Default for MicOptions {
+    fn default() -> Self {
+        Self {
+            channels: None,
+            outlier_db: None,
+            a_weighting: false,
+            calibration_db: None,
+            highpass_hz: Some(DEFAULT_MIC_HIGHPASS_HZ),
+            agc: None,
+            noise_band_hz: None,
+            multiband: false,
+            kalman: None,
+            spl_weighting: None,
+            vad: None,
+            self_masking_coupling_db: None,
+            dropout_timeout_s: DropoutHold::DEFAULT_TIMEOUT_S,
+            clip_backoff: true,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ProcessArgs {
+    pub input: String,
+    pub output: String,
+    pub gain: f32,
+    pub auto: bool,
+    pub trace: Option<String>,
+    pub controller: ControllerKind,
+    pub target_lufs: Option<f32>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AnalyzeArgs {
+    pub wav: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TelemetryArgs {
+    pub port: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Simulate(SimulateArgs),
+    Play(PlayArgs),
+    Stream(Box<StreamArgs>),
+    Process(ProcessArgs),
+    Analyze(AnalyzeArgs),
+    Telemetry(TelemetryArgs),
+    ListDevices,
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cli {
+    pub global: GlobalArgs,
+    pub command: Command,
+}
+
+/// Flags each subcommand accepts: (flags taking a value, switches)
+fn command_flags(command: &str) -> Option<(&'static [&'static str], &'static [&'static str])> {
+    Some(match command {
+        "simulate" => (&["--trace", "--seed"], &[]),
+        "play" => (&["--trace", "--output-device", "--eq", "--target-lufs"], &["--auto"]),
+        "stream" => (
+            &[
+                "--speed-unit",
+                "--obd",
+                "--nmea",
+                "--record",
+                "--input-device",
+                "--output-device",
+                "--eq",
+                "--controller",
+                "--control",
+                "--nav",
+                "--nav-repeat-s",
+                "--duck-threshold-db",
+                "--duck-depth-db",
+                "--duck-attack-ms",
+                "--duck-release-ms",
+                "--metrics-port",
+                "--mic-channels",
+                "--mic-outlier-db",
+                "--mic-weighting",
+                "--mic-calibration-db",
+                "--mic-highpass-hz",
+                "--mic-agc",
+                "--mic-noise-band",
+                "--cabin-kalman",
+                "--spl-weighting",
+                "--self-masking-coupling-db",
+                "--vad-onset-db",
+                "--vad-band-ratio",
+                "--vad-hangover-blocks",
+                "--dropout-timeout-s",
+            ],
+            &["--loop", "--loudness-comp", "--multiband", "--vad", "--no-clip-backoff"],
+        ),
+        "process" => (&["--gain", "--trace", "--controller", "--target-lufs"], &["--auto"]),
+        "analyze" => (&[], &[]),
+        "telemetry" => (&[], &[]),
+        _ => return None,
+    })
+}
+
+/// Parse the arguments after the program name
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Cli> {
+    let mut global = GlobalArgs::default();
+    let mut command: Option<String> = None;
+    let mut positional = Vec::new();
+    let mut values: Vec<(String, String)> = Vec::new();
+    let mut switches: Vec<String> = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(Cli { global, command: Command::Help });
+        }
+        if arg == "--list-devices" {
+            return Ok(Cli { global, command: Command::ListDevices });
+        }
+        if !arg.starts_with("--") {
+            match command {
+                None => command = Some(arg),
+                Some(_) => positional.push(arg),
+            }
+            continue;
+        }
+        // --flag=value or --flag value
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let (value_flags, switch_flags) = command.as_deref().and_then(command_flags).unwrap_or((&[], &[]));
+        if GLOBAL_FLAGS.contains(&flag.as_str()) || value_flags.contains(&flag.as_str()) {
+            let value = match inline.or_else(|| args.next()) {
+                Some(value) => value,
+                None => bail!("{} needs a value", flag),
+            };
+            values.push((flag, value));
+        } else if inline.is_none()
+            && (GLOBAL_SWITCHES.contains(&flag.as_str()) || switch_flags.contains(&flag.as_str()))
+        {
+            switches.push(flag);
+        } else {
+            match &command {
+                Some(c) => bail!("unknown option '{}' for '{}'", flag, c),
+                None => bail!("unknown option '{}'", flag),
+            }
+        }
+    }
+
+    let flags = Flags { values, switches };
+    let controller = || -> Result<ControllerKind> {
+        flags.value("--controller").map_or(Ok(ControllerKind::default()), |v| v.parse().map_err(anyhow::Error::msg))
+    };
+
+    global.preset = flags.value("--preset").map(|v| v.parse().map_err(anyhow::Error::msg)).transpose()?;
+    global.target_db = flags.number("--target-db")?;
+    global.offset_db = flags.number("--offset-db")?;
+    global.profile = flags.value("--profile");
+    global.config = flags.value("--config");
+    global.night = flags.switch("--night");
+    global.log_level = flags
+        .value("--log-level")
+        .map(|v| v.parse().ok().with_context(|| format!("--log-level expects a level like info or debug, got '{}'", v)))
+        .transpose()?;
+
+    let mut positional = positional.into_iter();
+    let command = match command.as_deref() {
+        None => bail!("missing command"),
+        Some("simulate") => Command::Simulate(SimulateArgs {
+            trace: flags.value("--trace"),
+            seed: flags.value("--seed")
+                .map(|v| v.parse().with_context(|| format!("--seed expects a whole number, got '{}'", v)))
+                .transpose()?,
+        }),
+        Some("play") => Command::Play(PlayArgs {
+            wav: positional.next().unwrap_or_else(|| "test_audio.wav".to_string()),
+            auto: flags.switch("--auto"),
+            trace: flags.value("--trace"),
+            output_device: flags.value("--output-device"),
+            eq: flags.value("--eq"),
+            target_lufs: flags.number("--target-lufs")?,
+        }),
+        Some("stream") => Command::Stream(Box::new(StreamArgs {
+            wav: positional.next().unwrap_or_else(|| "test_audio.wav".to_string()),
+            speed_url: positional.next().unwrap_or_else(|| "http://127.0.0.1:5005/speed".to_string()),
+            loop_playback: flags.switch("--loop"),
+            speed_unit: match flags.value("--speed-unit") {
+                Some(v) => v.parse().map_err(anyhow::Error::msg)?,
+                None => SpeedUnit::default(),
+            },
+            obd: flags.value("--obd"),
+            nmea: flags.value("--nmea"),
+            record: flags.value("--record"),
+            input_device: flags.value("--input-device"),
+            output_device: flags.value("--output-device"),
+            eq: flags.value("--eq"),
+            loudness_comp: flags.switch("--loudness-comp"),
+            controller: controller()?,
+            control: flags.value("--control"),
+            nav: nav_options(&flags)?,
+            metrics_port: flags.value("--metrics-port")
+                .map(|v| v.parse().with_context(|| format!("--metrics-port expects a port number, got '{}'", v)))
+                .transpose()?,
+            mic: mic_options(&flags)?,
+        })),
+        Some("process") => {
+            let (Some(input), Some(output)) = (positional.next(), positional.next()) else {
+                bail!("process needs <in.wav> <out.wav>");
+            };
+            Command::Process(ProcessArgs {
+                input,
+                output,
+                gain: flags.number("--gain")?.unwrap_or(1.5),
+                auto: flags.switch("--auto"),
+                trace: flags.value("--trace"),
+                controller: controller()?,
+                target_lufs: flags.number("--target-lufs")?,
+            })
+        }
+        Some("analyze") => match positional.next() {
+            Some(wav) => Command::Analyze(AnalyzeArgs { wav }),
+            None => bail!("analyze needs a WAV file"),
+        },
+        Some("telemetry") => match positional.next() {
+            Some(port) => Command::Telemetry(TelemetryArgs { port }),
+            None => bail!("telemetry needs the serial port of the board, e.g. /dev/ttyACM0"),
+        },
+        Some(other) => bail!("unknown command '{}'", other),
+    };
+    if let Some(extra) = positional.next() {
+        bail!("unexpected argument '{}'", extra);
+    }
+    Ok(Cli { global, command })
+}
+
+/// Flag values and switches from the command line; the last occurrence of a flag wins
+struct Flags {
+    values: Vec<(String, String)>,
+    switches: Vec<String>,
+}
+
+impl Flags {
+    fn value(&self, name: &str) -> Option<String> {
+        self.values.iter().rev().find(|(f, _)| f == name).map(|(_, v)| v.clone())
+    }
+
+    fn number(&self, name: &str) -> Result<Option<f32>> {
+        self.value(name)
+            .map(|v| v.parse::<f32>().with_context(|| format!("{} expects a number, got '{}'", name, v)))
+            .transpose()
+    }
+
+    /// A number that has to be finite and at least `min`
+    fn number_at_least(&self, name: &str, min: f32) -> Result<Option<f32>> {
+        match self.number(name)? {
+            Some(n) if !(n.is_finite() && n >= min) => {
+                bail!("{} expects a number of at least {}, got {}", name, min, n)
+            }
+            n => Ok(n),
+        }
+    }
+
+    /// `<a><sep><b>`, or `keyword` for `keyword_value`
+    fn pair(&self, name: &str, sep: char, keyword: &str, keyword_value: (f32, f32)) -> Result<Option<(f32, f32)>> {
+        let Some(v) = self.value(name) else {
+            return Ok(None);
+        };
+        if v.eq_ignore_ascii_case(keyword) {
+            return Ok(Some(keyword_value));
+        }
+        let parsed = v.split_once(sep).and_then(|(a, b)| Some((a.trim().parse().ok()?, b.trim().parse().ok()?)));
+        match parsed {
+            Some((a, b)) if f32::is_finite(a) && f32::is_finite(b) => Ok(Some((a, b))),
+            _ => bail!("{} expects {} or <number>{}<number>, got '{}'", name, keyword, sep, v),
+        }
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|s| s == name)
+    }
+}
+
+/// The `stream` mic and controller flags, with the defaults for the ones not given
+fn mic_options(flags: &Flags) -> Result<MicOptions> {
+    let defaults = MicOptions::default();
+    let channels = match flags.value("--mic-channels") {
+        Some(v) => {
+            let channels: Vec<usize> = v
+                .split(',')
+                .map(|c| c.trim().parse().ok())
+                .collect::<Option<_>>()
+                .with_context(|| format!("--mic-channels expects channel indices like 0,1, got '{}'", v))?;
+            if let Some((i, &c)) = channels.iter().enumerate().find(|&(i, c)| channels[..i].contains(c)) {
+                bail!("--mic-channels lists channel {} twice (position {})", c, i + 1);
+            }
+            Some(channels)
+        }
+        None => None,
+    };
+    let outlier_db = match flags.number("--mic-outlier-db")? {
+        Some(db) if !(db.is_finite() && db > 0.0) => {
+            bail!("--mic-outlier-db expects a positive number of dB, got {}", db)
+        }
+        db => db,
+    };
+    let a_weighting = match flags.value("--mic-weighting") {
+        None => defaults.a_weighting,
+        Some(v) if v.eq_ignore_ascii_case("a") => true,
+        Some(v) if v.eq_ignore_ascii_case("z") => false,
+        Some(v) => bail!("--mic-weighting expects a or z, got '{}'", v),
+    };
+    let calibration_db = match flags.number("--mic-calibration-db")? {
+        Some(db) if !db.is_finite() => bail!("--mic-calibration-db expects a number of dB, got {}", db),
+        db => db,
+    };
+    let highpass_hz = match flags.number_at_least("--mic-highpass-hz", 0.0)? {
+        Some(0.0) => None,
+        Some(hz) => Some(hz),
+        None => defaults.highpass_hz,
+    };
+    let agc = flags.pair("--mic-agc", ',', "on", (MicAgc::DEFAULT_TARGET_DBFS, MicAgc::DEFAULT_CALIBRATION_S))?;
+    if let Some((_, window_s)) = agc.filter(|&(_, window_s)| window_s <= 0.0) {
+        bail!("--mic-agc: the calibration window must be positive, got {} s", window_s);
+    }
+    let noise_band_hz = flags.pair("--mic-noise-band", '-', "road", SPECTRAL_BAND_HZ)?;
+    if let Some((low, high)) = noise_band_hz.filter(|&(low, high)| !(0.0 <= low && low < high)) {
+        bail!("--mic-noise-band: expected 0 <= low < high, got {}-{} Hz", low, high);
+    }
+    let kalman = flags.pair("--cabin-kalman", ',', "on", (DEFAULT_PROCESS_NOISE_DB2, DEFAULT_MEASUREMENT_NOISE_DB2))?;
+    if let Some((q, r)) = kalman.filter(|&(q, r)| !(q > 0.0 && r > 0.0)) {
+        bail!("--cabin-kalman: both noise figures must be positive, got {},{}", q, r);
+    }
+    let spl_weighting =
+        flags.value("--spl-weighting").map(|v| v.parse().map_err(anyhow::Error::msg)).transpose()?;
+    let vad = vad_thresholds(flags)?;
+    let self_masking_coupling_db = match flags.number("--self-masking-coupling-db")? {
+        Some(db) if !db.is_finite() => bail!("--self-masking-coupling-db expects a number of dB, got {}", db),
+        db => db,
+    };
+    Ok(MicOptions {
+        channels,
+        outlier_db,
+        a_weighting,
+        calibration_db,
+        highpass_hz,
+        agc,
+        noise_band_hz,
+        multiband: flags.switch("--multiband"),
+        kalman,
+        spl_weighting,
+        vad,
+        self_masking_coupling_db,
+        dropout_timeout_s: flags.number_at_least("--dropout-timeout-s", 0.0)?.unwrap_or(defaults.dropout_timeout_s),
+        clip_backoff: !flags.switch("--no-clip-backoff"),
+    })
+}
+
+/// `--nav` with its repeat and ducking flags; those without `--nav` are an error
+fn nav_options(flags: &Flags) -> Result<Option<NavOptions>> {
+    const NAV_FLAGS: [&str; 5] =
+        ["--nav-repeat-s", "--duck-threshold-db", "--duck-depth-db", "--duck-attack-ms", "--duck-release-ms"];
+    let Some(path) = flags.value("--nav") else {
+        if let Some(flag) = NAV_FLAGS.iter().find(|&&f| flags.value(f).is_some()) {
+            bail!("{} needs --nav", flag);
+        }
+        return Ok(None);
+    };
+    let repeat_s = flags.number("--nav-repeat-s")?.unwrap_or(DEFAULT_NAV_REPEAT_S);
+    let repeat = Duration::try_from_secs_f32(repeat_s)
+        .with_context(|| format!("--nav-repeat-s expects a pause in seconds, got {}", repeat_s))?;
+    let finite = |name: &str, default: f32| -> Result<f32> {
+        match flags.number(name)? {
+            Some(db) if !db.is_finite() => bail!("{} expects a number, got {}", name, db),
+            db => Ok(db.unwrap_or(default)),
+        }
+    };
+    Ok(Some(NavOptions {
+        path,
+        repeat,
+        duck_threshold_db: finite("--duck-threshold-db", Ducker::DEFAULT_THRESHOLD_DB)?,
+        duck_depth_db: flags.number_at_least("--duck-depth-db", 0.0)?.unwrap_or(Ducker::DEFAULT_DEPTH_DB),
+        duck_attack_ms: flags.number_at_least("--duck-attack-ms", 0.0)?.unwrap_or(Ducker::DEFAULT_ATTACK_MS),
+        duck_release_ms: flags.number_at_least("--duck-release-ms", 0.0)?.unwrap_or(Ducker::DEFAULT_RELEASE_MS),
+    }))
+}
+
+/// `--vad` with any threshold flags on top of the defaults; a threshold without `--vad` is an error
+fn vad_thresholds(flags: &Flags) -> Result<Option<VadThresholds>> {
+    const THRESHOLD_FLAGS: [&str; 3] = ["--vad-onset-db", "--vad-band-ratio", "--vad-hangover-blocks"];
+    if !flags.switch("--vad") {
+        if let Some(flag) = THRESHOLD_FLAGS.iter().find(|&&f| flags.value(f).is_some()) {
+            bail!("{} needs --vad", flag);
+        }
+        return Ok(None);
+    }
+    let defaults = VadThresholds::default();
+    let min_band_ratio = flags.number_at_least("--vad-band-ratio", 0.0)?.unwrap_or(defaults.min_band_ratio);
+    if min_band_ratio > 1.0 {
+        bail!("--vad-band-ratio is a share of the energy (0..1), got {}", min_band_ratio);
+    }
+    let hangover_blocks = flags
+        .value("--vad-hangover-blocks")
+        .map(|v| v.parse().with_context(|| format!("--vad-hangover-blocks expects a whole number, got '{}'", v)))
+        .transpose()?;
+    Ok(Some(VadThresholds {
+        onset_db: flags.number_at_least("--vad-onset-db", 0.0)?.unwrap_or(defaults.onset_db),
+        min_band_ratio,
+        hangover_blocks: hangover_blocks.unwrap_or(defaults.hangover_blocks),
+        ..defaults
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(line: &str) -> Result<Cli> {
+        parse(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_subcommands() {
+        let cli = parse_str("--target-db 78 stream song.wav ws://car:9000 --loop --obd /dev/ttyUSB0 --offset-db=-3").unwrap();
+        assert_eq!(cli.global.target_db, Some(78.0));
+        assert_eq!(cli.global.offset_db, Some(-3.0), "globals are accepted after the subcommand too");
+        assert_eq!(
+            cli.command,
+            Command::Stream(Box::new(StreamArgs {
+                wav: "song.wav".into(),
+                speed_url: "ws://car:9000".into(),
+                loop_playback: true,
+                speed_unit: SpeedUnit::Kmh,
+                obd: Some("/dev/ttyUSB0".into()),
+                nmea: None,
+                record: None,
+                input_device: None,
+                output_device: None,
+                eq: None,
+                loudness_comp: false,
+                controller: ControllerKind::Direct,
+                control: None,
+                nav: None,
+                metrics_port: None,
+                mic: MicOptions::default(),
+            }))
+        );
+
+        assert_eq!(
+            parse_str("play --auto").unwrap().command,
+            Command::Play(PlayArgs {
+                wav: "test_audio.wav".into(),
+                auto: true,
+                trace: None,
+                output_device: None,
+                eq: None,
+                target_lufs: None,
+            })
+        );
+        let line = "stream --input-device 2 --output-device=Amp --eq door.json --control 0.0.0.0:8080 --nav turn.wav";
+        match parse_str(&format!("{} --loudness-comp", line)).unwrap().command {
+            Command::Stream(args) => {
+                assert!(args.loudness_comp);
+                assert_eq!(args.control.as_deref(), Some("0.0.0.0:8080"));
+                let nav = args.nav.unwrap();
+                assert_eq!(nav.path, "turn.wav");
+                assert_eq!(nav.repeat, Duration::from_secs(15));
+                assert_eq!(nav.duck_depth_db, Ducker::DEFAULT_DEPTH_DB);
+                assert_eq!(args.input_device.as_deref(), Some("2"));
+                assert_eq!(args.output_device.as_deref(), Some("Amp"));
+                assert_eq!(args.eq.as_deref(), Some("door.json"));
+            }
+            other => panic!("expected stream, got {:?}", other),
+        }
+        assert_eq!(
+            parse_str("process in.wav out.wav --gain 0.5").unwrap().command,
+            Command::Process(ProcessArgs {
+                input: "in.wav".into(),
+                output: "out.wav".into(),
+                gain: 0.5,
+                auto: false,
+                trace: None,
+                controller: ControllerKind::Direct,
+                target_lufs: None,
+            })
+        );
+        match parse_str("stream --metrics-port=9100").unwrap().command {
+            Command::Stream(args) => assert_eq!(args.metrics_port, Some(9100)),
+            other => panic!("expected stream, got {:?}", other),
+        }
+        match parse_str("process in.wav out.wav --trace drive.csv --controller pid").unwrap().command {
+            Command::Process(args) => assert_eq!(args.controller, ControllerKind::Pid),
+            other => panic!("expected process, got {:?}", other),
+        }
+        match parse_str("play song.wav --target-lufs -16").unwrap().command {
+            Command::Play(args) => assert_eq!(args.target_lufs, Some(-16.0)),
+            other => panic!("expected play, got {:?}", other),
+        }
+        assert_eq!(
+            parse_str("simulate --trace drive.csv").unwrap().command,
+            Command::Simulate(SimulateArgs { trace: Some("drive.csv".into()), seed: None })
+        );
+        assert_eq!(
+            parse_str("simulate --seed 7").unwrap().command,
+            Command::Simulate(SimulateArgs { trace: None, seed: Some(7) })
+        );
+        assert_eq!(
+            parse_str("analyze song.wav").unwrap().command,
+            Command::Analyze(AnalyzeArgs { wav: "song.wav".into() })
+        );
+        assert_eq!(
+            parse_str("telemetry /dev/ttyACM0").unwrap().command,
+            Command::Telemetry(TelemetryArgs { port: "/dev/ttyACM0".into() })
+        );
+        assert_eq!(parse_str("--profile car.json simulate").unwrap().global.profile.as_deref(), Some("car.json"));
+        assert!(parse_str("--night stream").unwrap().global.night);
+        assert_eq!(parse_str("simulate --preset loud").unwrap().global.preset, Some(Preset::Loud));
+        assert!(parse_str("play song.wav --night").unwrap().global.night, "a global switch after the subcommand");
+        assert!(!parse_str("play song.wav").unwrap().global.night);
+        assert_eq!(parse_str("stream --log-level debug").unwrap().global.log_level, Some(LevelFilter::Debug));
+        assert_eq!(parse_str("stream -h").unwrap().command, Command::Help);
+        assert_eq!(parse_str("--list-devices").unwrap().command, Command::ListDevices);
+        assert_eq!(parse_str("stream --list-devices").unwrap().command, Command::ListDevices);
+    }
+
+    #[test]
+    fn test_parse_stream_mic_options() {
+        let stream = |line: &str| match parse_str(line).unwrap().command {
+            Command::Stream(args) => args.mic,
+            other => panic!("expected stream, got {:?}", other),
+        };
+        let mic = stream(
+            "stream --mic-weighting A --mic-calibration-db 90 --mic-highpass-hz 0 --mic-agc on \
+             --mic-noise-band 80-400 --multiband --cabin-kalman 0.1,2 --spl-weighting slow \
+             --self-masking-coupling-db -20 --dropout-timeout-s 5 --no-clip-backoff",
+        );
+        assert_eq!(
+            mic,
+            MicOptions {
+                channels: None,
+                outlier_db: None,
+                a_weighting: true,
+                calibration_db: Some(90.0),
+                highpass_hz: None,
+                agc: Some((MicAgc::DEFAULT_TARGET_DBFS, MicAgc::DEFAULT_CALIBRATION_S)),
+                noise_band_hz: Some((80.0, 400.0)),
+                multiband: true,
+                kalman: Some((0.1, 2.0)),
+                spl_weighting: Some(Weighting::Slow),
+                vad: None,
+                self_masking_coupling_db: Some(-20.0),
+                dropout_timeout_s: 5.0,
+                clip_backoff: false,
+            }
+        );
+        let mic = stream("stream --mic-highpass-hz 40 --mic-agc=-24,600 --mic-noise-band road --cabin-kalman on");
+        assert_eq!(mic.highpass_hz, Some(40.0));
+        assert_eq!(mic.agc, Some((-24.0, 600.0)));
+        assert_eq!(mic.noise_band_hz, Some(SPECTRAL_BAND_HZ));
+        assert_eq!(mic.kalman, Some((DEFAULT_PROCESS_NOISE_DB2, DEFAULT_MEASUREMENT_NOISE_DB2)));
+        assert_eq!(stream("stream --vad").vad, Some(VadThresholds::default()));
+        let vad = stream("stream --vad --vad-onset-db 9 --vad-band-ratio 0.1 --vad-hangover-blocks 4").vad.unwrap();
+        assert_eq!((vad.onset_db, vad.min_band_ratio, vad.hangover_blocks), (9.0, 0.1, 4));
+        let line = "stream --nav turn.wav --nav-repeat-s 2.5 --duck-threshold-db -50 --duck-depth-db 6 \
+                    --duck-attack-ms 20 --duck-release-ms 300";
+        match parse_str(line).unwrap().command {
+            Command::Stream(args) => assert_eq!(
+                args.nav,
+                Some(NavOptions {
+                    path: "turn.wav".into(),
+                    repeat: Duration::from_millis(2500),
+                    duck_threshold_db: -50.0,
+                    duck_depth_db: 6.0,
+                    duck_attack_ms: 20.0,
+                    duck_release_ms: 300.0,
+                })
+            ),
+            other => panic!("expected stream, got {:?}", other),
+        }
+        let mic = stream("stream --mic-channels 0,2 --mic-outlier-db 10");
+        assert_eq!(mic.channels, Some(vec![0, 2]));
+        assert_eq!(mic.outlier_db, Some(10.0));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_str("").is_err(), "a command is required");
+        assert!(parse_str("fly").is_err());
+        assert!(parse_str("play --loop").is_err(), "--loop belongs to stream");
+        assert!(parse_str("simulate --target-db").is_err(), "missing value");
+        assert!(parse_str("simulate --target-db loud").is_err());
+        assert!(parse_str("process only_in.wav").is_err());
+        assert!(parse_str("simulate extra").is_err());
+        assert!(parse_str("simulate --seed -1").is_err());
+        assert!(parse_str("telemetry").is_err(), "port is required");
+        assert!(parse_str("analyze").is_err(), "file is required");
+        assert!(parse_str("stream --speed-unit knots").is_err());
+        assert!(parse_str("process a.wav b.wav --output-device 0").is_err(), "process has no audio device");
+        assert!(parse_str("simulate --eq door.json").is_err(), "EQ is for the playback paths");
+        assert!(parse_str("stream --controller fuzzy").is_err());
+        assert!(parse_str("process a.wav b.wav --target-lufs loud").is_err());
+        assert!(parse_str("stream --target-lufs -16").is_err(), "normalization needs the whole file up front");
+        assert!(parse_str("stream --night=1").is_err(), "switches take no value");
+        assert!(parse_str("--preset max simulate").is_err());
+        assert!(parse_str("--log-level chatty simulate").is_err());
+        assert!(parse_str("stream --metrics-port 70000").is_err());
+        for bad in [
+            "--mic-channels 0,x",
+            "--mic-channels 0,",
+            "--mic-channels -1",
+            "--mic-channels 1,1",
+            "--mic-outlier-db loud",
+            "--mic-outlier-db 0",
+            "--mic-weighting c",
+            "--mic-calibration-db inf",
+            "--mic-highpass-hz abc",
+            "--mic-highpass-hz -20",
+            "--mic-agc 1",
+            "--mic-agc -30,0",
+            "--mic-noise-band 500-100",
+            "--mic-noise-band low-high",
+            "--cabin-kalman x",
+            "--cabin-kalman 0,4",
+            "--spl-weighting loud",
+            "--self-masking-coupling-db NaN",
+            "--dropout-timeout-s -1",
+            "--vad-onset-db 9",
+            "--duck-depth-db 6",
+            "--nav a.wav --nav-repeat-s inf",
+            "--nav a.wav --nav-repeat-s 1e30",
+            "--nav a.wav --nav-repeat-s -1",
+            "--nav a.wav --duck-threshold-db NaN",
+            "--nav a.wav --duck-attack-ms -5",
+            "--vad --vad-onset-db high",
+            "--vad --vad-band-ratio 2",
+            "--vad --vad-hangover-blocks 1.5",
+        ] {
+            assert!(parse_str(&format!("stream {}", bad)).is_err(), "{}", bad);
+        }
+        assert!(parse_str("play --multiband").is_err(), "mic options belong to stream");
+        assert!(parse_str("stream --loudness-comp=1").is_err(), "switches take no value");
+    }
+}
@@ -0,0 +1,103 @@
+//! The binary's `log` backend: one line per record on stderr with the time since start, the level
+//! and the name of the thread it came from, so the controller, monitor and speed threads can be told
+//! apart. The level is `--log-level`, else `RUST_LOG`, else `info`; `RUST_LOG` also takes
+//! `module=level` entries, e.g. `RUST_LOG=info,adaptive_vol::speed_source=debug`.
+
+use std::io::Write;
+use std::time::Instant;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+struct Logger {
+    default: LevelFilter,
+    /// Per-module levels; the longest matching module path wins
+    modules: Vec<(String, LevelFilter)>,
+    started: Instant,
+}
+
+impl Logger {
+    /// Parse a `RUST_LOG`-style spec; entries that don't parse are ignored
+    fn from_spec(spec: &str) -> Self {
+        let mut logger = Self { default: LevelFilter::Info, modules: Vec::new(), started: Instant::now() };
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            match entry.split_once('=') {
+                Some((module, level)) => {
+                    if let Ok(level) = level.trim().parse() {
+                        logger.modules.push((module.trim().to_string(), level));
+                    }
+                }
+                None => {
+                    if let Ok(level) = entry.parse() {
+                        logger.default = level;
+                    }
+                }
+            }
+        }
+        logger
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(module, _)| {
+                target == module || target.strip_prefix(module.as_str()).is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.default, |&(_, level)| level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules.iter().map(|&(_, level)| level).fold(self.default, Ord::max)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let thread = std::thread::current();
+        let _ = writeln!(
+            std::io::stderr().lock(),
+            "{:>9.3} {:<5} [{}] {}",
+            self.started.elapsed().as_secs_f32(),
+            record.level(),
+            thread.name().unwrap_or("?"),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// Install the logger: `level` (from `--log-level`) overrides the default level of `RUST_LOG`
+pub fn init(level: Option<LevelFilter>) {
+    let mut logger = Logger::from_spec(&std::env::var("RUST_LOG").unwrap_or_default());
+    if let Some(level) = level {
+        logger.default = level;
+    }
+    log::set_max_level(logger.max_level());
+    // only fails if a logger is already installed, which leaves that one in place
+    let _ = log::set_logger(Box::leak(Box::new(logger)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_sets_default_and_module_levels() {
+        let logger = Logger::from_spec("warn, adaptive_vol::speed_source=debug,adaptive_vol=error,bogus=loud");
+        assert_eq!(logger.level_for("adaptive_vol::speed_source"), LevelFilter::Debug);
+        assert_eq!(logger.level_for("adaptive_vol::obd"), LevelFilter::Error, "longest matching module wins");
+        assert_eq!(logger.level_for("adaptive_vol_extra"), LevelFilter::Warn, "whole path segments only");
+        assert_eq!(logger.max_level(), LevelFilter::Debug);
+        assert_eq!(Logger::from_spec("").level_for("adaptive_vol"), LevelFilter::Info);
+    }
+}
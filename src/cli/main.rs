@@ -0,0 +1,144 @@
+//! `adaptive_vol`: one binary for every host-side mode.
+//!
+//!   adaptive_vol simulate                      mocked sensors, no audio device
+//!   adaptive_vol play song.wav --auto          rodio playback
+//!   adaptive_vol stream song.wav <speed-url>   live cpal output with mic + speed source
+//!   adaptive_vol process in.wav out.wav        offline WAV processing
+//!   adaptive_vol analyze song.wav              integrated loudness and peak of a WAV
+//!   adaptive_vol telemetry /dev/ttyACM0        live gain frames from the embedded board
+//!   adaptive_vol --list-devices                audio devices for --input-device/--output-device
+
+mod analyze;
+mod args;
+mod logger;
+mod play;
+#[cfg(test)]
+mod play_test;
+mod process;
+mod simulate;
+mod stream;
+mod telemetry;
+
+use anyhow::Result;
+
+use adaptive_vol::adaptive_gain::{mock_get_cabin_noise_db, mock_get_speed_kmh, NoiseCombine};
+use adaptive_vol::controller::{ControllerKind, GainController, PidGains};
+use adaptive_vol::synthetic::SyntheticNoise;
+use adaptive_vol::trace::TraceSource;
+use adaptive_vol::{Config, NoiseModel, VehicleProfile};
+use args::{Command, GlobalArgs, USAGE};
+
+/// Tuning shared by every subcommand: config.toml with the top-level flag overrides applied,
+/// plus the optional vehicle profile
+pub struct Settings {
+    pub config: Config,
+    pub profile: Option<VehicleProfile>,
+    /// `--night`: the config already has the night target and ceiling; playback adds the compressor
+    pub night: bool,
+}
+
+impl Settings {
+    fn load(global: &GlobalArgs) -> Result<Self> {
+        let mut config = Config::load_or_default(global.config.as_deref())?;
+        // the preset replaces the file's target and offset; explicit flags still win over it
+        if let Some(preset) = global.preset {
+            config.target_db = preset.target_db();
+            config.user_offset_db = preset.user_offset_db();
+        }
+        if let Some(target_db) = global.target_db {
+            config.target_db = target_db;
+        }
+        if let Some(offset_db) = global.offset_db {
+            config.user_offset_db = offset_db;
+        }
+        if global.night {
+            config = config.night();
+        }
+        let profile = global.profile.as_ref().map(VehicleProfile::load).transpose()?;
+        Ok(Self { config, profile, night: global.night })
+    }
+
+    /// Speed -> noise curve: the vehicle profile's table, else the built-in log model
+    pub fn noise_model(&self) -> NoiseModel {
+        self.profile.as_ref().map(|p| p.noise_model()).unwrap_or_default()
+    }
+
+    /// The `--controller` choice, configured from config.toml, the profile and NOISE_COMBINE
+    pub fn gain_controller(&self, kind: ControllerKind) -> Box<dyn GainController + Send> {
+        let noise_combine = NoiseCombine::from_env();
+        match kind {
+            ControllerKind::Direct => {
+                let mut ag = self.config.adaptive_gain();
+                ag.set_noise_combine(noise_combine);
+                ag.set_noise_model(self.noise_model());
+                Box::new(ag)
+            }
+            ControllerKind::Pid => {
+                let mut pid = self.config.pid_controller(PidGains::default());
+                pid.set_noise_combine(noise_combine);
+                pid.set_noise_model(self.noise_model());
+                Box::new(pid)
+            }
+        }
+    }
+}
+
+/// Speed/noise inputs for the modes without real sensors: a replayed `--trace`, the sine mocks, or
+/// (`--seed`) seeded random cabin noise with the mock speed
+pub enum Sensors {
+    Mock,
+    Trace(TraceSource),
+    Synthetic(Box<SyntheticNoise>),
+}
+
+impl Sensors {
+    /// A trace takes precedence over a seed
+    pub fn load(trace: Option<&str>, seed: Option<u64>) -> Result<Self> {
+        Ok(match (trace, seed) {
+            (Some(path), _) => Sensors::Trace(TraceSource::load(path)?),
+            (None, Some(seed)) => Sensors::Synthetic(Box::new(SyntheticNoise::new(seed))),
+            (None, None) => Sensors::Mock,
+        })
+    }
+
+    /// `(cabin_db, speed_kmh)` at simulated time `t` (s); the synthetic noise only moves forward
+    pub fn at(&mut self, t: f32) -> (f32, f32) {
+        match self {
+            Sensors::Mock => (mock_get_cabin_noise_db(t), mock_get_speed_kmh(t)),
+            Sensors::Trace(trace) => trace.sample(t),
+            Sensors::Synthetic(noise) => (noise.level_at(t), mock_get_speed_kmh(t)),
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = match args::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("error: {:#}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    logger::init(cli.global.log_level);
+    match cli.command {
+        Command::Help => {
+            print!("{}", USAGE);
+            return Ok(());
+        }
+        Command::ListDevices => {
+            adaptive_vol::device::print_devices(&cpal::default_host())?;
+            return Ok(());
+        }
+        _ => {}
+    }
+    let settings = Settings::load(&cli.global)?;
+    match &cli.command {
+        Command::Simulate(args) => simulate::run(&settings, args),
+        Command::Play(args) => play::run(&settings, args),
+        Command::Stream(args) => stream::run(&settings, args),
+        Command::Process(args) => process::run(&settings, args),
+        Command::Analyze(args) => analyze::run(args),
+        Command::Telemetry(args) => telemetry::run(args),
+        Command::ListDevices | Command::Help => unreachable!("handled above"),
+    }
+}
@@ -0,0 +1,229 @@
+// `play`: rodio playback with per-chunk adaptive gain
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use log::{debug, info, warn};
+use rodio::{buffer::SamplesBuffer, Decoder, OutputStreamBuilder, Sink, Source};
+
+use adaptive_vol::adaptive_gain::{
+    db_to_lin, process_chunk_in_place, Limiter,
+    NoiseCombine,
+};
+use adaptive_vol::device::{find_device, DeviceKind};
+use adaptive_vol::dynamics::Compressor;
+use adaptive_vol::eq::{EqPreset, Equalizer};
+use adaptive_vol::util::FrameClock;
+
+use crate::analyze::loudness_baseline;
+use crate::args::PlayArgs;
+use crate::{Sensors, Settings};
+
+// Length of the gain crossfade at the start of each chunk
+const CHUNK_CROSSFADE_MS: f32 = 5.0;
+
+/// Apply gain to one interleaved chunk, ramping linearly from `prev_gain` to `gain` over the first
+/// `ramp_frames` frames so gain steps between appended chunks don't click. Output goes through `limiter`.
+pub(crate) fn apply_chunk_gain(
+    chunk: &mut [f32],
+    channels: usize,
+    prev_gain: f32,
+    gain: f32,
+    ramp_frames: usize,
+    limiter: &mut Limiter,
+) {
+    let channels = channels.max(1);
+    let ramp_len = (ramp_frames * channels).min(chunk.len());
+    let (ramp, rest) = chunk.split_at_mut(ramp_len);
+    for (i, frame) in ramp.chunks_mut(channels).enumerate() {
+        let g = prev_gain + (gain - prev_gain) * (i + 1) as f32 / ramp_frames as f32;
+        for s in frame.iter_mut() {
+            *s = limiter.process(*s * g).clamp(-1.0_f32, 1.0_f32);
+        }
+    }
+    process_chunk_in_place(rest, gain, limiter);
+}
+
+// Blocking HTTP fetch (returns None on any error)
+pub(crate) fn fetch_remote_state(url: &str) -> Option<(f32, f32)> {
+    // note: reqwest + serde_json are required in Cargo.toml
+    let resp = reqwest::blocking::get(url).ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+    let json: serde_json::Value = resp.json().ok()?;
+    let cabin_db = json.get("cabin_db")?.as_f64()? as f32;
+    let speed_kmh = json.get("speed_kmh")?.as_f64()? as f32;
+    Some((cabin_db, speed_kmh))
+}
+
+pub fn run(settings: &Settings, args: &PlayArgs) -> Result<()> {
+    // ---------- config ----------
+    let input_path = args.wav.as_str();
+    // --trace <csv> replays a recorded drive in place of the mocks (and implies --auto)
+    let mut sensors = Sensors::load(args.trace.as_deref(), None)?;
+    let auto_mode = args.auto || args.trace.is_some();
+    // speed/noise curve from --profile (default: built-in log model)
+    let noise_model = settings.noise_model();
+    // tuning constants from --config / config.toml and the top-level overrides
+    let config = &settings.config;
+    let (min_gain_db, max_gain_db) = config.gain_bounds_db((-24.0, 24.0));
+    let noise_combine = NoiseCombine::from_env();
+    // --eq <json>: parametric EQ on the output
+    let eq_preset = args.eq.as_ref().map(EqPreset::load).transpose()?;
+
+    if !std::path::Path::new(input_path).exists() {
+        bail!("Input file '{}' not found. Pass the path of an existing WAV file.", input_path);
+    }
+
+    // --target-lufs: first pass over the whole file for the normalization gain under the adaptive gain
+    let baseline_lin =
+        args.target_lufs.map(|target| loudness_baseline(input_path, target)).transpose()?.unwrap_or(1.0);
+
+    // Remote UI endpoint (used in manual mode to fetch cabin_db/speed each chunk)
+    let remote_url =
+        std::env::var("SPEED_UI_URL").unwrap_or_else(|_| "http://127.0.0.1:5005/state".into());
+
+    // ---------- audio init ----------
+    // --output-device: a specific device by index or name, else the default
+    let stream_handle = match &args.output_device {
+        Some(selector) => {
+            let device = find_device(&cpal::default_host(), DeviceKind::Output, selector)?;
+            OutputStreamBuilder::from_device(device)?.open_stream_or_fallback()?
+        }
+        None => OutputStreamBuilder::open_default_stream()?,
+    };
+    let sink = Sink::connect_new(stream_handle.mixer());
+    let sink = std::sync::Arc::new(sink);
+
+    // ---------- open decoder (streamed) ----------
+    // Samples are pulled from the decoder one chunk at a time so the whole file is never held in memory.
+    // Decoder yields f32 samples in [-1.0,1.0] when converted.
+    let file = BufReader::new(File::open(input_path)?);
+    let mut source = Decoder::new(file)?;
+    let sample_rate = source.sample_rate();
+    let channels = source.channels();
+
+    // chunk_frames = ~0.1s
+    let chunk_frames = (sample_rate as usize / 10).max(1);
+    let chunk_size = chunk_frames * channels as usize; // interleaved samples per chunk
+
+    // Smoother for gain in dB: attack/release from config.toml (default 0.1 s / 1.0 s, as used previously)
+    let mut smoother = config.smoother();
+
+    // Time tracking for mocks (auto mode)
+    let mut t = 0.0_f32;
+    let dt = chunk_frames as f32 / sample_rate as f32;
+
+    // gain applied at the end of the previous chunk (smoother starts at 0 dB)
+    let mut prev_gain_lin = db_to_lin(smoother.value_db) * baseline_lin;
+    let crossfade_frames = ((sample_rate as f32 * CHUNK_CROSSFADE_MS / 1000.0) as usize).max(1);
+    let mut limiter = Limiter::for_sample_rate(sample_rate as f32 * channels as f32); // linked across interleaved channels
+    // --night: linked across the interleaved channels like the limiter
+    let mut compressor = settings.night.then(|| Compressor::night(sample_rate as f32 * channels as f32));
+    let mut equalizers = match &eq_preset {
+        Some(preset) => (0..channels).map(|_| Equalizer::new(preset, sample_rate as f32)).collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+
+    info!(
+        "Starting playback: '{}' ({} Hz, {} channels) — mode: {}",
+        input_path,
+        sample_rate,
+        channels,
+        match (&sensors, auto_mode) {
+            (Sensors::Trace(_), _) => "AUTO (trace replay)",
+            (Sensors::Mock, true) => "AUTO (mocked)",
+            (Sensors::Mock, false) => "MANUAL (remote UI poll)",
+            (Sensors::Synthetic(_), _) => "AUTO (seeded noise)",
+        }
+    );
+
+    smoother.reset_clock(); // don't count setup time as smoothing time
+    let mut clock = FrameClock::new(Duration::from_secs_f32(dt));
+    // main chunk loop — read a chunk, compute gain, apply, append, and sleep to pace playback.
+    // Runs until the decoder is exhausted, so it works for decoders that don't report a total length.
+    loop {
+        let mut chunk: Vec<f32> = source.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            break;
+        }
+
+        // fetch inputs: either from mocks (auto) or remote UI (manual)
+        let (cabin_db, speed_kmh) = if auto_mode {
+            sensors.at(t)
+        } else {
+            match fetch_remote_state(&remote_url) {
+                Some((c, s)) => (c, s),
+                None => {
+                    warn!(
+                        "failed to fetch remote state from {}, using last-known mock values",
+                        remote_url
+                    );
+                    sensors.at(t)
+                }
+            }
+        };
+
+        // convert speed to noise model and combine with cabin_db (max or power sum, see NOISE_COMBINE)
+        let speed_noise_db = noise_model.noise_db(speed_kmh);
+        let noise_db: f32 = noise_combine.combine(cabin_db, speed_noise_db);
+
+    // compute raw gain in dB and clamp it
+    // Previous behaviour tried to maintain a target playback level: gain = L_DESIRED - noise.
+    // To make volume increase with speed/noise we compute a baseline gain at a
+    // reference (quiet cabin) and then add a scaled boost proportional to
+    // how much the measured noise is above that baseline.
+    let baseline_noise_db = config.base_noise_db;
+    let sensitivity = config.gain_sensitivity; // how many dB playback gain per 1 dB noise increase
+    let base_gain_db = config.target_db - baseline_noise_db;
+    let mut gain_db_raw = base_gain_db + sensitivity * (noise_db - baseline_noise_db) + config.user_offset_db;
+    // keep gain within reasonable bounds to avoid extreme boosting
+    gain_db_raw = gain_db_raw.max(min_gain_db).min(max_gain_db);
+
+        // smooth and convert to linear
+        let gain_db = smoother.step(gain_db_raw);
+        let gain_lin = db_to_lin(gain_db) * baseline_lin;
+
+        // EQ first: the gain is a plain scale factor, so this equals EQ after the gain while
+        // keeping the limiter last
+        if !equalizers.is_empty() {
+            for frame in chunk.chunks_mut(channels as usize) {
+                for (s, eq) in frame.iter_mut().zip(equalizers.iter_mut()) {
+                    *s = eq.process(*s);
+                }
+            }
+        }
+
+        // apply gain (crossfaded from the previous chunk's gain) and limit to [-1.0,1.0]
+        apply_chunk_gain(&mut chunk, channels as usize, prev_gain_lin, gain_lin, crossfade_frames, &mut limiter);
+        prev_gain_lin = gain_lin;
+        // the compressor only ever turns the level down, so after the limiter the output stays in range
+        if let Some(comp) = compressor.as_mut() {
+            for s in chunk.iter_mut() {
+                *s = comp.process(*s);
+            }
+        }
+
+        // create samples buffer (interleaved samples) and append
+        let src = SamplesBuffer::new(channels, sample_rate, chunk);
+        sink.append(src);
+
+        // Log live status (kept short)
+        debug!(
+            "[{:>6.2}s] speed={:>5.1} km/h, cabin={:>5.1} dB, gain_db={:>+5.2} dB, gain_lin={:.3}",
+            t, speed_kmh, noise_db, gain_db, gain_lin
+        );
+
+        // advance time for mocks & pace appending to avoid queue blowout (drift-free, so the
+        // appended audio and the sink stay level over a long file)
+        clock.tick();
+        t = clock.frames() as f32 * dt;
+    }
+
+    // Wait until playback ends
+    sink.sleep_until_end();
+    info!("✅ Playback finished.");
+    Ok(())
+}
@@ -1,6 +1,10 @@
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use crate::play::{apply_chunk_gain, fetch_remote_state};
+    use adaptive_vol::adaptive_gain::{
+        apply_gain_and_limit, db_to_lin, mock_get_cabin_noise_db, mock_get_speed_kmh, soft_limit, speed_to_noise,
+        Limiter, Smoother,
+    };
     use std::f32::consts::PI;
 
     #[test]
@@ -19,11 +23,11 @@ mod tests {
             let noise = mock_get_cabin_noise_db(t);
             
             // Speed should be within reasonable bounds (0-120 km/h as per mock function)
-            assert!(speed >= 20.0 && speed <= 100.0, 
+            assert!((20.0..=100.0).contains(&speed), 
                 "Speed {} at time {} should be within reasonable bounds", speed, t);
             
             // Cabin noise should be within reasonable bounds (base is 60dB with modulation)
-            assert!(noise >= 47.0 && noise <= 73.0,
+            assert!((47.0..=73.0).contains(&noise),
                 "Noise {} at time {} should be within reasonable bounds", noise, t);
         }
     }
@@ -35,8 +39,8 @@ mod tests {
         for speed in test_speeds {
             let noise = speed_to_noise(speed);
             
-            // Noise should increase with speed
-            assert!(noise > 40.0, "Noise level should be above 40dB");
+            // Noise should increase with speed, from the 40 dB base at standstill
+            assert!(noise >= 40.0, "Noise level should be at least 40dB");
             assert!(noise < 90.0, "Noise level should be below 90dB");
             
             if speed > 0.0 {
@@ -135,8 +139,10 @@ mod tests {
             
             // Check that output is properly scaled and limited
             for &sample in &output {
-                assert!(sample <= i16::MAX && sample >= i16::MIN,
-                    "Output should be within i16 bounds: {}", sample);
+                // limiting only pulls samples down: never beyond the scaled input's peak or full scale
+                let bound = (i16::MAX as f32 * gain).min(i16::MAX as f32) + 1.0;
+                assert!((sample as f32).abs() <= bound,
+                    "Output {} should be within {} (gain {})", sample, bound, gain);
                 
                 if gain <= 1.0 {
                     // For gains <= 1.0, output should be strictly scaled
@@ -147,4 +153,26 @@ mod tests {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_chunk_crossfade_is_monotonic() {
+        // constant-level stereo chunk; the gain steps from 1.0 to 2.0 at the chunk boundary
+        let channels = 2;
+        let ramp_frames = 8;
+        let mut chunk = vec![0.25f32; 32 * channels];
+        let mut limiter = Limiter::default();
+        apply_chunk_gain(&mut chunk, channels, 1.0, 2.0, ramp_frames, &mut limiter);
+
+        let left: Vec<f32> = chunk.iter().step_by(channels).copied().collect();
+        assert!((left[0] - 0.25).abs() < 0.25 / ramp_frames as f32 + 1e-6,
+            "first sample should start near the previous gain: {}", left[0]);
+        for pair in left.windows(2) {
+            assert!(pair[1] >= pair[0], "crossfade must not jump back: {:?}", pair);
+            assert!(pair[1] - pair[0] <= 0.25 / ramp_frames as f32 + 1e-6,
+                "crossfade step too large: {:?}", pair);
+        }
+        assert!(left[ramp_frames..].iter().all(|&s| (s - 0.5).abs() < 1e-6),
+            "after the ramp the new gain applies");
+        assert_eq!(chunk[0], chunk[1], "both channels get the same gain");
+    }
+}
@@ -0,0 +1,78 @@
+// `process`: write a gain-adjusted copy of a WAV file
+use anyhow::Result;
+use log::info;
+
+use adaptive_vol::adaptive_gain::{mock_get_cabin_noise_db, mock_get_speed_kmh};
+use adaptive_vol::offline::process_wav_with_baseline;
+use adaptive_vol::trace::TraceSource;
+
+use crate::analyze::loudness_baseline;
+use crate::args::ProcessArgs;
+use crate::Settings;
+
+pub fn run(settings: &Settings, args: &ProcessArgs) -> Result<()> {
+    if args.auto || args.trace.is_some() {
+        return run_adaptive(settings, args);
+    }
+    // Input and output files
+    let input_path = args.input.as_str();
+    let output_path = args.output.as_str();
+    // 1.5 increases volume by 50%, < 1.0 attenuates; --target-lufs measures the file for it instead
+    let gain: f32 = match args.target_lufs {
+        Some(target) => loudness_baseline(input_path, target)?,
+        None => args.gain,
+    };
+
+    // Open the input WAV file
+    let mut reader = hound::WavReader::open(input_path)?;
+    let spec = reader.spec(); // Save WAV format (sample rate, bits, channels, etc.)
+
+    // Create the output WAV file
+    let mut writer = hound::WavWriter::create(output_path, spec)?;
+
+    // Process each sample
+    match spec.sample_format {
+        hound::SampleFormat::Int => {
+            let max_val = (1i64 << (spec.bits_per_sample - 1)) - 1;
+            for sample in reader.samples::<i32>() {
+                let s = sample? as f64;
+                let amplified = (s * (gain as f64)).clamp(-max_val as f64, max_val as f64);
+                writer.write_sample(amplified as i32)?;
+            }
+        }
+        hound::SampleFormat::Float => {
+            for sample in reader.samples::<f32>() {
+                let s: f32 = sample?;
+                let amplified: f32 = (s * gain).clamp(-1.0_f32, 1.0_f32);
+                writer.write_sample(amplified)?;
+            }
+        }
+    }
+
+    writer.finalize()?;
+    info!("✅ Gain applied successfully! Output written to '{}'", output_path);
+    Ok(())
+}
+
+/// Adaptive gain over the whole file, following `--trace` or else the mocked speed/noise sampled
+/// every 100 ms
+fn run_adaptive(settings: &Settings, args: &ProcessArgs) -> Result<()> {
+    let trace = match &args.trace {
+        Some(path) => TraceSource::load(path)?,
+        None => {
+            let reader = hound::WavReader::open(&args.input)?;
+            let duration_s = reader.duration() as f32 / reader.spec().sample_rate as f32;
+            let rows = (0..=(duration_s * 10.0).ceil() as usize)
+                .map(|i| i as f32 * 0.1)
+                .map(|t| (t, mock_get_cabin_noise_db(t), mock_get_speed_kmh(t)))
+                .collect();
+            TraceSource::from_rows(rows)?
+        }
+    };
+
+    let baseline = args.target_lufs.map(|target| loudness_baseline(&args.input, target)).transpose()?;
+    let mut gain = settings.gain_controller(args.controller);
+    process_wav_with_baseline(&args.input, &args.output, &trace, gain.as_mut(), baseline.unwrap_or(1.0))?;
+    info!("✅ Adaptive gain applied! Output written to '{}'", args.output);
+    Ok(())
+}
@@ -0,0 +1,73 @@
+// `simulate`: host simulation with mocked sensors and a sine in place of the audio device
+use std::time::Duration;
+
+use anyhow::Result;
+
+use adaptive_vol::adaptive_gain::{
+    apply_gain_and_limit_in_place, db_to_lin, NoiseCombine, CHUNK_SAMPLES, SAMPLE_RATE,
+};
+
+use adaptive_vol::util::FrameClock;
+
+use crate::args::SimulateArgs;
+use crate::{Sensors, Settings};
+
+pub fn run(settings: &Settings, args: &SimulateArgs) -> Result<()> {
+    // --trace <csv>: replay a recorded drive instead of the sine mocks; --seed <n>: seeded random cabin noise
+    let mut sensors = Sensors::load(args.trace.as_deref(), args.seed)?;
+    let config = &settings.config;
+    let noise_model = settings.noise_model();
+    let (min_gain_db, max_gain_db) = config.gain_bounds_db((-24.0, 24.0));
+    let mut smoother = config.smoother(); // time constants from config.toml (default attack 0.1 s, release 1 s)
+    let noise_combine = NoiseCombine::from_env();
+    let mut t = 0.0f32;
+    let dt = CHUNK_SAMPLES as f32 / SAMPLE_RATE as f32;
+    // a trace runs for its own length; the mocks for 1000 chunks
+    let iterations = match &sensors {
+        Sensors::Trace(trace) => (trace.end_time() / dt).ceil() as usize + 1,
+        Sensors::Mock | Sensors::Synthetic(_) => 1000,
+    };
+    smoother.reset_clock();
+    // paced against absolute deadlines so simulated and wall-clock time stay in step
+    let mut clock = FrameClock::new(Duration::from_secs_f32(dt));
+    let mut chunk = vec![0i16; CHUNK_SAMPLES];
+    for _iter in 0..iterations {
+        // 1) read simulated (or replayed) sensors
+        let (cabin_db, speed) = sensors.at(t);
+        let speed_noise = noise_model.noise_db(speed);
+        let noise_db = noise_combine.combine(cabin_db, speed_noise);
+
+        // 2) compute raw gain dB
+        let gain_db_raw = config.target_db - noise_db + config.user_offset_db;
+
+        // clamp gain_db within reasonable bounds
+        let gain_db_raw = gain_db_raw.max(min_gain_db).min(max_gain_db);
+
+        // 3) smooth
+        let gain_db = smoother.step(gain_db_raw);
+
+        // 4) convert to linear
+        let gain_lin = db_to_lin(gain_db);
+
+        // 5) simulate input audio chunk (sine), reusing the buffer from the last iteration
+        for (n, out) in chunk.iter_mut().enumerate() {
+            let sample = 0.4 * (2.0 * std::f32::consts::PI * 1000.0 * (t + n as f32 / SAMPLE_RATE as f32)).sin() ;
+            *out = (sample * i16::MAX as f32) as i16;
+        }
+
+        // 6) apply
+        apply_gain_and_limit_in_place(&mut chunk, gain_lin);
+
+        // here you'd send chunk to audio device / DMA
+
+        // logging (print every 50 iter)
+        if _iter % 50 == 0 {
+            println!("t={:.2}s speed={:.1} km/h cabin_db={:.1} noise_db={:.1} gain_db={:.2} gain_lin={:.3}",
+                t, speed, cabin_db, noise_db, gain_db, gain_lin);
+        }
+
+        clock.tick(); // real time: wait for this chunk's deadline
+        t = clock.frames() as f32 * dt;
+    }
+    Ok(())
+}
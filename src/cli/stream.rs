@@ -0,0 +1,2021 @@
+// `stream`: live cpal playback with mic noise measurement and a speed source
+use anyhow::{bail, Context, Result};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat};
+use hound::WavReader;
+use log::{debug, error, info, warn};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use adaptive_vol::adaptive_gain::{db_to_lin, downmix_to_mono, mock_get_cabin_noise_db};
+use adaptive_vol::control::{ControlServer, ControlState};
+use adaptive_vol::controller::GainController;
+use adaptive_vol::core_dsp::{clip_aware_rms, is_measurement, CLIP_LEVEL};
+use adaptive_vol::device::{input_device, output_device};
+use adaptive_vol::dropout::{is_mic_dropout, DropoutHold, HoldState};
+use adaptive_vol::dynamics::{ClipBackoff, Compressor, Ducker, LookaheadLimiter, MicAgc};
+use adaptive_vol::eq::{EqPreset, Equalizer};
+use adaptive_vol::fade::{FadeEnvelope, FadeShape};
+use adaptive_vol::filters::{AWeighting, Biquad, LoudnessCompensation, SpeedTilt};
+use adaptive_vol::kalman::KalmanLevel;
+use adaptive_vol::meter::{level_bar, Meter, PeakHold};
+use adaptive_vol::mic_array::MicArray;
+use adaptive_vol::metrics::Metrics;
+use adaptive_vol::mixer::Mixer;
+use adaptive_vol::multiband::{MultibandGain, DEFAULT_CROSSOVERS_HZ};
+use adaptive_vol::spectral::SpectralNoiseEstimator;
+use adaptive_vol::nmea::NmeaSource;
+use adaptive_vol::obd::Obd2Source;
+use adaptive_vol::resample::LinearResampler;
+use adaptive_vol::self_masking::SelfMaskingCompensator;
+use adaptive_vol::spl::SplMeter;
+use adaptive_vol::speed::{SpeedSmoother, SpeedUnit, SpeedValidator};
+use adaptive_vol::speed_source::{HttpPoller, PollBackoff, SharedSpeed, SpeedPublisher, SpeedSource, WebSocketSource};
+use adaptive_vol::spsc::{spsc_ring, Consumer, Producer, RingMonitor};
+use adaptive_vol::trace::{RecordRow, TraceRecorder};
+use adaptive_vol::util::{install_ctrlc_handler, spawn_named, AtomicF32};
+use adaptive_vol::vad::Vad;
+use adaptive_vol::VehicleProfile;
+
+use crate::args::{MicOptions, NavOptions, StreamArgs};
+use crate::Settings;
+
+/// Longest wait between retries while the speed server keeps failing
+const SPEED_POLL_MAX_BACKOFF: Duration = Duration::from_secs(2);
+/// Consecutive failed polls before the speed is considered stale
+const SPEED_STALE_AFTER_POLLS: u32 = 5;
+/// Gain used once a mic dropout or stale speed outlasts the dropout timeout (dB)
+const SAFE_GAIN_DB: f32 = 0.0;
+
+/// First wait before rebuilding a failed output stream; doubles per failed attempt
+const RECONNECT_BASE_DELAY: Duration = Duration::from_millis(500);
+/// Longest wait between output reconnect attempts
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(8);
+/// Failed reconnect attempts in a row before giving up
+const RECONNECT_MAX_ATTEMPTS: u32 = 10;
+
+/// Extra wait after the shutdown fade-out for the device to play the last faded buffers
+const SHUTDOWN_FADE_MARGIN: Duration = Duration::from_millis(50);
+
+/// Mixer source names of the WAV and the --nav prompt
+const MUSIC_SOURCE: &str = "music";
+const NAV_SOURCE: &str = "nav";
+
+/// Default playback prefill before the output stream starts (ms)
+const DEFAULT_PREFILL_MS: f32 = 200.0;
+/// Default crossfade between the end and the start of the file with --loop (ms)
+const DEFAULT_LOOP_CROSSFADE_MS: f32 = 10.0;
+
+/// Per-band mic level (dB SPL) above which --multiband starts boosting that band
+const MULTIBAND_REFERENCE_DB: f32 = 60.0;
+
+/// Monitor level meter: bar scale (dBFS at the empty end), width, and peak-hold fall rate
+const METER_FLOOR_DB: f32 = -60.0;
+const METER_WIDTH: usize = 40;
+const METER_PEAK_DECAY_DB_PER_S: f32 = 6.0;
+
+/// Default mic calibration offset (dB) added to the RMS level in dBFS
+const DEFAULT_MIC_CALIBRATION_DB: f32 = 94.0;
+
+/// Settings owned by the controller thread
+struct ControllerConfig {
+    /// The mic and controller flags
+    mic: MicOptions,
+    /// Offset mapping mic dBFS to dB SPL: --mic-calibration-db, else the profile's, else
+    /// `DEFAULT_MIC_CALIBRATION_DB`. Calibrate by playing a 94 dB SPL reference tone and adjusting
+    /// until the controller reports cabin_db=94.0.
+    mic_calibration_db: f32,
+    /// Subtract our own playback from the mic level (see `SelfMaskingCompensator`)
+    self_masking: Option<SelfMaskingCompensator>,
+}
+
+impl ControllerConfig {
+    fn new(mic: &MicOptions, profile: Option<&VehicleProfile>) -> Self {
+        let mic_calibration_db = mic
+            .calibration_db
+            .or(profile.and_then(|p| p.calibration_db))
+            .unwrap_or(DEFAULT_MIC_CALIBRATION_DB);
+        let self_masking = mic.self_masking_coupling_db.map(SelfMaskingCompensator::new);
+        Self { mic: mic.clone(), mic_calibration_db, self_masking }
+    }
+}
+
+/// Output-path settings: the flags, plus the ones still read from the environment
+struct OutputOptions {
+    /// --eq <json>: parametric EQ after the gain
+    eq: Option<EqPreset>,
+    /// --loudness-comp: bass/treble shelves that follow the playback level (see `LoudnessCompensation`)
+    loudness_comp: bool,
+    /// LOOP_CROSSFADE_MS: crossfade between the end and the start of the file with --loop (0 disables)
+    loop_crossfade_ms: f32,
+    /// PREFILL_MS: audio queued before the output stream starts, so the first callbacks don't
+    /// underrun (0 disables)
+    prefill_ms: f32,
+    /// SPEED_TILT=1: low-shelf boost rising with speed, as (SPEED_TILT_SLOPE dB per km/h,
+    /// SPEED_TILT_MAX_DB)
+    speed_tilt: Option<(f32, f32)>,
+    /// FADE_IN_MS / FADE_OUT_MS: output fade when playback starts and on Ctrl-C (0 disables)
+    fade_in_ms: f32,
+    fade_out_ms: f32,
+    /// FADE_SHAPE: raised-cosine unless `linear`
+    fade_shape: FadeShape,
+    /// MUTE_FADE_MS: fade for --control's mute and unmute
+    mute_fade_ms: f32,
+}
+
+impl OutputOptions {
+    fn from_args(args: &StreamArgs) -> Result<Self> {
+        let mut options = Self::from_vars(|name| std::env::var(name).ok())?;
+        options.eq = args.eq.as_ref().map(EqPreset::load).transpose()?;
+        options.loudness_comp = args.loudness_comp;
+        Ok(options)
+    }
+
+    /// The environment settings, looked up with `var`; a value that doesn't parse is an error
+    /// rather than the default
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let number = |name: &str, default: f32| -> Result<f32> {
+            match var(name) {
+                None => Ok(default),
+                Some(value) => match value.trim().parse::<f32>() {
+                    Ok(n) if n.is_finite() => Ok(n),
+                    _ => bail!("{}={:?}: expected a number", name, value),
+                },
+            }
+        };
+        let ms = |name: &str, default: f32| -> Result<f32> {
+            let ms = number(name, default)?;
+            if ms < 0.0 {
+                bail!("{}={}: expected a time of 0 ms or more", name, ms);
+            }
+            Ok(ms)
+        };
+        let speed_tilt = match var("SPEED_TILT").as_deref() {
+            None | Some("0") => None,
+            Some("1") => Some((
+                number("SPEED_TILT_SLOPE", SpeedTilt::DEFAULT_SLOPE_DB_PER_KMH)?,
+                number("SPEED_TILT_MAX_DB", SpeedTilt::DEFAULT_MAX_DB)?,
+            )),
+            Some(other) => bail!("SPEED_TILT={:?}: expected 0 or 1", other),
+        };
+        let fade_shape = match var("FADE_SHAPE") {
+            None => FadeShape::RaisedCosine,
+            Some(v) if v.eq_ignore_ascii_case("linear") => FadeShape::Linear,
+            Some(v) if v.eq_ignore_ascii_case("raised-cosine") => FadeShape::RaisedCosine,
+            Some(v) => bail!("FADE_SHAPE={:?}: expected linear or raised-cosine", v),
+        };
+        Ok(Self {
+            eq: None,
+            loudness_comp: false,
+            loop_crossfade_ms: ms("LOOP_CROSSFADE_MS", DEFAULT_LOOP_CROSSFADE_MS)?,
+            prefill_ms: ms("PREFILL_MS", DEFAULT_PREFILL_MS)?,
+            speed_tilt,
+            fade_in_ms: ms("FADE_IN_MS", FadeEnvelope::DEFAULT_TRANSPORT_FADE_MS)?,
+            fade_out_ms: ms("FADE_OUT_MS", FadeEnvelope::DEFAULT_TRANSPORT_FADE_MS)?,
+            fade_shape,
+            mute_fade_ms: ms("MUTE_FADE_MS", FadeEnvelope::DEFAULT_MUTE_FADE_MS)?,
+        })
+    }
+}
+
+/// Handles shared between the controller, the output callback, the monitor and the servers
+#[derive(Clone)]
+struct SharedState {
+    /// Latest linear gain to apply (lock-free for the audio callback)
+    gain_lin: Arc<AtomicF32>,
+    /// km/h, plus stale flag and rejected count
+    speed: Arc<SharedSpeed>,
+    /// The controller's smoothed km/h, for SPEED_TILT=1
+    smoothed_speed: Arc<AtomicF32>,
+    /// --multiband with a mic: the band gains (dB) from the controller
+    band_gains_db: Option<Arc<[AtomicF32; 3]>>,
+    /// --self-masking-coupling-db: dBFS of the last output callback
+    output_level_db: Option<Arc<AtomicF32>>,
+    /// Played/underrun/clip counters and the loader's end-of-file flag
+    stats: Arc<PlaybackStats>,
+    /// Output peak/RMS for the monitor's level meter
+    level_meter: Arc<Meter>,
+    /// --control: the remote offset and mute, and the readings for /status
+    control: Option<Arc<ControlState>>,
+    /// --metrics-port: gauges and counters for the Prometheus endpoint
+    metrics: Option<Arc<Metrics>>,
+    /// Set by Ctrl-C; every worker loop checks it
+    stop: &'static AtomicBool,
+}
+
+/// The output stream as the supervisor last left it, for the monitor
+#[derive(Debug, Default)]
+struct OutputStatus {
+    /// Successful reconnects so far
+    reconnects: AtomicUsize,
+    /// The stream failed and hasn't been rebuilt yet
+    down: AtomicBool,
+    /// Prefill is done and the stream was started
+    playing: AtomicBool,
+}
+
+pub fn run(settings: &Settings, args: &StreamArgs) -> Result<()> {
+    // --loop: restart the WAV when it ends instead of going silent (kiosk/demo mode)
+    let loop_playback = args.loop_playback;
+    // --eq, --loudness-comp and the output settings read from the environment
+    let output = OutputOptions::from_args(args)?;
+    // --nav <wav>: prompt mixed over the music every --nav-repeat-s, with the music ducked under it
+    let nav = args.nav.as_ref();
+    // the mic flags, with the mic calibration falling back to the --profile's
+    let ctrl_config = ControllerConfig::new(&args.mic, settings.profile.as_ref());
+    // --record <csv>: one row per controller step; created up front so a bad path fails before audio starts
+    let recorder = args.record.as_ref().map(TraceRecorder::create).transpose()?;
+    // set by Ctrl-C; every worker loop checks it so main can join them and exit cleanly
+    let stop = install_ctrlc_handler();
+    let mut workers = Vec::new();
+
+    log_startup(settings, args, &output, &ctrl_config);
+
+    // 1) Start the speed source (OBD-II, GPS, HTTP polling, or pushed messages for ws:// URLs)
+    let speed_shared = Arc::new(SharedSpeed::new());
+    workers.push(start_speed_source(args, speed_shared.clone(), stop)?);
+
+    // --control <addr>: HTTP knob for the user offset, starting from the configured one
+    let control = match &args.control {
+        Some(addr) => {
+            let server =
+                ControlServer::bind(addr.as_str()).with_context(|| format!("binding control server to {}", addr))?;
+            info!("Control server: http://{}", server.local_addr()?);
+            let state = Arc::new(ControlState::new(settings.config.user_offset_db));
+            workers.push(server.spawn(state.clone(), stop));
+            Some(state)
+        }
+        None => None,
+    };
+
+    // --metrics-port <port>: Prometheus gauges and counters for the controller and the output
+    let metrics = match args.metrics_port {
+        Some(port) => {
+            let (metrics, worker) = start_metrics_server(port, stop)?;
+            workers.push(worker);
+            Some(metrics)
+        }
+        None => None,
+    };
+
+    // 2) Start audio host, output stream consumes from playback_queue and applies latest gain
+    let host = cpal::default_host();
+
+    // playback needs an output device; without a mic the controller falls back to simulated cabin noise
+    // --output-device / --input-device pick a device by index or name instead of the defaults
+    let output_device = output_device(&host, args.output_device.as_deref())?;
+    info!("Output device: {}", output_device.name()?);
+
+    let input_device = input_device(&host, args.input_device.as_deref())?;
+    let mic_available = input_device.is_some();
+    match &input_device {
+        Some(device) => info!("Input device: {}", device.name()?),
+        None => info!("Input device: none found, using simulated cabin noise"),
+    }
+
+    let out_config = output_device.default_output_config()?;
+    let in_config = input_device.as_ref().map(|d| d.default_input_config()).transpose()?;
+    info!("Output config: {:?}", out_config);
+    if let Some(in_config) = &in_config {
+        info!("Input config: {:?}", in_config);
+        if let Some(channels) = &ctrl_config.mic.channels {
+            check_mic_channels(channels, in_config.channels() as usize)?;
+            info!("Mics: input channels {:?}, power-averaged", channels);
+        }
+    }
+
+    // Use f32 pipeline for simplicity; convert if devices are other formats
+    let sample_rate = out_config.sample_rate().0;
+    let channels_out = out_config.channels() as usize;
+    let in_sample_rate = in_config.as_ref().map_or(sample_rate, |c| c.sample_rate().0) as f32;
+
+    let shared = SharedState {
+        gain_lin: Arc::new(AtomicF32::new(1.0)),
+        speed: speed_shared,
+        smoothed_speed: Arc::new(AtomicF32::new(0.0)),
+        band_gains_db: (ctrl_config.mic.multiband && mic_available)
+            .then(|| Arc::new(std::array::from_fn(|_| AtomicF32::new(0.0)))),
+        output_level_db: ctrl_config.self_masking.is_some().then(|| Arc::new(AtomicF32::new(-180.0))),
+        stats: Arc::new(PlaybackStats::default()),
+        level_meter: Arc::new(Meter::new()),
+        control,
+        metrics,
+        stop,
+    };
+
+    // 3) Read WAV file (synchronously so we know it's loaded), resampled to the output device rate.
+    //    Samples stay interleaved with the WAV's own channel count.
+    let (wav_samples, wav_channels) = match read_wav_samples(&args.wav, sample_rate) {
+        Ok((samples, channels)) => {
+            info!("WAV loaded. samples={} channels={}", samples.len(), channels);
+            (samples, channels)
+        }
+        Err(e) => {
+            error!("Failed to load WAV: {e:?}");
+            (Vec::new(), 1)
+        }
+    };
+
+    // Lock-free playback queue (~2 s of audio): the loader thread produces, the output callback consumes.
+    // The loader tops the ring up whenever it drains below capacity; with --loop it starts over at the end.
+    let playback_capacity = sample_rate as usize * wav_channels * 2;
+    let (playback_tx, playback_rx) = spsc_ring(playback_capacity);
+    let playback_monitor = playback_tx.monitor();
+    let loop_fade_frames =
+        loop_playback.then(|| (sample_rate as f32 * output.loop_crossfade_ms / 1000.0) as usize);
+    let loader_stats = shared.stats.clone();
+    workers.push(spawn_named("wav-loader", move || {
+        run_wav_loader(wav_samples, wav_channels, loop_fade_frames, playback_tx, loader_stats, stop)
+    }));
+
+    // --nav: a second queue (~1 s) the prompt is pushed into whole, then again after each pause
+    let nav_queue = match nav {
+        Some(nav) => {
+            let (nav_samples, nav_channels) = read_wav_samples(&nav.path, sample_rate)
+                .with_context(|| format!("loading nav prompt {}", nav.path))?;
+            let repeat = nav.repeat;
+            let (nav_tx, nav_rx) = spsc_ring(sample_rate as usize * nav_channels);
+            workers.push(spawn_named("nav-prompts", move || run_nav_prompts(nav_samples, repeat, nav_tx, stop)));
+            Some((nav_rx, nav_channels, nav))
+        }
+        None => None,
+    };
+
+    // Output stream - pulls from the playback queue and applies latest gain
+    // reconnects so far, and whether the output is down or still prefilling (for the monitor)
+    let output_status = Arc::new(OutputStatus::default());
+    // interleaved samples to queue before play(); capped at the ring so it can always be reached
+    let prefill_samples =
+        ((sample_rate as f32 * output.prefill_ms / 1000.0) as usize * wav_channels).min(playback_capacity);
+    // shared so a rebuilt stream carries on with the same queue, gain ramp and limiter state
+    let renderer = OutputRenderer::new(
+        playback_rx,
+        shared.gain_lin.clone(),
+        wav_channels,
+        channels_out,
+        sample_rate,
+        shared.stats.clone(),
+    );
+    let renderer = Arc::new(Mutex::new(configure_renderer(
+        renderer,
+        settings,
+        &output,
+        &shared,
+        nav_queue,
+        sample_rate,
+    )?));
+    // a reconnect re-resolves the device the user picked by its name (or the default again), and
+    // keeps the original stream config since the queue is already resampled to that rate
+    let reconnect_device = args.output_device.as_ref().map(|_| output_device.name()).transpose()?;
+    let out_stream_config = out_config.config();
+    // kept alive until shutdown; dropping the stream stops playback. The flag is set by the
+    // stream's error callback. Built now but only started by the supervisor once prefilled.
+    let mut output_stream: Option<(cpal::Stream, Arc<AtomicBool>)> = {
+        let failed = Arc::new(AtomicBool::new(false));
+        let stream = open_output_stream(
+            &output_device,
+            &out_stream_config,
+            out_config.sample_format(),
+            renderer.clone(),
+            failed.clone(),
+        )?;
+        Some((stream, failed))
+    };
+
+    // Input stream - collects mic frames and sends them to controller via channel-like arrangement
+    // Mic samples accumulate in a bounded ring (1 s of history); the controller drains fixed windows from it
+    // With several mics the ring holds their samples interleaved, a frame per capture frame
+    let controller = Controller::new(ctrl_config, in_sample_rate);
+    let mic_queue = match (input_device, in_config) {
+        (Some(input_dev), Some(supported_in)) => {
+            let n_mics = controller.n_mics;
+            let queue = Arc::new(Mutex::new(BoundedRing::with_capacity(in_sample_rate as usize * n_mics)));
+            let mic_q = queue.clone();
+            let mic_channels = controller.config.mic.channels.clone();
+            workers.push(spawn_named("mic-input", move || {
+                if let Err(e) = run_input_stream(&input_dev, &supported_in, mic_channels, mic_q, stop) {
+                    error!("Input stream failed, cabin noise unavailable: {:#}", e);
+                }
+            }));
+            Some(queue)
+        }
+        _ => None,
+    };
+
+    // Start a small monitor to help diagnose playback (queue length, played samples, current gain)
+    {
+        let (queue, shared, status) = (playback_monitor.clone(), shared.clone(), output_status.clone());
+        workers.push(spawn_named("monitor", move || run_monitor(queue, &shared, &status)));
+    }
+
+    // 4) Controller thread: periodically reads the mic queue (simulated cabin noise without one) and
+    //    the speed, computes gain via the --controller choice, and writes linear gain into shared.gain_lin
+    {
+        let gain = settings.gain_controller(args.controller);
+        let shared = shared.clone();
+        workers.push(spawn_named("controller", move || run_controller(controller, gain, mic_queue, recorder, &shared)));
+    }
+
+    // Keep main alive until Ctrl-C, supervising the output stream: when its error callback fires
+    // (e.g. a USB DAC re-enumerating) tear it down, back off, and rebuild it on the re-resolved
+    // device. The loader keeps the playback queue topped up meanwhile, so playback resumes from it.
+    let mut reconnect = PollBackoff::new(RECONNECT_BASE_DELAY, RECONNECT_MAX_DELAY, RECONNECT_MAX_ATTEMPTS);
+    let mut retry_at: Option<Instant> = None;
+    let mut gave_up = None;
+    while !stop.load(Ordering::Relaxed) {
+        let playing = output_status.playing.load(Ordering::Relaxed);
+        let loader_done = shared.stats.source_done.load(Ordering::Relaxed);
+        if !playing && prefill_complete(playback_monitor.len(), prefill_samples, loader_done) {
+            if let Some((stream, failed)) = &output_stream {
+                match stream.play() {
+                    Ok(()) => info!("Output stream started ({} queued samples).", playback_monitor.len()),
+                    // handled like any other stream error: torn down and rebuilt below
+                    Err(e) => {
+                        error!("output stream error: {}", e);
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                }
+            }
+            output_status.playing.store(true, Ordering::Relaxed);
+        }
+        if output_stream.as_ref().is_some_and(|(_, failed)| failed.load(Ordering::Relaxed)) {
+            drop(output_stream.take());
+            output_status.down.store(true, Ordering::Relaxed);
+            retry_at = Some(Instant::now() + reconnect.delay());
+            warn!("[Supervisor] output stream failed; reconnecting in {:?}", reconnect.delay());
+        }
+        if retry_at.is_some_and(|at| Instant::now() >= at) {
+            match reopen_output_stream(&host, reconnect_device.as_deref(), &out_stream_config, renderer.clone()) {
+                Ok(reopened) => {
+                    output_stream = Some(reopened);
+                    output_status.down.store(false, Ordering::Relaxed);
+                    output_status.playing.store(true, Ordering::Relaxed);
+                    reconnect.on_success();
+                    retry_at = None;
+                    let n = output_status.reconnects.fetch_add(1, Ordering::Relaxed) + 1;
+                    info!("[Supervisor] output stream restored (reconnect #{})", n);
+                }
+                Err(e) => {
+                    // the backoff's "stale" threshold doubles as the attempt cap
+                    if reconnect.on_failure() {
+                        gave_up = Some(e.context(format!(
+                            "output device did not come back after {} attempts",
+                            RECONNECT_MAX_ATTEMPTS
+                        )));
+                        stop.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                    retry_at = Some(Instant::now() + reconnect.delay());
+                    warn!("[Supervisor] reconnect failed: {:#}; retrying in {:?}", e, reconnect.delay());
+                }
+            }
+        }
+        // poll quickly while prefilling so playback starts as soon as the threshold is reached
+        thread::sleep(Duration::from_millis(if playing { 100 } else { 5 }));
+    }
+
+    info!("Shutting down...");
+    if let Some((stream, _)) = output_stream.take() {
+        // the callback fades out once it sees the stop flag; let that play out (plus a buffer or
+        // two) before pausing, unless there's nothing playing to fade
+        if output_status.playing.load(Ordering::Relaxed) && gave_up.is_none() {
+            thread::sleep(Duration::from_secs_f32(output.fade_out_ms / 1000.0) + SHUTDOWN_FADE_MARGIN);
+        }
+        let _ = stream.pause();
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+    info!(
+        "Final stats: played_total={} underruns={} reconnects={} clipped={}",
+        shared.stats.played.load(Ordering::Relaxed),
+        shared.stats.underruns.load(Ordering::Relaxed),
+        output_status.reconnects.load(Ordering::Relaxed),
+        shared.stats.clipped.load(Ordering::Relaxed)
+    );
+    match gave_up {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Log what the stream is about to run with
+fn log_startup(settings: &Settings, args: &StreamArgs, output: &OutputOptions, ctrl_config: &ControllerConfig) {
+    info!("Adaptive Volume Rust");
+    info!("WAV file: {}", args.wav);
+    match (&args.obd, &args.nmea) {
+        (Some(port), _) => info!("Speed source: OBD-II on {} @ {} baud", port, Obd2Source::baud_from_env()),
+        (None, Some(address)) => info!("Speed source: NMEA GPS on {}", address),
+        (None, None) => info!("Speed API URL: {} ({:?})", args.speed_url, args.speed_unit),
+    }
+    if let Some(path) = &args.record {
+        info!("Recording controller steps to {}", path);
+    }
+    if args.loop_playback {
+        info!("Looping playback (crossfade {:.0} ms)", output.loop_crossfade_ms);
+    }
+    if let Some(profile) = &settings.profile {
+        info!("Vehicle profile: {} noise points", profile.points.len());
+    }
+    if let Some(nav) = &args.nav {
+        info!(
+            "Nav prompt: {} every {:.0} s, music ducked {:.0} dB above {:.0} dBFS ({:.0}/{:.0} ms)",
+            nav.path,
+            nav.repeat.as_secs_f32(),
+            nav.duck_depth_db,
+            nav.duck_threshold_db,
+            nav.duck_attack_ms,
+            nav.duck_release_ms
+        );
+    }
+    if settings.night {
+        info!(
+            "Night mode: target {:.1} dB, max gain {:+.1} dB, compressor {:.0} dBFS {}:1",
+            settings.config.target_db,
+            settings.config.max_gain_db.unwrap_or_default(),
+            Compressor::NIGHT_THRESHOLD_DB,
+            Compressor::NIGHT_RATIO
+        );
+    }
+    info!("Prefill: {:.0} ms", output.prefill_ms);
+    info!("Mic weighting: {}", if ctrl_config.mic.a_weighting { "A" } else { "Z (flat)" });
+    if let Some((low, high)) = ctrl_config.mic.noise_band_hz {
+        info!("Mic noise estimate: {:.0}-{:.0} Hz band (FFT)", low, high);
+    }
+    if ctrl_config.mic.multiband {
+        info!("Multiband gain: crossovers {:?} Hz", DEFAULT_CROSSOVERS_HZ);
+    }
+    info!("Mic calibration: {:+.1} dB", ctrl_config.mic_calibration_db);
+    match ctrl_config.mic.highpass_hz {
+        Some(hz) => info!("Mic high-pass: {:.0} Hz", hz),
+        None => info!("Mic high-pass: off"),
+    }
+    if let Some((target_dbfs, window_s)) = ctrl_config.mic.agc {
+        info!("Mic AGC: target {:.1} dBFS, calibrated over the first {:.0} s", target_dbfs, window_s);
+    }
+    if let Some(weighting) = ctrl_config.mic.spl_weighting {
+        info!("Level time weighting: {:?}", weighting);
+    }
+    if let Some(sm) = ctrl_config.self_masking.as_ref() {
+        info!("Self-masking compensation: coupling {:+.1} dB", sm.coupling_db());
+    }
+}
+
+/// Start the speed source publishing into `speed`: OBD-II, GPS, HTTP polling, or pushed messages
+/// for ws:// URLs
+fn start_speed_source(args: &StreamArgs, speed: Arc<SharedSpeed>, stop: &'static AtomicBool) -> Result<JoinHandle<()>> {
+    let poll_period_ms = 150u64; // how often to poll speed API
+    let backoff = PollBackoff::new(
+        Duration::from_millis(poll_period_ms),
+        SPEED_POLL_MAX_BACKOFF,
+        SPEED_STALE_AFTER_POLLS,
+    );
+    // OBD-II and the NMEA parser always report km/h
+    let unit = if args.obd.is_some() || args.nmea.is_some() { SpeedUnit::Kmh } else { args.speed_unit };
+    let publisher = SpeedPublisher::new(speed, unit, SpeedValidator::from_env(), backoff);
+    if let Some(port) = &args.obd {
+        let source = Obd2Source::new(port.clone(), Obd2Source::baud_from_env(), Duration::from_millis(poll_period_ms));
+        Ok(source.spawn(publisher, stop))
+    } else if let Some(address) = &args.nmea {
+        Ok(NmeaSource::new(address.clone(), NmeaSource::baud_from_env()).spawn(publisher, stop))
+    } else if args.speed_url.starts_with("ws://") {
+        Ok(WebSocketSource::new(args.speed_url.clone()).spawn(publisher, stop))
+    } else {
+        Ok(HttpPoller::new(args.speed_url.clone())?.spawn(publisher, stop))
+    }
+}
+
+/// Push `samples` (`channels` interleaved) into the playback queue as it drains, then set
+/// `stats.source_done`. With `loop_fade_frames` (--loop) it starts over instead, crossfading the end
+/// of the file into its start over that many frames.
+fn run_wav_loader(
+    samples: Vec<f32>,
+    channels: usize,
+    loop_fade_frames: Option<usize>,
+    mut queue: Producer,
+    stats: Arc<PlaybackStats>,
+    stop: &'static AtomicBool,
+) {
+    // first pass stops where the loop crossfade begins; later passes replay `cycle`
+    let (intro_len, cycle) = match loop_fade_frames {
+        Some(fade_frames) => {
+            let (intro_len, cycle) = loop_cycle(&samples, channels, fade_frames);
+            (intro_len, Some(cycle))
+        }
+        None => (samples.len(), None),
+    };
+    let mut source = &samples[..intro_len];
+    let mut pos = 0;
+    while !stop.load(Ordering::Relaxed) {
+        if pos == source.len() {
+            match cycle.as_deref() {
+                Some(c) if !c.is_empty() => {
+                    source = c;
+                    pos = 0;
+                }
+                _ => break,
+            }
+        }
+        let pushed = queue.push_slice(&source[pos..]);
+        pos += pushed;
+        if pushed == 0 {
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+    stats.source_done.store(true, Ordering::Release);
+}
+
+/// Push the --nav prompt into its queue whole, then again `repeat` after it has gone in
+fn run_nav_prompts(samples: Vec<f32>, repeat: Duration, mut queue: Producer, stop: &'static AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        let mut pos = 0;
+        while pos < samples.len() && !stop.load(Ordering::Relaxed) {
+            let pushed = queue.push_slice(&samples[pos..]);
+            pos += pushed;
+            if pushed == 0 {
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+        let resume = Instant::now() + repeat;
+        while Instant::now() < resume && !stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+/// Monitor thread: once a second, log the queue length, playback counters, gain, speed rejections
+/// and output state, and the output level meter
+fn run_monitor(queue: RingMonitor, shared: &SharedState, status: &OutputStatus) {
+    let mut peak_hold = PeakHold::new(METER_PEAK_DECAY_DB_PER_S);
+    let mut last_count = 0usize;
+    let mut last_underruns = 0usize;
+    let mut last_report = Instant::now();
+    while !shared.stop.load(Ordering::Relaxed) {
+        let qlen = queue.len();
+        let gain = shared.gain_lin.load(Ordering::Relaxed);
+        let count = shared.stats.played.load(Ordering::Relaxed);
+        let underruns = shared.stats.underruns.load(Ordering::Relaxed);
+        let clipped = shared.stats.clipped.load(Ordering::Relaxed);
+        let underruns_per_s = (underruns - last_underruns) as f32 / last_report.elapsed().as_secs_f32().max(1e-3);
+        let speed_rejected = shared.speed.rejected();
+        let output = if status.down.load(Ordering::Relaxed) {
+            "down"
+        } else if !status.playing.load(Ordering::Relaxed) {
+            "prefilling"
+        } else if shared.stats.source_done.load(Ordering::Relaxed) && qlen == 0 {
+            "ended"
+        } else {
+            "playing"
+        };
+        let reconnects = status.reconnects.load(Ordering::Relaxed);
+        info!(
+            "[Monitor] queue_len={} gain={:.3} played_total={} delta={} underruns={} ({:.1}/s) speed_rejected={} output={} reconnects={} clipped={}",
+            qlen, gain, count, count - last_count, underruns, underruns_per_s, speed_rejected, output,
+            reconnects, clipped
+        );
+        // output level: a gain pinned at the ceiling shows as a peak stuck near 0 dBFS
+        if let Some(reading) = shared.level_meter.take() {
+            let peak_db = peak_hold.update(reading.peak_dbfs(), last_report.elapsed().as_secs_f32());
+            info!(
+                "[Meter] out: {:6.1} dBFS peak / {:6.1} dBFS rms [{}]",
+                peak_db,
+                reading.rms_dbfs(),
+                level_bar(reading.rms_dbfs(), peak_db, METER_FLOOR_DB, METER_WIDTH)
+            );
+        }
+        last_count = count;
+        last_underruns = underruns;
+        last_report = Instant::now();
+        // sleep in short steps so shutdown isn't delayed by a whole second
+        for _ in 0..10 {
+            if shared.stop.load(Ordering::Relaxed) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+/// Whether the output stream may start: `threshold` samples are queued, or the loader has
+/// already pushed everything it has (a file shorter than the prefill, or an empty one)
+fn prefill_complete(queued: usize, threshold: usize, loader_done: bool) -> bool {
+    loader_done || queued >= threshold
+}
+
+/// Resolve the output device again (`name`, else the default) and start a new stream on it with
+/// `config`, in whatever sample format the device now prefers
+fn reopen_output_stream(
+    host: &cpal::Host,
+    name: Option<&str>,
+    config: &cpal::StreamConfig,
+    renderer: Arc<Mutex<OutputRenderer>>,
+) -> Result<(cpal::Stream, Arc<AtomicBool>)> {
+    let device = output_device(host, name)?;
+    let format = device.default_output_config()?.sample_format();
+    let failed = Arc::new(AtomicBool::new(false));
+    let stream = open_output_stream(&device, config, format, renderer, failed.clone())?;
+    stream.play()?;
+    Ok((stream, failed))
+}
+
+/// Read WAV file samples and resample them to `device_rate` as interleaved f32 samples.
+/// Also returns the WAV channel count so the output stream can de-interleave the queue.
+fn read_wav_samples(path: &str, device_rate: u32) -> Result<(Vec<f32>, usize)> {
+    let f = File::open(path)?;
+    let mut reader = WavReader::new(BufReader::new(f))?;
+    let spec = reader.spec();
+    info!("WAV spec: {:?}", spec);
+
+    let mut samples = Vec::<f32>::new();
+    match spec.sample_format {
+        hound::SampleFormat::Float => {
+            for s in reader.samples::<f32>() {
+                let v = s?;
+                samples.push(v);
+            }
+        }
+        hound::SampleFormat::Int => match spec.bits_per_sample {
+            // hound reads 8/16-bit PCM straight into i16
+            8 | 16 => {
+                let max_amplitude = (1i32 << (spec.bits_per_sample - 1)) as f32;
+                for s in reader.samples::<i16>() {
+                    samples.push(s? as f32 / max_amplitude);
+                }
+            }
+            // 24-bit is packed as 3 bytes per sample; hound sign-extends it into i32,
+            // so full scale is 2^23
+            24 => {
+                let max_amplitude = (1i32 << 23) as f32;
+                for s in reader.samples::<i32>() {
+                    samples.push(s? as f32 / max_amplitude);
+                }
+            }
+            32 => {
+                let max_amplitude = (1i64 << 31) as f32;
+                for s in reader.samples::<i32>() {
+                    samples.push(s? as f32 / max_amplitude);
+                }
+            }
+            bits => anyhow::bail!("unsupported PCM bit depth: {} bits", bits),
+        },
+    }
+
+    // Match the device rate so playback isn't pitched/slowed (also drops a trailing partial frame)
+    let channels = (spec.channels as usize).max(1);
+    let resampler = LinearResampler::new(spec.sample_rate, device_rate, channels);
+    if !resampler.is_passthrough() {
+        info!(
+            "Resampling WAV {} Hz -> {} Hz (ratio {:.5})",
+            spec.sample_rate, device_rate, resampler.ratio()
+        );
+    }
+    Ok((resampler.process(&samples), channels))
+}
+
+/// Prepare interleaved `samples` for seamless looping.
+/// Returns the length of the first pass (the file minus its last `fade_frames` frames) and the buffer
+/// to repeat on every later pass: a crossfade from the file's tail into its head, then the rest of the file.
+fn loop_cycle(samples: &[f32], channels: usize, fade_frames: usize) -> (usize, Vec<f32>) {
+    let channels = channels.max(1);
+    let frames = samples.len() / channels;
+    let fade = fade_frames.min(frames / 2);
+    let n = fade * channels;
+    let tail_start = frames * channels - n;
+
+    let mut cycle = Vec::with_capacity(tail_start);
+    for f in 0..fade {
+        let w = (f as f32 + 0.5) / fade as f32;
+        for c in 0..channels {
+            let tail = samples[tail_start + f * channels + c];
+            let head = samples[f * channels + c];
+            cycle.push(tail * (1.0 - w) + head * w);
+        }
+    }
+    cycle.extend_from_slice(&samples[n..tail_start]);
+    (tail_start, cycle)
+}
+
+/// Fixed-capacity FIFO of mic samples shared between the input callback and the controller.
+/// Pushing into a full ring drops the oldest sample so memory stays bounded.
+struct BoundedRing {
+    buf: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl BoundedRing {
+    fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self { buf: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    fn push(&mut self, sample: f32) {
+        if self.buf.len() == self.capacity {
+            self.buf.pop_front();
+        }
+        self.buf.push_back(sample);
+    }
+
+    /// Remove and return the oldest `len` samples, or None until that many have accumulated
+    fn pop_window(&mut self, len: usize) -> Option<Vec<f32>> {
+        if len == 0 || self.buf.len() < len {
+            return None;
+        }
+        Some(self.buf.drain(..len).collect())
+    }
+}
+
+/// Fail unless every --mic-channels index is a channel of an input device with `device_channels`
+fn check_mic_channels(channels: &[usize], device_channels: usize) -> Result<()> {
+    match channels.iter().find(|&&c| c >= device_channels) {
+        Some(bad) => bail!("--mic-channels: channel {} not on the input device ({} channels)", bad, device_channels),
+        None => Ok(()),
+    }
+}
+
+/// Split `window` (`mics` samples per frame, interleaved) into one buffer per mic
+fn deinterleave(window: &[f32], mics: usize) -> Vec<Vec<f32>> {
+    if mics <= 1 {
+        return vec![window.to_vec()];
+    }
+    (0..mics).map(|m| window.iter().skip(m).step_by(mics).copied().collect()).collect()
+}
+
+/// Sample-wise mean of the mics, for the estimators that take a single signal
+fn mix_down(mics: &[Vec<f32>]) -> Vec<f32> {
+    match mics {
+        [mic] => mic.clone(),
+        _ => (0..mics[0].len()).map(|i| mics.iter().map(|m| m[i]).sum::<f32>() / mics.len() as f32).collect(),
+    }
+}
+
+/// One mic's conditioning ahead of the level estimate; its filter state persists across windows
+struct MicFrontEnd {
+    highpass: Option<Biquad>,
+    agc: Option<MicAgc>,
+    weighting: Option<AWeighting>,
+}
+
+impl MicFrontEnd {
+    fn new(config: &ControllerConfig, sample_rate: f32) -> Self {
+        Self {
+            highpass: config
+                .mic
+                .highpass_hz
+                .map(|hz| Biquad::highpass(sample_rate, hz, std::f32::consts::FRAC_1_SQRT_2)),
+            agc: config
+                .mic
+                .agc
+                .map(|(target, window_s)| MicAgc::new(target, window_s, MicAgc::DEFAULT_RELEASE_MS, sample_rate)),
+            weighting: config.mic.a_weighting.then(|| AWeighting::new(sample_rate)),
+        }
+    }
+
+    /// High-pass, then AGC
+    fn condition(&mut self, samples: &mut [f32]) {
+        if let Some(hpf) = self.highpass.as_mut() {
+            for s in samples.iter_mut() {
+                *s = hpf.process(*s);
+            }
+        }
+        if let Some(agc) = self.agc.as_mut() {
+            for s in samples.iter_mut() {
+                *s = agc.process(*s);
+            }
+        }
+    }
+
+    /// Frequency weighting for the level estimate
+    fn weight(&mut self, samples: &mut [f32]) {
+        if let Some(w) = self.weighting.as_mut() {
+            for s in samples.iter_mut() {
+                *s = w.process(*s);
+            }
+        }
+    }
+}
+
+/// What the mic windows of one controller tick came to
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct MicReading {
+    cabin_db: f32,
+    /// --vad heard passengers talking
+    speech: bool,
+    /// The mic clipped, so its level reads low
+    clipped: bool,
+    /// A dead mic, or no usable level
+    dropout: bool,
+}
+
+/// One controller tick's gain, with the clip back-off trim already in it
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct GainStep {
+    gain_db: f32,
+    gain_lin: f32,
+    trim_db: f32,
+}
+
+/// Controller-thread state: the mic front ends and level estimators, and the holds and back-off
+/// between the gain controller and the output. Filter state persists across ticks.
+struct Controller {
+    config: ControllerConfig,
+    n_mics: usize,
+    /// Samples per mic in one RMS window: 50 ms of mic audio
+    window_len: usize,
+    window_dt: f32,
+    /// One per mic
+    front_ends: Vec<MicFrontEnd>,
+    mic_array: MicArray,
+    /// Per-band levels are measured here; the output callback applies the resulting gains
+    multiband: Option<MultibandGain>,
+    spectral: Option<SpectralNoiseEstimator>,
+    kalman: Option<KalmanLevel>,
+    spl_meter: Option<SplMeter>,
+    vad: Option<Vad>,
+    clip_backoff: Option<ClipBackoff>,
+    dropout_hold: DropoutHold,
+    /// Clipped/played totals at the previous tick, for the per-window clip rate
+    last_clip_counts: (usize, usize),
+}
+
+impl Controller {
+    fn new(config: ControllerConfig, sample_rate: f32) -> Self {
+        let mic = &config.mic;
+        let n_mics = mic.channels.as_ref().map_or(1, Vec::len);
+        let window_len = ((sample_rate * 0.05) as usize).max(1);
+        let mut mic_array = MicArray::new(n_mics);
+        mic_array.outlier_db = mic.outlier_db;
+        let multiband = mic
+            .multiband
+            .then(|| MultibandGain::new(sample_rate, DEFAULT_CROSSOVERS_HZ, [MULTIBAND_REFERENCE_DB; 3]));
+        Self {
+            n_mics,
+            window_len,
+            window_dt: window_len as f32 / sample_rate,
+            front_ends: (0..n_mics).map(|_| MicFrontEnd::new(&config, sample_rate)).collect(),
+            mic_array,
+            multiband,
+            spectral: mic.noise_band_hz.map(|band| SpectralNoiseEstimator::new(sample_rate, band, window_len)),
+            kalman: mic.kalman.map(|(q, r)| KalmanLevel::new(q, r)),
+            spl_meter: mic.spl_weighting.map(|w| SplMeter::new(sample_rate, w, config.mic_calibration_db)),
+            vad: mic.vad.map(|thresholds| Vad::with_thresholds(sample_rate, thresholds)),
+            clip_backoff: mic.clip_backoff.then(ClipBackoff::default),
+            dropout_hold: DropoutHold::new(mic.dropout_timeout_s, SAFE_GAIN_DB),
+            last_clip_counts: (0, 0),
+            config,
+        }
+    }
+
+    /// Interleaved samples in one window across all the mics
+    fn window_samples(&self) -> usize {
+        self.window_len * self.n_mics
+    }
+
+    /// Run every window captured since the last tick through the front ends and estimators, so
+    /// their state stays continuous, and take the cabin level from the most recent one.
+    /// `output_level_db` is the last output level (dBFS) for self-masking; with --multiband the
+    /// band gains are published to `band_gains_db`.
+    fn measure(
+        &mut self,
+        windows: Vec<Vec<f32>>,
+        output_level_db: f32,
+        band_gains_db: Option<&[AtomicF32; 3]>,
+    ) -> MicReading {
+        let calibration_db = self.config.mic_calibration_db;
+        let mut reading = MicReading::default();
+        for window in windows {
+            // judged on the raw samples: after weighting a clipped sample no longer sits at full scale
+            reading.clipped = !clip_aware_rms(&window, CLIP_LEVEL).is_reliable();
+            reading.dropout = is_mic_dropout(&window);
+            if reading.dropout {
+                // a dead window would drag the estimators (and the Kalman state) toward -inf
+                continue;
+            }
+            let mut mics = deinterleave(&window, self.n_mics);
+            self.mic_array.observe(&mics);
+            if let Some(v) = self.vad.as_mut() {
+                reading.speech = v.is_speech(&mix_down(&mics));
+            }
+            for (mic, front_end) in mics.iter_mut().zip(self.front_ends.iter_mut()) {
+                front_end.condition(mic);
+            }
+            // band levels from the unweighted signal
+            if let Some(mb) = self.multiband.as_mut() {
+                let gains_db = mb.update(&mix_down(&mics), calibration_db, self.window_dt);
+                for (shared, g) in band_gains_db.into_iter().flatten().zip(gains_db) {
+                    shared.store(g, Ordering::Relaxed);
+                }
+            }
+            for (mic, front_end) in mics.iter_mut().zip(self.front_ends.iter_mut()) {
+                front_end.weight(mic);
+            }
+            reading.cabin_db = match (self.spectral.as_mut(), self.spl_meter.as_mut()) {
+                (Some(est), _) => est.estimate_db(&mix_down(&mics), calibration_db),
+                (None, Some(meter)) => meter.process(&mix_down(&mics)),
+                (None, None) => {
+                    let levels_db: Vec<f32> =
+                        mics.iter().map(|mic| clip_aware_rms(mic, CLIP_LEVEL).db(calibration_db)).collect();
+                    self.mic_array.combine(&levels_db).unwrap_or(reading.cabin_db)
+                }
+            };
+            if let Some(sm) = self.config.self_masking.as_ref() {
+                reading.cabin_db = sm.ambient_db(reading.cabin_db, output_level_db);
+            }
+            if let Some(k) = self.kalman.as_mut() {
+                reading.cabin_db = k.update(reading.cabin_db);
+            }
+        }
+        // an empty window or a NaN/inf level (e.g. from a NaN sample) is no reading at all
+        reading.dropout |= !is_measurement(reading.cabin_db);
+        reading
+    }
+
+    /// The gain for a tick `dt` s after the previous one. It is held while the mic is dead or
+    /// `speed_stale` (the safe gain once that outlasts the dropout timeout), and while passengers
+    /// talk or the mic clips; then trimmed while the output keeps clipping (`clip_counts`: the
+    /// clipped and played sample totals).
+    fn step_gain(
+        &mut self,
+        gain: &mut dyn GainController,
+        reading: &MicReading,
+        speed_kmh: f32,
+        speed_stale: bool,
+        dt: f32,
+        clip_counts: (usize, usize),
+    ) -> GainStep {
+        let previous_hold = self.dropout_hold.state();
+        let (gain_db, gain_lin) = self.dropout_hold.step(gain, reading.dropout || speed_stale, dt, |g| {
+            if reading.speech || reading.clipped {
+                g.hold()
+            } else {
+                g.compute_gain(reading.cabin_db, speed_kmh)
+            }
+        });
+        match (previous_hold, self.dropout_hold.state()) {
+            (HoldState::Live, HoldState::Holding) => {
+                let sensor = if reading.dropout { "mic input is dead" } else { "speed is stale" };
+                warn!("[Controller] {}: holding the gain at {:.1} dB", sensor, gain_db)
+            }
+            (HoldState::Holding, HoldState::TimedOut) => {
+                let timeout_s = self.dropout_hold.timeout_s;
+                warn!("[Controller] dropout outlasted {:.0} s: safe gain {:.1} dB", timeout_s, gain_db)
+            }
+            (HoldState::Holding | HoldState::TimedOut, HoldState::Live) => {
+                info!("[Controller] sensors back, adapting again")
+            }
+            _ => {}
+        }
+
+        // back off while the output keeps clipping; the trim decays once it stops
+        let mut trim_db = 0.0;
+        if let Some(backoff) = self.clip_backoff.as_mut() {
+            let (clipped, played) = clip_counts;
+            trim_db = backoff.update(clipped - self.last_clip_counts.0, played - self.last_clip_counts.1, dt);
+            self.last_clip_counts = clip_counts;
+        }
+        GainStep { gain_db: gain_db + trim_db, gain_lin: gain_lin * db_to_lin(trim_db), trim_db }
+    }
+}
+
+/// Controller thread: every 50 ms, measures the cabin from `mic_queue` (simulated without a mic),
+/// reads the speed, steps `gain` through `controller` and publishes the result to the output
+/// callback, the servers and the --record trace
+fn run_controller(
+    mut controller: Controller,
+    mut gain: Box<dyn GainController + Send>,
+    mic_queue: Option<Arc<Mutex<BoundedRing>>>,
+    mut recorder: Option<TraceRecorder<BufWriter<File>>>,
+    shared: &SharedState,
+) {
+    // controller runs at ~ 20 Hz (50 ms)
+    let interval = Duration::from_millis(50);
+    // speed jitter is smoothed here, separately from the gain smoother
+    let mut speed_smoother = SpeedSmoother::from_env();
+    // offset last handed to the controller, to pass on only remote changes
+    let mut applied_offset_db = shared.control.as_ref().map(|c| c.offset_db());
+    let mut last_speed_update = Instant::now();
+    let started = Instant::now();
+    while !shared.stop.load(Ordering::Relaxed) {
+        let reading = match mic_queue.as_ref() {
+            None => MicReading {
+                cabin_db: mock_get_cabin_noise_db(started.elapsed().as_secs_f32()),
+                ..MicReading::default()
+            },
+            Some(queue) => {
+                // take every complete window accumulated since the last tick
+                let windows: Vec<Vec<f32>> = {
+                    let mut ring = queue.lock().unwrap();
+                    std::iter::from_fn(|| ring.pop_window(controller.window_samples())).collect()
+                };
+                if windows.is_empty() {
+                    thread::sleep(interval);
+                    continue;
+                }
+                let output_level_db = shared.output_level_db.as_ref().map_or(-180.0, |l| l.load(Ordering::Relaxed));
+                controller.measure(windows, output_level_db, shared.band_gains_db.as_deref())
+            }
+        };
+
+        // read latest speed and low-pass it
+        let now = Instant::now();
+        let speed_dt = (now - last_speed_update).as_secs_f32();
+        last_speed_update = now;
+        let speed_kmh = speed_smoother.step(shared.speed.speed_kmh(), speed_dt);
+        shared.smoothed_speed.store(speed_kmh, Ordering::Relaxed);
+
+        // a remote offset change goes through the controller's smoothing, so it ramps in
+        if let Some(control) = shared.control.as_ref() {
+            let offset_db = control.offset_db();
+            if applied_offset_db != Some(offset_db) {
+                gain.set_user_offset_db(offset_db);
+                applied_offset_db = Some(offset_db);
+            }
+        }
+
+        let stats = &shared.stats;
+        let clip_counts = (stats.clipped.load(Ordering::Relaxed), stats.played.load(Ordering::Relaxed));
+        let speed_stale = shared.speed.is_stale();
+        let step = controller.step_gain(&mut *gain, &reading, speed_kmh, speed_stale, speed_dt, clip_counts);
+
+        // update shared gain_lin for output callback
+        shared.gain_lin.store(step.gain_lin, Ordering::Relaxed);
+        if let Some(control) = shared.control.as_ref() {
+            control.publish(reading.cabin_db, speed_kmh, step.gain_db);
+        }
+        if let Some(metrics) = shared.metrics.as_ref() {
+            metrics.set_controller(step.gain_db, reading.cabin_db, speed_kmh);
+            metrics.set_speed_poll_errors(shared.speed.errors());
+        }
+
+        debug!(
+            "[Controller] cabin_db={:.1} dB | speed={:.1} km/h | gain_db={:.2} | gain_lin={:.3} | trim_db={:.1}",
+            reading.cabin_db, speed_kmh, step.gain_db, step.gain_lin, step.trim_db
+        );
+
+        if let Some(rec) = recorder.as_mut() {
+            let row = RecordRow {
+                timestamp: started.elapsed().as_secs_f32(),
+                cabin_db: reading.cabin_db,
+                speed_kmh,
+                gain_db: step.gain_db,
+                gain_lin: step.gain_lin,
+                underruns: stats.underruns.load(Ordering::Relaxed),
+                clipped: stats.clipped.load(Ordering::Relaxed),
+            };
+            if let Err(e) = rec.record(&row) {
+                warn!("[Controller] recording stopped: {:#}", e);
+                recorder = None;
+            }
+        }
+
+        thread::sleep(interval);
+    }
+    // Ctrl-C path: flush the recording before main joins us
+    if let Some(rec) = recorder {
+        if let Err(e) = rec.finish() {
+            error!("[Controller] failed to finalize recording: {:#}", e);
+        }
+    }
+}
+
+/// Per-stream linear gain ramp. Each output callback ramps from the gain applied at the end of
+/// the previous callback to the latest controller target, so gain updates don't cause zipper noise.
+struct GainRamp {
+    current: f32,
+    step: f32,
+}
+
+impl GainRamp {
+    fn new(initial: f32) -> Self {
+        Self { current: initial, step: 0.0 }
+    }
+
+    /// Spread the move to `target` evenly over the next `frames` frames
+    fn set_target(&mut self, target: f32, frames: usize) {
+        self.step = (target - self.current) / frames.max(1) as f32;
+    }
+
+    /// Gain to apply to the next frame
+    fn next_gain(&mut self) -> f32 {
+        self.current += self.step;
+        self.current
+    }
+}
+
+/// Playback counters shared by the loader, the output callback, the monitor and the recorder
+#[derive(Default)]
+struct PlaybackStats {
+    /// Samples written from non-silent frames
+    played: AtomicUsize,
+    /// Callbacks that ran out of queued audio while more was still coming
+    underruns: AtomicUsize,
+    /// Samples over the limiter threshold before limiting, i.e. the ones it had to pull down
+    clipped: AtomicUsize,
+    /// Set once the loader has pushed the whole file (never with --loop); an empty queue after
+    /// this is the end of the stream, not an underrun
+    source_done: AtomicBool,
+}
+
+/// Output callback state. Mixes the playback queue (and any --nav prompts) onto the device channels,
+/// and applies the controller gain (ramped per frame) to every channel before
+/// the limiter. Every device sample format goes through this one f32 path.
+struct OutputRenderer {
+    /// The music under `MUSIC_SOURCE`, plus `NAV_SOURCE` with --nav
+    mixer: Mixer,
+    gain_ref: Arc<AtomicF32>,
+    channels: usize,
+    stats: Arc<PlaybackStats>,
+    ramp: GainRamp,
+    limiter: LookaheadLimiter,
+    /// --multiband: one band splitter per output channel, with the band gains (dB) from the controller
+    multiband: Option<(Vec<MultibandGain>, Arc<[AtomicF32; 3]>)>,
+    /// --eq: one equalizer per output channel
+    equalizers: Option<Vec<Equalizer>>,
+    /// --loudness-comp: per-channel loudness shelves, and the playback level (dB SPL) at 0 dB gain
+    loudness: Option<(Vec<LoudnessCompensation>, f32)>,
+    /// SPEED_TILT=1: per-channel bass tilt, and the controller's smoothed speed (km/h)
+    speed_tilt: Option<(Vec<SpeedTilt>, Arc<AtomicF32>)>,
+    /// --self-masking-coupling-db: where to publish the level (dBFS) of each rendered buffer
+    output_meter: Option<Arc<AtomicF32>>,
+    /// Peak/RMS of the output for the monitor's level meter
+    level_meter: Option<Arc<Meter>>,
+    /// --metrics-port: underrun and clip counters for the Prometheus endpoint
+    metrics: Option<Arc<Metrics>>,
+    /// --night: compressor ahead of the limiter, linked across channels like it
+    compressor: Option<Compressor>,
+    /// --control: fade toward silence while the shared mute flag is set, and back up once cleared
+    mute: Option<(FadeEnvelope, Arc<AtomicBool>)>,
+    /// Fade in from the first callback, and out over the given time (ms) once the stop flag is set
+    transport_fade: Option<(FadeEnvelope, f32, &'static AtomicBool)>,
+    // scratch frame reused across callbacks (no allocation on the audio thread)
+    out_frame: Vec<f32>,
+}
+
+impl OutputRenderer {
+    fn new(
+        playback_queue: Consumer,
+        gain_ref: Arc<AtomicF32>,
+        src_channels: usize,
+        channels: usize,
+        sample_rate: u32,
+        stats: Arc<PlaybackStats>,
+    ) -> Self {
+        let channels = channels.max(1);
+        let ramp = GainRamp::new(gain_ref.load(Ordering::Relaxed));
+        // one gain per frame across all channels (linked); 1 ms lookahead keeps transients under the
+        // threshold without noticeable latency
+        let limiter = LookaheadLimiter::new(0.99, sample_rate as usize / 1000, channels, 100.0, sample_rate as f32);
+        // no headroom: music alone goes through untouched, and only a sum over full scale is limited
+        let mut mixer = Mixer::new(channels, 0.0, sample_rate as f32);
+        mixer.add_source(MUSIC_SOURCE, playback_queue, src_channels, 1.0);
+        Self {
+            mixer,
+            gain_ref,
+            channels,
+            stats,
+            ramp,
+            limiter,
+            multiband: None,
+            equalizers: None,
+            loudness: None,
+            speed_tilt: None,
+            output_meter: None,
+            level_meter: None,
+            metrics: None,
+            compressor: None,
+            mute: None,
+            transport_fade: None,
+            out_frame: vec![0.0; channels],
+        }
+    }
+
+    /// Apply the controller's per-band gains to each output channel before the broadband gain
+    fn with_multiband(mut self, band_gains_db: Arc<[AtomicF32; 3]>, sample_rate: u32) -> Self {
+        let splitters = (0..self.channels)
+            .map(|_| MultibandGain::new(sample_rate as f32, DEFAULT_CROSSOVERS_HZ, [MULTIBAND_REFERENCE_DB; 3]))
+            .collect();
+        self.multiband = Some((splitters, band_gains_db));
+        self
+    }
+
+    /// Run each output channel through `preset` after the gain
+    fn with_equalizer(mut self, preset: &EqPreset, sample_rate: u32) -> Result<Self> {
+        let equalizers = (0..self.channels)
+            .map(|_| Equalizer::new(preset, sample_rate as f32))
+            .collect::<Result<_>>()?;
+        self.equalizers = Some(equalizers);
+        Ok(self)
+    }
+
+    /// Loudness-compensate each output channel after the gain. The playback level is estimated as
+    /// `nominal_db` (the controller target) plus the current gain.
+    fn with_loudness_compensation(mut self, nominal_db: f32, sample_rate: u32) -> Self {
+        let shelves = (0..self.channels)
+            .map(|_| LoudnessCompensation::new(sample_rate as f32, LoudnessCompensation::DEFAULT_REFERENCE_DB))
+            .collect();
+        self.loudness = Some((shelves, nominal_db));
+        self
+    }
+
+    /// Tilt each output channel toward the bass as the smoothed speed rises, after the gain
+    fn with_speed_tilt(mut self, speed_ref: Arc<AtomicF32>, slope_db_per_kmh: f32, max_db: f32, sample_rate: u32) -> Self {
+        let tilts = (0..self.channels)
+            .map(|_| SpeedTilt::new(sample_rate as f32, slope_db_per_kmh, max_db))
+            .collect();
+        self.speed_tilt = Some((tilts, speed_ref));
+        self
+    }
+
+    /// Publish the level of everything sent to the device, so the controller can take it back out
+    /// of the mic reading
+    fn with_output_meter(mut self, level_ref: Arc<AtomicF32>) -> Self {
+        self.output_meter = Some(level_ref);
+        self
+    }
+
+    /// Accumulate the peak and energy of everything sent to the device into `meter`
+    fn with_level_meter(mut self, meter: Arc<Meter>) -> Self {
+        self.level_meter = Some(meter);
+        self
+    }
+
+    /// Count underruns and clipped samples into `metrics` as well as the stats
+    fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Compress everything sent to the device with the night-mode compressor, before the limiter
+    fn with_night_compressor(mut self, sample_rate: u32) -> Self {
+        self.compressor = Some(Compressor::night((sample_rate as usize * self.channels) as f32));
+        self
+    }
+
+    /// Follow `muted` with a `fade_ms` fade, applied on top of the controller gain
+    fn with_mute(mut self, muted: Arc<AtomicBool>, fade_ms: f32, sample_rate: u32) -> Self {
+        let initial = if muted.load(Ordering::Relaxed) { 0.0 } else { 1.0 };
+        self.mute = Some((FadeEnvelope::new(fade_ms, sample_rate as f32, initial), muted));
+        self
+    }
+
+    /// Mix the prompts queued in `nav_queue` over the music, ducking the music under them
+    fn with_nav(mut self, nav_queue: Consumer, nav_channels: usize, ducker: Ducker) -> Self {
+        self.mixer.add_source(NAV_SOURCE, nav_queue, nav_channels, 1.0);
+        self.mixer.set_ducker(NAV_SOURCE, ducker);
+        self
+    }
+
+    /// Fade in over `fade_in_ms` when playback starts, and out over `fade_out_ms` once `stop` is set
+    fn with_transport_fade(
+        mut self,
+        fade_in_ms: f32,
+        fade_out_ms: f32,
+        shape: FadeShape,
+        stop: &'static AtomicBool,
+        sample_rate: u32,
+    ) -> Self {
+        let mut fade = FadeEnvelope::new(fade_in_ms, sample_rate as f32, 0.0).with_shape(shape);
+        fade.set_target(1.0);
+        self.transport_fade = Some((fade, fade_out_ms, stop));
+        self
+    }
+
+    /// Fill one interleaved device buffer. If the playback queue empties, the music is silent; that counts
+    /// as one underrun for the callback unless the loader has already pushed the whole file.
+    fn render<T: cpal::FromSample<f32>>(&mut self, data: &mut [T]) {
+        // read before the queue: once set, every sample the loader will ever push is already queued
+        let source_done = self.stats.source_done.load(Ordering::Acquire);
+        let gain = self.gain_ref.load(Ordering::Relaxed);
+        self.ramp.set_target(gain, data.len() / self.channels);
+        if let Some((shelves, nominal_db)) = self.loudness.as_mut() {
+            let level_db = *nominal_db + 20.0 * gain.max(1e-6).log10();
+            for lc in shelves.iter_mut() {
+                lc.set_playback_db(level_db);
+            }
+        }
+        if let Some((tilts, speed_ref)) = self.speed_tilt.as_mut() {
+            let speed_kmh = speed_ref.load(Ordering::Relaxed);
+            for tilt in tilts.iter_mut() {
+                tilt.set_speed(speed_kmh);
+            }
+        }
+        if let Some((fade, muted)) = self.mute.as_mut() {
+            fade.set_target(if muted.load(Ordering::Relaxed) { 0.0 } else { 1.0 });
+        }
+        if let Some((fade, fade_out_ms, stop)) = self.transport_fade.as_mut() {
+            if stop.load(Ordering::Relaxed) && fade.target() > 0.0 {
+                fade.fade_to(0.0, *fade_out_ms);
+            }
+        }
+        if let Some((splitters, band_gains)) = self.multiband.as_mut() {
+            let gains_db = [0, 1, 2].map(|b| band_gains[b].load(Ordering::Relaxed));
+            for mb in splitters.iter_mut() {
+                mb.set_gains_db(gains_db);
+            }
+        }
+
+        let mut energy = 0.0f32;
+        let mut peak = 0.0f32;
+        let mut clipped = 0usize;
+        for frame in data.chunks_mut(self.channels) {
+            // a source short of a frame (underrun or partial frame) is silent; never blocks on a producer
+            self.mixer.next_frame(&mut self.out_frame);
+            if let Some((splitters, _)) = self.multiband.as_mut() {
+                for (s, mb) in self.out_frame.iter_mut().zip(splitters.iter_mut()) {
+                    *s = mb.process_sample(*s);
+                }
+            }
+
+            // the fades scale the ramped gain without touching it, so unmuting lands on the current level
+            let mut g = self.ramp.next_gain();
+            if let Some((fade, _)) = self.mute.as_mut() {
+                g *= fade.next_gain();
+            }
+            if let Some((fade, _, _)) = self.transport_fade.as_mut() {
+                g *= fade.next_gain();
+            }
+            let mut wrote_nonzero = false;
+            for (i, s) in self.out_frame.iter_mut().enumerate() {
+                // detect non-silence (simple): if source sample != 0.0
+                wrote_nonzero = wrote_nonzero || *s != 0.0f32;
+                // Apply gain (then EQ, loudness shelves, speed tilt and the night compressor); the lookahead
+                // limiter then keeps the whole frame's peaks under 0.99
+                let mut out = *s * g;
+                if let Some(equalizers) = self.equalizers.as_mut() {
+                    out = equalizers[i].process(out);
+                }
+                if let Some((shelves, _)) = self.loudness.as_mut() {
+                    out = shelves[i].process(out);
+                }
+                if let Some((tilts, _)) = self.speed_tilt.as_mut() {
+                    out = tilts[i].process(out);
+                }
+                if let Some(comp) = self.compressor.as_mut() {
+                    out = comp.process(out);
+                }
+                clipped += (out.abs() > self.limiter.threshold) as usize;
+                *s = out;
+            }
+            self.limiter.process_frame(&mut self.out_frame);
+            for (ch, &out) in frame.iter_mut().zip(self.out_frame.iter()) {
+                energy += out * out;
+                peak = peak.max(out.abs());
+                *ch = T::from_sample_(out);
+            }
+            if wrote_nonzero {
+                self.stats.played.fetch_add(frame.len(), Ordering::Relaxed);
+            }
+        }
+        if clipped > 0 {
+            self.stats.clipped.fetch_add(clipped, Ordering::Relaxed);
+            if let Some(metrics) = self.metrics.as_ref() {
+                metrics.add_clips(clipped);
+            }
+        }
+        if let Some(meter) = self.level_meter.as_ref() {
+            meter.record_block(peak, energy, data.len());
+        }
+        if let Some(meter) = self.output_meter.as_ref() {
+            let mean_square = energy / data.len().max(1) as f32;
+            meter.store(10.0 * mean_square.max(1e-18).log10(), Ordering::Relaxed);
+        }
+        // starved mid-stream (an audible gap), as opposed to silence after the end of the file
+        // the prompts are not a stream: an empty nav queue is just the gap between them
+        if self.mixer.take_starved(MUSIC_SOURCE) && !source_done {
+            self.stats.underruns.fetch_add(1, Ordering::Relaxed);
+            if let Some(metrics) = self.metrics.as_ref() {
+                metrics.add_underruns(1);
+            }
+        }
+    }
+}
+
+/// Add the optional stages to a new `renderer`: --multiband, --eq, --loudness-comp, SPEED_TILT,
+/// the self-masking output meter, --nav, --night, the transport fades and --control's mute, plus
+/// the level meter and metrics
+fn configure_renderer(
+    mut renderer: OutputRenderer,
+    settings: &Settings,
+    output: &OutputOptions,
+    shared: &SharedState,
+    nav: Option<(Consumer, usize, &NavOptions)>,
+    sample_rate: u32,
+) -> Result<OutputRenderer> {
+    if let Some(band_gains_db) = shared.band_gains_db.as_ref() {
+        renderer = renderer.with_multiband(band_gains_db.clone(), sample_rate);
+    }
+    if let Some(preset) = &output.eq {
+        renderer = renderer.with_equalizer(preset, sample_rate)?;
+    }
+    if output.loudness_comp {
+        renderer = renderer.with_loudness_compensation(settings.config.target_db, sample_rate);
+    }
+    if let Some((slope_db_per_kmh, max_db)) = output.speed_tilt {
+        renderer = renderer.with_speed_tilt(shared.smoothed_speed.clone(), slope_db_per_kmh, max_db, sample_rate);
+    }
+    if let Some(output_level_db) = shared.output_level_db.as_ref() {
+        renderer = renderer.with_output_meter(output_level_db.clone());
+    }
+    if let Some((nav_rx, nav_channels, nav)) = nav {
+        let ducker = Ducker::new(
+            nav.duck_threshold_db,
+            nav.duck_depth_db,
+            nav.duck_attack_ms,
+            nav.duck_release_ms,
+            sample_rate as f32,
+        );
+        renderer = renderer.with_nav(nav_rx, nav_channels, ducker);
+    }
+    if settings.night {
+        renderer = renderer.with_night_compressor(sample_rate);
+    }
+    renderer = renderer.with_transport_fade(
+        output.fade_in_ms,
+        output.fade_out_ms,
+        output.fade_shape,
+        shared.stop,
+        sample_rate,
+    );
+    if let Some(control) = shared.control.as_ref() {
+        renderer = renderer.with_mute(control.mute_flag(), output.mute_fade_ms, sample_rate);
+    }
+    renderer = renderer.with_level_meter(shared.level_meter.clone());
+    if let Some(metrics) = shared.metrics.as_ref() {
+        renderer = renderer.with_metrics(metrics.clone());
+    }
+    Ok(renderer)
+}
+
+/// Serve `/metrics` on `port` (all interfaces, for the fleet scraper) until `stop` is set
+#[cfg(feature = "metrics")]
+fn start_metrics_server(port: u16, stop: &'static AtomicBool) -> Result<(Arc<Metrics>, JoinHandle<()>)> {
+    let server = adaptive_vol::metrics::MetricsServer::bind(("0.0.0.0", port))
+        .with_context(|| format!("binding metrics server to port {}", port))?;
+    info!("Metrics: http://{}/metrics", server.local_addr()?);
+    let metrics = Arc::new(Metrics::new());
+    let worker = server.spawn(metrics.clone(), stop);
+    Ok((metrics, worker))
+}
+
+#[cfg(not(feature = "metrics"))]
+fn start_metrics_server(_port: u16, _stop: &'static AtomicBool) -> Result<(Arc<Metrics>, JoinHandle<()>)> {
+    bail!("--metrics-port needs a build with the `metrics` feature (cargo build --features cli,metrics)")
+}
+
+/// Open the output stream in sample format `format`, converting from f32. `failed` is set when the
+/// stream reports an error (typically the device going away).
+fn open_output_stream(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    format: SampleFormat,
+    renderer: Arc<Mutex<OutputRenderer>>,
+    failed: Arc<AtomicBool>,
+) -> Result<cpal::Stream> {
+    match format {
+        SampleFormat::I8 => build_output_stream::<i8>(device, config, renderer, failed),
+        SampleFormat::I16 => build_output_stream::<i16>(device, config, renderer, failed),
+        SampleFormat::I24 => build_output_stream::<cpal::I24>(device, config, renderer, failed),
+        SampleFormat::I32 => build_output_stream::<i32>(device, config, renderer, failed),
+        SampleFormat::I64 => build_output_stream::<i64>(device, config, renderer, failed),
+        SampleFormat::U8 => build_output_stream::<u8>(device, config, renderer, failed),
+        SampleFormat::U16 => build_output_stream::<u16>(device, config, renderer, failed),
+        SampleFormat::U32 => build_output_stream::<u32>(device, config, renderer, failed),
+        SampleFormat::U64 => build_output_stream::<u64>(device, config, renderer, failed),
+        SampleFormat::F32 => build_output_stream::<f32>(device, config, renderer, failed),
+        SampleFormat::F64 => build_output_stream::<f64>(device, config, renderer, failed),
+        format => bail!("output device uses unsupported sample format {:?}", format),
+    }
+}
+
+fn build_output_stream<T>(
+    output_device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    renderer: Arc<Mutex<OutputRenderer>>,
+    failed: Arc<AtomicBool>,
+) -> Result<cpal::Stream>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+{
+    let err_fn = move |err| {
+        error!("output stream error: {}", err);
+        failed.store(true, Ordering::Relaxed);
+    };
+    let stream = output_device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| render_shared(&renderer, data),
+        err_fn,
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// Render through the shared renderer. Only one stream is alive at a time so the lock is
+/// uncontended; if it ever is busy, output silence rather than block the audio thread.
+fn render_shared<T: cpal::Sample + cpal::FromSample<f32>>(renderer: &Mutex<OutputRenderer>, data: &mut [T]) {
+    match renderer.try_lock() {
+        Ok(mut renderer) => renderer.render(data),
+        Err(_) => data.fill(T::EQUILIBRIUM),
+    }
+}
+
+/// Capture the mic on this thread until `stop`: every frame is converted to f32 and pushed into
+/// `ring` for the controller, either downmixed to mono or as the `mic_channels` it picks out
+fn run_input_stream(
+    device: &cpal::Device,
+    supported: &cpal::SupportedStreamConfig,
+    mic_channels: Option<Vec<usize>>,
+    ring: Arc<Mutex<BoundedRing>>,
+    stop: &AtomicBool,
+) -> Result<()> {
+    let config = supported.config();
+    let stream = match supported.sample_format() {
+        SampleFormat::I8 => build_input_stream::<i8>(device, &config, mic_channels, ring),
+        SampleFormat::I16 => build_input_stream::<i16>(device, &config, mic_channels, ring),
+        SampleFormat::I24 => build_input_stream::<cpal::I24>(device, &config, mic_channels, ring),
+        SampleFormat::I32 => build_input_stream::<i32>(device, &config, mic_channels, ring),
+        SampleFormat::I64 => build_input_stream::<i64>(device, &config, mic_channels, ring),
+        SampleFormat::U8 => build_input_stream::<u8>(device, &config, mic_channels, ring),
+        SampleFormat::U16 => build_input_stream::<u16>(device, &config, mic_channels, ring),
+        SampleFormat::U32 => build_input_stream::<u32>(device, &config, mic_channels, ring),
+        SampleFormat::U64 => build_input_stream::<u64>(device, &config, mic_channels, ring),
+        SampleFormat::F32 => build_input_stream::<f32>(device, &config, mic_channels, ring),
+        SampleFormat::F64 => build_input_stream::<f64>(device, &config, mic_channels, ring),
+        format => bail!("input device uses unsupported sample format {:?}", format),
+    }?;
+    stream.play()?;
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(100));
+    }
+    let _ = stream.pause();
+    Ok(())
+}
+
+fn build_input_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mic_channels: Option<Vec<usize>>,
+    ring: Arc<Mutex<BoundedRing>>,
+) -> Result<cpal::Stream>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    let channels = (config.channels as usize).max(1);
+    // scratch for converting one frame before the mono downmix
+    let mut frame_f32 = Vec::<f32>::with_capacity(channels);
+    let err_fn = |err| error!("input stream error: {}", err);
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let mut local = ring.lock().unwrap();
+            for frame in data.chunks(channels) {
+                frame_f32.clear();
+                frame_f32.extend(frame.iter().map(|&s| f32::from_sample_(s)));
+                match mic_channels.as_deref() {
+                    Some(picked) => picked.iter().for_each(|&c| local.push(frame_f32.get(c).copied().unwrap_or(0.0))),
+                    None => local.push(downmix_to_mono(&frame_f32)),
+                }
+            }
+        },
+        err_fn,
+        None,
+    )?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adaptive_vol::adaptive_gain::NoiseCombine;
+    use adaptive_vol::gain::AdaptiveGain;
+    use adaptive_vol::Config;
+
+    /// Mono renderer at 1 kHz: the limiter's lookahead is then a single sample
+    fn renderer(samples: &[f32]) -> OutputRenderer {
+        let (mut tx, rx) = spsc_ring(16);
+        tx.push_slice(samples);
+        OutputRenderer::new(rx, Arc::new(AtomicF32::new(1.0)), 1, 1, 1000, Arc::new(PlaybackStats::default()))
+    }
+
+    #[test]
+    fn test_underruns_counted_only_before_end_of_stream() {
+        let mut r = renderer(&[0.5; 4]);
+        let mut out = [0f32; 4];
+        r.render(&mut out);
+        assert_eq!(r.stats.underruns.load(Ordering::Relaxed), 0);
+        // queue empty while the loader is still going: starvation
+        r.render(&mut out);
+        r.render(&mut out);
+        assert_eq!(r.stats.underruns.load(Ordering::Relaxed), 2);
+        // queue empty after the loader finished: just the end of the file
+        r.stats.source_done.store(true, Ordering::Relaxed);
+        r.render(&mut out);
+        assert_eq!(r.stats.underruns.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_renderer_converts_to_i32_and_f64() {
+        let mut out = [0i32; 3];
+        renderer(&[0.5, -0.25]).render(&mut out);
+        assert_eq!(out[0], 0, "one sample of limiter lookahead");
+        assert!((out[1] as f64 / i32::MAX as f64 - 0.5).abs() < 1e-6, "{}", out[1]);
+        assert!((out[2] as f64 / i32::MAX as f64 + 0.25).abs() < 1e-6, "{}", out[2]);
+
+        let mut out = [0f64; 3];
+        renderer(&[0.5, -0.25]).render(&mut out);
+        assert_eq!(out, [0.0, 0.5, -0.25]);
+    }
+
+    #[test]
+    fn test_nav_prompt_is_mixed_over_ducked_music_without_underruns() {
+        let (mut tx, rx) = spsc_ring(8);
+        tx.push_slice(&[0.25; 2]);
+        // instant attack so the first prompt frame already ducks the music by 12 dB
+        let mut r = renderer(&[0.5; 8]).with_nav(rx, 1, Ducker::new(-45.0, 12.0, 0.0, 1000.0, 1000.0));
+        let mut out = [0f32; 5];
+        r.render(&mut out);
+        let ducked = 0.5 * db_to_lin(-12.0);
+        assert!((out[1] - (ducked + 0.25)).abs() < 1e-4, "{:?}", out);
+        assert!((out[3] - ducked).abs() < 0.01, "still ducked between prompts: {:?}", out);
+        assert_eq!(r.stats.underruns.load(Ordering::Relaxed), 0, "an empty nav queue is not an underrun");
+    }
+
+    #[test]
+    fn test_shared_renderer_outputs_silence_while_locked() {
+        let renderer = Mutex::new(renderer(&[0.5, 0.5, 0.5]));
+        let mut out = [1u16; 2];
+        {
+            let _held = renderer.lock().unwrap();
+            render_shared(&renderer, &mut out);
+        }
+        assert_eq!(out, [<u16 as cpal::Sample>::EQUILIBRIUM; 2]);
+        // nothing was consumed while locked
+        let mut out = [0f32; 3];
+        render_shared(&renderer, &mut out);
+        assert_eq!(out, [0.0, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_prefill_gates_on_queue_or_eof() {
+        let threshold = 4800 * 2; // 200 ms of 48 kHz stereo
+        let (mut tx, _rx) = spsc_ring(48_000 * 2 * 2);
+        let monitor = tx.monitor();
+        assert!(!prefill_complete(monitor.len(), threshold, false));
+        tx.push_slice(&vec![0.1; threshold - 1]);
+        assert!(!prefill_complete(monitor.len(), threshold, false), "one sample short");
+        tx.push_slice(&[0.1]);
+        assert!(prefill_complete(monitor.len(), threshold, false));
+        // a clip shorter than the prefill starts once it's fully loaded
+        assert!(prefill_complete(100, threshold, true));
+        assert!(prefill_complete(0, 0, false), "prefill disabled");
+    }
+
+    #[test]
+    fn test_gain_ramp_smooths_target_jump() {
+        let frames = 64;
+        let mut ramp = GainRamp::new(1.0);
+        ramp.set_target(2.0, frames);
+
+        let gains: Vec<f32> = (0..frames).map(|_| ramp.next_gain()).collect();
+        let first = gains[0];
+        let last = gains[frames - 1];
+        assert!((first - 1.0).abs() < 0.05, "first gain should stay near the old value: {}", first);
+        assert!((last - 2.0).abs() < 1e-4, "last gain should reach the target: {}", last);
+
+        let max_step = 1.0 / frames as f32 + 1e-5;
+        for pair in gains.windows(2) {
+            let step = pair[1] - pair[0];
+            assert!(step > 0.0 && step <= max_step, "non-smooth step {}", step);
+        }
+    }
+
+    #[test]
+    fn test_bounded_ring_windows_and_overflow() {
+        let mut ring = BoundedRing::with_capacity(4);
+        for s in [1.0, 2.0, 3.0] {
+            ring.push(s);
+        }
+        assert!(ring.pop_window(4).is_none(), "incomplete window must stay queued");
+        assert_eq!(ring.pop_window(2), Some(vec![1.0, 2.0]));
+
+        // overfill: oldest samples are dropped
+        for s in [4.0, 5.0, 6.0, 7.0] {
+            ring.push(s);
+        }
+        assert_eq!(ring.pop_window(4), Some(vec![4.0, 5.0, 6.0, 7.0]));
+        assert!(ring.pop_window(1).is_none());
+    }
+
+    #[test]
+    fn test_mic_channels_are_checked_against_the_input_device() {
+        assert!(check_mic_channels(&[0, 1], 2).is_ok());
+        let err = check_mic_channels(&[0, 2], 2).unwrap_err();
+        assert!(err.to_string().contains("channel 2 not on the input device (2 channels)"), "{}", err);
+    }
+
+    #[test]
+    fn test_loop_cycle_crossfades_tail_into_head() {
+        let samples: Vec<f32> = (0..10).map(|i| i as f32).collect();
+        let (intro_len, cycle) = loop_cycle(&samples, 1, 2);
+
+        assert_eq!(intro_len, 8, "first pass stops before the faded tail");
+        assert_eq!(cycle.len(), 8, "every pass has the same length");
+        // crossfade starts mostly on the tail and ends mostly on the head
+        assert!((cycle[0] - (8.0 * 0.75 + 0.0 * 0.25)).abs() < 1e-6);
+        assert!((cycle[1] - (9.0 * 0.25 + 1.0 * 0.75)).abs() < 1e-6);
+        assert_eq!(&cycle[2..], &samples[2..8]);
+
+        // no crossfade: plain repeat of the whole file
+        let (intro_len, cycle) = loop_cycle(&samples, 1, 0);
+        assert_eq!(intro_len, 10);
+        assert_eq!(cycle, samples);
+    }
+
+    #[test]
+    fn test_read_wav_24bit_full_scale() {
+        let path = std::env::temp_dir().join(format!("adaptive_vol_24bit_{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 24,
+            sample_format: hound::SampleFormat::Int,
+        };
+        {
+            let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+            for s in [(1 << 23) - 1, -(1 << 23), 0, 1 << 22] {
+                writer.write_sample(s).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+
+        let (samples, channels) = read_wav_samples(path.to_str().unwrap(), 48000).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(channels, 1);
+        assert_eq!(samples.len(), 4);
+        assert!((samples[0] - 1.0).abs() < 1e-6, "positive full scale -> {}", samples[0]);
+        assert_eq!(samples[1], -1.0);
+        assert_eq!(samples[2], 0.0);
+        assert!((samples[3] - 0.5).abs() < 1e-6);
+    }
+
+    /// A controller at 8 kHz (400-sample windows) with the default mic flags plus `tweak`
+    fn controller(tweak: impl FnOnce(&mut MicOptions)) -> Controller {
+        let mut mic = MicOptions::default();
+        tweak(&mut mic);
+        Controller::new(ControllerConfig::new(&mic, None), 8000.0)
+    }
+
+    /// A gain adapted to 70 dB of cabin noise, so a held gain is told apart from 0 dB
+    fn adapted_gain() -> AdaptiveGain {
+        let mut ag = AdaptiveGain::new(75.0, 0.1, 0.1, 0.0, -12.0, 12.0, 0.0);
+        ag.set_noise_combine(NoiseCombine::Max);
+        for _ in 0..100 {
+            ag.compute_gain_dt(70.0, 0.0, 0.05);
+        }
+        ag
+    }
+
+    #[test]
+    fn test_output_options_parse_the_environment_strictly() {
+        let vars = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| pairs.iter().find(|(n, _)| *n == name).map(|(_, v)| v.to_string())
+        };
+        let defaults = OutputOptions::from_vars(vars(&[])).unwrap();
+        assert_eq!(defaults.prefill_ms, DEFAULT_PREFILL_MS);
+        assert_eq!(defaults.loop_crossfade_ms, DEFAULT_LOOP_CROSSFADE_MS);
+        assert_eq!(defaults.fade_out_ms, FadeEnvelope::DEFAULT_TRANSPORT_FADE_MS);
+        assert_eq!(defaults.fade_shape, FadeShape::RaisedCosine);
+        assert!(defaults.speed_tilt.is_none());
+
+        let set = OutputOptions::from_vars(vars(&[
+            ("PREFILL_MS", " 50 "),
+            ("SPEED_TILT", "1"),
+            ("SPEED_TILT_MAX_DB", "4"),
+            ("FADE_SHAPE", "Linear"),
+            ("MUTE_FADE_MS", "0"),
+        ]))
+        .unwrap();
+        assert_eq!(set.prefill_ms, 50.0);
+        assert_eq!(set.speed_tilt, Some((SpeedTilt::DEFAULT_SLOPE_DB_PER_KMH, 4.0)));
+        assert_eq!(set.fade_shape, FadeShape::Linear);
+        assert_eq!(set.mute_fade_ms, 0.0);
+
+        for bad in [
+            &[("PREFILL_MS", "abc")][..],
+            &[("FADE_IN_MS", "-5")],
+            &[("LOOP_CROSSFADE_MS", "inf")],
+            &[("SPEED_TILT", "yes")],
+            &[("SPEED_TILT", "1"), ("SPEED_TILT_SLOPE", "steep")],
+            &[("FADE_SHAPE", "square")],
+        ] {
+            assert!(OutputOptions::from_vars(vars(bad)).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_controller_measure_flags_a_dead_mic() {
+        let mut c = controller(|_| {});
+        assert_eq!(c.window_samples(), 400);
+        assert!(c.measure(vec![vec![0.0; 400]], -180.0, None).dropout);
+        // a NaN sample leaves no usable level
+        assert!(c.measure(vec![vec![f32::NAN; 400]], -180.0, None).dropout);
+
+        let noise: Vec<f32> = (0..400).map(|i| ((i * 7919) % 200) as f32 / 1000.0 - 0.1).collect();
+        let reading = c.measure(vec![vec![0.0; 400], noise], -180.0, None);
+        assert!(!reading.dropout && !reading.clipped && !reading.speech, "{:?}", reading);
+        assert!(reading.cabin_db.is_finite() && reading.cabin_db > 0.0, "{:?}", reading);
+
+        let clipped = c.measure(vec![[1.0, -1.0].repeat(200)], -180.0, None);
+        assert!(clipped.clipped, "{:?}", clipped);
+    }
+
+    #[test]
+    fn test_controller_step_holds_through_speech_and_dropouts() {
+        let mut c = controller(|mic| mic.dropout_timeout_s = 0.1);
+        let mut ag = adapted_gain();
+        let held = ag.hold().0;
+        assert!(held > 1.0, "adapted gain {}", held);
+
+        let speech = MicReading { cabin_db: 40.0, speech: true, ..MicReading::default() };
+        let step = c.step_gain(&mut ag, &speech, 50.0, false, 0.05, (0, 0));
+        assert_eq!(step.gain_db, held, "no boost toward a quiet cabin while passengers talk");
+        assert_eq!(c.dropout_hold.state(), HoldState::Live);
+
+        let dead = MicReading { dropout: true, ..MicReading::default() };
+        for _ in 0..2 {
+            assert_eq!(c.step_gain(&mut ag, &dead, 50.0, false, 0.05, (0, 0)).gain_db, held);
+        }
+        assert_eq!(c.dropout_hold.state(), HoldState::Holding);
+        let step = c.step_gain(&mut ag, &dead, 50.0, false, 0.05, (0, 0));
+        assert_eq!((step.gain_db, step.gain_lin), (SAFE_GAIN_DB, db_to_lin(SAFE_GAIN_DB)));
+        assert_eq!(c.dropout_hold.state(), HoldState::TimedOut);
+
+        // a stale speed holds just like a dead mic
+        let live = MicReading { cabin_db: 70.0, ..MicReading::default() };
+        c.step_gain(&mut ag, &live, 50.0, false, 0.05, (0, 0));
+        assert_eq!(c.dropout_hold.state(), HoldState::Live);
+        c.step_gain(&mut ag, &live, 50.0, true, 0.05, (0, 0));
+        assert_eq!(c.dropout_hold.state(), HoldState::Holding);
+    }
+
+    #[test]
+    fn test_controller_step_backs_off_while_the_output_clips() {
+        let mut c = controller(|_| {});
+        let mut ag = adapted_gain();
+        let held = ag.hold().0;
+        let speech = MicReading { cabin_db: 70.0, speech: true, ..MicReading::default() };
+
+        // 10% of each window over the limiter threshold
+        let mut trim_db = 0.0;
+        for tick in 1..=3 {
+            let step = c.step_gain(&mut ag, &speech, 0.0, false, 0.05, (100 * tick, 1000 * tick));
+            assert!(step.trim_db < trim_db, "tick {}: {:?}", tick, step);
+            assert!((step.gain_db - (held + step.trim_db)).abs() < 1e-5, "{:?}", step);
+            assert!((step.gain_lin - db_to_lin(step.gain_db)).abs() < 1e-5, "{:?}", step);
+            trim_db = step.trim_db;
+        }
+        // clean output: the trim recovers
+        let step = c.step_gain(&mut ag, &speech, 0.0, false, 0.05, (300, 4000));
+        assert!(step.trim_db > trim_db, "{:?}", step);
+
+        let mut off = controller(|mic| mic.clip_backoff = false);
+        assert_eq!(off.step_gain(&mut ag, &speech, 0.0, false, 0.05, (300, 1000)).trim_db, 0.0);
+    }
+
+    #[test]
+    fn test_configure_renderer_adds_only_the_enabled_stages() {
+        let settings = Settings { config: Config::default(), profile: None, night: false };
+        let mut output = OutputOptions::from_vars(|_| None).unwrap();
+        let stop: &'static AtomicBool = Box::leak(Box::new(AtomicBool::new(false)));
+        let mut shared = SharedState {
+            gain_lin: Arc::new(AtomicF32::new(1.0)),
+            speed: Arc::new(SharedSpeed::new()),
+            smoothed_speed: Arc::new(AtomicF32::new(0.0)),
+            band_gains_db: None,
+            output_level_db: None,
+            stats: Arc::new(PlaybackStats::default()),
+            level_meter: Arc::new(Meter::new()),
+            control: None,
+            metrics: None,
+            stop,
+        };
+
+        let plain = configure_renderer(renderer(&[]), &settings, &output, &shared, None, 1000).unwrap();
+        assert!(plain.multiband.is_none() && plain.output_meter.is_none() && plain.speed_tilt.is_none());
+        assert!(plain.mute.is_none() && plain.compressor.is_none() && plain.metrics.is_none());
+        assert!(plain.transport_fade.is_some() && plain.level_meter.is_some());
+
+        output.speed_tilt = Some((0.05, 6.0));
+        shared.control = Some(Arc::new(ControlState::new(0.0)));
+        shared.output_level_db = Some(Arc::new(AtomicF32::new(-180.0)));
+        shared.band_gains_db = Some(Arc::new(std::array::from_fn(|_| AtomicF32::new(0.0))));
+        let full = configure_renderer(renderer(&[]), &settings, &output, &shared, None, 48_000).unwrap();
+        assert!(full.multiband.is_some() && full.output_meter.is_some() && full.speed_tilt.is_some());
+        assert!(full.mute.is_some());
+    }
+}
@@ -0,0 +1,57 @@
+// `telemetry`: print the embedded app's gain frames from its USART as CSV
+use std::io::{self, Read, Write};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::info;
+
+use adaptive_vol::serial::open_serial;
+use adaptive_vol::telemetry::FrameParser;
+use adaptive_vol::util::install_ctrlc_handler;
+
+use crate::args::TelemetryArgs;
+
+// Baud rate of the embedded app's debug USART
+const DEFAULT_BAUD: u32 = 115_200;
+
+pub fn run(args: &TelemetryArgs) -> Result<()> {
+    let baud = std::env::var("TELEMETRY_BAUD").ok().and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_BAUD);
+    let mut port = open_serial(&args.port, baud, Duration::from_millis(200))
+        .with_context(|| format!("opening telemetry port {}", args.port))?;
+    info!("Reading telemetry from {} @ {} baud (Ctrl-C to stop)", args.port, baud);
+
+    let stop = install_ctrlc_handler();
+    let mut parser = FrameParser::new();
+    let mut last_seq: Option<u8> = None;
+    let mut lost = 0u64;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    writeln!(out, "seq,smoothed_level,target_gain,gain_db")?;
+    let mut buf = [0u8; 256];
+    while !stop.load(Ordering::Relaxed) {
+        let n = match port.read(&mut buf) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).context("reading telemetry"),
+        };
+        for frame in buf[..n].iter().filter_map(|&b| parser.push(b)) {
+            // sequence gaps are frames dropped by the board (TX busy) or corrupted on the wire
+            if let Some(prev) = last_seq {
+                lost += frame.seq.wrapping_sub(prev).wrapping_sub(1) as u64;
+            }
+            last_seq = Some(frame.seq);
+            writeln!(
+                out,
+                "{},{:.2},{:.4},{:.2}",
+                frame.seq,
+                frame.smoothed_level,
+                frame.target_gain,
+                20.0 * frame.target_gain.max(1e-6).log10()
+            )?;
+        }
+        out.flush()?;
+    }
+    info!("Lost frames: {}", lost);
+    Ok(())
+}
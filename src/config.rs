@@ -0,0 +1,321 @@
+//! Tuning constants loaded from `config.toml`, so the gain law can be retuned without recompiling:
+//!
+//! ```toml
+//! target_db = 78.0       # L_DESIRED_DB
+//! tau_release_s = 2.0
+//! max_gain_db = 18.0
+//! ```
+//!
+//! Every field is optional and falls back to the built-in constant. Only the flat `key = value` subset
+//! of TOML is read (numbers, booleans, strings, single-line arrays, `#` comments, `[table]` headers).
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+
+use crate::adaptive_gain::{Smoother, BASE_NOISE_DB, GAIN_SENSITIVITY, L_DESIRED_DB, USER_OFFSET_DB};
+use crate::controller::{PidGainController, PidGains};
+use crate::gain::AdaptiveGain;
+
+/// Config file read when no `--config <path>` is given
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// `--night`: how far the target level drops (dB) and the gain ceiling (dB)
+pub const NIGHT_TARGET_REDUCTION_DB: f32 = 6.0;
+pub const NIGHT_MAX_GAIN_DB: f32 = 6.0;
+
+/// Bounds of `AdaptiveGain` and the PID controller (dB) for a side the file leaves unset. The chunked
+/// players' ±24 dB are wider, so bounds valid here are valid there too.
+pub const CONTROLLER_GAIN_BOUNDS_DB: (f32, f32) = (-12.0, 12.0);
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Target perceived playback level (dB)
+    pub target_db: f32,
+    /// User volume offset on top of the computed gain (dB)
+    pub user_offset_db: f32,
+    /// Reference cabin noise for the sensitivity gain law (dB)
+    pub base_noise_db: f32,
+    /// dB of playback gain per dB of noise above `base_noise_db`
+    pub gain_sensitivity: f32,
+    /// Smoother time constant while gain rises (s)
+    pub tau_attack_s: f32,
+    /// Smoother time constant while gain falls (s)
+    pub tau_release_s: f32,
+    /// Gain clamp (dB). `None` keeps the binary's own bound: ±24 dB in the chunked players,
+    /// ±12 dB in `AdaptiveGain`.
+    pub min_gain_db: Option<f32>,
+    pub max_gain_db: Option<f32>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            target_db: L_DESIRED_DB,
+            user_offset_db: USER_OFFSET_DB,
+            base_noise_db: BASE_NOISE_DB,
+            gain_sensitivity: GAIN_SENSITIVITY,
+            tau_attack_s: 0.1,
+            tau_release_s: 1.0,
+            min_gain_db: None,
+            max_gain_db: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load `path`, or the defaults if it doesn't exist
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading config {}", path.display()))?;
+        Self::from_toml(&text).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let config: Config = serde_json::from_value(Value::Object(parse_toml(text)?))?;
+        // checked with the defaults filled in: `min_gain_db = 15` alone is above the default ceiling
+        let (min, max) = config.gain_bounds_db(CONTROLLER_GAIN_BOUNDS_DB);
+        if min > max {
+            bail!("min_gain_db ({}) is above max_gain_db ({})", min, max);
+        }
+        Ok(config)
+    }
+
+    /// Load an explicitly named file, which has to exist, else `config.toml` in the working directory
+    /// if there is one
+    pub fn load_or_default(path: Option<&str>) -> Result<Self> {
+        match path {
+            Some(path) if !Path::new(path).exists() => bail!("config file {} not found", path),
+            Some(path) => Self::load(path),
+            None => Self::load(DEFAULT_CONFIG_PATH),
+        }
+    }
+
+    /// The night-mode bundle on top of this config: the target `NIGHT_TARGET_REDUCTION_DB` lower and
+    /// the gain capped at `NIGHT_MAX_GAIN_DB` (or a lower ceiling the file already sets). The output
+    /// compressor that goes with it is `dynamics::Compressor::night`.
+    pub fn night(mut self) -> Self {
+        self.target_db -= NIGHT_TARGET_REDUCTION_DB;
+        let max_gain_db = self.max_gain_db.map_or(NIGHT_MAX_GAIN_DB, |max| max.min(NIGHT_MAX_GAIN_DB));
+        self.max_gain_db = Some(max_gain_db);
+        self.min_gain_db = self.min_gain_db.map(|min| min.min(max_gain_db));
+        self
+    }
+
+    /// Clamp bounds, falling back to `default` for any side the file leaves unset
+    pub fn gain_bounds_db(&self, default: (f32, f32)) -> (f32, f32) {
+        (self.min_gain_db.unwrap_or(default.0), self.max_gain_db.unwrap_or(default.1))
+    }
+
+    /// Gain smoother starting at 0 dB with the configured time constants
+    pub fn smoother(&self) -> Smoother {
+        Smoother::new(0.0, self.tau_attack_s, self.tau_release_s)
+    }
+
+    /// `AdaptiveGain` with the configured target, offset, time constants and bounds
+    pub fn adaptive_gain(&self) -> AdaptiveGain {
+        let (min_gain_db, max_gain_db) = self.gain_bounds_db(CONTROLLER_GAIN_BOUNDS_DB);
+        AdaptiveGain::builder()
+            .target_db(self.target_db)
+            .user_offset_db(self.user_offset_db)
+            .tau_attack(self.tau_attack_s)
+            .tau_release(self.tau_release_s)
+            .min_gain_db(min_gain_db)
+            .max_gain_db(max_gain_db)
+            .build()
+    }
+
+    /// `PidGainController` with the configured target, offset and bounds (the time constants
+    /// don't apply: its dynamics come from `gains`)
+    pub fn pid_controller(&self, gains: PidGains) -> PidGainController {
+        let mut pid = PidGainController::new(self.target_db, gains);
+        pid.set_user_offset_db(self.user_offset_db);
+        let (min_gain_db, max_gain_db) = self.gain_bounds_db(CONTROLLER_GAIN_BOUNDS_DB);
+        pid.set_bounds_db(min_gain_db, max_gain_db);
+        pid
+    }
+}
+
+/// Parse the flat TOML subset into a JSON object (tables become nested objects) for serde
+fn parse_toml(text: &str) -> Result<Map<String, Value>> {
+    let mut root = Map::new();
+    let mut table: Option<String> = None;
+    for (n, raw) in text.lines().enumerate() {
+        let line = strip_comment(raw).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let line_no = n + 1;
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let name = name.trim();
+            if name.is_empty() || name.contains(['.', '[', ']']) {
+                bail!("line {}: unsupported table header [{}]", line_no, name);
+            }
+            root.entry(name.to_string()).or_insert_with(|| Value::Object(Map::new()));
+            table = Some(name.to_string());
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("line {}: expected `key = value`", line_no);
+        };
+        let key = key.trim().trim_matches('"');
+        if key.is_empty() || key.contains('.') {
+            bail!("line {}: unsupported key '{}'", line_no, key);
+        }
+        let value = parse_value(value.trim()).with_context(|| format!("line {}: bad value for '{}'", line_no, key))?;
+        let target = match &table {
+            Some(name) => root.get_mut(name).and_then(Value::as_object_mut).expect("table inserted with its header"),
+            None => &mut root,
+        };
+        if target.insert(key.to_string(), value).is_some() {
+            bail!("line {}: duplicate key '{}'", line_no, key);
+        }
+    }
+    Ok(root)
+}
+
+/// Drop a trailing `# comment`, leaving `#` inside strings alone
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '#') => return &line[..i],
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(text: &str) -> Result<Value> {
+    match text {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+    if let Some(s) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        return Ok(Value::String(unescape(s)?));
+    }
+    if let Some(s) = text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        return Ok(Value::String(s.to_string()));
+    }
+    if let Some(items) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        let items = items.trim().trim_end_matches(',');
+        if items.trim().is_empty() {
+            return Ok(Value::Array(Vec::new()));
+        }
+        // nested arrays and commas inside strings aren't part of the subset
+        return items.split(',').map(|item| parse_value(item.trim())).collect::<Result<Vec<_>>>().map(Value::Array);
+    }
+    let digits = text.replace('_', "");
+    if let Ok(i) = digits.parse::<i64>() {
+        return Ok(Value::Number(i.into()));
+    }
+    match digits.parse::<f64>().ok().and_then(Number::from_f64) {
+        Some(n) => Ok(Value::Number(n)),
+        None => bail!("unrecognised value `{}`", text),
+    }
+}
+
+fn unescape(s: &str) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            other => bail!("unsupported escape \\{}", other.map(String::from).unwrap_or_default()),
+        });
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_toml_overrides_only_given_fields() {
+        let config = Config::from_toml(
+            "# quieter cabin, slower release\n\
+             target_db = 72\n\
+             tau_release_s = 2.5   # seconds\n\
+             max_gain_db = 18.0\n",
+        )
+        .unwrap();
+        let expected = Config { target_db: 72.0, tau_release_s: 2.5, max_gain_db: Some(18.0), ..Config::default() };
+        assert_eq!(config, expected);
+        assert_eq!(config.gain_bounds_db((-24.0, 24.0)), (-24.0, 18.0));
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_rejects_typos_and_bad_values() {
+        assert!(Config::from_toml("target_dB = 70").is_err(), "unknown keys are reported");
+        assert!(Config::from_toml("target_db = loud").is_err());
+        assert!(Config::from_toml("min_gain_db = 6\nmax_gain_db = -6").is_err());
+        assert!(Config::from_toml("target_db = 70\ntarget_db = 71").is_err());
+    }
+
+    #[test]
+    fn test_one_sided_bounds_are_checked_against_the_defaults() {
+        // each is above/below the other side's default, which used to panic when the gain was built
+        let err = Config::from_toml("min_gain_db = 15").unwrap_err();
+        assert!(err.to_string().contains("above max_gain_db (12)"), "{}", err);
+        assert!(Config::from_toml("max_gain_db = -15").is_err());
+
+        let config = Config::from_toml("min_gain_db = 6").unwrap();
+        assert_eq!(config.gain_bounds_db(CONTROLLER_GAIN_BOUNDS_DB), (6.0, 12.0));
+        config.adaptive_gain();
+        config.pid_controller(PidGains::default());
+        Config::from_toml("max_gain_db = -6").unwrap().pid_controller(PidGains::default());
+    }
+
+    #[test]
+    fn test_parse_toml_subset() {
+        let map = parse_toml("name = \"sedan # 1\"\n[mic]\nweights = [1, 0.5, 2_000]\nenabled = true").unwrap();
+        assert_eq!(map["name"], "sedan # 1");
+        assert_eq!(map["mic"]["weights"], serde_json::json!([1, 0.5, 2000]));
+        assert_eq!(map["mic"]["enabled"], true);
+    }
+
+    #[test]
+    fn test_night_mode_gain_is_below_normal() {
+        let normal = Config::default();
+        let night = normal.night();
+        assert_eq!(night.target_db, normal.target_db - NIGHT_TARGET_REDUCTION_DB);
+        assert_eq!(night.gain_bounds_db((-24.0, 24.0)), (-24.0, NIGHT_MAX_GAIN_DB));
+        for cabin_db in [55.0, 70.0, 85.0] {
+            let (mut normal_gain, mut night_gain) = (normal.adaptive_gain(), night.adaptive_gain());
+            let (mut normal_db, mut night_db) = (0.0, 0.0);
+            for _ in 0..100 {
+                normal_db = normal_gain.compute_gain_dt(cabin_db, 100.0, 0.1).0;
+                night_db = night_gain.compute_gain_dt(cabin_db, 100.0, 0.1).0;
+            }
+            assert!(night_db < normal_db, "{} dB cabin: night {} dB, normal {} dB", cabin_db, night_db, normal_db);
+            assert!(night_db <= NIGHT_MAX_GAIN_DB + 1e-3);
+        }
+        // a stricter ceiling from the file is kept
+        let quiet = Config { max_gain_db: Some(3.0), min_gain_db: Some(4.0), ..Config::default() }.night();
+        assert_eq!(quiet.gain_bounds_db((-24.0, 24.0)), (3.0, 3.0));
+    }
+
+    #[test]
+    fn test_missing_file_uses_defaults() {
+        assert_eq!(Config::load("does/not/exist.toml").unwrap(), Config::default());
+    }
+}
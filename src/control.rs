@@ -0,0 +1,302 @@
+//! Remote volume knob: a tiny HTTP server so a phone on the car's network can nudge the user offset
+//! and read back what the controller is doing. Written against std like `ws`; one request per
+//! connection, which is all a knob needs. Each connection gets its own short-lived thread, so one
+//! slow client can't hold up the others.
+//!
+//! ```text
+//! POST /offset {"db": 3.0}   200 {"offset_db":3.0}; 400 for bad JSON or outside ±MAX_OFFSET_DB
+//! POST /mute, POST /unmute   200 {"muted":true|false}; the output fades rather than cuts
+//! GET  /status               200 {"cabin_db":..,"speed_kmh":..,"gain_db":..,"offset_db":..,"muted":..}
+//! ```
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::warn;
+
+use crate::util::{spawn_named, AtomicF32};
+
+/// Largest offset (dB) accepted either way
+pub const MAX_OFFSET_DB: f32 = 12.0;
+/// Request bodies are a few bytes of JSON; anything bigger is refused
+const MAX_BODY_LEN: usize = 4096;
+/// Request line plus headers; a phone's request is a few hundred bytes
+const MAX_HEAD_LEN: u64 = 8192;
+/// Connections served at once; more are closed unanswered until one finishes
+const MAX_CONNECTIONS: usize = 8;
+/// A client gets this long to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often the accept loop checks `stop` while idle
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+
+/// The offset and mute set remotely, and the controller's latest readings for `/status`
+#[derive(Debug, Default)]
+pub struct ControlState {
+    offset_db: AtomicF32,
+    /// Shared with the output callback, which fades to silence while it is set
+    muted: Arc<AtomicBool>,
+    cabin_db: AtomicF32,
+    speed_kmh: AtomicF32,
+    gain_db: AtomicF32,
+}
+
+impl ControlState {
+    /// Start from the configured user offset
+    pub fn new(offset_db: f32) -> Self {
+        let state = Self::default();
+        state.offset_db.store(offset_db, Ordering::Relaxed);
+        state
+    }
+
+    /// User offset (dB) the controller should use
+    pub fn offset_db(&self) -> f32 {
+        self.offset_db.load(Ordering::Relaxed)
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    /// The mute flag, for the output callback to follow
+    pub fn mute_flag(&self) -> Arc<AtomicBool> {
+        self.muted.clone()
+    }
+
+    /// Record one controller step for `/status`
+    pub fn publish(&self, cabin_db: f32, speed_kmh: f32, gain_db: f32) {
+        self.cabin_db.store(cabin_db, Ordering::Relaxed);
+        self.speed_kmh.store(speed_kmh, Ordering::Relaxed);
+        self.gain_db.store(gain_db, Ordering::Relaxed);
+    }
+
+    fn status_json(&self) -> String {
+        serde_json::json!({
+            "cabin_db": self.cabin_db.load(Ordering::Relaxed),
+            "speed_kmh": self.speed_kmh.load(Ordering::Relaxed),
+            "gain_db": self.gain_db.load(Ordering::Relaxed),
+            "offset_db": self.offset_db(),
+            "muted": self.is_muted(),
+        })
+        .to_string()
+    }
+}
+
+fn error_json(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+/// Route one request; returns the status code and a JSON body
+pub fn handle_request(method: &str, path: &str, body: &[u8], state: &ControlState) -> (u16, String) {
+    let path = path.split('?').next().unwrap_or(path);
+    match (method, path) {
+        ("GET", "/status") => (200, state.status_json()),
+        ("POST", "/offset") => {
+            let db = serde_json::from_slice::<serde_json::Value>(body)
+                .ok()
+                .and_then(|json| json.get("db")?.as_f64())
+                .map(|db| db as f32);
+            match db {
+                Some(db) if db.is_finite() && db.abs() <= MAX_OFFSET_DB => {
+                    state.offset_db.store(db, Ordering::Relaxed);
+                    (200, serde_json::json!({ "offset_db": db }).to_string())
+                }
+                Some(db) => (400, error_json(&format!("offset {} dB is outside ±{} dB", db, MAX_OFFSET_DB))),
+                None => (400, error_json("expected a JSON body like {\"db\": 3.0}")),
+            }
+        }
+        ("POST", "/mute" | "/unmute") => {
+            let muted = path == "/mute";
+            state.muted.store(muted, Ordering::Relaxed);
+            (200, serde_json::json!({ "muted": muted }).to_string())
+        }
+        (_, "/status" | "/offset" | "/mute" | "/unmute") => (405, error_json("method not allowed")),
+        _ => (404, error_json("not found")),
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Error",
+    }
+}
+
+/// Read one HTTP/1.1 request from `stream`, answer it and close
+fn serve_connection(stream: TcpStream, state: &ControlState) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    // the request line and headers are read through `take`, so a line that never ends can't grow
+    // past MAX_HEAD_LEN
+    let mut head = BufReader::new(stream.try_clone()?).take(MAX_HEAD_LEN);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+    let mut content_length = 0usize;
+    let mut head_ended = false;
+    loop {
+        let mut header = String::new();
+        if head.read_line(&mut header)? == 0 {
+            break;
+        }
+        if header.trim().is_empty() {
+            head_ended = true;
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let head_too_long = !head_ended && head.limit() == 0;
+    let mut reader = head.into_inner();
+
+    let (status, body) = if head_too_long {
+        (431, error_json("request head too large"))
+    } else if content_length > MAX_BODY_LEN {
+        (413, error_json("request body too large"))
+    } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        handle_request(method, path, &body, state)
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason(status),
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Listens for control requests and serves them on its own thread
+pub struct ControlServer {
+    listener: TcpListener,
+}
+
+impl ControlServer {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        // polled so the thread notices `stop` between connections
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve requests against `state` until `stop` is set, each connection on its own thread
+    pub fn spawn(self, state: Arc<ControlState>, stop: &'static AtomicBool) -> JoinHandle<()> {
+        spawn_named("control", move || {
+            let open = Arc::new(AtomicUsize::new(0));
+            while !stop.load(Ordering::Relaxed) {
+                match self.listener.accept() {
+                    Ok((stream, peer)) => {
+                        if open.load(Ordering::Relaxed) >= MAX_CONNECTIONS {
+                            warn!("[Control] {} connections already open; dropping {}", MAX_CONNECTIONS, peer);
+                            continue;
+                        }
+                        open.fetch_add(1, Ordering::Relaxed);
+                        let (state, open) = (state.clone(), open.clone());
+                        spawn_named("control-conn", move || {
+                            let served = stream.set_nonblocking(false).and_then(|_| serve_connection(stream, &state));
+                            if let Err(e) = served {
+                                warn!("[Control] request from {} failed: {}", peer, e);
+                            }
+                            open.fetch_sub(1, Ordering::Relaxed);
+                        });
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL),
+                    Err(e) => {
+                        warn!("[Control] accept failed: {}", e);
+                        thread::sleep(ACCEPT_POLL);
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_is_validated_and_status_reports_it() {
+        let state = ControlState::new(0.0);
+        assert_eq!(handle_request("POST", "/offset", br#"{"db": 3.5}"#, &state).0, 200);
+        assert_eq!(state.offset_db(), 3.5);
+        for bad in [&br#"{"db": 40}"#[..], br#"{"db": "loud"}"#, b"3.0", b""] {
+            assert_eq!(handle_request("POST", "/offset", bad, &state).0, 400, "{:?}", String::from_utf8_lossy(bad));
+        }
+        assert_eq!(state.offset_db(), 3.5, "rejected requests leave the offset alone");
+        assert_eq!(handle_request("GET", "/offset", b"", &state).0, 405);
+        assert_eq!(handle_request("GET", "/volume", b"", &state).0, 404);
+
+        state.publish(68.0, 90.0, 4.25);
+        let (status, body) = handle_request("GET", "/status?verbose=1", b"", &state);
+        assert_eq!(status, 200);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["gain_db"], 4.25);
+        assert_eq!(json["offset_db"], 3.5);
+
+        let flag = state.mute_flag();
+        assert_eq!(handle_request("POST", "/mute", b"", &state), (200, r#"{"muted":true}"#.to_string()));
+        assert!(flag.load(Ordering::Relaxed), "the output callback sees the mute");
+        assert_eq!(handle_request("GET", "/mute", b"", &state).0, 405);
+        assert_eq!(handle_request("POST", "/unmute", b"", &state).0, 200);
+        assert!(!state.is_muted());
+    }
+
+    #[test]
+    fn test_server_answers_over_tcp() {
+        let server = ControlServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let state = Arc::new(ControlState::new(0.0));
+        let stop: &'static AtomicBool = Box::leak(Box::new(AtomicBool::new(false)));
+        let handle = server.spawn(state.clone(), stop);
+
+        let request = |text: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(text.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let body = r#"{"db": -2}"#;
+        let response = request(&format!("POST /offset HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}", body.len(), body));
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert_eq!(state.offset_db(), -2.0);
+        let response = request("POST /offset HTTP/1.1\r\nContent-Length: 11\r\n\r\n{\"db\": 99}\n");
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+        assert!(request("GET /status HTTP/1.1\r\n\r\n").ends_with(&state.status_json()));
+
+        // a client that stalls mid-request doesn't hold up the next one
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        stalled.write_all(b"GET /sta").unwrap();
+        let started = std::time::Instant::now();
+        assert!(request("GET /status HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 200 OK"));
+        assert!(started.elapsed() < READ_TIMEOUT, "waited {:?} behind the stalled client", started.elapsed());
+        // and a line that never ends is cut off rather than buffered
+        let endless = format!("GET /{}", "a".repeat(MAX_HEAD_LEN as usize - 5));
+        assert!(request(&endless).starts_with("HTTP/1.1 431"));
+        drop(stalled);
+
+        stop.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+}
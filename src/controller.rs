@@ -0,0 +1,287 @@
+//! Gain control strategies behind one interface, so the direct map (`AdaptiveGain`) and
+//! alternatives like the PID regulator below can be swapped and compared on the same input.
+
+use std::time::Instant;
+
+use crate::adaptive_gain::{db_to_lin, NoiseCombine, NoiseModel};
+use crate::gain::AdaptiveGain;
+
+/// Turns a cabin noise reading and the vehicle speed into a playback gain
+pub trait GainController {
+    /// Gain for the current cabin noise (dB) and speed (km/h), as (dB, linear), advancing
+    /// smoothing by the wall-clock time since the last call
+    fn compute_gain(&mut self, cabin_db: f32, speed_kmh: f32) -> (f32, f32);
+
+    /// Like `compute_gain`, but advances by `dt` seconds of simulated time (offline processing,
+    /// replayed traces)
+    fn compute_gain_dt(&mut self, cabin_db: f32, speed_kmh: f32, dt: f32) -> (f32, f32);
+
+    /// Keep the current gain without adapting to the input (speech in the cabin, see `Vad`)
+    fn hold(&mut self) -> (f32, f32);
+
+    /// Change the user's volume offset (dB); the gain follows through the controller's own
+    /// smoothing rather than stepping
+    fn set_user_offset_db(&mut self, db: f32);
+}
+
+impl GainController for AdaptiveGain {
+    fn compute_gain(&mut self, cabin_db: f32, speed_kmh: f32) -> (f32, f32) {
+        AdaptiveGain::compute_gain(self, cabin_db, speed_kmh)
+    }
+
+    fn compute_gain_dt(&mut self, cabin_db: f32, speed_kmh: f32, dt: f32) -> (f32, f32) {
+        AdaptiveGain::compute_gain_dt(self, cabin_db, speed_kmh, dt)
+    }
+
+    fn hold(&mut self) -> (f32, f32) {
+        AdaptiveGain::hold(self)
+    }
+
+    fn set_user_offset_db(&mut self, db: f32) {
+        AdaptiveGain::set_user_offset_db(self, db)
+    }
+}
+
+/// Which `GainController` the binaries run (`--controller direct|pid`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ControllerKind {
+    /// `AdaptiveGain`: gain = target - noise, smoothed
+    #[default]
+    Direct,
+    /// `PidGainController` with the default `PidGains`
+    Pid,
+}
+
+impl std::str::FromStr for ControllerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "direct" => Ok(ControllerKind::Direct),
+            "pid" => Ok(ControllerKind::Pid),
+            other => Err(format!("unknown controller '{}' (expected direct or pid)", other)),
+        }
+    }
+}
+
+/// PID coefficients. The error is in dB and the output is a gain in dB, so `kp` is
+/// dimensionless, `ki` is per second and `kd` is in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PidGains {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+impl Default for PidGains {
+    /// Settles a step within a few seconds at a 50 ms update rate
+    fn default() -> Self {
+        Self { kp: 0.3, ki: 2.0, kd: 0.0 }
+    }
+}
+
+/// Regulates the estimated playback level toward the target instead of mapping noise to gain
+/// directly.
+///
+/// The estimated playback level is taken as the applied gain plus the noise it has to rise above
+/// (the same model `AdaptiveGain` inverts), so the error is `target + offset - noise - gain`.
+/// The integral term removes any steady-state offset; the derivative acts on the estimate rather
+/// than the error so target changes don't kick the output. Anti-windup: the output is clamped to
+/// the gain bounds and the integral stops accumulating while the output is saturated in the
+/// direction of the error.
+pub struct PidGainController {
+    gains: PidGains,
+    target_db: f32,
+    user_offset_db: f32,
+    min_gain_db: f32,
+    max_gain_db: f32,
+    noise_combine: NoiseCombine,
+    noise_model: NoiseModel,
+    gain_db: f32,
+    integral: f32,
+    prev_level_db: Option<f32>,
+    last_update: Instant,
+    /// Longest gap one update integrates over (seconds); longer pauses count as this much
+    max_dt: f32,
+}
+
+impl PidGainController {
+    pub fn new(target_db: f32, gains: PidGains) -> Self {
+        Self {
+            gains,
+            target_db,
+            user_offset_db: 0.0,
+            min_gain_db: -12.0,
+            max_gain_db: 12.0,
+            noise_combine: NoiseCombine::default(),
+            noise_model: NoiseModel::default(),
+            gain_db: 0.0,
+            integral: 0.0,
+            prev_level_db: None,
+            last_update: Instant::now(),
+            max_dt: Self::DEFAULT_MAX_DT,
+        }
+    }
+
+    /// Same cap as `Smoother::DEFAULT_MAX_DT`
+    pub const DEFAULT_MAX_DT: f32 = 0.1;
+
+    /// Output bounds (dB); same defaults as `AdaptiveGain`
+    pub fn set_bounds_db(&mut self, min_gain_db: f32, max_gain_db: f32) {
+        assert!(min_gain_db <= max_gain_db, "min_gain_db must not exceed max_gain_db");
+        self.min_gain_db = min_gain_db;
+        self.max_gain_db = max_gain_db;
+    }
+
+    pub fn set_user_offset_db(&mut self, db: f32) {
+        self.user_offset_db = db;
+    }
+
+    pub fn set_noise_combine(&mut self, noise_combine: NoiseCombine) {
+        self.noise_combine = noise_combine;
+    }
+
+    pub fn set_noise_model(&mut self, noise_model: NoiseModel) {
+        self.noise_model = noise_model;
+    }
+}
+
+impl GainController for PidGainController {
+    fn compute_gain(&mut self, cabin_db: f32, speed_kmh: f32) -> (f32, f32) {
+        let now = Instant::now();
+        let dt = (now - self.last_update).as_secs_f32();
+        self.last_update = now;
+        self.compute_gain_dt(cabin_db, speed_kmh, dt)
+    }
+
+    fn compute_gain_dt(&mut self, cabin_db: f32, speed_kmh: f32, dt: f32) -> (f32, f32) {
+        // no measurement or a non-finite reading is a dropout: nothing to integrate, keep the gain
+        if dt <= 0.0 || !(core_dsp::is_measurement(cabin_db) && speed_kmh.is_finite() && dt.is_finite()) {
+            return (self.gain_db, db_to_lin(self.gain_db));
+        }
+        // a stall (a blocked thread, a suspended laptop) must not land as one huge integral step
+        let dt = dt.min(self.max_dt);
+        let noise_db = self.noise_combine.combine(cabin_db, self.noise_model.noise_db(speed_kmh));
+        let level_db = self.gain_db + noise_db;
+        let error = self.target_db + self.user_offset_db - level_db;
+        let derivative = match self.prev_level_db {
+            Some(prev) => -(level_db - prev) / dt,
+            None => 0.0,
+        };
+        self.prev_level_db = Some(level_db);
+
+        let integral = self.integral + error * dt;
+        let unclamped = self.gains.kp * error + self.gains.ki * integral + self.gains.kd * derivative;
+        // anti-windup: only integrate while that doesn't push further into saturation
+        let saturated = (unclamped > self.max_gain_db && error > 0.0) || (unclamped < self.min_gain_db && error < 0.0);
+        if !saturated {
+            self.integral = integral;
+        }
+        self.gain_db = unclamped.clamp(self.min_gain_db, self.max_gain_db);
+        debug_assert!(self.gain_db.is_finite(), "non-finite gain");
+        (self.gain_db, db_to_lin(self.gain_db))
+    }
+
+    fn hold(&mut self) -> (f32, f32) {
+        // the integral and the derivative's reference are frozen along with the output
+        self.last_update = Instant::now();
+        (self.gain_db, db_to_lin(self.gain_db))
+    }
+
+    fn set_user_offset_db(&mut self, db: f32) {
+        PidGainController::set_user_offset_db(self, db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.05;
+
+    fn pid() -> PidGainController {
+        let mut pid = PidGainController::new(75.0, PidGains::default());
+        pid.set_noise_combine(NoiseCombine::Max);
+        pid.set_bounds_db(-24.0, 24.0);
+        pid
+    }
+
+    #[test]
+    fn test_pid_settles_without_steady_state_offset() {
+        let mut pid = pid();
+        let mut gain_db = 0.0;
+        for _ in 0..400 {
+            gain_db = pid.compute_gain_dt(68.0, 0.0, DT).0;
+        }
+        // the direct map gives target - noise = 7 dB; the integral term must land exactly there
+        assert!((gain_db - 7.0).abs() < 0.01, "settled at {} dB", gain_db);
+
+        pid.set_user_offset_db(-3.0);
+        for _ in 0..400 {
+            gain_db = pid.compute_gain_dt(68.0, 0.0, DT).0;
+        }
+        assert!((gain_db - 4.0).abs() < 0.01, "settled at {} dB after the offset", gain_db);
+    }
+
+    #[test]
+    fn test_pid_anti_windup_recovers_quickly_from_saturation() {
+        let mut pid = pid();
+        // 10 s of silence asks for +55 dB; the output sits at the +24 dB bound
+        for _ in 0..200 {
+            assert!(pid.compute_gain_dt(20.0, 0.0, DT).0 <= 24.0);
+        }
+        // then loud noise: without anti-windup the integral would hold the gain up for many seconds
+        let mut steps = 0;
+        while pid.compute_gain_dt(85.0, 0.0, DT).0 > -9.0 {
+            steps += 1;
+            assert!(steps < 100, "still above -9 dB after {} s", steps as f32 * DT);
+        }
+    }
+
+    #[test]
+    fn test_pid_ignores_non_finite_cabin_db() {
+        let mut pid = pid();
+        let mut gain_db = 0.0;
+        for _ in 0..100 {
+            gain_db = pid.compute_gain_dt(68.0, 0.0, DT).0;
+        }
+        for poison in [f32::NAN, f32::NEG_INFINITY, f32::INFINITY] {
+            let (held_db, held_lin) = pid.compute_gain_dt(poison, 0.0, DT);
+            assert_eq!(held_db, gain_db, "cabin_db {} moved the gain", poison);
+            assert!(held_lin.is_finite());
+        }
+        // the integral wasn't poisoned either
+        assert!(pid.compute_gain_dt(68.0, 0.0, DT).0.is_finite());
+    }
+
+    #[test]
+    fn test_pid_caps_a_long_gap_at_max_dt() {
+        let (mut stalled, mut capped) = (pid(), pid());
+        for _ in 0..20 {
+            stalled.compute_gain_dt(68.0, 0.0, DT);
+            capped.compute_gain_dt(68.0, 0.0, DT);
+        }
+        // 30 s without an update integrates like one max_dt step, not 30 s of error at once
+        let after_gap = stalled.compute_gain_dt(50.0, 0.0, 30.0).0;
+        let one_step = capped.compute_gain_dt(50.0, 0.0, PidGainController::DEFAULT_MAX_DT).0;
+        assert_eq!(after_gap, one_step);
+        assert!(after_gap < 24.0, "jumped to the bound: {} dB", after_gap);
+    }
+
+    #[test]
+    fn test_controller_kind_parses() {
+        assert_eq!("PID".parse(), Ok(ControllerKind::Pid));
+        assert_eq!("direct".parse(), Ok(ControllerKind::Direct));
+        assert!("fuzzy".parse::<ControllerKind>().is_err());
+    }
+
+    #[test]
+    fn test_controllers_are_interchangeable() {
+        let mut controllers: Vec<Box<dyn GainController>> =
+            vec![Box::new(AdaptiveGain::default()), Box::new(PidGainController::new(75.0, PidGains::default()))];
+        for controller in controllers.iter_mut() {
+            let (gain_db, gain_lin) = controller.compute_gain(70.0, 50.0);
+            assert!((gain_lin - db_to_lin(gain_db)).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Audio device lookup with errors that say which device was missing, instead of panicking on a
+//! headless box or a machine without a microphone. Devices can also be picked by index or name
+//! (`--input-device` / `--output-device`), since head units often expose several ALSA devices and the
+//! default isn't the amp.
+
+use std::fmt;
+
+use cpal::traits::{DeviceTrait, HostTrait};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceKind {
+    Input,
+    Output,
+}
+
+impl fmt::Display for DeviceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DeviceKind::Input => "input (microphone)",
+            DeviceKind::Output => "output",
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AudioError {
+    /// The host has no default device of this kind
+    MissingDevice(DeviceKind),
+    /// No device matched the requested index or name
+    NoMatch { kind: DeviceKind, selector: String },
+    /// The requested name matched more than one device
+    Ambiguous { kind: DeviceKind, selector: String, matches: Vec<String> },
+    /// The host failed to enumerate its devices
+    Enumerate(String),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioError::MissingDevice(kind) => write!(
+                f,
+                "no audio {} device available; check that one is connected and not held by another program",
+                kind
+            ),
+            AudioError::NoMatch { kind, selector } => write!(
+                f,
+                "no audio {} device matches '{}'; run with --list-devices to see what's available",
+                kind, selector
+            ),
+            AudioError::Ambiguous { kind, selector, matches } => write!(
+                f,
+                "'{}' matches {} audio {} devices ({}); use a longer name or the index",
+                selector,
+                matches.len(),
+                kind,
+                matches.join(", ")
+            ),
+            AudioError::Enumerate(e) => write!(f, "failed to list audio devices: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+/// The host's default output device; playback can't run without one
+pub fn default_output_device(host: &cpal::Host) -> Result<cpal::Device, AudioError> {
+    host.default_output_device().ok_or(AudioError::MissingDevice(DeviceKind::Output))
+}
+
+/// The host's default input device, if any. Callers fall back to simulated cabin noise without one.
+pub fn default_input_device(host: &cpal::Host) -> Option<cpal::Device> {
+    host.default_input_device()
+}
+
+/// The output device named by `selector` (see `find_device`), else the host default
+pub fn output_device(host: &cpal::Host, selector: Option<&str>) -> Result<cpal::Device, AudioError> {
+    match selector {
+        Some(selector) => find_device(host, DeviceKind::Output, selector),
+        None => default_output_device(host),
+    }
+}
+
+/// The input device named by `selector`, else the host default if there is one. A requested
+/// device that can't be found is an error rather than a silent fallback to simulated noise.
+pub fn input_device(host: &cpal::Host, selector: Option<&str>) -> Result<Option<cpal::Device>, AudioError> {
+    match selector {
+        Some(selector) => find_device(host, DeviceKind::Input, selector).map(Some),
+        None => Ok(default_input_device(host)),
+    }
+}
+
+/// Resolve `selector` against the host's devices of `kind`: a number is an index into the
+/// `--list-devices` order, anything else a case-insensitive name substring that must match exactly
+/// one device (an exact name match wins over substring matches)
+pub fn find_device(host: &cpal::Host, kind: DeviceKind, selector: &str) -> Result<cpal::Device, AudioError> {
+    let mut devices = list(host, kind)?;
+    let names: Vec<String> = devices.iter().map(device_name).collect();
+    let index = select(&names, kind, selector)?;
+    Ok(devices.swap_remove(index))
+}
+
+/// Print every input and output device with its index and supported stream configs
+pub fn print_devices(host: &cpal::Host) -> Result<(), AudioError> {
+    for kind in [DeviceKind::Output, DeviceKind::Input] {
+        let default_name = match kind {
+            DeviceKind::Input => host.default_input_device(),
+            DeviceKind::Output => host.default_output_device(),
+        }
+        .map(|d| device_name(&d));
+        println!("{} devices:", if kind == DeviceKind::Input { "Input" } else { "Output" });
+        let devices = list(host, kind)?;
+        if devices.is_empty() {
+            println!("  (none)");
+        }
+        for (i, device) in devices.iter().enumerate() {
+            let name = device_name(device);
+            let marker = if default_name.as_deref() == Some(name.as_str()) { " (default)" } else { "" };
+            println!("  {}: {}{}", i, name, marker);
+            let configs: Vec<cpal::SupportedStreamConfigRange> = match kind {
+                DeviceKind::Input => device.supported_input_configs().map(|c| c.collect()),
+                DeviceKind::Output => device.supported_output_configs().map(|c| c.collect()),
+            }
+            .unwrap_or_default();
+            for c in configs {
+                println!(
+                    "       {:?} {} ch {}-{} Hz",
+                    c.sample_format(),
+                    c.channels(),
+                    c.min_sample_rate().0,
+                    c.max_sample_rate().0
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn list(host: &cpal::Host, kind: DeviceKind) -> Result<Vec<cpal::Device>, AudioError> {
+    let devices = match kind {
+        DeviceKind::Input => host.input_devices().map(|d| d.collect()),
+        DeviceKind::Output => host.output_devices().map(|d| d.collect()),
+    };
+    devices.map_err(|e| AudioError::Enumerate(e.to_string()))
+}
+
+fn device_name(device: &cpal::Device) -> String {
+    device.name().unwrap_or_else(|_| "<unnamed>".to_string())
+}
+
+/// Index of the device `selector` picks out of `names`
+fn select(names: &[String], kind: DeviceKind, selector: &str) -> Result<usize, AudioError> {
+    let no_match = || AudioError::NoMatch { kind, selector: selector.to_string() };
+    if let Ok(index) = selector.trim().parse::<usize>() {
+        return if index < names.len() { Ok(index) } else { Err(no_match()) };
+    }
+    let wanted = selector.to_lowercase();
+    if let Some(exact) = names.iter().position(|n| n.to_lowercase() == wanted) {
+        return Ok(exact);
+    }
+    let matches: Vec<usize> = (0..names.len()).filter(|&i| names[i].to_lowercase().contains(&wanted)).collect();
+    match matches[..] {
+        [] => Err(no_match()),
+        [index] => Ok(index),
+        _ => Err(AudioError::Ambiguous {
+            kind,
+            selector: selector.to_string(),
+            matches: matches.iter().map(|&i| names[i].clone()).collect(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_by_index_or_name() {
+        let names: Vec<String> = ["default", "hw:CARD=PCH,DEV=0", "hw:CARD=Amp,DEV=0", "hw:CARD=Amp,DEV=1"]
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        let out = DeviceKind::Output;
+        assert_eq!(select(&names, out, "2"), Ok(2));
+        assert_eq!(select(&names, out, "pch"), Ok(1), "case-insensitive substring");
+        assert_eq!(select(&names, out, "DEFAULT"), Ok(0));
+        assert_eq!(select(&names, out, "amp,dev=1"), Ok(3));
+        assert_eq!(select(&names, out, "4"), Err(AudioError::NoMatch { kind: out, selector: "4".into() }));
+        assert_eq!(select(&names, out, "hdmi"), Err(AudioError::NoMatch { kind: out, selector: "hdmi".into() }));
+        match select(&names, out, "amp") {
+            Err(e @ AudioError::Ambiguous { .. }) => {
+                let msg = e.to_string();
+                assert!(msg.contains("hw:CARD=Amp,DEV=0") && msg.contains("hw:CARD=Amp,DEV=1"), "{}", msg);
+            }
+            other => panic!("expected an ambiguous match, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_missing_device_message_names_the_device() {
+        let msg = AudioError::MissingDevice(DeviceKind::Output).to_string();
+        assert!(msg.contains("no audio output device"), "{}", msg);
+        let msg = AudioError::MissingDevice(DeviceKind::Input).to_string();
+        assert!(msg.contains("input (microphone)"), "{}", msg);
+    }
+}
@@ -0,0 +1,134 @@
+//! Sensor dropout handling. An unplugged mic reads all zeros, so its level collapses toward -inf dB
+//! and the controller would answer with its maximum boost; a stale speed source is no better. While
+//! either is out the gain adapted to before the dropout is held, and if the dropout outlasts a
+//! timeout the controller settles on a fixed safe gain until the sensors come back.
+
+use crate::controller::GainController;
+
+/// Below this a window is taken as a dead input rather than a quiet cabin (a real mic's self-noise
+/// sits well above it)
+pub const DROPOUT_FLOOR_DBFS: f32 = -100.0;
+
+/// Whether a window of raw mic samples looks like a dropout: near-zero energy, or no variation at
+/// all (a stuck converter reading a constant)
+pub fn is_mic_dropout(samples: &[f32]) -> bool {
+    let (low, high) = samples.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &s| (lo.min(s), hi.max(s)));
+    samples.is_empty() || high - low <= f32::EPSILON || core_dsp::rms_to_db(samples, 0.0) < DROPOUT_FLOOR_DBFS
+}
+
+/// Where the gain comes from on a controller tick
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HoldState {
+    /// Sensors are good: the gain adapts
+    Live,
+    /// In a dropout: the last adapted gain is held
+    Holding,
+    /// The dropout outlasted the timeout: the safe gain is used
+    TimedOut,
+}
+
+/// Holds the gain through sensor dropouts of up to `timeout_s`
+#[derive(Clone, Debug)]
+pub struct DropoutHold {
+    pub timeout_s: f32,
+    pub safe_gain_db: f32,
+    held_s: f32,
+    state: HoldState,
+}
+
+impl DropoutHold {
+    pub const DEFAULT_TIMEOUT_S: f32 = 10.0;
+
+    pub fn new(timeout_s: f32, safe_gain_db: f32) -> Self {
+        Self { timeout_s: timeout_s.max(0.0), safe_gain_db, held_s: 0.0, state: HoldState::Live }
+    }
+
+    pub fn state(&self) -> HoldState {
+        self.state
+    }
+
+    /// Gain (dB, linear) for one tick `dt` seconds after the previous one: `live` when the sensors
+    /// are good, else the gain held from before the dropout, or the safe gain once it times out
+    pub fn step<G: GainController + ?Sized>(
+        &mut self,
+        gain: &mut G,
+        dropout: bool,
+        dt: f32,
+        live: impl FnOnce(&mut G) -> (f32, f32),
+    ) -> (f32, f32) {
+        if !dropout {
+            self.held_s = 0.0;
+            self.state = HoldState::Live;
+            return live(gain);
+        }
+        self.held_s += dt.max(0.0);
+        if self.held_s > self.timeout_s {
+            self.state = HoldState::TimedOut;
+            (self.safe_gain_db, core_dsp::db_to_lin(self.safe_gain_db))
+        } else {
+            self.state = HoldState::Holding;
+            gain.hold()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adaptive_gain::NoiseCombine;
+    use crate::gain::AdaptiveGain;
+    use core_dsp::rms_to_db;
+
+    /// A gain adapted to 70 dB of cabin noise (a 5 dB boost toward the 75 dB target)
+    fn adapted() -> (AdaptiveGain, f32) {
+        let mut ag = AdaptiveGain::new(75.0, 0.1, 0.1, 0.0, -12.0, 12.0, 0.0);
+        ag.set_noise_combine(NoiseCombine::Max);
+        let gain_db = (0..100).map(|_| ag.compute_gain_dt(70.0, 0.0, 0.05).0).last().unwrap();
+        (ag, gain_db)
+    }
+
+    #[test]
+    fn test_all_zeros_mic_holds_the_previous_gain() {
+        let (mut ag, before) = adapted();
+        let mut hold = DropoutHold::new(DropoutHold::DEFAULT_TIMEOUT_S, 0.0);
+
+        let unplugged = vec![0.0f32; 2400];
+        assert!(is_mic_dropout(&unplugged));
+        assert!(is_mic_dropout(&[0.2; 2400]), "stuck at a constant");
+        let hiss: Vec<f32> = (0..2400).map(|i| if i % 2 == 0 { 1e-4 } else { -1e-4 }).collect();
+        assert!(!is_mic_dropout(&hiss), "a quiet mic is still a mic");
+
+        // a live step on a reading this low (about 14 dB) would boost toward the maximum
+        let cabin_db = rms_to_db(&hiss, 94.0);
+        for _ in 0..20 {
+            let (gain_db, _) = hold.step(&mut ag, is_mic_dropout(&unplugged), 0.05, |ag| {
+                ag.compute_gain_dt(cabin_db, 0.0, 0.05)
+            });
+            assert_eq!(gain_db, before);
+        }
+        assert_eq!(hold.state(), HoldState::Holding);
+
+        // signal back: adapting again
+        let (gain_db, _) = hold.step(&mut ag, false, 0.05, |ag| ag.compute_gain_dt(64.0, 0.0, 0.05));
+        assert!(gain_db > before);
+        assert_eq!(hold.state(), HoldState::Live);
+    }
+
+    #[test]
+    fn test_stale_speed_holds_the_previous_gain_until_the_timeout() {
+        let (mut ag, before) = adapted();
+        let mut hold = DropoutHold::new(1.02, 0.0);
+        let stale = true;
+        for _ in 0..20 {
+            let (gain_db, _) = hold.step(&mut ag, stale, 0.05, |_| unreachable!("no live gain while stale"));
+            assert_eq!(gain_db, before);
+        }
+        let (gain_db, gain_lin) = hold.step(&mut ag, stale, 0.05, |_| unreachable!());
+        assert_eq!((gain_db, gain_lin), (0.0, 1.0), "safe gain after the timeout");
+        assert_eq!(hold.state(), HoldState::TimedOut);
+
+        // a fresh reading restarts the timeout
+        hold.step(&mut ag, false, 0.05, |ag| ag.compute_gain_dt(70.0, 0.0, 0.05));
+        assert_eq!(hold.step(&mut ag, stale, 0.05, |_| unreachable!()).0, before);
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::VecDeque;
+
+/// Peak limiter with lookahead: the signal is delayed by `lookahead` frames while the limiter tracks
+/// the smallest gain any frame in that window needs, so gain reduction is already in place when the
+/// peak reaches the output. One gain per frame, set by the loudest channel, so the channels stay
+/// linked. Unlike `adaptive_gain::Limiter`, the output never exceeds `threshold`.
+pub struct LookaheadLimiter {
+    pub threshold: f32,
+    channels: usize,
+    // `lookahead` interleaved frames; `pos` is the next frame to overwrite == the oldest in the window
+    delay: Vec<f32>,
+    pos: usize,
+    // running minimum of the gain each frame in the window needs: (frame number, gain) with the
+    // gains rising front to back, so the front is the minimum and each frame is pushed/popped once
+    required: VecDeque<(u64, f32)>,
+    frame: u64,
+    // the released minimum over the last `lookahead + 1` frames and their sum; averaging them ramps
+    // the gain down over the lookahead instead of stepping it when a peak enters the window
+    held: Vec<f32>,
+    held_pos: usize,
+    held_sum: f64,
+    release_coeff: f32,
+    hold: f32,
+}
+
+impl LookaheadLimiter {
+    pub fn new(threshold: f32, lookahead: usize, channels: usize, release_ms: f32, sample_rate: f32) -> Self {
+        let lookahead = lookahead.max(1);
+        let channels = channels.max(1);
+        Self {
+            threshold,
+            channels,
+            delay: vec![0.0; lookahead * channels],
+            pos: 0,
+            required: VecDeque::with_capacity(lookahead + 1),
+            frame: 0,
+            held: vec![1.0; lookahead + 1],
+            held_pos: 0,
+            held_sum: (lookahead + 1) as f64,
+            release_coeff: one_pole_coeff(release_ms, sample_rate),
+            hold: 1.0,
+        }
+    }
+
+    /// Push one interleaved frame in and replace it with the limited frame `lookahead` frames back
+    pub fn process_frame(&mut self, frame: &mut [f32]) {
+        debug_assert_eq!(frame.len(), self.channels, "frame length must match the channel count");
+        let peak = frame.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        let required = if peak > self.threshold { self.threshold / peak } else { 1.0 };
+        while self.required.back().is_some_and(|&(_, g)| g >= required) {
+            self.required.pop_back();
+        }
+        self.required.push_back((self.frame, required));
+        let window = self.held.len() as u64;
+        while self.required.front().is_some_and(|&(n, _)| n + window <= self.frame) {
+            self.required.pop_front();
+        }
+        self.frame += 1;
+
+        // release toward unity, but never above what the window needs; every frame in the average
+        // already has a peak in view by the time it reaches the output, so the mean is low enough
+        let window_min = self.required.front().map_or(1.0, |&(_, g)| g);
+        self.hold = (self.release_coeff * self.hold + (1.0 - self.release_coeff)).min(window_min);
+        self.held_sum += (self.hold - self.held[self.held_pos]) as f64;
+        self.held[self.held_pos] = self.hold;
+        self.held_pos += 1;
+        if self.held_pos == self.held.len() {
+            self.held_pos = 0;
+        }
+        let gain = (self.held_sum / window as f64) as f32;
+
+        let start = self.pos * self.channels;
+        for (s, d) in frame.iter_mut().zip(&mut self.delay[start..start + self.channels]) {
+            let out = std::mem::replace(d, *s);
+            *s = out * gain;
+        }
+        self.pos += 1;
+        if self.pos * self.channels == self.delay.len() {
+            self.pos = 0;
+        }
+    }
+
+    /// One sample through a single-channel limiter
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let mut frame = [sample];
+        self.process_frame(&mut frame);
+        frame[0]
+    }
+}
+
+/// Downward compressor: above `threshold_db` (dBFS) the output level rises only 1/`ratio` dB per dB
+/// of input. The gain reduction follows a peak envelope with attack/release smoothing, so one
+/// instance fed interleaved samples compresses the channels linked. No makeup gain: loud passages
+/// come down, quiet ones are left as they are.
+pub struct Compressor {
+    pub threshold_db: f32,
+    pub ratio: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    envelope: f32,
+}
+
+impl Compressor {
+    /// `--night`: a low threshold and a modest ratio, slow enough not to pump on every drum hit
+    pub const NIGHT_THRESHOLD_DB: f32 = -24.0;
+    pub const NIGHT_RATIO: f32 = 2.0;
+    pub const NIGHT_ATTACK_MS: f32 = 10.0;
+    pub const NIGHT_RELEASE_MS: f32 = 300.0;
+
+    pub fn new(threshold_db: f32, ratio: f32, attack_ms: f32, release_ms: f32, sample_rate: f32) -> Self {
+        Self {
+            threshold_db,
+            ratio: ratio.max(1.0),
+            attack_coeff: one_pole_coeff(attack_ms, sample_rate),
+            release_coeff: one_pole_coeff(release_ms, sample_rate),
+            envelope: 0.0,
+        }
+    }
+
+    /// The night-mode settings at `sample_rate`
+    pub fn night(sample_rate: f32) -> Self {
+        let (attack_ms, release_ms) = (Self::NIGHT_ATTACK_MS, Self::NIGHT_RELEASE_MS);
+        Self::new(Self::NIGHT_THRESHOLD_DB, Self::NIGHT_RATIO, attack_ms, release_ms, sample_rate)
+    }
+
+    /// Current gain reduction (dB, zero or positive)
+    pub fn gain_reduction_db(&self) -> f32 {
+        let over_db = 20.0 * self.envelope.max(1e-9).log10() - self.threshold_db;
+        over_db.max(0.0) * (1.0 - 1.0 / self.ratio)
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let level = sample.abs();
+        let coeff = if level > self.envelope { self.attack_coeff } else { self.release_coeff };
+        self.envelope = coeff * self.envelope + (1.0 - coeff) * level;
+        sample * 10f32.powf(-self.gain_reduction_db() / 20.0)
+    }
+}
+
+/// Sidechain ducking: while the key signal (e.g. a navigation prompt) is above `threshold_db`, the
+/// gain it returns for the other signal falls to `-depth_db`, then recovers once the key stops. The
+/// attack and release smooth the gain in dB; the key's own level is followed with a short hold so a
+/// prompt's pauses between words don't let the music swell back in.
+pub struct Ducker {
+    pub threshold_db: f32,
+    pub depth_db: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    key_coeff: f32,
+    key_envelope: f32,
+    gain_db: f32,
+}
+
+impl Ducker {
+    pub const DEFAULT_THRESHOLD_DB: f32 = -45.0;
+    pub const DEFAULT_DEPTH_DB: f32 = 12.0;
+    pub const DEFAULT_ATTACK_MS: f32 = 50.0;
+    pub const DEFAULT_RELEASE_MS: f32 = 600.0;
+    /// Release of the key level follower: bridges gaps between words
+    const KEY_RELEASE_MS: f32 = 150.0;
+
+    pub fn new(threshold_db: f32, depth_db: f32, attack_ms: f32, release_ms: f32, sample_rate: f32) -> Self {
+        Self {
+            threshold_db,
+            depth_db: depth_db.abs(),
+            attack_coeff: one_pole_coeff(attack_ms, sample_rate),
+            release_coeff: one_pole_coeff(release_ms, sample_rate),
+            key_coeff: one_pole_coeff(Self::KEY_RELEASE_MS, sample_rate),
+            key_envelope: 0.0,
+            gain_db: 0.0,
+        }
+    }
+
+    /// Current gain for the ducked signal (dB, zero or negative)
+    pub fn gain_db(&self) -> f32 {
+        self.gain_db
+    }
+
+    /// Feed one key sample (or a frame's largest magnitude); returns the linear gain for the
+    /// ducked signal at that point
+    pub fn process(&mut self, key: f32) -> f32 {
+        let level = key.abs();
+        self.key_envelope = if level > self.key_envelope { level } else { self.key_coeff * self.key_envelope };
+        let keyed = 20.0 * self.key_envelope.max(1e-9).log10() > self.threshold_db;
+        let target_db = if keyed { -self.depth_db } else { 0.0 };
+        let coeff = if target_db < self.gain_db { self.attack_coeff } else { self.release_coeff };
+        self.gain_db = coeff * self.gain_db + (1.0 - coeff) * target_db;
+        10f32.powf(self.gain_db / 20.0)
+    }
+}
+
+/// Automatic gain control for the mic path: over a calibration window at startup a tracker follows
+/// the mic's mean square and the gain pulls that level to `target_dbfs`; after the window the gain is
+/// frozen. A hotter or quieter mic/preamp then reads the same and one calibration offset fits them
+/// all, while the cabin's own changes over the drive (city to highway, a window opening) come through
+/// unnormalized. A fast peak limiter after the gain keeps a gain raised for a quiet mic from
+/// overshooting full scale when the level jumps.
+pub struct MicAgc {
+    pub target_dbfs: f32,
+    /// The gain stays within +-this, so silence or a dead mic isn't amplified without bound
+    pub max_gain_db: f32,
+    pub ceiling: f32,
+    tracking_coeff: f32,
+    release_coeff: f32,
+    mean_square: f32,
+    /// Samples tracked so far, and how many the calibration takes before the gain freezes
+    tracked: usize,
+    calibration_len: usize,
+    gain_db: f32,
+    limiter_gain: f32,
+}
+
+impl MicAgc {
+    pub const DEFAULT_TARGET_DBFS: f32 = -30.0;
+    pub const DEFAULT_CALIBRATION_S: f32 = 20.0;
+    pub const DEFAULT_RELEASE_MS: f32 = 50.0;
+    pub const DEFAULT_MAX_GAIN_DB: f32 = 40.0;
+
+    /// Calibrate over the first `calibration_s` seconds (the tracker's time constant is a fifth of
+    /// that, so it has settled when the gain freezes); the limiter attacks instantly and releases
+    /// over `release_ms`. Starts at unity gain.
+    pub fn new(target_dbfs: f32, calibration_s: f32, release_ms: f32, sample_rate: f32) -> Self {
+        let calibration_s = calibration_s.max(0.0);
+        Self {
+            target_dbfs,
+            max_gain_db: Self::DEFAULT_MAX_GAIN_DB,
+            ceiling: 1.0,
+            tracking_coeff: one_pole_coeff(calibration_s * 1000.0 / 5.0, sample_rate),
+            release_coeff: one_pole_coeff(release_ms, sample_rate),
+            mean_square: 0.0,
+            tracked: 0,
+            calibration_len: (calibration_s * sample_rate.max(0.0)) as usize,
+            gain_db: 0.0,
+            limiter_gain: 1.0,
+        }
+    }
+
+    /// Current gain (dB), before the limiter; fixed once calibrated
+    pub fn gain_db(&self) -> f32 {
+        self.gain_db
+    }
+
+    /// Whether the calibration window is over and the gain frozen
+    pub fn is_calibrated(&self) -> bool {
+        self.tracked >= self.calibration_len
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        if !self.is_calibrated() {
+            self.tracked += 1;
+            // a plain running mean until it spans a time constant, so the start doesn't bias the level
+            let coeff = self.tracking_coeff.min(1.0 - 1.0 / self.tracked as f32);
+            self.mean_square = coeff * self.mean_square + (1.0 - coeff) * sample * sample;
+            let level_db = 10.0 * self.mean_square.max(1e-18).log10();
+            self.gain_db = (self.target_dbfs - level_db).clamp(-self.max_gain_db, self.max_gain_db);
+        }
+        let out = sample * 10f32.powf(self.gain_db / 20.0);
+        let released = self.release_coeff * self.limiter_gain + (1.0 - self.release_coeff);
+        let peak = out.abs();
+        self.limiter_gain = if peak * released > self.ceiling { self.ceiling / peak } else { released };
+        out * self.limiter_gain
+    }
+}
+
+// one-pole smoothing coefficient for a time constant in ms (0 ms => follow instantly)
+fn one_pole_coeff(ms: f32, sample_rate: f32) -> f32 {
+    if ms <= 0.0 || sample_rate <= 0.0 {
+        0.0
+    } else {
+        (-1.0 / (ms * 0.001 * sample_rate)).exp()
+    }
+}
+
+/// Safety trim for a gain that keeps driving the output into the limiter. Fed the number of samples
+/// the limiter had to pull down in each controller window, it steps a negative trim in while the clip
+/// rate is above `max_clip_rate`, and lets it recover toward 0 dB at `recovery_db_per_s` once the
+/// output is clean again.
+#[derive(Clone, Debug)]
+pub struct ClipBackoff {
+    max_clip_rate: f32,
+    step_db: f32,
+    max_trim_db: f32,
+    recovery_db_per_s: f32,
+    trim_db: f32,
+}
+
+impl ClipBackoff {
+    /// Above one clipped sample in a thousand the limiter is audibly working
+    pub const DEFAULT_MAX_CLIP_RATE: f32 = 0.001;
+    pub const DEFAULT_STEP_DB: f32 = 1.0;
+    pub const DEFAULT_MAX_TRIM_DB: f32 = 6.0;
+    /// A full trim is back to 0 dB in 4 s
+    pub const DEFAULT_RECOVERY_DB_PER_S: f32 = 1.5;
+
+    pub fn new(max_clip_rate: f32, step_db: f32, max_trim_db: f32, recovery_db_per_s: f32) -> Self {
+        Self {
+            max_clip_rate: max_clip_rate.max(0.0),
+            step_db: step_db.abs(),
+            max_trim_db: max_trim_db.abs(),
+            recovery_db_per_s: recovery_db_per_s.abs(),
+            trim_db: 0.0,
+        }
+    }
+
+    /// Current trim (dB, zero or negative)
+    pub fn trim_db(&self) -> f32 {
+        self.trim_db
+    }
+
+    /// Account for one window of `samples` output samples, `clipped` of them over the limiter
+    /// threshold, `dt` seconds after the previous one; returns the trim to add to the gain
+    pub fn update(&mut self, clipped: usize, samples: usize, dt: f32) -> f32 {
+        if samples > 0 && clipped as f32 / samples as f32 > self.max_clip_rate {
+            self.trim_db = (self.trim_db - self.step_db).max(-self.max_trim_db);
+        } else {
+            self.trim_db = (self.trim_db + self.recovery_db_per_s * dt.max(0.0)).min(0.0);
+        }
+        self.trim_db
+    }
+}
+
+impl Default for ClipBackoff {
+    fn default() -> Self {
+        Self::new(
+            Self::DEFAULT_MAX_CLIP_RATE,
+            Self::DEFAULT_STEP_DB,
+            Self::DEFAULT_MAX_TRIM_DB,
+            Self::DEFAULT_RECOVERY_DB_PER_S,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_dsp::rms_to_db;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_impulse_peak_held_at_threshold() {
+        let lookahead = 32;
+        let mut limiter = LookaheadLimiter::new(0.9, lookahead, 1, 50.0, 48000.0);
+        let mut input = vec![0.2f32; 256];
+        input[100] = 4.0;
+        let out: Vec<f32> = input.iter().map(|&s| limiter.process(s)).collect();
+
+        let peak = out.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak <= 0.9 + 1e-6, "output peak {} above threshold", peak);
+        assert!((out[100 + lookahead] - 0.9).abs() < 1e-4, "impulse limited to threshold: {}", out[100 + lookahead]);
+        // reduction ramps in ahead of the impulse instead of stepping on it
+        for pair in out[100..=100 + lookahead - 1].windows(2) {
+            assert!(pair[1] <= pair[0] + 1e-6, "gain only falls while the impulse approaches: {:?}", pair);
+        }
+        assert!(out[100 + lookahead - 1] < 0.2 * 0.3);
+        assert!((out[99] - 0.2).abs() < 1e-6, "output before the impulse enters the window is untouched");
+    }
+
+    #[test]
+    fn test_releases_after_peak() {
+        let mut limiter = LookaheadLimiter::new(0.5, 8, 1, 5.0, 48000.0);
+        limiter.process(2.0);
+        let tail: Vec<f32> = (0..4800).map(|_| limiter.process(0.25)).collect();
+        assert!((tail.last().unwrap() - 0.25).abs() < 1e-3, "gain recovers after release");
+    }
+
+    #[test]
+    fn test_channels_share_one_gain_per_frame() {
+        let lookahead = 16;
+        let mut limiter = LookaheadLimiter::new(0.5, lookahead, 2, 50.0, 48000.0);
+        let mut out = Vec::new();
+        for i in 0..128 {
+            // a peak on the left only; the right channel is a steady 0.25
+            let mut frame = [if i == 40 { 2.0 } else { 0.1 }, 0.25];
+            limiter.process_frame(&mut frame);
+            out.push(frame);
+        }
+        let peak = out.iter().flatten().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak <= 0.5 + 1e-6, "output peak {} above threshold", peak);
+        assert!((out[40 + lookahead][0] - 0.5).abs() < 1e-4);
+        // the right channel dips with the left: both come out of the same per-frame gain
+        for [l, r] in &out[lookahead..] {
+            let (gain_l, gain_r) = (l / if *l > 0.2 { 2.0 } else { 0.1 }, r / 0.25);
+            assert!((gain_l - gain_r).abs() < 1e-5, "unlinked gains {} and {}", gain_l, gain_r);
+        }
+        assert!(out[40 + lookahead][1] < 0.25 * 0.3, "right channel ducked with the left peak");
+    }
+
+    #[test]
+    fn test_compressor_tames_loud_passages_only() {
+        let rate = 48_000.0;
+        let tone = |amplitude: f32| -> Vec<f32> {
+            let phase = |i: usize| 2.0 * std::f32::consts::PI * 440.0 * i as f32 / rate;
+            (0..rate as usize).map(|i| amplitude * phase(i).sin()).collect()
+        };
+        let peak_db = |samples: &[f32]| 20.0 * samples.iter().fold(0.0f32, |m, s| m.max(s.abs())).log10();
+
+        // 12 dB over the threshold comes out 6 dB over at 2:1, once the attack has settled
+        let mut comp = Compressor::night(rate);
+        let out: Vec<f32> = tone(10f32.powf(-12.0 / 20.0)).iter().map(|&s| comp.process(s)).collect();
+        let settled = peak_db(&out[out.len() / 2..]);
+        assert!((settled - (Compressor::NIGHT_THRESHOLD_DB + 6.0)).abs() < 0.5, "{} dBFS", settled);
+
+        // below the threshold nothing changes
+        let mut comp = Compressor::night(rate);
+        let quiet = tone(10f32.powf(-36.0 / 20.0));
+        let out: Vec<f32> = quiet.iter().map(|&s| comp.process(s)).collect();
+        assert_eq!(out, quiet);
+        assert_eq!(comp.gain_reduction_db(), 0.0);
+    }
+
+    #[test]
+    fn test_nav_prompt_ducks_music_by_the_depth_and_releases() {
+        let rate = 8000.0;
+        let mut ducker = Ducker::new(-45.0, 12.0, 50.0, 600.0, rate);
+        // a 1 s prompt: a 300 Hz tone at -20 dBFS with a 100 ms pause between "words"
+        let prompt: Vec<f32> = (0..rate as usize)
+            .map(|i| {
+                let pause = (4000..4800).contains(&i);
+                if pause { 0.0 } else { 0.1 * (2.0 * std::f32::consts::PI * 300.0 * i as f32 / rate).sin() }
+            })
+            .collect();
+        let gains: Vec<f32> = prompt.iter().map(|&s| ducker.process(s)).collect();
+        let db = |g: f32| 20.0 * g.log10();
+        assert!((db(gains[3999]) + 12.0).abs() < 0.1, "ducked by the depth: {} dB", db(gains[3999]));
+        assert!(db(gains[4799]) < -11.0, "held through the pause: {} dB", db(gains[4799]));
+        assert!((ducker.gain_db() + 12.0).abs() < 0.1);
+
+        // no prompt: back within 1 dB after ~4 release time constants, not at once
+        let after: Vec<f32> = (0..(3.0 * rate) as usize).map(|_| ducker.process(0.0)).collect();
+        assert!(db(after[(0.2 * rate) as usize]) < -6.0, "releases gradually");
+        assert!(db(*after.last().unwrap()) > -1.0, "recovered: {} dB", db(*after.last().unwrap()));
+
+        // quiet key below the threshold doesn't duck
+        let mut ducker = Ducker::new(-45.0, 12.0, 50.0, 600.0, rate);
+        let g = (0..8000).map(|i| ducker.process(if i % 2 == 0 { 0.001 } else { -0.001 })).last().unwrap();
+        assert_eq!(g, 1.0);
+    }
+
+    #[test]
+    fn test_clip_backoff_trims_an_over_clipping_stream_then_recovers() {
+        // a loud passage at +6 dB gain: the 0.8 peaks of a 100 Hz sine land at 1.6, over the 0.99 threshold
+        let rate = 48_000.0;
+        let window = 2400; // one 50 ms controller tick
+        let dt = window as f32 / rate;
+        let loud: Vec<f32> =
+            (0..window).map(|i| 0.8 * (2.0 * std::f32::consts::PI * 100.0 * i as f32 / rate).sin()).collect();
+        let gain = 10f32.powf(6.0 / 20.0);
+        let clipped_at = |trim_db: f32| {
+            let g = gain * 10f32.powf(trim_db / 20.0);
+            loud.iter().filter(|&&s| (s * g).abs() > 0.99).count()
+        };
+
+        let mut backoff = ClipBackoff::default();
+        let mut clipped = Vec::new();
+        for _ in 0..40 {
+            clipped.push(clipped_at(backoff.trim_db()));
+            backoff.update(*clipped.last().unwrap(), window, dt);
+        }
+        // untrimmed, a third of the samples clip; ~4.2 dB of trim brings the peaks under the threshold
+        assert!(clipped[0] > window / 4, "{}", clipped[0]);
+        let settled = backoff.trim_db();
+        assert!((-ClipBackoff::DEFAULT_MAX_TRIM_DB..-4.0).contains(&settled), "{} dB", settled);
+        let still_clipping = clipped[10..].iter().filter(|&&n| n > 0).count();
+        assert!(still_clipping <= 3, "clipped in {} of the last 30 windows", still_clipping);
+
+        // the passage ends: the trim decays back to 0 dB over a few seconds, not at once
+        let after_1s = (0..20).map(|_| backoff.update(0, window, dt)).last().unwrap();
+        assert!(after_1s < 0.0 && after_1s > settled, "{} -> {}", settled, after_1s);
+        let after_5s = (0..80).map(|_| backoff.update(0, window, dt)).last().unwrap();
+        assert_eq!(after_5s, 0.0);
+    }
+
+    #[test]
+    fn test_mic_agc_reads_an_attenuated_mic_at_the_same_level() {
+        let rate = 8000.0;
+        // 200 Hz "road noise" plus a slower 37 Hz component, 20 s
+        let noise =
+            |i: usize| (2.0 * PI * 200.0 * i as f32 / rate).sin() + 0.5 * (2.0 * PI * 37.0 * i as f32 / rate).sin();
+        let settled_db = |scale: f32| {
+            let mut agc = MicAgc::new(MicAgc::DEFAULT_TARGET_DBFS, 2.0, MicAgc::DEFAULT_RELEASE_MS, rate);
+            let out: Vec<f32> = (0..20 * rate as usize).map(|i| agc.process(scale * noise(i))).collect();
+            // the last second, through the controller's dB mapping
+            rms_to_db(&out[out.len() - rate as usize..], 94.0)
+        };
+        let (mic, quiet_mic) = (settled_db(0.1), settled_db(0.05));
+        assert!((mic - quiet_mic).abs() < 0.1, "{} vs {} dB", mic, quiet_mic);
+        assert!((mic - (94.0 + MicAgc::DEFAULT_TARGET_DBFS)).abs() < 0.5, "at the target: {} dB", mic);
+
+        // a gain raised for a quiet mic doesn't overshoot full scale when the level jumps
+        let mut agc = MicAgc::new(-20.0, 2.0, MicAgc::DEFAULT_RELEASE_MS, rate);
+        for i in 0..20 * rate as usize {
+            agc.process(0.001 * noise(i));
+        }
+        assert!(agc.gain_db() > 30.0, "{}", agc.gain_db());
+        let peak = (0..rate as usize).map(|i| agc.process(0.5 * noise(i)).abs()).fold(0.0f32, f32::max);
+        assert!(peak <= 1.0 + 1e-6, "limited: {}", peak);
+    }
+
+    #[test]
+    fn test_mic_agc_keeps_reporting_a_sustained_cabin_step() {
+        let rate = 2000.0;
+        let noise = |i: usize| (2.0 * PI * 200.0 * i as f32 / rate).sin();
+        let mut agc = MicAgc::new(MicAgc::DEFAULT_TARGET_DBFS, MicAgc::DEFAULT_CALIBRATION_S, 50.0, rate);
+        // city: calibrates on the first 20 s, then a further 10 s
+        let city: Vec<f32> = (0..30 * rate as usize).map(|i| agc.process(0.01 * noise(i))).collect();
+        assert!(agc.is_calibrated());
+        let city_db = rms_to_db(&city[city.len() - rate as usize..], 94.0);
+        assert!((city_db - (94.0 + MicAgc::DEFAULT_TARGET_DBFS)).abs() < 0.5, "at the target: {} dB", city_db);
+
+        // highway: 10 dB louder, and still 10 dB louder a minute later
+        let highway: Vec<f32> = (0..60 * rate as usize).map(|i| agc.process(0.01 * 10f32.sqrt() * noise(i))).collect();
+        let highway_db = rms_to_db(&highway[highway.len() - rate as usize..], 94.0);
+        assert!((highway_db - city_db - 10.0).abs() < 0.1, "{} -> {} dB", city_db, highway_db);
+    }
+}
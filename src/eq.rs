@@ -0,0 +1,159 @@
+//! Parametric EQ applied to the output before the adaptive gain. A preset is a list of biquad bands
+//! loaded from JSON; `Equalizer` builds the band cascade for one channel at one sample rate.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::filters::Biquad;
+
+/// Largest boost or cut a preset band may ask for (dB)
+pub const MAX_BAND_GAIN_DB: f32 = 24.0;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BandType {
+    Peaking,
+    Lowshelf,
+    Highshelf,
+    Lowpass,
+    Highpass,
+}
+
+/// One EQ band. `q` is the bandwidth for peaking/pass filters and the shelf slope for shelves
+/// (1.0 = steepest without overshoot); `gain_db` is ignored by lowpass/highpass.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct EqBand {
+    #[serde(rename = "type")]
+    pub kind: BandType,
+    pub freq: f32,
+    #[serde(default = "default_q")]
+    pub q: f32,
+    #[serde(default)]
+    pub gain_db: f32,
+}
+
+fn default_q() -> f32 {
+    std::f32::consts::FRAC_1_SQRT_2
+}
+
+/// Parametric EQ preset loaded from a JSON file such as `profiles/example_eq.json`:
+/// `{ "bands": [{ "type": "peaking", "freq": 120, "q": 4, "gain_db": -6 }] }`
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct EqPreset {
+    pub bands: Vec<EqBand>,
+}
+
+impl EqPreset {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading EQ preset {}", path.display()))?;
+        Self::from_json(&text).with_context(|| format!("invalid EQ preset {}", path.display()))
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        let preset: EqPreset = serde_json::from_str(text)?;
+        preset.validate()?;
+        Ok(preset)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (i, band) in self.bands.iter().enumerate() {
+            if !(band.freq.is_finite() && band.freq > 0.0) {
+                bail!("band {}: freq must be a positive number of Hz, got {}", i, band.freq);
+            }
+            if !(band.q.is_finite() && band.q > 0.0) {
+                bail!("band {}: q must be positive, got {}", i, band.q);
+            }
+            if !(band.gain_db.is_finite() && band.gain_db.abs() <= MAX_BAND_GAIN_DB) {
+                bail!("band {}: gain_db must be within ±{} dB, got {}", i, MAX_BAND_GAIN_DB, band.gain_db);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A cascade of biquads built from an `EqPreset` for one channel at one sample rate
+pub struct Equalizer {
+    bands: Vec<Biquad>,
+}
+
+impl Equalizer {
+    /// Fails if a band sits at or above the Nyquist frequency of `sample_rate`
+    pub fn new(preset: &EqPreset, sample_rate: f32) -> Result<Self> {
+        let bands = preset
+            .bands
+            .iter()
+            .map(|band| {
+                let nyquist = sample_rate / 2.0;
+                if band.freq >= nyquist {
+                    bail!("EQ band at {} Hz is at or above the Nyquist frequency ({} Hz)", band.freq, nyquist);
+                }
+                Ok(match band.kind {
+                    BandType::Peaking => Biquad::peaking(sample_rate, band.freq, band.q, band.gain_db),
+                    BandType::Lowshelf => Biquad::lowshelf(sample_rate, band.freq, band.q, band.gain_db),
+                    BandType::Highshelf => Biquad::highshelf(sample_rate, band.freq, band.q, band.gain_db),
+                    BandType::Lowpass => Biquad::lowpass(sample_rate, band.freq, band.q),
+                    BandType::Highpass => Biquad::highpass(sample_rate, band.freq, band.q),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { bands })
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.bands.iter_mut().fold(sample, |y, b| b.process(y))
+    }
+
+    /// Filter a block of mono samples in place
+    pub fn process_block(&mut self, samples: &mut [f32]) {
+        for s in samples.iter_mut() {
+            *s = self.process(*s);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_gain_bands_pass_audio_through() {
+        let preset = EqPreset::from_json(
+            r#"{ "bands": [
+                { "type": "peaking", "freq": 120, "q": 4, "gain_db": 0 },
+                { "type": "lowshelf", "freq": 80, "q": 1, "gain_db": 0 },
+                { "type": "highshelf", "freq": 8000, "gain_db": 0 }
+            ] }"#,
+        )
+        .unwrap();
+        let mut eq = Equalizer::new(&preset, 48_000.0).unwrap();
+        let input: Vec<f32> = (0..4800).map(|i| 0.5 * (i as f32 * 0.05).sin()).collect();
+        let mut block = input.clone();
+        eq.process_block(&mut block);
+        for (x, y) in input.iter().zip(&block) {
+            assert!((x - y).abs() < 1e-5, "{} -> {}", x, y);
+        }
+    }
+
+    #[test]
+    fn test_load_shipped_example_preset() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/profiles/example_eq.json");
+        let preset = EqPreset::load(path).unwrap();
+        assert_eq!(preset.bands[0].kind, BandType::Peaking);
+        assert!(Equalizer::new(&preset, 44_100.0).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_bands_rejected() {
+        let err = EqPreset::from_json(r#"{ "bands": [{ "type": "peaking", "freq": -5, "gain_db": 3 }] }"#).unwrap_err();
+        assert!(err.to_string().contains("freq"), "{}", err);
+        let err = EqPreset::from_json(r#"{ "bands": [{ "type": "lowshelf", "freq": 100, "gain_db": 40 }] }"#).unwrap_err();
+        assert!(err.to_string().contains("gain_db"), "{}", err);
+        assert!(EqPreset::from_json(r#"{ "bands": [{ "type": "notch", "freq": 100 }] }"#).is_err());
+
+        let preset = EqPreset::from_json(r#"{ "bands": [{ "type": "highshelf", "freq": 30000 }] }"#).unwrap();
+        assert!(Equalizer::new(&preset, 48_000.0).is_err(), "above Nyquist");
+    }
+}
@@ -0,0 +1,148 @@
+//! Gain envelopes for starting, stopping and muting playback without a pop: the output is scaled by
+//! a multiplier that moves between 0 and 1 over a fade time. It sits apart from the adaptive gain, so
+//! when a fade back up finishes the output is at whatever level the controller wants by then.
+
+use std::f32::consts::PI;
+
+/// How the multiplier moves across a fade
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FadeShape {
+    /// Straight ramp
+    #[default]
+    Linear,
+    /// Half a cosine period: starts and ends with zero slope, so there is no corner to hear
+    RaisedCosine,
+}
+
+impl FadeShape {
+    /// Multiplier at `position` (0..1) of the way through a fade up
+    pub fn apply(self, position: f32) -> f32 {
+        match self {
+            FadeShape::Linear => position,
+            FadeShape::RaisedCosine => 0.5 - 0.5 * (PI * position).cos(),
+        }
+    }
+}
+
+/// A 0..1 gain multiplier moving toward its target at a fixed rate, advanced once per frame
+#[derive(Clone, Debug)]
+pub struct FadeEnvelope {
+    shape: FadeShape,
+    sample_rate: f32,
+    /// Change in `position` per frame
+    step: f32,
+    /// Progress through the fade (0..1) before shaping
+    position: f32,
+    target: f32,
+}
+
+impl FadeEnvelope {
+    /// Mute/unmute fade: long enough not to click, short enough to feel immediate
+    pub const DEFAULT_MUTE_FADE_MS: f32 = 50.0;
+    /// Fade-in when playback starts and fade-out on shutdown
+    pub const DEFAULT_TRANSPORT_FADE_MS: f32 = 200.0;
+
+    /// Full-scale fades take `fade_ms` at `sample_rate` frames per second; 0 ms switches instantly
+    pub fn new(fade_ms: f32, sample_rate: f32, initial: f32) -> Self {
+        let initial = initial.clamp(0.0, 1.0);
+        let step = Self::step(fade_ms, sample_rate);
+        Self { shape: FadeShape::Linear, sample_rate, step, position: initial, target: initial }
+    }
+
+    pub fn with_shape(mut self, shape: FadeShape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    fn step(fade_ms: f32, sample_rate: f32) -> f32 {
+        let frames = fade_ms * 0.001 * sample_rate;
+        if frames >= 1.0 {
+            1.0 / frames
+        } else {
+            1.0
+        }
+    }
+
+    /// Head for `target` (clamped to 0..1) from wherever the envelope is now
+    pub fn set_target(&mut self, target: f32) {
+        self.target = target.clamp(0.0, 1.0);
+    }
+
+    /// Head for `target` at a new rate: a full-scale fade now takes `fade_ms`
+    pub fn fade_to(&mut self, target: f32, fade_ms: f32) {
+        self.step = Self::step(fade_ms, self.sample_rate);
+        self.set_target(target);
+    }
+
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    /// Multiplier for the next frame
+    pub fn next_gain(&mut self) -> f32 {
+        // within a step (plus rounding from the repeated adds) the target is reached exactly
+        self.position = if (self.target - self.position).abs() <= self.step + 1e-5 {
+            self.target
+        } else if self.position < self.target {
+            self.position + self.step
+        } else {
+            self.position - self.step
+        };
+        self.gain()
+    }
+
+    /// Multiplier applied to the last frame
+    pub fn gain(&self) -> f32 {
+        self.shape.apply(self.position)
+    }
+
+    /// True once the envelope has reached its target
+    pub fn is_settled(&self) -> bool {
+        self.position == self.target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mute_fades_to_zero_over_the_fade_time_and_back() {
+        // 50 ms at 1 kHz: 50 frames per full fade
+        let mut fade = FadeEnvelope::new(50.0, 1000.0, 1.0);
+        fade.set_target(0.0);
+        let down: Vec<f32> = (0..50).map(|_| fade.next_gain()).collect();
+        assert!(down.windows(2).all(|w| w[1] < w[0]), "falls every frame: no instant cut");
+        assert!((down[24] - 0.5).abs() < 1e-4, "halfway after half the fade: {}", down[24]);
+        assert!(down[48] > 0.0);
+        assert_eq!(down[49], 0.0, "silent exactly at the fade time");
+        assert!(fade.is_settled());
+        assert_eq!(fade.next_gain(), 0.0, "stays muted");
+
+        // unmuting halfway through a fade turns around from where it is
+        fade.set_target(1.0);
+        let up: Vec<f32> = (0..25).map(|_| fade.next_gain()).collect();
+        fade.set_target(0.0);
+        assert!((fade.next_gain() - (up[24] - 0.02)).abs() < 1e-5);
+        assert_eq!(FadeEnvelope::new(0.0, 1000.0, 1.0).gain(), 1.0);
+    }
+
+    #[test]
+    fn test_start_fades_in_to_unity_and_shutdown_fades_out() {
+        // 200 ms in, 100 ms out, at 1 kHz
+        let mut fade = FadeEnvelope::new(200.0, 1000.0, 0.0).with_shape(FadeShape::RaisedCosine);
+        fade.set_target(1.0);
+        let fade_in: Vec<f32> = (0..200).map(|_| fade.next_gain()).collect();
+        assert!(fade_in[0] < 1e-3, "starts from silence with zero slope: {}", fade_in[0]);
+        assert!((fade_in[99] - 0.5).abs() < 1e-3);
+        assert!(fade_in[198] < 1.0);
+        assert_eq!(fade_in[199], 1.0, "unity after the fade-in time");
+        assert_eq!(fade.next_gain(), 1.0);
+
+        fade.fade_to(0.0, 100.0);
+        let fade_out: Vec<f32> = (0..100).map(|_| fade.next_gain()).collect();
+        assert!(fade_out.windows(2).all(|w| w[1] < w[0]));
+        assert!(1.0 - fade_out[0] < 1e-3, "leaves unity gently: {}", fade_out[0]);
+        assert_eq!(fade_out[99], 0.0, "silent after the fade-out time");
+    }
+}
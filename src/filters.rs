@@ -0,0 +1,454 @@
+//! Biquad IIR filters (RBJ cookbook) and the filters built from them: A-weighting, crossovers,
+//! loudness compensation and the speed tilt.
+
+/// Second-order IIR section (transposed direct form II), coefficients normalised so a0 == 1.
+/// Coefficients and state are f64 because the A-weighting low-frequency poles sit very close to
+/// the unit circle; samples go in and out as f32.
+#[derive(Clone, Debug)]
+pub struct Biquad {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    /// From unnormalised coefficients `b = [b0, b1, b2]`, `a = [a0, a1, a2]`
+    pub fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b0: b[0] / a[0],
+            b1: b[1] / a[0],
+            b2: b[2] / a[0],
+            a1: a[1] / a[0],
+            a2: a[2] / a[0],
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        self.tick(x as f64) as f32
+    }
+
+    /// One sample at full precision, for cascades that stay in f64 between sections
+    fn tick(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+
+    /// Clear the filter state (coefficients are kept)
+    pub fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+
+    /// Low-pass at `freq_hz` with quality `q` (0.707 for Butterworth)
+    pub fn lowpass(sample_rate: f32, freq_hz: f32, q: f32) -> Self {
+        let (c, alpha) = Self::q_terms(sample_rate, freq_hz, q);
+        Self::new([(1.0 - c) / 2.0, 1.0 - c, (1.0 - c) / 2.0], [1.0 + alpha, -2.0 * c, 1.0 - alpha])
+    }
+
+    /// High-pass at `freq_hz` with quality `q`
+    pub fn highpass(sample_rate: f32, freq_hz: f32, q: f32) -> Self {
+        let (c, alpha) = Self::q_terms(sample_rate, freq_hz, q);
+        Self::new([(1.0 + c) / 2.0, -(1.0 + c), (1.0 + c) / 2.0], [1.0 + alpha, -2.0 * c, 1.0 - alpha])
+    }
+
+    /// Peaking EQ: `gain_db` at `freq_hz`, bandwidth set by `q`
+    pub fn peaking(sample_rate: f32, freq_hz: f32, q: f32, gain_db: f32) -> Self {
+        let a = 10f64.powf(gain_db as f64 / 40.0);
+        let (c, alpha) = Self::q_terms(sample_rate, freq_hz, q);
+        Self::new(
+            [1.0 + alpha * a, -2.0 * c, 1.0 - alpha * a],
+            [1.0 + alpha / a, -2.0 * c, 1.0 - alpha / a],
+        )
+    }
+
+    /// Low shelf: `gain_db` below `freq_hz`, shelf slope `slope` (1 = steepest monotonic)
+    pub fn lowshelf(sample_rate: f32, freq_hz: f32, slope: f32, gain_db: f32) -> Self {
+        let (a, c, sqrt_a_alpha) = Self::shelf_terms(sample_rate, freq_hz, slope, gain_db);
+        Self::new(
+            [
+                a * ((a + 1.0) - (a - 1.0) * c + sqrt_a_alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * c),
+                a * ((a + 1.0) - (a - 1.0) * c - sqrt_a_alpha),
+            ],
+            [
+                (a + 1.0) + (a - 1.0) * c + sqrt_a_alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * c),
+                (a + 1.0) + (a - 1.0) * c - sqrt_a_alpha,
+            ],
+        )
+    }
+
+    /// High shelf: `gain_db` above `freq_hz`
+    pub fn highshelf(sample_rate: f32, freq_hz: f32, slope: f32, gain_db: f32) -> Self {
+        let (a, c, sqrt_a_alpha) = Self::shelf_terms(sample_rate, freq_hz, slope, gain_db);
+        Self::new(
+            [
+                a * ((a + 1.0) + (a - 1.0) * c + sqrt_a_alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * c),
+                a * ((a + 1.0) + (a - 1.0) * c - sqrt_a_alpha),
+            ],
+            [
+                (a + 1.0) - (a - 1.0) * c + sqrt_a_alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * c),
+                (a + 1.0) - (a - 1.0) * c - sqrt_a_alpha,
+            ],
+        )
+    }
+
+    // (cos w0, alpha) for the Q-parameterised formulas
+    fn q_terms(sample_rate: f32, freq_hz: f32, q: f32) -> (f64, f64) {
+        let w0 = 2.0 * std::f64::consts::PI * freq_hz as f64 / sample_rate as f64;
+        (w0.cos(), w0.sin() / (2.0 * q as f64))
+    }
+
+    // (A, cos w0, 2 sqrt(A) alpha) shared by the shelf formulas
+    fn shelf_terms(sample_rate: f32, freq_hz: f32, slope: f32, gain_db: f32) -> (f64, f64, f64) {
+        let a = 10f64.powf(gain_db as f64 / 40.0);
+        let w0 = 2.0 * std::f64::consts::PI * freq_hz as f64 / sample_rate as f64;
+        let alpha = w0.sin() / 2.0 * ((a + 1.0 / a) * (1.0 / slope as f64 - 1.0) + 2.0).sqrt();
+        (a, w0.cos(), 2.0 * a.sqrt() * alpha)
+    }
+
+    /// Take `other`'s coefficients but keep this filter's state, so a retuned filter doesn't click
+    pub fn retune(&mut self, other: Biquad) {
+        *self = Biquad { z1: self.z1, z2: self.z2, ..other };
+    }
+
+    /// Magnitude response |H(e^jw)| at `freq_hz`
+    pub fn magnitude_at(&self, freq_hz: f64, sample_rate: f64) -> f64 {
+        let w = 2.0 * std::f64::consts::PI * freq_hz / sample_rate;
+        let (c1, s1) = (w.cos(), -w.sin());
+        let (c2, s2) = ((2.0 * w).cos(), -(2.0 * w).sin());
+        let num_re = self.b0 + self.b1 * c1 + self.b2 * c2;
+        let num_im = self.b1 * s1 + self.b2 * s2;
+        let den_re = 1.0 + self.a1 * c1 + self.a2 * c2;
+        let den_im = self.a1 * s1 + self.a2 * s2;
+        ((num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im)).sqrt()
+    }
+}
+
+/// Corner of the high-pass in front of the mic level estimate: removes the capsule/ADC DC offset and
+/// subsonic wind rumble, which inflate the RMS without being heard, and stays below the audible band
+pub const DEFAULT_MIC_HIGHPASS_HZ: f32 = 20.0;
+
+/// IEC 61672 A-weighting filter as a cascade of three biquads (bilinear transform of the analog
+/// prototype), normalised to 0 dB at 1 kHz.
+pub struct AWeighting {
+    sections: [Biquad; 3],
+    gain: f64,
+}
+
+impl AWeighting {
+    // Analog pole frequencies of the A-weighting curve (Hz)
+    const F1: f64 = 20.598_997;
+    const F2: f64 = 107.652_65;
+    const F3: f64 = 737.862_23;
+    const F4: f64 = 12_194.217;
+
+    pub fn new(sample_rate: f32) -> Self {
+        let fs = sample_rate as f64;
+        let k = 2.0 * fs;
+        let w = |f: f64| 2.0 * std::f64::consts::PI * f;
+        let (w1, w2, w3, w4) = (w(Self::F1), w(Self::F2), w(Self::F3), w(Self::F4));
+
+        // Bilinear transform of (s + wa)(s + wb)
+        let poles = |wa: f64, wb: f64| {
+            [
+                (k + wa) * (k + wb),
+                (k + wa) * (wb - k) + (wa - k) * (k + wb),
+                (wa - k) * (wb - k),
+            ]
+        };
+        // s^2 and 1 numerators after the same transform
+        let s2 = [k * k, -2.0 * k * k, k * k];
+        let one = [1.0, 2.0, 1.0];
+
+        // H(s) = s^4 / ((s + w1)^2 (s + w2)(s + w3)(s + w4)^2)
+        let sections = [
+            Biquad::new(s2, poles(w1, w1)),
+            Biquad::new(s2, poles(w2, w3)),
+            Biquad::new(one, poles(w4, w4)),
+        ];
+        let mag_1k: f64 = sections.iter().map(|s| s.magnitude_at(1000.0, fs)).product();
+        Self { sections, gain: 1.0 / mag_1k }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let mut y = sample as f64;
+        for s in self.sections.iter_mut() {
+            y = s.tick(y);
+        }
+        (y * self.gain) as f32
+    }
+}
+
+/// 4th-order Linkwitz-Riley crossover: each branch is two cascaded Butterworth sections, so the
+/// low and high outputs are in phase and sum to an all-pass (flat magnitude) response.
+pub struct LinkwitzRiley {
+    low: [Biquad; 2],
+    high: [Biquad; 2],
+}
+
+impl LinkwitzRiley {
+    pub fn new(sample_rate: f32, crossover_hz: f32) -> Self {
+        let (fs, f) = (sample_rate, crossover_hz);
+        let q = std::f32::consts::FRAC_1_SQRT_2;
+        Self {
+            low: [Biquad::lowpass(fs, f, q), Biquad::lowpass(fs, f, q)],
+            high: [Biquad::highpass(fs, f, q), Biquad::highpass(fs, f, q)],
+        }
+    }
+
+    /// Split one sample into its (low, high) parts
+    pub fn split(&mut self, sample: f32) -> (f32, f32) {
+        let x = sample as f64;
+        let low = self.low.iter_mut().fold(x, |y, s| s.tick(y));
+        let high = self.high.iter_mut().fold(x, |y, s| s.tick(y));
+        (low as f32, high as f32)
+    }
+}
+
+/// Equal-loudness compensation: ISO 226 contours spread apart at low levels (the ear loses bass,
+/// and some treble, faster than midrange), so below `reference_db` a low and a high shelf add back
+/// what is lost and flatten out at the reference. Sits after the adaptive gain, driven by the
+/// estimated playback level.
+pub struct LoudnessCompensation {
+    sample_rate: f32,
+    reference_db: f32,
+    level_db: f32,
+    low: Biquad,
+    high: Biquad,
+}
+
+impl LoudnessCompensation {
+    /// Typical reference listening level at which the correction is flat (dB SPL)
+    pub const DEFAULT_REFERENCE_DB: f32 = 83.0;
+    const LOW_SHELF_HZ: f32 = 100.0;
+    const HIGH_SHELF_HZ: f32 = 10_000.0;
+    // shelf boost per dB below the reference and its ceiling: roughly how far the 100 Hz and
+    // 10 kHz points of the ISO 226:2003 contours move relative to 1 kHz between 80 and 40 phon
+    const LOW_DB_PER_DB: f32 = 0.4;
+    const LOW_MAX_DB: f32 = 15.0;
+    const HIGH_DB_PER_DB: f32 = 0.15;
+    const HIGH_MAX_DB: f32 = 6.0;
+    /// Level changes smaller than this don't recompute the shelves
+    const RETUNE_STEP_DB: f32 = 0.25;
+
+    pub fn new(sample_rate: f32, reference_db: f32) -> Self {
+        let fs = sample_rate;
+        let mut comp = Self {
+            sample_rate: fs,
+            reference_db,
+            level_db: reference_db,
+            low: Biquad::lowshelf(fs, Self::LOW_SHELF_HZ, 1.0, 0.0),
+            high: Biquad::highshelf(fs, Self::HIGH_SHELF_HZ.min(0.45 * fs), 1.0, 0.0),
+        };
+        comp.retune();
+        comp
+    }
+
+    /// Estimated playback level (dB SPL) the correction is tuned for
+    pub fn set_playback_db(&mut self, level_db: f32) {
+        if (level_db - self.level_db).abs() >= Self::RETUNE_STEP_DB {
+            self.level_db = level_db;
+            self.retune();
+        }
+    }
+
+    /// (low shelf, high shelf) boost in dB at the current playback level
+    pub fn shelf_gains_db(&self) -> (f32, f32) {
+        let below = (self.reference_db - self.level_db).max(0.0);
+        (
+            (below * Self::LOW_DB_PER_DB).min(Self::LOW_MAX_DB),
+            (below * Self::HIGH_DB_PER_DB).min(Self::HIGH_MAX_DB),
+        )
+    }
+
+    fn retune(&mut self) {
+        let (low_db, high_db) = self.shelf_gains_db();
+        let fs = self.sample_rate;
+        self.low.retune(Biquad::lowshelf(fs, Self::LOW_SHELF_HZ, 1.0, low_db));
+        self.high.retune(Biquad::highshelf(fs, Self::HIGH_SHELF_HZ.min(0.45 * fs), 1.0, high_db));
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.high.process(self.low.process(sample))
+    }
+}
+
+/// Speed-dependent bass tilt: road noise masks the low end more as speed rises, so a low shelf
+/// is boosted `slope_db_per_kmh` per km/h up to `max_db`, independent of the overall gain.
+pub struct SpeedTilt {
+    sample_rate: f32,
+    slope_db_per_kmh: f32,
+    max_db: f32,
+    tilt_db: f32,
+    shelf: Biquad,
+}
+
+impl SpeedTilt {
+    pub const DEFAULT_SLOPE_DB_PER_KMH: f32 = 0.05;
+    pub const DEFAULT_MAX_DB: f32 = 6.0;
+    const SHELF_HZ: f32 = 120.0;
+    /// Tilt changes smaller than this don't recompute the shelf
+    const RETUNE_STEP_DB: f32 = 0.1;
+
+    pub fn new(sample_rate: f32, slope_db_per_kmh: f32, max_db: f32) -> Self {
+        let fs = sample_rate;
+        Self {
+            sample_rate: fs,
+            slope_db_per_kmh,
+            max_db,
+            tilt_db: 0.0,
+            shelf: Biquad::lowshelf(fs, Self::SHELF_HZ, 1.0, 0.0),
+        }
+    }
+
+    /// Retune for the (smoothed) vehicle speed
+    pub fn set_speed(&mut self, speed_kmh: f32) {
+        let tilt_db = (speed_kmh.max(0.0) * self.slope_db_per_kmh).clamp(0.0, self.max_db.max(0.0));
+        if (tilt_db - self.tilt_db).abs() >= Self::RETUNE_STEP_DB || (tilt_db == 0.0) != (self.tilt_db == 0.0) {
+            self.tilt_db = tilt_db;
+            self.shelf.retune(Biquad::lowshelf(self.sample_rate, Self::SHELF_HZ, 1.0, tilt_db));
+        }
+    }
+
+    /// Low-shelf boost currently applied (dB)
+    pub fn tilt_db(&self) -> f32 {
+        self.tilt_db
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.shelf.process(sample)
+    }
+
+    /// Apply the tilt to a block of mono samples in place
+    pub fn process_block(&mut self, block: &mut [f32]) {
+        for s in block.iter_mut() {
+            *s = self.process(*s);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lowpass_passes_dc_and_blocks_nyquist() {
+        let fs = 48_000.0;
+        let mut lp = Biquad::lowpass(fs, 1000.0, std::f32::consts::FRAC_1_SQRT_2);
+        assert!((lp.magnitude_at(0.0, fs as f64) - 1.0).abs() < 1e-9);
+        assert!(lp.magnitude_at(fs as f64 / 2.0, fs as f64) < 1e-9);
+
+        let dc = (0..4800).map(|_| lp.process(0.5)).last().unwrap();
+        assert!((dc - 0.5).abs() < 1e-4, "DC settles to the input: {}", dc);
+        lp.reset();
+        let nyquist: Vec<f32> = (0..4800).map(|i| lp.process(if i % 2 == 0 { 0.5 } else { -0.5 })).collect();
+        assert!(nyquist[4000..].iter().all(|y| y.abs() < 1e-4), "Nyquist is removed");
+    }
+
+    #[test]
+    fn test_flat_peaking_filter_is_identity() {
+        let mut peq = Biquad::peaking(48_000.0, 1000.0, 1.0, 0.0);
+        for i in 0..1000 {
+            let x = (i as f32 * 0.37).sin();
+            assert!((peq.process(x) - x).abs() < 1e-6);
+        }
+    }
+
+    fn sine(freq_hz: f32, sample_rate: f32, n: usize) -> impl Iterator<Item = f32> {
+        (0..n).map(move |i| (2.0 * std::f32::consts::PI * freq_hz * i as f32 / sample_rate).sin())
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_mic_highpass_removes_dc_offset_from_rms() {
+        let fs = 48_000.0;
+        let mut hpf = Biquad::highpass(fs, DEFAULT_MIC_HIGHPASS_HZ, std::f32::consts::FRAC_1_SQRT_2);
+        let ac: Vec<f32> = sine(100.0, fs, 48_000).map(|s| 0.5 * s).collect();
+        let filtered: Vec<f32> = ac.iter().map(|&s| hpf.process(s + 0.3)).collect();
+        // after the first half second of settling
+        let (offset, clean) = (rms(&ac.iter().map(|s| s + 0.3).collect::<Vec<_>>()), rms(&ac[24_000..]));
+        assert!(offset > clean * 1.3, "the offset inflates the raw RMS: {} vs {}", offset, clean);
+        assert!((rms(&filtered[24_000..]) - clean).abs() < clean * 0.01, "{} vs {}", rms(&filtered[24_000..]), clean);
+    }
+
+    #[test]
+    fn test_linkwitz_riley_splits_and_sums_flat() {
+        let fs = 48_000.0;
+        let mut xo = LinkwitzRiley::new(fs, 1000.0);
+        let (mut low, mut high, mut sum) = (Vec::new(), Vec::new(), Vec::new());
+        for s in sine(100.0, fs, 48_000) {
+            let (l, h) = xo.split(s);
+            low.push(l);
+            high.push(h);
+            sum.push(l + h);
+        }
+        // skip the first half second of filter settling
+        let tail = 24_000..;
+        assert!((rms(&low[tail.clone()]) - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.01);
+        assert!(rms(&high[tail.clone()]) < 0.01, "100 Hz is ~3.3 octaves below the crossover");
+        assert!((rms(&sum[tail]) - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.01, "low + high is all-pass");
+
+        // at the crossover both branches sit 6 dB down
+        let mut xo = LinkwitzRiley::new(fs, 1000.0);
+        let (low, high): (Vec<f32>, Vec<f32>) = sine(1000.0, fs, 48_000).map(|s| xo.split(s)).unzip();
+        for branch in [&low, &high] {
+            let db = 20.0 * (rms(&branch[24_000..]) / std::f32::consts::FRAC_1_SQRT_2).log10();
+            assert!((db + 6.02).abs() < 0.1, "{} dB at the crossover", db);
+        }
+    }
+
+    #[test]
+    fn test_loudness_comp_boosts_bass_only_below_reference() {
+        let fs = 48_000.0;
+        let mut comp = LoudnessCompensation::new(fs, LoudnessCompensation::DEFAULT_REFERENCE_DB);
+        assert_eq!(comp.shelf_gains_db(), (0.0, 0.0));
+        let at_50hz = |c: &LoudnessCompensation| 20.0 * c.low.magnitude_at(50.0, fs as f64).log10() as f32;
+        assert!(at_50hz(&comp).abs() < 0.01, "flat at the reference: {} dB", at_50hz(&comp));
+
+        comp.set_playback_db(60.0);
+        let (low_db, high_db) = comp.shelf_gains_db();
+        assert!(low_db > 0.0 && high_db > 0.0 && low_db > high_db, "{} / {}", low_db, high_db);
+        assert!((at_50hz(&comp) - low_db).abs() < 1.0, "shelf reaches ~{} dB at 50 Hz: {}", low_db, at_50hz(&comp));
+        let mid = 20.0 * comp.low.magnitude_at(1000.0, fs as f64).log10() as f32;
+        assert!(mid.abs() < 0.5, "midrange untouched: {} dB", mid);
+
+        // louder than the reference: no cut, just flat
+        comp.set_playback_db(95.0);
+        assert_eq!(comp.shelf_gains_db(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_speed_tilt_from_flat_to_max() {
+        let fs = 48_000.0;
+        let mut tilt = SpeedTilt::new(fs, SpeedTilt::DEFAULT_SLOPE_DB_PER_KMH, SpeedTilt::DEFAULT_MAX_DB);
+        let bass_db = |t: &SpeedTilt| 20.0 * t.shelf.magnitude_at(30.0, fs as f64).log10() as f32;
+
+        tilt.set_speed(0.0);
+        assert_eq!(tilt.tilt_db(), 0.0);
+        assert!(bass_db(&tilt).abs() < 0.01, "flat at standstill: {} dB", bass_db(&tilt));
+
+        tilt.set_speed(200.0);
+        assert_eq!(tilt.tilt_db(), SpeedTilt::DEFAULT_MAX_DB);
+        assert!((bass_db(&tilt) - SpeedTilt::DEFAULT_MAX_DB).abs() < 0.5, "shelf at max: {} dB", bass_db(&tilt));
+
+        // a constant (DC) block picks up the full shelf gain
+        let mut block = vec![0.1; 48_000];
+        tilt.process_block(&mut block);
+        let expected = 0.1 * 10f32.powf(SpeedTilt::DEFAULT_MAX_DB / 20.0);
+        assert!((block[47_999] - expected).abs() < 1e-3, "{} vs {}", block[47_999], expected);
+    }
+}
@@ -1,4 +1,47 @@
 use std::time::Instant;
+use crate::adaptive_gain::{NoiseCombine, NoiseModel, L_DESIRED_DB, USER_OFFSET_DB};
+
+/// Named volume settings: a target level and a user offset that change together
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Preset {
+    Quiet,
+    #[default]
+    Normal,
+    Loud,
+}
+
+impl Preset {
+    /// Desired perceived playback level (dB)
+    pub fn target_db(self) -> f32 {
+        match self {
+            Preset::Quiet => L_DESIRED_DB - 4.0,
+            Preset::Normal => L_DESIRED_DB,
+            Preset::Loud => L_DESIRED_DB + 4.0,
+        }
+    }
+
+    /// User volume offset on top of the computed gain (dB)
+    pub fn user_offset_db(self) -> f32 {
+        match self {
+            Preset::Quiet => USER_OFFSET_DB - 2.0,
+            Preset::Normal => USER_OFFSET_DB,
+            Preset::Loud => USER_OFFSET_DB + 2.0,
+        }
+    }
+}
+
+impl std::str::FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "quiet" => Ok(Preset::Quiet),
+            "normal" => Ok(Preset::Normal),
+            "loud" => Ok(Preset::Loud),
+            other => Err(format!("unknown preset '{}' (expected quiet, normal or loud)", other)),
+        }
+    }
+}
 
 pub struct AdaptiveGain {
     last_gain_db: f32,
@@ -7,44 +50,351 @@ pub struct AdaptiveGain {
     tau_release: f32,
     l_desired_db: f32,
     user_offset_db: f32,
+    noise_combine: NoiseCombine,
+    noise_model: NoiseModel,
+    // bounds applied to the raw gain before smoothing (dB)
+    min_gain_db: f32,
+    max_gain_db: f32,
+    // raw gain changes smaller than this (dB) are ignored to stop gain hunting
+    dead_band_db: f32,
 }
 
 impl AdaptiveGain {
-    pub fn new(l_desired_db: f32, tau_attack: f32, tau_release: f32, user_offset_db: f32) -> Self {
+    pub fn new(
+        l_desired_db: f32,
+        tau_attack: f32,
+        tau_release: f32,
+        user_offset_db: f32,
+        min_gain_db: f32,
+        max_gain_db: f32,
+        dead_band_db: f32,
+    ) -> Self {
+        Self::builder()
+            .target_db(l_desired_db)
+            .tau_attack(tau_attack)
+            .tau_release(tau_release)
+            .user_offset_db(user_offset_db)
+            .min_gain_db(min_gain_db)
+            .max_gain_db(max_gain_db)
+            .dead_band_db(dead_band_db)
+            .build()
+    }
+
+    /// Start from the defaults and override individual settings
+    pub fn builder() -> AdaptiveGainBuilder {
+        AdaptiveGainBuilder::default()
+    }
+
+    pub fn set_noise_combine(&mut self, noise_combine: NoiseCombine) {
+        self.noise_combine = noise_combine;
+    }
+
+    pub fn set_noise_model(&mut self, noise_model: NoiseModel) {
+        self.noise_model = noise_model;
+    }
+
+    /// Change the desired playback level; takes effect on the next `compute_gain`
+    pub fn set_target_level_db(&mut self, db: f32) {
+        self.l_desired_db = db;
+    }
+
+    /// Change the user's volume offset; takes effect on the next `compute_gain`
+    pub fn set_user_offset_db(&mut self, db: f32) {
+        self.user_offset_db = db;
+    }
+
+    /// Switch to `preset`'s target and offset. The gain moves there through the smoother over the
+    /// next `compute_gain` calls rather than jumping.
+    pub fn apply_preset(&mut self, preset: Preset) {
+        self.set_target_level_db(preset.target_db());
+        self.set_user_offset_db(preset.user_offset_db());
+    }
+
+    /// Drop the smoothing state: gain restarts from 0 dB and timing from now
+    pub fn reset(&mut self) {
+        self.last_gain_db = 0.0;
+        self.last_update = Instant::now();
+    }
+
+    /// Keep the current gain without adapting (e.g. while passengers talk); timing restarts from
+    /// now so the next `compute_gain` doesn't smooth over the held interval
+    pub fn hold(&mut self) -> (f32, f32) {
+        self.last_update = Instant::now();
+        (self.last_gain_db, core_dsp::db_to_lin(self.last_gain_db))
+    }
+
+    pub fn compute_gain(&mut self, cabin_db: f32, speed_kmh: f32) -> (f32, f32) {
+        let now = Instant::now();
+        let dt = (now - self.last_update).as_secs_f32();
+        self.last_update = now;
+        self.compute_gain_dt(cabin_db, speed_kmh, dt)
+    }
+
+    /// Like `compute_gain`, but smoothing advances by `dt` seconds of simulated time instead of the
+    /// wall clock (offline processing, replayed traces)
+    pub fn compute_gain_dt(&mut self, cabin_db: f32, speed_kmh: f32, dt: f32) -> (f32, f32) {
+        // no measurement (empty or all-zero mic buffer) or a non-finite reading is a dropout: keep the gain
+        if !(core_dsp::is_measurement(cabin_db) && speed_kmh.is_finite() && dt.is_finite()) {
+            return (self.last_gain_db, core_dsp::db_to_lin(self.last_gain_db));
+        }
+        let noise_db = self.noise_combine.combine(cabin_db, self.noise_model.noise_db(speed_kmh));
+        let mut raw_gain_db = self.l_desired_db - noise_db + self.user_offset_db;
+        raw_gain_db = raw_gain_db.clamp(self.min_gain_db, self.max_gain_db);
+
+        // hysteresis: hold the current gain while the new target stays inside the dead-band
+        if (raw_gain_db - self.last_gain_db).abs() <= self.dead_band_db {
+            raw_gain_db = self.last_gain_db;
+        }
+
+        self.last_gain_db +=
+            core_dsp::attack_release_delta(self.last_gain_db, raw_gain_db, self.tau_attack, self.tau_release, dt);
+
+        let gain_lin = core_dsp::db_to_lin(self.last_gain_db);
+        debug_assert!(self.last_gain_db.is_finite() && gain_lin.is_finite(), "non-finite gain");
+        (self.last_gain_db, gain_lin)
+    }
+}
+
+impl Default for AdaptiveGain {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Named settings for `AdaptiveGain`; unset fields keep the car-audio defaults below
+#[derive(Clone, Debug)]
+pub struct AdaptiveGainBuilder {
+    target_db: f32,
+    tau_attack: f32,
+    tau_release: f32,
+    user_offset_db: f32,
+    min_gain_db: f32,
+    max_gain_db: f32,
+    dead_band_db: f32,
+    noise_model: NoiseModel,
+}
+
+impl Default for AdaptiveGainBuilder {
+    fn default() -> Self {
         Self {
+            target_db: 75.0,
+            // fast enough to follow acceleration, slow enough not to pump on road bumps
+            tau_attack: 0.1,
+            tau_release: 1.0,
+            user_offset_db: 0.0,
+            min_gain_db: -12.0,
+            max_gain_db: 12.0,
+            dead_band_db: 0.0,
+            noise_model: NoiseModel::default(),
+        }
+    }
+}
+
+impl AdaptiveGainBuilder {
+    /// Desired perceived playback level (dB)
+    pub fn target_db(mut self, db: f32) -> Self {
+        self.target_db = db;
+        self
+    }
+
+    /// Time constant (s) when the gain rises
+    pub fn tau_attack(mut self, seconds: f32) -> Self {
+        self.tau_attack = seconds;
+        self
+    }
+
+    /// Time constant (s) when the gain falls
+    pub fn tau_release(mut self, seconds: f32) -> Self {
+        self.tau_release = seconds;
+        self
+    }
+
+    pub fn user_offset_db(mut self, db: f32) -> Self {
+        self.user_offset_db = db;
+        self
+    }
+
+    pub fn min_gain_db(mut self, db: f32) -> Self {
+        self.min_gain_db = db;
+        self
+    }
+
+    pub fn max_gain_db(mut self, db: f32) -> Self {
+        self.max_gain_db = db;
+        self
+    }
+
+    pub fn dead_band_db(mut self, db: f32) -> Self {
+        self.dead_band_db = db;
+        self
+    }
+
+    /// Speed -> noise curve for the vehicle
+    pub fn noise_model(mut self, noise_model: NoiseModel) -> Self {
+        self.noise_model = noise_model;
+        self
+    }
+
+    pub fn build(self) -> AdaptiveGain {
+        assert!(self.min_gain_db <= self.max_gain_db, "min_gain_db must not exceed max_gain_db");
+        AdaptiveGain {
             last_gain_db: 0.0,
             last_update: Instant::now(),
-            tau_attack,
-            tau_release,
-            l_desired_db,
-            user_offset_db,
+            tau_attack: self.tau_attack,
+            tau_release: self.tau_release,
+            l_desired_db: self.target_db,
+            user_offset_db: self.user_offset_db,
+            noise_combine: NoiseCombine::default(),
+            noise_model: self.noise_model,
+            min_gain_db: self.min_gain_db,
+            max_gain_db: self.max_gain_db,
+            dead_band_db: self.dead_band_db.max(0.0),
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adaptive_gain::speed_to_noise;
 
-    fn speed_to_noise(speed_kmh: f32) -> f32 {
-        let a = 6.0;
-        let b = 40.0;
-        a * (speed_kmh + 1.0).ln() + b
+    #[test]
+    fn test_dead_band_holds_gain() {
+        let mut ag = AdaptiveGain::new(75.0, 0.1, 1.0, 0.0, -12.0, 12.0, 1.0);
+        ag.set_noise_combine(NoiseCombine::Max);
+        assert!(speed_to_noise(0.0) < 70.0);
+
+        // noise wiggling +-0.5 dB around the level that gives 0 dB gain
+        for i in 0..50 {
+            let cabin_db = if i % 2 == 0 { 74.5 } else { 75.5 };
+            let (gain_db, gain_lin) = ag.compute_gain_dt(cabin_db, 0.0, 0.001);
+            assert_eq!(gain_db, 0.0, "gain moved inside the dead-band at step {}", i);
+            assert_eq!(gain_lin, 1.0);
+        }
     }
 
-    pub fn compute_gain(&mut self, cabin_db: f32, speed_kmh: f32) -> (f32, f32) {
-        let noise_db = cabin_db.max(Self::speed_to_noise(speed_kmh));
-        let mut raw_gain_db = self.l_desired_db - noise_db + self.user_offset_db;
-        raw_gain_db = raw_gain_db.clamp(-12.0, 12.0);
+    #[test]
+    fn test_zero_dead_band_tracks_small_changes() {
+        let mut ag = AdaptiveGain::new(75.0, 0.1, 1.0, 0.0, -12.0, 12.0, 0.0);
+        ag.set_noise_combine(NoiseCombine::Max);
+        let (gain_db, _) = ag.compute_gain_dt(74.5, 0.0, 0.01);
+        assert!(gain_db > 0.0, "without a dead-band the gain should follow: {}", gain_db);
+    }
 
-        let now = Instant::now();
-        let dt = (now - self.last_update).as_secs_f32();
-        self.last_update = now;
+    #[test]
+    fn test_non_finite_cabin_db_keeps_a_finite_gain() {
+        let mut ag = AdaptiveGain::new(75.0, 0.1, 0.1, 0.0, -12.0, 12.0, 0.0);
+        ag.set_noise_combine(NoiseCombine::Max);
+        let before = (0..100).map(|_| ag.compute_gain_dt(70.0, 0.0, 0.05).0).last().unwrap();
+        for poison in [f32::NAN, f32::NEG_INFINITY, f32::INFINITY, core_dsp::NO_MEASUREMENT_DB] {
+            let (gain_db, gain_lin) = ag.compute_gain_dt(poison, 0.0, 0.05);
+            assert_eq!(gain_db, before, "cabin_db {} moved the gain", poison);
+            assert!(gain_lin.is_finite());
+        }
+        assert_eq!(ag.compute_gain_dt(70.0, f32::NAN, 0.05).0, before);
+        assert_eq!(ag.compute_gain_dt(70.0, 0.0, f32::NAN).0, before);
+        // and it adapts normally afterwards
+        assert!(ag.compute_gain_dt(60.0, 0.0, 0.05).0 > before);
+    }
+
+    fn converge(ag: &mut AdaptiveGain, cabin_db: f32) -> f32 {
+        let mut gain_db = 0.0;
+        for _ in 0..40 {
+            gain_db = ag.compute_gain_dt(cabin_db, 0.0, 0.005).0;
+        }
+        gain_db
+    }
+
+    #[test]
+    fn test_raising_target_raises_converged_gain() {
+        let mut ag = AdaptiveGain::new(75.0, 0.01, 0.01, 0.0, -24.0, 24.0, 0.0);
+        ag.set_noise_combine(NoiseCombine::Max);
+        let before = converge(&mut ag, 70.0);
+        assert!((before - 5.0).abs() < 0.1, "converged gain {}", before);
+
+        ag.set_target_level_db(81.0);
+        let after = converge(&mut ag, 70.0);
+        assert!((after - before - 6.0).abs() < 0.1, "+6 dB target gave {} -> {}", before, after);
+
+        ag.set_user_offset_db(-3.0);
+        let offset = converge(&mut ag, 70.0);
+        assert!((offset - after + 3.0).abs() < 0.1, "-3 dB offset gave {} -> {}", after, offset);
+    }
 
-        let tau = if raw_gain_db > self.last_gain_db {
-            self.tau_attack
-        } else {
-            self.tau_release
+    #[test]
+    fn test_loud_preset_converges_above_quiet() {
+        let converged = |preset: Preset| {
+            let mut ag = AdaptiveGain::default();
+            ag.set_noise_combine(NoiseCombine::Max);
+            ag.apply_preset(preset);
+            (0..100).map(|_| ag.compute_gain_dt(72.0, 0.0, 0.1).0).last().unwrap()
         };
-        let alpha = 1.0 - (-dt / tau).exp();
-        self.last_gain_db += alpha * (raw_gain_db - self.last_gain_db);
+        let (quiet, normal, loud) = (converged(Preset::Quiet), converged(Preset::Normal), converged(Preset::Loud));
+        assert!(quiet < normal && normal < loud, "quiet {} normal {} loud {}", quiet, normal, loud);
+        assert!((loud - quiet - 12.0).abs() < 0.1, "{} dB apart", loud - quiet);
 
-        let gain_lin = 10f32.powf(self.last_gain_db / 20.0);
-        (self.last_gain_db, gain_lin)
+        // switching ramps through the smoother: one 50 ms step covers only part of the change
+        let mut ag = AdaptiveGain::default();
+        ag.set_noise_combine(NoiseCombine::Max);
+        let settled = (0..100).map(|_| ag.compute_gain_dt(72.0, 0.0, 0.1).0).last().unwrap();
+        ag.apply_preset(Preset::Quiet);
+        let first = ag.compute_gain_dt(72.0, 0.0, 0.05).0;
+        assert!(first < settled && first > quiet + 1.0, "{} -> {} toward {}", settled, first, quiet);
+        assert_eq!("LOUD".parse(), Ok(Preset::Loud));
+        assert!("max".parse::<Preset>().is_err());
+    }
+
+    #[test]
+    fn test_reset_clears_smoothing_state() {
+        let mut ag = AdaptiveGain::new(75.0, 0.01, 0.01, 0.0, -24.0, 24.0, 0.0);
+        ag.set_noise_combine(NoiseCombine::Max);
+        converge(&mut ag, 60.0);
+        ag.reset();
+        let (gain_db, _) = ag.compute_gain(60.0, 0.0);
+        assert!(gain_db < 15.0 * 0.5, "first step after reset starts near 0 dB: {}", gain_db);
+    }
+
+    #[test]
+    fn test_builder_overrides_only_named_fields() {
+        let ag = AdaptiveGain::builder().target_db(80.0).max_gain_db(18.0).build();
+        let default = AdaptiveGain::default();
+        assert_eq!(ag.l_desired_db, 80.0);
+        assert_eq!(ag.max_gain_db, 18.0);
+        assert_eq!(ag.min_gain_db, default.min_gain_db);
+        assert_eq!(ag.tau_attack, default.tau_attack);
+        assert_eq!(ag.tau_release, default.tau_release);
+    }
+
+    #[test]
+    #[should_panic(expected = "min_gain_db must not exceed max_gain_db")]
+    fn test_builder_rejects_inverted_bounds() {
+        AdaptiveGain::builder().min_gain_db(6.0).max_gain_db(-6.0).build();
+    }
+
+    #[test]
+    fn test_noise_model_drives_gain() {
+        // flat 70 dB road noise regardless of speed, louder than the 60 dB cabin
+        let mut ag = AdaptiveGain::builder()
+            .tau_attack(0.01)
+            .tau_release(0.01)
+            .noise_model(NoiseModel::Table(vec![(0.0, 70.0), (200.0, 70.0)]))
+            .build();
+        ag.set_noise_combine(NoiseCombine::Max);
+        let gain_db = converge(&mut ag, 60.0);
+        assert!((gain_db - 5.0).abs() < 0.1, "75 - 70 = 5 dB, got {}", gain_db);
+    }
+
+    #[test]
+    fn test_gain_clamped_to_max() {
+        let mut ag = AdaptiveGain::new(75.0, 0.01, 1.0, 0.0, -6.0, 6.0, 0.0);
+        ag.set_noise_combine(NoiseCombine::Max);
+
+        // 40 dB of noise asks for +35 dB, which must be capped at +6 dB
+        let mut gain_db = 0.0;
+        for _ in 0..20 {
+            gain_db = ag.compute_gain_dt(40.0, 0.0, 0.005).0;
+            assert!(gain_db <= 6.0, "gain {} exceeded max_gain_db", gain_db);
+        }
+        assert!((gain_db - 6.0).abs() < 0.05, "smoother should settle at the clamp: {}", gain_db);
     }
 }
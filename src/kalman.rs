@@ -0,0 +1,126 @@
+//! 1-D Kalman filter for the cabin noise level. The raw RMS-to-dB estimate jitters by a few dB from
+//! window to window; a fixed EMA either lags real changes or passes the jitter. Here the level is a
+//! random walk measured with noise, and when a reading lands far outside what the filter expects
+//! (a door opening, a tunnel, a window going down) the uncertainty is inflated so the estimate
+//! jumps to the new level instead of crawling toward it, then settles back to heavy smoothing.
+
+/// Default process noise: how far the true level drifts per update (dB², 0.1 dB std)
+pub const DEFAULT_PROCESS_NOISE_DB2: f32 = 0.01;
+/// Default measurement noise: window-to-window jitter of the RMS level (dB², 2 dB std)
+pub const DEFAULT_MEASUREMENT_NOISE_DB2: f32 = 4.0;
+/// Normalised innovation (squared, in units of its expected variance) above which the level is
+/// taken to have moved: 3 sigma
+const MANEUVER_GATE: f32 = 9.0;
+
+#[derive(Clone, Debug)]
+pub struct KalmanLevel {
+    process_noise: f32,
+    measurement_noise: f32,
+    /// Current estimate (dB); `None` until the first measurement
+    estimate: Option<f32>,
+    /// Estimate variance (dB²)
+    variance: f32,
+}
+
+impl KalmanLevel {
+    /// `process_noise_db2`: expected drift of the true level per update; `measurement_noise_db2`:
+    /// variance of the readings around it. Larger `process / measurement` ratios track faster.
+    pub fn new(process_noise_db2: f32, measurement_noise_db2: f32) -> Self {
+        Self {
+            process_noise: process_noise_db2.max(0.0),
+            measurement_noise: measurement_noise_db2.max(1e-6),
+            estimate: None,
+            variance: 0.0,
+        }
+    }
+
+    /// Feed one level reading (dB); returns the filtered level
+    pub fn update(&mut self, measurement_db: f32) -> f32 {
+        let Some(estimate) = self.estimate else {
+            self.estimate = Some(measurement_db);
+            self.variance = self.measurement_noise;
+            return measurement_db;
+        };
+        // predict: the level may have drifted
+        let mut variance = self.variance + self.process_noise;
+        let innovation = measurement_db - estimate;
+        let expected = variance + self.measurement_noise;
+        // a reading this far out means the level really moved: take the surprise as uncertainty
+        if innovation * innovation > MANEUVER_GATE * expected {
+            variance += innovation * innovation - expected;
+        }
+        let gain = variance / (variance + self.measurement_noise);
+        let estimate = estimate + gain * innovation;
+        self.estimate = Some(estimate);
+        self.variance = (1.0 - gain) * variance;
+        estimate
+    }
+
+    /// Latest filtered level, if any measurement has arrived
+    pub fn estimate(&self) -> Option<f32> {
+        self.estimate
+    }
+}
+
+impl Default for KalmanLevel {
+    fn default() -> Self {
+        Self::new(DEFAULT_PROCESS_NOISE_DB2, DEFAULT_MEASUREMENT_NOISE_DB2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // 60 dB for 200 windows, then 75 dB, with ±3.5 dB uniform jitter (about 2 dB std)
+    fn noisy_step() -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(73);
+        (0..400).map(|k| if k < 200 { 60.0 } else { 75.0 } + rng.random_range(-3.5..3.5)).collect()
+    }
+
+    #[test]
+    fn test_tracks_a_step_with_less_overshoot_than_an_ema() {
+        let readings = noisy_step();
+        let mut kalman = KalmanLevel::default();
+        let filtered: Vec<f32> = readings.iter().map(|&z| kalman.update(z)).collect();
+        // an EMA fast enough to cross 90% of the step in about the same time
+        let alpha = 0.3;
+        let mut ema = readings[0];
+        let smoothed: Vec<f32> = readings
+            .iter()
+            .map(|&z| {
+                ema += alpha * (z - ema);
+                ema
+            })
+            .collect();
+
+        let settle = |track: &[f32]| (200..400).find(|&k| track[k] > 73.5).expect("reaches the new level") - 200;
+        assert!(
+            settle(&filtered) <= settle(&smoothed) + 2,
+            "kalman {} vs ema {} windows",
+            settle(&filtered),
+            settle(&smoothed)
+        );
+
+        let overshoot = |track: &[f32]| track[210..].iter().fold(f32::MIN, |m, &v| m.max(v)) - 75.0;
+        assert!(
+            overshoot(&filtered) < 0.5 * overshoot(&smoothed),
+            "overshoot: kalman {:.2} dB, ema {:.2} dB",
+            overshoot(&filtered),
+            overshoot(&smoothed)
+        );
+    }
+
+    #[test]
+    fn test_steady_level_is_smoothed() {
+        let readings = noisy_step();
+        let mut kalman = KalmanLevel::default();
+        let filtered: Vec<f32> = readings[..200].iter().map(|&z| kalman.update(z)).collect();
+        let spread =
+            |v: &[f32]| v.iter().fold(f32::MIN, |m, &x| m.max(x)) - v.iter().fold(f32::MAX, |m, &x| m.min(x));
+        assert!(spread(&filtered[50..]) < 0.25 * spread(&readings[50..200]), "{} dB", spread(&filtered[50..]));
+        assert_eq!(KalmanLevel::default().estimate(), None);
+    }
+}
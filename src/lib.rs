@@ -0,0 +1,55 @@
+//! Adaptive in-car volume control: the DSP and control code shared by the binaries.
+//!
+//! The default build is the algorithm alone. Device I/O is behind cargo features:
+//! `playback-cpal` (`device`), `speed-http` (`speed_source::HttpPoller`) and `wav` (`offline`);
+//! `playback-rodio` is only used by the CLI. The `cli` feature enables everything the
+//! `adaptive_vol` binary needs; `metrics` adds its Prometheus endpoint (`metrics::MetricsServer`).
+
+pub mod adaptive_gain;
+pub mod config;
+pub mod control;
+pub mod controller;
+#[cfg(feature = "playback-cpal")]
+pub mod device;
+pub mod dropout;
+pub mod dynamics;
+pub mod eq;
+pub mod fade;
+pub mod filters;
+pub mod gain;
+pub mod kalman;
+pub mod loudness;
+pub mod meter;
+pub mod metrics;
+pub mod mic_array;
+pub mod mixer;
+pub mod multiband;
+pub mod nmea;
+pub mod obd;
+#[cfg(feature = "wav")]
+pub mod offline;
+pub mod profile;
+pub mod resample;
+pub mod self_masking;
+pub mod serial;
+#[cfg(feature = "simd")]
+pub mod simd;
+pub mod spectral;
+pub mod speed;
+pub mod speed_source;
+pub mod spl;
+pub mod spsc;
+pub mod synthetic;
+pub mod telemetry;
+pub mod trace;
+pub mod true_peak;
+pub mod util;
+pub mod vad;
+mod ws;
+
+pub use config::Config;
+pub use controller::{ControllerKind, GainController, PidGainController, PidGains};
+pub use core_dsp;
+pub use adaptive_gain::{apply_gain_and_limit, db_to_lin, soft_limit, speed_to_noise, NoiseModel, Smoother};
+pub use gain::{AdaptiveGain, Preset};
+pub use profile::VehicleProfile;
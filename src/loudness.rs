@@ -0,0 +1,213 @@
+//! Integrated programme loudness per ITU-R BS.1770-4, in LUFS.
+//!
+//! Each channel is K-weighted (a +4 dB high shelf modelling the head, then the RLB high-pass),
+//! squared and averaged over 400 ms blocks that start every 100 ms. Blocks below -70 LUFS are
+//! dropped (absolute gate), then those more than 10 LU below the loudness of the remaining ones
+//! (relative gate); the integrated loudness is the level of what is left.
+
+use crate::filters::Biquad;
+
+/// Gating block length and hop (s): 400 ms blocks with 75% overlap
+const BLOCK_S: f32 = 0.4;
+const HOP_S: f32 = 0.1;
+/// Absolute gate (LUFS)
+pub const ABSOLUTE_GATE_LUFS: f32 = -70.0;
+/// Relative gate, below the absolute-gated loudness (LU)
+pub const RELATIVE_GATE_LU: f32 = 10.0;
+
+/// Analog prototypes of the K-weighting stages, from which the BS.1770-4 48 kHz coefficients come
+/// out exactly and which give the same response at any other rate
+const SHELF_HZ: f64 = 1681.974450955533;
+const SHELF_Q: f64 = 0.7071752369554196;
+const SHELF_DB: f64 = 3.999843853973347;
+const SHELF_BAND_EXPONENT: f64 = 0.4996667741545416;
+const HIGHPASS_HZ: f64 = 38.13547087602444;
+const HIGHPASS_Q: f64 = 0.5003270373238773;
+
+/// Loudness (LUFS) of a weighted channel power sum
+fn power_to_lufs(power: f64) -> f32 {
+    (-0.691 + 10.0 * power.max(1e-20).log10()) as f32
+}
+
+/// The two-stage K-weighting filter of one channel
+#[derive(Clone, Debug)]
+struct KWeighting {
+    shelf: Biquad,
+    highpass: Biquad,
+}
+
+impl KWeighting {
+    fn new(sample_rate: f32) -> Self {
+        let k = |freq_hz: f64| (std::f64::consts::PI * freq_hz / sample_rate as f64).tan();
+
+        let (k1, q1) = (k(SHELF_HZ), SHELF_Q);
+        let vh = 10f64.powf(SHELF_DB / 20.0);
+        let vb = vh.powf(SHELF_BAND_EXPONENT);
+        let shelf = Biquad::new(
+            [vh + vb * k1 / q1 + k1 * k1, 2.0 * (k1 * k1 - vh), vh - vb * k1 / q1 + k1 * k1],
+            [1.0 + k1 / q1 + k1 * k1, 2.0 * (k1 * k1 - 1.0), 1.0 - k1 / q1 + k1 * k1],
+        );
+
+        let (k2, q2) = (k(HIGHPASS_HZ), HIGHPASS_Q);
+        let a0 = 1.0 + k2 / q2 + k2 * k2;
+        // b stays [1, -2, 1] as in the standard, rather than normalised by a0
+        let highpass = Biquad::new(
+            [a0, -2.0 * a0, a0],
+            [a0, 2.0 * (k2 * k2 - 1.0), 1.0 - k2 / q2 + k2 * k2],
+        );
+        Self { shelf, highpass }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        self.highpass.process(self.shelf.process(x))
+    }
+}
+
+/// Measures the integrated loudness of interleaved audio fed in any chunk sizes
+pub struct LoudnessMeter {
+    channels: usize,
+    filters: Vec<KWeighting>,
+    /// BS.1770 channel weights: 1.0 for front channels, 1.41 for surrounds, 0 for the LFE
+    weights: Vec<f64>,
+    hop_frames: usize,
+    /// Weighted sum of squares of the hop in progress, and the frames in it
+    hop_energy: f64,
+    hop_filled: usize,
+    /// The last four complete hops, making up the next 400 ms block
+    recent_hops: [f64; 4],
+    hops_seen: usize,
+    /// Mean-square power of every complete block
+    block_powers: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: f32, channels: usize) -> Self {
+        let channels = channels.max(1);
+        // 5.1 in the usual L, R, C, LFE, Ls, Rs order
+        let weights = if channels == 6 { vec![1.0, 1.0, 1.0, 0.0, 1.41, 1.41] } else { vec![1.0; channels] };
+        Self {
+            channels,
+            filters: vec![KWeighting::new(sample_rate); channels],
+            weights,
+            hop_frames: ((sample_rate * HOP_S).round() as usize).max(1),
+            hop_energy: 0.0,
+            hop_filled: 0,
+            recent_hops: [0.0; 4],
+            hops_seen: 0,
+            block_powers: Vec::new(),
+        }
+    }
+
+    /// Feed interleaved samples (full scale 1.0); a trailing partial frame is ignored
+    pub fn process_interleaved(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for ((filter, weight), &x) in self.filters.iter_mut().zip(&self.weights).zip(frame) {
+                let y = filter.process(x) as f64;
+                self.hop_energy += weight * y * y;
+            }
+            self.hop_filled += 1;
+            if self.hop_filled == self.hop_frames {
+                self.finish_hop();
+            }
+        }
+    }
+
+    fn finish_hop(&mut self) {
+        self.recent_hops.rotate_left(1);
+        self.recent_hops[3] = self.hop_energy;
+        self.hop_energy = 0.0;
+        self.hop_filled = 0;
+        self.hops_seen += 1;
+        let hops_per_block = (BLOCK_S / HOP_S).round() as usize;
+        if self.hops_seen >= hops_per_block {
+            let energy: f64 = self.recent_hops.iter().sum();
+            self.block_powers.push(energy / (hops_per_block * self.hop_frames) as f64);
+        }
+    }
+
+    /// Gated integrated loudness of everything fed so far (LUFS); `None` until a 400 ms block
+    /// passes the absolute gate
+    pub fn integrated_lufs(&self) -> Option<f32> {
+        let above_absolute: Vec<f64> =
+            self.block_powers.iter().copied().filter(|&p| power_to_lufs(p) > ABSOLUTE_GATE_LUFS).collect();
+        if above_absolute.is_empty() {
+            return None;
+        }
+        let mean = |powers: &[f64]| powers.iter().sum::<f64>() / powers.len() as f64;
+        let relative_gate = power_to_lufs(mean(&above_absolute)) - RELATIVE_GATE_LU;
+        let gated: Vec<f64> = above_absolute.into_iter().filter(|&p| power_to_lufs(p) > relative_gate).collect();
+        Some(power_to_lufs(mean(&gated)))
+    }
+
+    /// Loudness of the most recent 400 ms block (LUFS, ungated), if one has completed
+    pub fn momentary_lufs(&self) -> Option<f32> {
+        self.block_powers.last().map(|&p| power_to_lufs(p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const RATE: f32 = 48_000.0;
+
+    // Interleaved stereo 1 kHz sine, both channels at `dbfs` peak level
+    fn stereo_sine(dbfs: f32, seconds: f32) -> Vec<f32> {
+        let amplitude = 10f32.powf(dbfs / 20.0);
+        (0..(seconds * RATE) as usize)
+            .flat_map(|i| {
+                let s = amplitude * (2.0 * PI * 1000.0 * i as f32 / RATE).sin();
+                [s, s]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_k_weighting_matches_the_standard_at_48k() {
+        let k = KWeighting::new(RATE);
+        let shelf = [k.shelf.b0, k.shelf.b1, k.shelf.b2, k.shelf.a1, k.shelf.a2];
+        let reference = [1.53512485958697, -2.69169618940638, 1.19839281085285, -1.69065929318241, 0.73248077421585];
+        for (got, want) in shelf.iter().zip(reference) {
+            assert!((got - want).abs() < 1e-9, "shelf {:?}", shelf);
+        }
+        assert!((k.highpass.a1 + 1.99004745483398).abs() < 1e-9 && (k.highpass.a2 - 0.99007225036621).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_reference_sine_reads_minus_23_lufs() {
+        // EBU Tech 3341 case 1: stereo 1 kHz at -23 dBFS is -23 LUFS
+        for rate in [RATE, 44_100.0] {
+            let amplitude = 10f32.powf(-23.0 / 20.0);
+            let signal: Vec<f32> = (0..(5.0 * rate) as usize)
+                .flat_map(|i| {
+                    let s = amplitude * (2.0 * PI * 1000.0 * i as f32 / rate).sin();
+                    [s, s]
+                })
+                .collect();
+            let mut meter = LoudnessMeter::new(rate, 2);
+            // odd chunk sizes so hops straddle calls
+            for chunk in signal.chunks(2 * 997) {
+                meter.process_interleaved(chunk);
+            }
+            let lufs = meter.integrated_lufs().unwrap();
+            assert!((lufs + 23.0).abs() < 0.5, "{} Hz: {} LUFS", rate, lufs);
+        }
+    }
+
+    #[test]
+    fn test_relative_gate_ignores_quiet_passages() {
+        // EBU Tech 3341 case 3 in short: quiet -36 dBFS passages around a -23 dBFS one still read -23
+        let mut meter = LoudnessMeter::new(RATE, 2);
+        meter.process_interleaved(&stereo_sine(-36.0, 2.0));
+        meter.process_interleaved(&stereo_sine(-23.0, 6.0));
+        meter.process_interleaved(&stereo_sine(-36.0, 2.0));
+        let lufs = meter.integrated_lufs().unwrap();
+        assert!((lufs + 23.0).abs() < 0.5, "{} LUFS", lufs);
+
+        let mut silent = LoudnessMeter::new(RATE, 2);
+        silent.process_interleaved(&vec![0.0; 2 * RATE as usize]);
+        assert_eq!(silent.integrated_lufs(), None, "silence is below the absolute gate");
+        assert!(silent.momentary_lufs().unwrap() < ABSOLUTE_GATE_LUFS);
+    }
+}
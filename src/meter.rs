@@ -0,0 +1,119 @@
+//! Output level metering across threads. The audio callback folds each buffer's peak and energy
+//! into a `Meter` with a few atomic operations (no locks, no allocation); a monitor thread takes the
+//! accumulated reading at its own pace and shows it in dBFS with a decaying peak hold.
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::util::AtomicF32;
+
+/// Peak and RMS of everything recorded since the last `Meter::take`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeterReading {
+    pub peak: f32,
+    pub rms: f32,
+}
+
+impl MeterReading {
+    pub fn peak_dbfs(&self) -> f32 {
+        20.0 * self.peak.max(1e-9).log10()
+    }
+
+    pub fn rms_dbfs(&self) -> f32 {
+        20.0 * self.rms.max(1e-9).log10()
+    }
+}
+
+/// Peak and energy accumulator written by the audio callback and drained by a monitor
+#[derive(Default)]
+pub struct Meter {
+    /// Largest magnitude, as f32 bits: for non-negative floats the bit patterns order like the values
+    peak_bits: AtomicU32,
+    energy: AtomicF32,
+    samples: AtomicUsize,
+}
+
+impl Meter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one block's largest magnitude, sum of squares and sample count
+    pub fn record_block(&self, peak: f32, sum_squares: f32, samples: usize) {
+        self.peak_bits.fetch_max(peak.abs().to_bits(), Ordering::Relaxed);
+        let mut current = self.energy.load(Ordering::Relaxed);
+        while let Err(actual) =
+            self.energy.compare_exchange(current, current + sum_squares, Ordering::Relaxed, Ordering::Relaxed)
+        {
+            current = actual;
+        }
+        self.samples.fetch_add(samples, Ordering::Relaxed);
+    }
+
+    /// The reading since the previous call, resetting the accumulators; `None` if nothing was recorded
+    pub fn take(&self) -> Option<MeterReading> {
+        let samples = self.samples.swap(0, Ordering::Relaxed);
+        let energy = self.energy.swap(0.0, Ordering::Relaxed);
+        let peak = f32::from_bits(self.peak_bits.swap(0, Ordering::Relaxed));
+        (samples > 0).then(|| MeterReading { peak, rms: (energy / samples as f32).sqrt() })
+    }
+}
+
+/// Peak display that jumps up immediately and falls back at `decay_db_per_s`
+pub struct PeakHold {
+    decay_db_per_s: f32,
+    held_db: f32,
+}
+
+impl PeakHold {
+    pub fn new(decay_db_per_s: f32) -> Self {
+        Self { decay_db_per_s, held_db: f32::NEG_INFINITY }
+    }
+
+    /// Feed the latest peak (dB) after `dt` seconds; returns the held value
+    pub fn update(&mut self, peak_db: f32, dt: f32) -> f32 {
+        self.held_db = peak_db.max(self.held_db - self.decay_db_per_s * dt.max(0.0));
+        self.held_db
+    }
+}
+
+/// `width`-character bar for `level_db` on a `floor_db`..0 dBFS scale, with `|` marking `peak_db`
+pub fn level_bar(level_db: f32, peak_db: f32, floor_db: f32, width: usize) -> String {
+    let cells = |db: f32| (((db - floor_db) / -floor_db).clamp(0.0, 1.0) * width as f32).round() as usize;
+    let (fill, peak) = (cells(level_db), cells(peak_db).min(width.saturating_sub(1)));
+    (0..width)
+        .map(|i| if i < fill { '#' } else if i == peak && peak_db > floor_db { '|' } else { '-' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter_accumulates_and_resets() {
+        let meter = Meter::new();
+        assert_eq!(meter.take(), None);
+        // two blocks: a 0.5 square wave, then silence with one -0.25 spike, across threads
+        std::thread::scope(|s| {
+            s.spawn(|| meter.record_block(0.5, 0.25 * 100.0, 100));
+            s.spawn(|| meter.record_block(-0.25, 0.0625, 100));
+        });
+        let reading = meter.take().unwrap();
+        assert_eq!(reading.peak, 0.5);
+        assert!((reading.rms - ((25.0 + 0.0625) / 200.0f32).sqrt()).abs() < 1e-6);
+        assert!((reading.peak_dbfs() + 6.02).abs() < 0.01);
+        assert_eq!(meter.take(), None, "taking resets the meter");
+    }
+
+    #[test]
+    fn test_peak_hold_decays_and_bar_renders() {
+        let mut hold = PeakHold::new(10.0);
+        assert_eq!(hold.update(-3.0, 0.1), -3.0);
+        assert_eq!(hold.update(-20.0, 0.5), -8.0, "falls at 10 dB/s");
+        assert_eq!(hold.update(-1.0, 0.1), -1.0, "jumps straight up");
+
+        assert_eq!(level_bar(-30.0, -12.0, -60.0, 10), "#####---|-");
+        assert_eq!(level_bar(-90.0, -90.0, -60.0, 4), "----");
+        assert_eq!(level_bar(3.0, 3.0, -60.0, 4), "####");
+    }
+}
@@ -0,0 +1,224 @@
+//! Prometheus metrics for a fleet dashboard. `Metrics` is a set of atomics the controller and the
+//! audio callback update as they run; with the `metrics` feature, `MetricsServer` serves them in the
+//! Prometheus text format on `GET /metrics` (std only, like `control`).
+//!
+//! ```text
+//! gain_db, cabin_db, speed_kmh                                gauges, from the controller
+//! underruns_total, clips_total                                counters, from the output callback
+//! speed_poll_errors_total                                     counter, from the speed source
+//! ```
+
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::util::AtomicF32;
+
+/// Gauges and counters shared by the threads that update them and the server that reports them
+#[derive(Debug, Default)]
+pub struct Metrics {
+    gain_db: AtomicF32,
+    cabin_db: AtomicF32,
+    speed_kmh: AtomicF32,
+    underruns: AtomicUsize,
+    clips: AtomicUsize,
+    speed_poll_errors: AtomicUsize,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one controller step
+    pub fn set_controller(&self, gain_db: f32, cabin_db: f32, speed_kmh: f32) {
+        self.gain_db.store(gain_db, Ordering::Relaxed);
+        self.cabin_db.store(cabin_db, Ordering::Relaxed);
+        self.speed_kmh.store(speed_kmh, Ordering::Relaxed);
+    }
+
+    pub fn add_underruns(&self, count: usize) {
+        self.underruns.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn add_clips(&self, count: usize) {
+        self.clips.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// The speed source's running error count (it only ever grows)
+    pub fn set_speed_poll_errors(&self, total: usize) {
+        self.speed_poll_errors.fetch_max(total, Ordering::Relaxed);
+    }
+
+    /// Everything in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let gauges = [
+            ("gain_db", "Gain applied to the playback (dB)", self.gain_db.load(Ordering::Relaxed)),
+            ("cabin_db", "Cabin noise level the controller sees (dB SPL)", self.cabin_db.load(Ordering::Relaxed)),
+            ("speed_kmh", "Smoothed vehicle speed (km/h)", self.speed_kmh.load(Ordering::Relaxed)),
+        ];
+        let counters = [
+            ("underruns_total", "Output callbacks that ran out of queued audio", &self.underruns),
+            ("clips_total", "Output samples the limiter had to pull down", &self.clips),
+            ("speed_poll_errors_total", "Failed speed polls, lost connections and unusable messages", &self.speed_poll_errors),
+        ];
+        let mut text = String::new();
+        for (name, help, value) in gauges {
+            let _ = writeln!(text, "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}");
+        }
+        for (name, help, value) in counters {
+            let _ = writeln!(text, "# HELP {name} {help}\n# TYPE {name} counter\n{name} {}", value.load(Ordering::Relaxed));
+        }
+        text
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub use server::MetricsServer;
+
+#[cfg(feature = "metrics")]
+mod server {
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    use log::warn;
+
+    use super::Metrics;
+    use crate::util::spawn_named;
+
+    /// A scraper gets this long to send its request
+    const READ_TIMEOUT: Duration = Duration::from_secs(2);
+    /// How often the accept loop checks `stop` while idle
+    const ACCEPT_POLL: Duration = Duration::from_millis(50);
+
+    /// Answer one request: the metrics on `GET /metrics`, 404/405 otherwise
+    fn serve_connection(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // a scrape has no body; skip the headers
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+        }
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        let (status, body) = match (method, path.split('?').next().unwrap_or(path)) {
+            ("GET", "/metrics") => ("200 OK", metrics.render()),
+            (_, "/metrics") => ("405 Method Not Allowed", String::new()),
+            _ => ("404 Not Found", String::new()),
+        };
+        let mut stream = stream;
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        stream.flush()
+    }
+
+    /// Serves `/metrics` on its own thread
+    pub struct MetricsServer {
+        listener: TcpListener,
+    }
+
+    impl MetricsServer {
+        pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+            let listener = TcpListener::bind(addr)?;
+            // polled so the thread notices `stop` between scrapes
+            listener.set_nonblocking(true)?;
+            Ok(Self { listener })
+        }
+
+        pub fn local_addr(&self) -> io::Result<SocketAddr> {
+            self.listener.local_addr()
+        }
+
+        /// Serve `metrics` until `stop` is set
+        pub fn spawn(self, metrics: Arc<Metrics>, stop: &'static AtomicBool) -> JoinHandle<()> {
+            spawn_named("metrics", move || {
+                while !stop.load(Ordering::Relaxed) {
+                    match self.listener.accept() {
+                        Ok((stream, peer)) => {
+                            let served =
+                                stream.set_nonblocking(false).and_then(|_| serve_connection(stream, &metrics));
+                            if let Err(e) = served {
+                                warn!("[Metrics] scrape from {} failed: {}", peer, e);
+                            }
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL),
+                        Err(e) => {
+                            warn!("[Metrics] accept failed: {}", e);
+                            thread::sleep(ACCEPT_POLL);
+                        }
+                    }
+                }
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::io::Read;
+
+        #[test]
+        fn test_server_serves_metrics_over_tcp() {
+            let server = MetricsServer::bind("127.0.0.1:0").unwrap();
+            let addr = server.local_addr().unwrap();
+            let metrics = Arc::new(Metrics::new());
+            metrics.add_clips(3);
+            let stop: &'static AtomicBool = Box::leak(Box::new(AtomicBool::new(false)));
+            let handle = server.spawn(metrics.clone(), stop);
+
+            let get = |path: &str| {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                write!(stream, "GET {} HTTP/1.1\r\nHost: car\r\n\r\n", path).unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+                response
+            };
+            let response = get("/metrics");
+            assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+            assert!(response.contains("\nclips_total 3\n"), "{}", response);
+            assert!(get("/").starts_with("HTTP/1.1 404"));
+
+            stop.store(true, Ordering::Relaxed);
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_prometheus_text() {
+        let metrics = Metrics::new();
+        metrics.set_controller(4.5, 68.25, 90.0);
+        metrics.add_underruns(2);
+        metrics.add_underruns(1);
+        metrics.set_speed_poll_errors(7);
+        metrics.set_speed_poll_errors(5);
+        let text = metrics.render();
+        for line in ["gain_db 4.5", "cabin_db 68.25", "speed_kmh 90", "underruns_total 3", "clips_total 0"] {
+            assert!(text.lines().any(|l| l == line), "missing {:?} in\n{}", line, text);
+        }
+        assert!(text.contains("speed_poll_errors_total 7\n"), "counters never go back down");
+        assert!(text.contains("# TYPE underruns_total counter\n") && text.contains("# TYPE gain_db gauge\n"));
+        // every sample line is `name value` with a parseable number
+        for line in text.lines().filter(|l| !l.starts_with('#')) {
+            let (_, value) = line.split_once(' ').unwrap();
+            value.parse::<f64>().unwrap();
+        }
+    }
+}
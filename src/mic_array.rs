@@ -0,0 +1,132 @@
+//! Cabin level from several mics (e.g. driver and passenger). Each mic's level is measured on its
+//! own and the levels are averaged in the power domain, so one mic sitting next to a vent or under
+//! a jacket moves the estimate less than it would alone. A mic that looks covered (its raw level
+//! near zero but jumping around, as when something rubs against it) is left out, and optionally so
+//! is any mic too far from the median of the others.
+
+use std::collections::VecDeque;
+
+use core_dsp::{is_measurement, rms_to_db};
+
+/// A mic's raw per-window levels kept for the obstruction check
+pub const HISTORY_WINDOWS: usize = 20;
+
+/// Power-domain mean of levels in dB; None for no levels
+pub fn power_mean_db(levels_db: &[f32]) -> Option<f32> {
+    if levels_db.is_empty() {
+        return None;
+    }
+    let mean_power = levels_db.iter().map(|db| 10f32.powf(db / 10.0)).sum::<f32>() / levels_db.len() as f32;
+    Some(10.0 * mean_power.max(1e-30).log10())
+}
+
+/// Combines the levels of `mics` mics into one
+pub struct MicArray {
+    /// A mic whose recent raw level averages below this (dBFS)...
+    pub obstructed_dbfs: f32,
+    /// ...while varying by more than this (standard deviation, dB) is taken to be covered
+    pub obstructed_std_db: f32,
+    /// Leave out mics further than this (dB) from the median level; None keeps them all
+    pub outlier_db: Option<f32>,
+    history: Vec<VecDeque<f32>>,
+}
+
+impl MicArray {
+    pub const DEFAULT_OBSTRUCTED_DBFS: f32 = -70.0;
+    pub const DEFAULT_OBSTRUCTED_STD_DB: f32 = 6.0;
+
+    pub fn new(mics: usize) -> Self {
+        Self {
+            obstructed_dbfs: Self::DEFAULT_OBSTRUCTED_DBFS,
+            obstructed_std_db: Self::DEFAULT_OBSTRUCTED_STD_DB,
+            outlier_db: None,
+            history: vec![VecDeque::with_capacity(HISTORY_WINDOWS); mics.max(1)],
+        }
+    }
+
+    pub fn mics(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Record one window of raw (unfiltered, unnormalized) samples per mic
+    pub fn observe(&mut self, raw: &[Vec<f32>]) {
+        for (history, samples) in self.history.iter_mut().zip(raw) {
+            if history.len() == HISTORY_WINDOWS {
+                history.pop_front();
+            }
+            history.push_back(rms_to_db(samples, 0.0));
+        }
+    }
+
+    /// Whether `mic` looks covered: near-zero energy with a high variance over the recent windows
+    pub fn is_obstructed(&self, mic: usize) -> bool {
+        let Some(history) = self.history.get(mic).filter(|h| h.len() > 1) else {
+            return false;
+        };
+        let n = history.len() as f32;
+        let mean = history.iter().sum::<f32>() / n;
+        let variance = history.iter().map(|db| (db - mean) * (db - mean)).sum::<f32>() / n;
+        mean < self.obstructed_dbfs && variance.sqrt() > self.obstructed_std_db
+    }
+
+    /// Combine one level per mic (dB, in mic order) into the cabin level. Mics without a measurement,
+    /// obstructed mics and, with `outlier_db`, outliers are left out; if that leaves nothing every mic
+    /// is used.
+    pub fn combine(&self, levels_db: &[f32]) -> Option<f32> {
+        let mut used: Vec<f32> = levels_db
+            .iter()
+            .enumerate()
+            .filter(|&(mic, &db)| is_measurement(db) && !self.is_obstructed(mic))
+            .map(|(_, &db)| db)
+            .collect();
+        if let Some(outlier_db) = self.outlier_db {
+            let mut sorted = used.clone();
+            sorted.sort_by(f32::total_cmp);
+            if let Some(&median) = sorted.get(sorted.len() / 2) {
+                used.retain(|db| (db - median).abs() <= outlier_db);
+            }
+        }
+        if used.is_empty() {
+            return power_mean_db(levels_db);
+        }
+        power_mean_db(&used)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_averages_mic_levels_in_power_leaving_out_an_obstructed_mic() {
+        let window = |amplitude: f32| vec![amplitude; 64];
+        let mut array = MicArray::new(3);
+        // mics 0 and 1 hear steady noise; mic 2 is covered: almost silent, with the odd rub
+        for i in 0..HISTORY_WINDOWS {
+            let covered = if i % 3 == 0 { 1e-3 } else { 1e-5 };
+            array.observe(&[window(0.01), window(0.02), window(covered)]);
+        }
+        assert!(!array.is_obstructed(0) && !array.is_obstructed(1));
+        assert!(array.is_obstructed(2));
+
+        // 60 and 66 dB: the power mean is 64 dB, not the 63 dB arithmetic mean
+        let expected = 10.0 * ((1e6f32 + 10f32.powf(6.6)) / 2.0).log10();
+        let combined = array.combine(&[60.0, 66.0, 30.0]).unwrap();
+        assert!((combined - expected).abs() < 1e-3, "{} vs {}", combined, expected);
+
+        // quiet but steady is a real reading, not a covered mic
+        let mut array = MicArray::new(2);
+        for _ in 0..HISTORY_WINDOWS {
+            array.observe(&[window(0.01), window(1e-5)]);
+        }
+        assert!(!array.is_obstructed(1));
+
+        // with outlier rejection a mic 20 dB off the median is left out
+        let mut array = MicArray::new(4);
+        array.outlier_db = Some(10.0);
+        let combined = array.combine(&[60.0, 61.0, 62.0, 40.0]).unwrap();
+        assert!((combined - 61.1).abs() < 0.1, "{}", combined);
+        assert_eq!(MicArray::new(1).combine(&[55.0]), Some(55.0));
+        assert_eq!(power_mean_db(&[]), None);
+    }
+}
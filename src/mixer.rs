@@ -0,0 +1,261 @@
+//! Mixes several named sources (music, nav prompts, chimes...) into one output frame at a time. Each
+//! source is an spsc queue of interleaved samples with its own channel count and gain; a source with
+//! nothing queued is silent rather than holding the others up. The sum is scaled by a fixed headroom
+//! and then limited with instant attack, so it never leaves the mixer above `ceiling`. Sources can be
+//! added, removed and re-gained between frames, from whichever thread owns the mixer.
+
+use crate::dynamics::Ducker;
+use crate::spsc::Consumer;
+
+/// Map one interleaved source frame onto an output frame with a different channel count.
+/// Extra output channels repeat the source channels (mono -> all speakers); surplus source
+/// channels are averaged into the output channel they wrap onto (stereo -> mono averages L and R).
+pub fn map_frame(src: &[f32], out: &mut [f32]) {
+    if src.len() == out.len() {
+        out.copy_from_slice(src);
+    } else if src.len() < out.len() {
+        for (c, o) in out.iter_mut().enumerate() {
+            *o = src[c % src.len()];
+        }
+    } else {
+        let n_out = out.len();
+        out.fill(0.0);
+        for (c, &s) in src.iter().enumerate() {
+            out[c % n_out] += s;
+        }
+        for (c, o) in out.iter_mut().enumerate() {
+            let folded = (src.len() - c).div_ceil(n_out);
+            *o /= folded as f32;
+        }
+    }
+}
+
+/// One input to the mixer
+struct MixerSource {
+    name: String,
+    queue: Consumer,
+    /// Linear gain applied before summing
+    gain: f32,
+    /// Set when a frame had to be skipped because the queue held less than a frame
+    starved: bool,
+    // scratch frames: as queued, and mapped to the output channels
+    src_frame: Vec<f32>,
+    out_frame: Vec<f32>,
+}
+
+impl MixerSource {
+    /// Pop and map the next frame, or silence if a whole one isn't queued yet
+    fn pull(&mut self) {
+        let have_frame = self.queue.len() >= self.src_frame.len();
+        self.starved |= !have_frame;
+        for s in self.src_frame.iter_mut() {
+            *s = if have_frame { self.queue.pop().unwrap_or(0.0) } else { 0.0 };
+        }
+        map_frame(&self.src_frame, &mut self.out_frame);
+    }
+}
+
+/// Sums named sources onto `channels` output channels
+pub struct Mixer {
+    channels: usize,
+    sources: Vec<MixerSource>,
+    /// Linear scale applied to the sum before limiting
+    headroom: f32,
+    pub ceiling: f32,
+    release_coeff: f32,
+    limiter_gain: f32,
+    /// Name of the source that keys the ducker, which then attenuates every other source
+    ducker: Option<(String, Ducker)>,
+}
+
+impl Mixer {
+    /// Limiter recovery time after a peak
+    pub const DEFAULT_RELEASE_MS: f32 = 100.0;
+
+    /// Mix onto `channels` channels at `sample_rate` frames per second, with the sum scaled by
+    /// `-headroom_db` and limited to full scale
+    pub fn new(channels: usize, headroom_db: f32, sample_rate: f32) -> Self {
+        let release_frames = Self::DEFAULT_RELEASE_MS * 0.001 * sample_rate;
+        Self {
+            channels: channels.max(1),
+            sources: Vec::new(),
+            headroom: 10f32.powf(-headroom_db.abs() / 20.0),
+            ceiling: 1.0,
+            release_coeff: if release_frames > 0.0 { (-1.0 / release_frames).exp() } else { 0.0 },
+            limiter_gain: 1.0,
+            ducker: None,
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Add `queue` (interleaved, `channels` wide) under `name` at linear `gain`, replacing any source
+    /// already called that
+    pub fn add_source(&mut self, name: &str, queue: Consumer, channels: usize, gain: f32) {
+        self.remove_source(name);
+        let channels = channels.max(1);
+        self.sources.push(MixerSource {
+            name: name.to_string(),
+            queue,
+            gain,
+            starved: false,
+            src_frame: vec![0.0; channels],
+            out_frame: vec![0.0; self.channels],
+        });
+    }
+
+    /// Remove the source called `name`, handing back its queue
+    pub fn remove_source(&mut self, name: &str) -> Option<Consumer> {
+        let index = self.sources.iter().position(|s| s.name == name)?;
+        Some(self.sources.remove(index).queue)
+    }
+
+    /// Set a source's linear gain; false if there is no such source
+    pub fn set_gain(&mut self, name: &str, gain: f32) -> bool {
+        match self.sources.iter_mut().find(|s| s.name == name) {
+            Some(source) => {
+                source.gain = gain;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn gain(&self, name: &str) -> Option<f32> {
+        self.sources.iter().find(|s| s.name == name).map(|s| s.gain)
+    }
+
+    pub fn source_names(&self) -> impl Iterator<Item = &str> {
+        self.sources.iter().map(|s| s.name.as_str())
+    }
+
+    /// Duck every other source under the source called `key` (which may be added later)
+    pub fn set_ducker(&mut self, key: &str, ducker: Ducker) {
+        self.ducker = Some((key.to_string(), ducker));
+    }
+
+    /// Whether `name` ran short of a frame since the last call, clearing the flag
+    pub fn take_starved(&mut self, name: &str) -> bool {
+        self.sources
+            .iter_mut()
+            .find(|s| s.name == name)
+            .is_some_and(|s| std::mem::take(&mut s.starved))
+    }
+
+    /// Current limiter gain (1.0 when the sum is under the ceiling)
+    pub fn limiter_gain(&self) -> f32 {
+        self.limiter_gain
+    }
+
+    /// Mix the next frame of every source into `out` (`channels` wide)
+    pub fn next_frame(&mut self, out: &mut [f32]) {
+        for source in self.sources.iter_mut() {
+            source.pull();
+        }
+        let duck = match self.ducker.as_mut() {
+            Some((key, ducker)) => {
+                let level = self
+                    .sources
+                    .iter()
+                    .find(|s| &s.name == key)
+                    .map_or(0.0, |s| s.out_frame.iter().fold(0.0f32, |m, x| m.max((x * s.gain).abs())));
+                Some((key.as_str(), ducker.process(level)))
+            }
+            None => None,
+        };
+
+        out.fill(0.0);
+        for source in &self.sources {
+            let gain = match duck {
+                Some((key, duck)) if source.name != key => source.gain * duck,
+                _ => source.gain,
+            };
+            for (o, &s) in out.iter_mut().zip(source.out_frame.iter()) {
+                *o += s * gain;
+            }
+        }
+
+        // instant attack: the frame's own peak sets the gain, so nothing over the ceiling gets out
+        let peak = out.iter().fold(0.0f32, |m, s| m.max(s.abs())) * self.headroom;
+        let released = self.release_coeff * self.limiter_gain + (1.0 - self.release_coeff);
+        self.limiter_gain = if peak > self.ceiling { released.min(self.ceiling / peak) } else { released };
+        let g = self.headroom * self.limiter_gain;
+        for o in out.iter_mut() {
+            *o *= g;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spsc::spsc_ring;
+
+    fn constant(level: f32, samples: usize) -> Consumer {
+        let (mut tx, rx) = spsc_ring(samples);
+        tx.push_slice(&vec![level; samples]);
+        rx
+    }
+
+    #[test]
+    fn test_sums_sources_and_limits_above_full_scale() {
+        let mut mixer = Mixer::new(2, 0.0, 1000.0);
+        mixer.add_source("music", constant(0.3, 6), 2, 1.0);
+        mixer.add_source("chime", constant(0.4, 4), 1, 0.5);
+        let mut out = [0.0f32; 2];
+        mixer.next_frame(&mut out);
+        assert!(out.iter().all(|&s| (s - 0.5).abs() < 1e-6), "0.3 + 0.5 * 0.4: {:?}", out);
+        assert_eq!(mixer.limiter_gain(), 1.0);
+
+        // 0.3 + 0.9 would be 1.2: held at full scale
+        assert!(mixer.set_gain("chime", 2.25));
+        mixer.next_frame(&mut out);
+        assert!(out.iter().all(|&s| (s - 1.0).abs() < 1e-6), "{:?}", out);
+        assert!((mixer.limiter_gain() - 1.0 / 1.2).abs() < 1e-6);
+
+        // removing the chime leaves the music alone, and the limiter recovers
+        assert!(mixer.remove_source("chime").is_some());
+        assert!(!mixer.take_starved("music"));
+        mixer.next_frame(&mut out);
+        assert!(out[0] < 0.3 && mixer.limiter_gain() > 1.0 / 1.2, "released gradually: {:?}", out);
+        mixer.next_frame(&mut out);
+        assert!(mixer.take_starved("music"), "ran out of queued frames");
+        assert_eq!(out, [0.0, 0.0]);
+        assert_eq!(mixer.source_names().collect::<Vec<_>>(), ["music"]);
+    }
+
+    #[test]
+    fn test_headroom_and_ducking() {
+        let mut mixer = Mixer::new(1, 6.0, 1000.0);
+        mixer.add_source("music", constant(0.8, 4), 1, 1.0);
+        mixer.set_ducker("nav", Ducker::new(-45.0, 12.0, 0.0, 500.0, 1000.0));
+        let mut out = [0.0f32];
+        mixer.next_frame(&mut out);
+        assert!((out[0] - 0.8 * 10f32.powf(-6.0 / 20.0)).abs() < 1e-6, "{}", out[0]);
+
+        mixer.add_source("nav", constant(0.5, 4), 1, 1.0);
+        mixer.next_frame(&mut out);
+        let expected = (0.8 * 10f32.powf(-12.0 / 20.0) + 0.5) * 10f32.powf(-6.0 / 20.0);
+        assert!((out[0] - expected).abs() < 1e-4, "music ducked 12 dB under the prompt: {}", out[0]);
+    }
+
+    #[test]
+    fn test_map_frame_channel_counts() {
+        let mut stereo = [0.0f32; 2];
+        map_frame(&[0.25, -0.5], &mut stereo);
+        assert_eq!(stereo, [0.25, -0.5], "matching layouts pass through");
+
+        map_frame(&[0.3], &mut stereo);
+        assert_eq!(stereo, [0.3, 0.3], "mono is duplicated to both speakers");
+
+        let mut mono = [0.0f32; 1];
+        map_frame(&[0.2, 0.6], &mut mono);
+        assert!((mono[0] - 0.4).abs() < 1e-6, "stereo folds to the average: {}", mono[0]);
+
+        let mut quad = [0.0f32; 4];
+        map_frame(&[0.1, 0.2], &mut quad);
+        assert_eq!(quad, [0.1, 0.2, 0.1, 0.2]);
+    }
+}
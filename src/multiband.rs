@@ -0,0 +1,173 @@
+//! Three-band adaptive gain. Road noise sits mostly in the low end, so a single broadband gain
+//! lifts everything when only the bass is masked. Here the output is split at two Linkwitz-Riley
+//! crossovers and each band gets its own gain from the mic level in the same band.
+
+use crate::adaptive_gain::{NoiseCombine, NoiseModel};
+use crate::filters::LinkwitzRiley;
+use crate::gain::AdaptiveGain;
+
+/// Default low/mid and mid/high crossover frequencies (Hz)
+pub const DEFAULT_CROSSOVERS_HZ: (f32, f32) = (300.0, 3000.0);
+/// Most a band is boosted above its quiet-cabin level (dB)
+pub const MAX_BAND_BOOST_DB: f32 = 12.0;
+/// Time constant of the per-sample glide from one band gain to the next (s)
+const GAIN_GLIDE_S: f32 = 0.01;
+
+/// Splits a signal into low/mid/high. The low band also runs through the upper crossover (both
+/// outputs summed, an all-pass) so all three bands share its phase and sum back flat.
+struct BandSplitter {
+    lower: LinkwitzRiley,
+    upper: LinkwitzRiley,
+    low_phase: LinkwitzRiley,
+}
+
+impl BandSplitter {
+    fn new(sample_rate: f32, (low_mid_hz, mid_high_hz): (f32, f32)) -> Self {
+        Self {
+            lower: LinkwitzRiley::new(sample_rate, low_mid_hz),
+            upper: LinkwitzRiley::new(sample_rate, mid_high_hz),
+            low_phase: LinkwitzRiley::new(sample_rate, mid_high_hz),
+        }
+    }
+
+    fn split(&mut self, sample: f32) -> [f32; 3] {
+        let (low, rest) = self.lower.split(sample);
+        let (mid, high) = self.upper.split(rest);
+        let (a, b) = self.low_phase.split(low);
+        [a + b, mid, high]
+    }
+}
+
+pub struct MultibandGain {
+    /// Per-band controllers (smoothing, dead-band and bounds as for the broadband gain)
+    bands: [AdaptiveGain; 3],
+    /// Mic level per band (dB SPL) at or below which that band is left at 0 dB
+    reference_db: [f32; 3],
+    mic: BandSplitter,
+    output: BandSplitter,
+    /// Latest per-band gains from `update` / `set_gains_db`
+    target_lin: [f32; 3],
+    /// Gains currently applied, gliding toward `target_lin` so updates don't click
+    applied_lin: [f32; 3],
+    glide: f32,
+}
+
+impl MultibandGain {
+    pub fn new(sample_rate: f32, crossovers_hz: (f32, f32), reference_db: [f32; 3]) -> Self {
+        Self {
+            bands: std::array::from_fn(|_| Self::band_controller()),
+            reference_db,
+            mic: BandSplitter::new(sample_rate, crossovers_hz),
+            output: BandSplitter::new(sample_rate, crossovers_hz),
+            target_lin: [1.0; 3],
+            applied_lin: [1.0; 3],
+            glide: 1.0 - (-1.0 / (GAIN_GLIDE_S * sample_rate)).exp(),
+        }
+    }
+
+    /// `AdaptiveGain` computes `target - noise` with the speed model folded in. A band controller
+    /// has a 0 dB target and no speed term, and `update` feeds it the band's level *below* its
+    /// reference, so the band gain rises 1 dB per dB of band noise above the reference.
+    fn band_controller() -> AdaptiveGain {
+        let mut ag = AdaptiveGain::builder()
+            .target_db(0.0)
+            .min_gain_db(0.0)
+            .max_gain_db(MAX_BAND_BOOST_DB)
+            .noise_model(NoiseModel::Linear { slope: 0.0, intercept: f32::NEG_INFINITY })
+            .build();
+        ag.set_noise_combine(NoiseCombine::Max);
+        ag
+    }
+
+    /// Measure a window of mono mic samples per band (`calibration_db` maps dBFS to dB SPL) and
+    /// step each band's gain by `dt` seconds. Returns the band gains in dB (low, mid, high).
+    pub fn update(&mut self, mic: &[f32], calibration_db: f32, dt: f32) -> [f32; 3] {
+        let mut sumsq = [0.0f32; 3];
+        for &s in mic {
+            for (acc, b) in sumsq.iter_mut().zip(self.mic.split(s)) {
+                *acc += b * b;
+            }
+        }
+        let mut gains_db = [0.0; 3];
+        for i in 0..3 {
+            let rms = (sumsq[i] / mic.len().max(1) as f32).sqrt().max(1e-9);
+            let band_db = 20.0 * rms.log10() + calibration_db;
+            let (gain_db, gain_lin) = self.bands[i].compute_gain_dt(self.reference_db[i] - band_db, 0.0, dt);
+            gains_db[i] = gain_db;
+            self.target_lin[i] = gain_lin;
+        }
+        gains_db
+    }
+
+    /// Set the band gains (dB) directly, e.g. when `update` runs on another instance that only
+    /// measures the mic
+    pub fn set_gains_db(&mut self, gains_db: [f32; 3]) {
+        self.target_lin = gains_db.map(crate::adaptive_gain::db_to_lin);
+    }
+
+    /// Apply the band gains to a block of mono output samples in place
+    pub fn process(&mut self, block: &mut [f32]) {
+        for sample in block.iter_mut() {
+            *sample = self.process_sample(*sample);
+        }
+    }
+
+    pub fn process_sample(&mut self, sample: f32) -> f32 {
+        let bands = self.output.split(sample);
+        let mut out = 0.0;
+        for (b, band) in bands.into_iter().enumerate() {
+            self.applied_lin[b] += self.glide * (self.target_lin[b] - self.applied_lin[b]);
+            out += band * self.applied_lin[b];
+        }
+        out
+    }
+
+    /// Current per-band gains (dB): low, mid, high
+    pub fn gains_db(&self) -> [f32; 3] {
+        self.target_lin.map(|g| 20.0 * g.log10())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FS: f32 = 16_000.0;
+
+    fn tone(freq_hz: f32, amplitude: f32, start: usize, n: usize) -> Vec<f32> {
+        (start..start + n)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * freq_hz * i as f32 / FS).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_low_frequency_noise_raises_only_low_band() {
+        let mut mb = MultibandGain::new(FS, DEFAULT_CROSSOVERS_HZ, [60.0; 3]);
+        let window = (FS * 0.05) as usize;
+
+        // quiet cabin: every band stays at 0 dB
+        let quiet = vec![0.0; window];
+        let gains = mb.update(&quiet, 94.0, 0.05);
+        assert_eq!(gains, [0.0; 3]);
+
+        // ~85 dB SPL of 80 Hz rumble
+        let mut gains = [0.0; 3];
+        for k in 0..40 {
+            gains = mb.update(&tone(80.0, 0.5, k * window, window), 94.0, 0.05);
+        }
+        assert!(gains[0] > MAX_BAND_BOOST_DB - 0.5, "low band boosted: {:?}", gains);
+        assert!(gains[1] < 0.01 && gains[2] < 0.01, "mid/high untouched: {:?}", gains);
+        assert!((mb.gains_db()[0] - gains[0]).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_unity_bands_pass_audio_through() {
+        let mut mb = MultibandGain::new(FS, DEFAULT_CROSSOVERS_HZ, [60.0; 3]);
+        for freq in [100.0, 1000.0, 5000.0] {
+            let mut block = tone(freq, 0.5, 0, 16_000);
+            mb.process(&mut block);
+            let rms = (block[8000..].iter().map(|s| s * s).sum::<f32>() / 8000.0).sqrt();
+            assert!((rms - 0.5 * std::f32::consts::FRAC_1_SQRT_2).abs() < 0.01, "{} Hz: rms {}", freq, rms);
+        }
+    }
+}
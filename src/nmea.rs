@@ -0,0 +1,191 @@
+//! Ground speed from an NMEA 0183 GPS receiver, either a USB/serial puck or a TCP feed
+//! (e.g. gpsd's raw NMEA port or a phone GPS-forwarding app).
+//!
+//! Only `RMC` and `VTG` sentences are used, from any talker (`$GP`, `$GN`, `$GL`, ...). NMEA 0183
+//! specifies 4800 baud; many USB pucks run at 9600 (set `NMEA_BAUD`), and CDC-ACM devices ignore it.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use log::warn;
+
+use crate::serial::open_serial;
+use crate::speed_source::{SpeedPublisher, SpeedSource};
+use crate::util::{sleep_while_running, spawn_named};
+
+const KMH_PER_KNOT: f32 = 1.852;
+
+/// Ground speed (km/h) from one NMEA sentence. `None` for other sentence types, a bad checksum,
+/// or a sentence flagged as having no valid fix.
+pub fn parse_sentence(line: &str) -> Option<f32> {
+    let body = line.trim().strip_prefix('$')?;
+    let body = match body.split_once('*') {
+        Some((body, checksum)) => {
+            let expected = u8::from_str_radix(checksum.get(..2)?, 16).ok()?;
+            if body.bytes().fold(0u8, |acc, b| acc ^ b) != expected {
+                return None;
+            }
+            body
+        }
+        // checksum is optional in NMEA 0183
+        None => body,
+    };
+    let fields: Vec<&str> = body.split(',').collect();
+    let kind = fields[0].get(2..)?;
+    let field = |i: usize| fields.get(i).copied().unwrap_or("");
+    let number = |s: &str| s.parse::<f32>().ok().filter(|v| v.is_finite());
+    match kind {
+        // $--RMC,time,status,lat,N/S,lon,E/W,speed_knots,course,date,...[,mode]
+        "RMC" => {
+            if field(2) != "A" || field(12).starts_with('N') {
+                return None;
+            }
+            number(field(7)).map(|knots| knots * KMH_PER_KNOT)
+        }
+        // $--VTG,course,T,course,M,speed_knots,N,speed_kmh,K[,mode]
+        "VTG" => {
+            if field(9).starts_with('N') {
+                return None;
+            }
+            number(field(7)).or_else(|| number(field(5)).map(|knots| knots * KMH_PER_KNOT))
+        }
+        _ => None,
+    }
+}
+
+/// Reads NMEA sentences from `address`: `tcp://host:port` or a serial device path
+pub struct NmeaSource {
+    address: String,
+    baud: u32,
+}
+
+impl NmeaSource {
+    pub const DEFAULT_BAUD: u32 = 4800;
+    /// How long a read waits before checking for shutdown and counting the receiver as quiet
+    const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+    pub fn new(address: impl Into<String>, baud: u32) -> Self {
+        Self { address: address.into(), baud }
+    }
+
+    /// Use NMEA_BAUD from the environment, else the NMEA 0183 rate
+    pub fn baud_from_env() -> u32 {
+        std::env::var("NMEA_BAUD").ok().and_then(|v| v.parse().ok()).unwrap_or(Self::DEFAULT_BAUD)
+    }
+
+    fn open(&self) -> io::Result<NmeaStream> {
+        match self.address.strip_prefix("tcp://") {
+            Some(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_read_timeout(Some(Self::READ_TIMEOUT))?;
+                Ok(NmeaStream::Tcp(stream))
+            }
+            None => Ok(NmeaStream::Serial(open_serial(&self.address, self.baud, Self::READ_TIMEOUT)?)),
+        }
+    }
+
+    /// Read sentences until the stream ends or `stop` is set. A fix-less or quiet receiver counts as a
+    /// failed reading so the speed goes stale rather than holding the last value forever.
+    fn run_stream(&self, stream: &mut NmeaStream, publisher: &mut SpeedPublisher, stop: &AtomicBool) -> io::Result<()> {
+        let mut pending = Vec::new();
+        let mut buf = [0u8; 256];
+        while !stop.load(Ordering::Relaxed) {
+            let n = match stream.read_chunk(&mut buf)? {
+                Some(n) => n,
+                None => {
+                    publisher.fail();
+                    continue;
+                }
+            };
+            pending.extend_from_slice(&buf[..n]);
+            while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let kind = line.trim().get(3..6).unwrap_or("");
+                if kind != "RMC" && kind != "VTG" {
+                    continue;
+                }
+                match parse_sentence(&line) {
+                    Some(kmh) => publisher.publish(kmh),
+                    None => publisher.fail(),
+                }
+            }
+            // a receiver spewing garbage without newlines shouldn't grow this forever
+            if pending.len() > 1024 {
+                pending.clear();
+            }
+        }
+        Ok(())
+    }
+}
+
+enum NmeaStream {
+    Serial(File),
+    Tcp(TcpStream),
+}
+
+impl NmeaStream {
+    /// `Ok(None)` when nothing arrived within the read timeout
+    fn read_chunk(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        match self {
+            // the serial port's VTIME makes a quiet read return 0 rather than block
+            NmeaStream::Serial(file) => file.read(buf).map(|n| (n > 0).then_some(n)),
+            NmeaStream::Tcp(stream) => match stream.read(buf) {
+                Ok(0) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed")),
+                Ok(n) => Ok(Some(n)),
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Ok(None),
+                Err(e) => Err(e),
+            },
+        }
+    }
+}
+
+impl SpeedSource for NmeaSource {
+    fn spawn(self, mut publisher: SpeedPublisher, stop: &'static AtomicBool) -> JoinHandle<()> {
+        spawn_named("speed-gps", move || {
+            while !stop.load(Ordering::Relaxed) {
+                let result = self.open().and_then(|mut stream| self.run_stream(&mut stream, &mut publisher, stop));
+                if let Err(e) = result {
+                    warn!("[GPS] {}: {}", self.address, e);
+                }
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                publisher.fail();
+                sleep_while_running(stop, publisher.retry_delay());
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vtg() {
+        let kmh = parse_sentence("$GPVTG,054.7,T,034.4,M,005.5,N,010.2,K*48").unwrap();
+        assert!((kmh - 10.2).abs() < 1e-4, "{}", kmh);
+        // without the km/h field, knots are converted
+        let kmh = parse_sentence("$GPVTG,054.7,T,034.4,M,010.0,N,,K").unwrap();
+        assert!((kmh - 18.52).abs() < 1e-4, "{}", kmh);
+        assert_eq!(parse_sentence("$GPVTG,,T,,M,0.0,N,0.0,K,N"), None, "mode N: no fix");
+    }
+
+    #[test]
+    fn test_parse_rmc() {
+        let kmh = parse_sentence("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A\r\n").unwrap();
+        assert!((kmh - 22.4 * KMH_PER_KNOT).abs() < 1e-3, "{}", kmh);
+        assert_eq!(
+            parse_sentence("$GPRMC,123519,V,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W"),
+            None,
+            "status V: invalid fix"
+        );
+        assert_eq!(parse_sentence("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*00"), None, "bad checksum");
+        assert_eq!(parse_sentence("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47"), None);
+    }
+}
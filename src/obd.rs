@@ -0,0 +1,189 @@
+//! Vehicle speed from an ELM327 OBD-II adapter over a serial port.
+//!
+//! ELM327 adapters talk 38400 baud 8N1 by default (some clones ship at 9600 or 115200; set
+//! `OBD_BAUD` to match). The source resets the adapter, turns echo and linefeeds off, lets it
+//! auto-detect the vehicle protocol, then sends mode 01 PID 0D (vehicle speed) on a timer.
+//! A reply of `41 0D 3C` means 0x3C = 60 km/h.
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::serial::open_serial;
+use crate::speed_source::{SpeedPublisher, SpeedSource};
+use crate::util::{sleep_while_running, spawn_named};
+
+/// How one `010D` query turned out
+#[derive(Debug, PartialEq)]
+pub enum ObdReply {
+    /// Vehicle speed in km/h
+    Speed(f32),
+    /// The ECU didn't answer (`NO DATA`, `UNABLE TO CONNECT`, `STOPPED`, ...); worth retrying
+    NoData,
+    /// Anything we couldn't make sense of
+    Unrecognised(String),
+}
+
+/// Parse the adapter's text reply to `010D`. `SEARCHING...` progress lines and the `>` prompt are
+/// ignored; hex bytes may or may not be space separated.
+pub fn parse_speed_reply(reply: &str) -> ObdReply {
+    let mut saw_no_data = false;
+    for line in reply.split(['\r', '\n']).map(|l| l.trim().trim_start_matches('>').trim()) {
+        if line.is_empty() || line.starts_with("SEARCHING") || line == "010D" {
+            continue;
+        }
+        let upper = line.to_ascii_uppercase();
+        if upper.contains("NO DATA") || upper.contains("UNABLE TO CONNECT") || upper.contains("STOPPED") {
+            saw_no_data = true;
+            continue;
+        }
+        let hex: String = upper.chars().filter(|c| !c.is_whitespace()).collect();
+        if let Some(pos) = hex.find("410D") {
+            if let Some(byte) = hex.get(pos + 4..pos + 6).and_then(|b| u8::from_str_radix(b, 16).ok()) {
+                return ObdReply::Speed(byte as f32);
+            }
+        }
+    }
+    if saw_no_data {
+        ObdReply::NoData
+    } else {
+        ObdReply::Unrecognised(reply.trim().to_string())
+    }
+}
+
+/// Send `command` and collect the reply up to the `>` prompt, giving up after `timeout`
+fn query(port: &mut (impl Read + Write), command: &str, timeout: Duration) -> io::Result<String> {
+    port.write_all(command.as_bytes())?;
+    port.write_all(b"\r")?;
+    port.flush()?;
+    let deadline = Instant::now() + timeout;
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 64];
+    while Instant::now() < deadline {
+        let n = port.read(&mut buf)?;
+        reply.extend_from_slice(&buf[..n]);
+        if reply.contains(&b'>') {
+            return Ok(String::from_utf8_lossy(&reply).into_owned());
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::TimedOut, format!("no prompt after '{}'", command)))
+}
+
+/// Polls vehicle speed from an ELM327 adapter at `path`
+pub struct Obd2Source {
+    path: String,
+    baud: u32,
+    period: Duration,
+}
+
+impl Obd2Source {
+    pub const DEFAULT_BAUD: u32 = 38400;
+    /// Resets and protocol search can take several seconds on the first query
+    const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub fn new(path: impl Into<String>, baud: u32, period: Duration) -> Self {
+        Self { path: path.into(), baud, period }
+    }
+
+    /// Use OBD_BAUD from the environment, else the ELM327 default
+    pub fn baud_from_env() -> u32 {
+        std::env::var("OBD_BAUD").ok().and_then(|v| v.parse().ok()).unwrap_or(Self::DEFAULT_BAUD)
+    }
+
+    fn init(port: &mut (impl Read + Write)) -> io::Result<()> {
+        // reset, echo off, linefeeds off, spaces on, automatic protocol
+        for cmd in ["ATZ", "ATE0", "ATL0", "ATS1", "ATSP0"] {
+            query(port, cmd, Self::QUERY_TIMEOUT)?;
+        }
+        Ok(())
+    }
+
+    /// Query speed until the port fails or `stop` is set
+    fn run_port(&self, port: &mut (impl Read + Write), publisher: &mut SpeedPublisher, stop: &AtomicBool) -> io::Result<()> {
+        Self::init(port)?;
+        while !stop.load(Ordering::Relaxed) {
+            match parse_speed_reply(&query(port, "010D", Self::QUERY_TIMEOUT)?) {
+                ObdReply::Speed(kmh) => publisher.publish(kmh),
+                ObdReply::NoData => publisher.fail(),
+                ObdReply::Unrecognised(reply) => {
+                    warn!("[OBD] unexpected reply: {:?}", reply);
+                    publisher.fail();
+                }
+            }
+            sleep_while_running(stop, publisher.retry_delay().max(self.period));
+        }
+        Ok(())
+    }
+}
+
+impl SpeedSource for Obd2Source {
+    fn spawn(self, mut publisher: SpeedPublisher, stop: &'static AtomicBool) -> JoinHandle<()> {
+        spawn_named("speed-obd", move || {
+            while !stop.load(Ordering::Relaxed) {
+                let result = open_serial(&self.path, self.baud, Duration::from_millis(200))
+                    .and_then(|mut port| self.run_port(&mut port, &mut publisher, stop));
+                if let Err(e) = result {
+                    warn!("[OBD] {}: {}", self.path, e);
+                    publisher.fail();
+                    sleep_while_running(stop, publisher.retry_delay());
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_speed_reply() {
+        assert_eq!(parse_speed_reply("41 0D 3C \r\r>"), ObdReply::Speed(60.0));
+        assert_eq!(parse_speed_reply("SEARCHING...\r410D00\r\r>"), ObdReply::Speed(0.0));
+        assert_eq!(parse_speed_reply("010D\r41 0D FF\r>"), ObdReply::Speed(255.0), "echo still on");
+        assert_eq!(parse_speed_reply("NO DATA\r\r>"), ObdReply::NoData);
+        assert_eq!(parse_speed_reply("SEARCHING...\rUNABLE TO CONNECT\r>"), ObdReply::NoData);
+        assert!(matches!(parse_speed_reply("?\r>"), ObdReply::Unrecognised(_)));
+    }
+
+    /// Fake adapter: answers each command with a scripted reply
+    struct FakeElm {
+        replies: Vec<&'static str>,
+        pending: Vec<u8>,
+        sent: String,
+    }
+
+    impl Read for FakeElm {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.pending.len());
+            buf[..n].copy_from_slice(&self.pending[..n]);
+            self.pending.drain(..n);
+            Ok(n)
+        }
+    }
+
+    impl Write for FakeElm {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.push_str(&String::from_utf8_lossy(buf));
+            if buf.ends_with(b"\r") && !self.replies.is_empty() {
+                self.pending.extend_from_slice(self.replies.remove(0).as_bytes());
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_query_reads_up_to_prompt() {
+        let mut elm = FakeElm { replies: vec!["41 0D 50\r\r>"], pending: Vec::new(), sent: String::new() };
+        let reply = query(&mut elm, "010D", Duration::from_secs(1)).unwrap();
+        assert_eq!(elm.sent, "010D\r");
+        assert_eq!(parse_speed_reply(&reply), ObdReply::Speed(80.0));
+    }
+}
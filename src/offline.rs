@@ -0,0 +1,277 @@
+//! Offline processing: apply the adaptive gain to a WAV file following a recorded or synthetic
+//! sensor trace, so tuning can be auditioned without real-time audio.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use hound::{SampleFormat, WavReader, WavWriter};
+
+use crate::adaptive_gain::Limiter;
+use crate::controller::GainController;
+use crate::core_dsp::{db_to_lin, lin_to_db};
+use crate::gain::AdaptiveGain;
+use crate::loudness::LoudnessMeter;
+use crate::trace::TraceSource;
+use crate::true_peak::TruePeakMeter;
+
+/// Gain is recomputed every 10 ms of audio and ramped across the chunk
+const CHUNK_MS: u32 = 10;
+
+/// Value of 1.0 in the file's sample format: integer PCM is scaled by it to f32 in [-1, 1]
+fn full_scale(spec: hound::WavSpec) -> f32 {
+    match spec.sample_format {
+        SampleFormat::Float => 1.0,
+        SampleFormat::Int => (1i64 << (spec.bits_per_sample - 1)) as f32,
+    }
+}
+
+/// Every sample of `reader`, interleaved, as f32 in [-1, 1]
+fn samples_f32<'a, R: std::io::Read + 'a>(
+    reader: &'a mut WavReader<R>,
+) -> Box<dyn Iterator<Item = hound::Result<f32>> + 'a> {
+    let spec = reader.spec();
+    let full_scale = full_scale(spec);
+    match spec.sample_format {
+        SampleFormat::Float => Box::new(reader.samples::<f32>()),
+        SampleFormat::Int => Box::new(reader.samples::<i32>().map(move |s| s.map(|v| v as f32 / full_scale))),
+    }
+}
+
+/// Loudness and level summary of a WAV file (`adaptive_vol analyze`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WavAnalysis {
+    /// BS.1770 integrated loudness (LUFS); `None` for a file that is silent below the gate
+    pub integrated_lufs: Option<f32>,
+    /// Largest sample magnitude (dBFS)
+    pub sample_peak_dbfs: f32,
+    /// Largest reconstructed magnitude between samples (dBTP, see `TruePeakMeter`)
+    pub true_peak_dbtp: f32,
+    pub duration_s: f32,
+}
+
+/// Default true-peak ceiling for loudness normalization (dBTP): the EBU R128 distribution limit
+pub const DEFAULT_PEAK_CEILING_DB: f32 = -1.0;
+
+/// The single gain that takes a file to a loudness target (see `normalization_gain`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Normalization {
+    pub gain_db: f32,
+    /// The gain was reduced below what the target needs to keep the true peak under the ceiling
+    pub peak_limited: bool,
+}
+
+impl Normalization {
+    pub fn gain_lin(&self) -> f32 {
+        db_to_lin(self.gain_db)
+    }
+}
+
+/// Gain bringing `analysis` to `target_lufs`, reduced if it would lift the true peak above
+/// `peak_ceiling_db` (dBTP). `None` for a file with no measurable loudness.
+pub fn normalization_gain(analysis: &WavAnalysis, target_lufs: f32, peak_ceiling_db: f32) -> Option<Normalization> {
+    let wanted_db = target_lufs - analysis.integrated_lufs?;
+    let headroom_db = peak_ceiling_db - analysis.true_peak_dbtp;
+    Some(Normalization { gain_db: wanted_db.min(headroom_db), peak_limited: wanted_db > headroom_db })
+}
+
+/// Measure `path`'s integrated loudness, sample peak and true peak in one pass
+pub fn analyze_wav(path: impl AsRef<Path>) -> Result<WavAnalysis> {
+    let path = path.as_ref();
+    let mut reader = WavReader::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let spec = reader.spec();
+    let channels = (spec.channels as usize).max(1);
+    let mut meter = LoudnessMeter::new(spec.sample_rate as f32, channels);
+    let mut true_peak = TruePeakMeter::new(channels);
+    let mut peak = 0.0f32;
+    let mut frames = 0usize;
+    // a second of audio at a time keeps memory flat for long files
+    let mut chunk = Vec::with_capacity(spec.sample_rate as usize * channels);
+    let mut samples = samples_f32(&mut reader);
+    loop {
+        chunk.clear();
+        for s in samples.by_ref().take(spec.sample_rate as usize * channels) {
+            chunk.push(s.with_context(|| format!("reading {}", path.display()))?);
+        }
+        if chunk.is_empty() {
+            break;
+        }
+        peak = chunk.iter().fold(peak, |p, s| p.max(s.abs()));
+        meter.process_interleaved(&chunk);
+        true_peak.process_interleaved(&chunk);
+        frames += chunk.len() / channels;
+    }
+    Ok(WavAnalysis {
+        integrated_lufs: meter.integrated_lufs(),
+        sample_peak_dbfs: lin_to_db(peak),
+        true_peak_dbtp: true_peak.true_peak_dbtp(),
+        duration_s: frames as f32 / spec.sample_rate as f32,
+    })
+}
+
+/// Read `in_path`, apply the default `AdaptiveGain` following `trace`, and write `out_path` with the
+/// same WAV spec. See `process_wav_with_gain`.
+pub fn process_wav_with_trace(
+    in_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+    trace: &[(f32 /*t*/, f32 /*cabin_db*/, f32 /*speed*/)],
+) -> Result<()> {
+    let trace = TraceSource::from_rows(trace.to_vec())?;
+    process_wav_with_gain(in_path, out_path, &trace, &mut AdaptiveGain::default())
+}
+
+/// Walk `in_path` in 10 ms chunks; for each chunk sample the trace at the chunk's start time, step
+/// `gain` by the chunk duration, and apply the result (ramped from the previous chunk's gain, then
+/// peak limited). The output keeps the input's channels, rate, bit depth and sample format.
+pub fn process_wav_with_gain(
+    in_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+    trace: &TraceSource,
+    gain: &mut dyn GainController,
+) -> Result<()> {
+    process_wav_with_baseline(in_path, out_path, trace, gain, 1.0)
+}
+
+/// `process_wav_with_gain` on top of a fixed `baseline_lin` gain (e.g. a loudness normalization
+/// from `normalization_gain`), applied before the adaptive gain and the limiter
+pub fn process_wav_with_baseline(
+    in_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+    trace: &TraceSource,
+    gain: &mut dyn GainController,
+    baseline_lin: f32,
+) -> Result<()> {
+    let (in_path, out_path) = (in_path.as_ref(), out_path.as_ref());
+    let mut reader = WavReader::open(in_path).with_context(|| format!("opening {}", in_path.display()))?;
+    let spec = reader.spec();
+    let channels = (spec.channels as usize).max(1);
+    let mut writer = WavWriter::create(out_path, spec).with_context(|| format!("creating {}", out_path.display()))?;
+
+    let full_scale = full_scale(spec);
+    let mut samples = samples_f32(&mut reader);
+
+    let chunk_frames = ((spec.sample_rate * CHUNK_MS / 1000) as usize).max(1);
+    let dt = chunk_frames as f32 / spec.sample_rate as f32;
+    let mut limiter = Limiter::for_sample_rate((spec.sample_rate as usize * channels) as f32);
+    let mut chunk = Vec::with_capacity(chunk_frames * channels);
+    let mut prev_gain = None;
+    let mut t = 0.0f32;
+    loop {
+        chunk.clear();
+        for s in samples.by_ref().take(chunk_frames * channels) {
+            chunk.push(s?);
+        }
+        if chunk.is_empty() {
+            break;
+        }
+        let (cabin_db, speed_kmh) = trace.sample(t);
+        let (_, adaptive_lin) = gain.compute_gain_dt(cabin_db, speed_kmh, dt);
+        let gain_lin = adaptive_lin * baseline_lin;
+        let from = prev_gain.unwrap_or(gain_lin);
+        let frames = chunk.len().div_ceil(channels);
+        for (i, frame) in chunk.chunks(channels).enumerate() {
+            let g = from + (gain_lin - from) * (i + 1) as f32 / frames as f32;
+            for &s in frame {
+                let out = limiter.process(s * g).clamp(-1.0, 1.0);
+                match spec.sample_format {
+                    SampleFormat::Float => writer.write_sample(out)?,
+                    SampleFormat::Int => {
+                        let v = (out * full_scale).round().clamp(-full_scale, full_scale - 1.0);
+                        writer.write_sample(v as i32)?
+                    }
+                }
+            }
+        }
+        prev_gain = Some(gain_lin);
+        t += dt;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adaptive_gain::NoiseCombine;
+
+    #[test]
+    fn test_process_preserves_spec_and_follows_trace() {
+        let dir = std::env::temp_dir();
+        let in_path = dir.join(format!("adaptive_vol_offline_in_{}.wav", std::process::id()));
+        let out_path = dir.join(format!("adaptive_vol_offline_out_{}.wav", std::process::id()));
+        let spec = hound::WavSpec { channels: 2, sample_rate: 8000, bits_per_sample: 16, sample_format: SampleFormat::Int };
+        {
+            let mut writer = WavWriter::create(&in_path, spec).unwrap();
+            // 2 s of a constant level so the output/input ratio is the gain
+            for _ in 0..2 * 8000 * 2 {
+                writer.write_sample(3277i16).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+
+        let trace = TraceSource::from_rows(vec![(0.0, 70.0, 0.0), (2.0, 70.0, 0.0)]).unwrap();
+        let mut gain = AdaptiveGain::builder().tau_attack(0.05).tau_release(0.05).build();
+        gain.set_noise_combine(NoiseCombine::Max);
+        process_wav_with_gain(&in_path, &out_path, &trace, &mut gain).unwrap();
+
+        let mut reader = WavReader::open(&out_path).unwrap();
+        let out: Vec<i16> = reader.samples::<i16>().map(|s| s.unwrap()).collect();
+        let out_spec = reader.spec();
+        std::fs::remove_file(&in_path).ok();
+        std::fs::remove_file(&out_path).ok();
+
+        assert_eq!(out_spec, spec);
+        assert_eq!(out.len(), 2 * 8000 * 2);
+        // target 75 - noise 70 = +5 dB once the smoother settles
+        let ratio = *out.last().unwrap() as f32 / 3277.0;
+        assert!((ratio - 10f32.powf(5.0 / 20.0)).abs() < 0.01, "settled gain {}", ratio);
+        assert!(out[0] < out[out.len() - 1], "gain ramps up from 0 dB");
+    }
+
+    #[test]
+    fn test_normalization_gain_respects_the_peak_ceiling() {
+        let quiet = WavAnalysis {
+            integrated_lufs: Some(-30.0),
+            sample_peak_dbfs: -12.0,
+            true_peak_dbtp: -11.5,
+            duration_s: 10.0,
+        };
+        let n = normalization_gain(&quiet, -23.0, DEFAULT_PEAK_CEILING_DB).unwrap();
+        assert_eq!(n, Normalization { gain_db: 7.0, peak_limited: false });
+
+        // +7 dB would put the reconstructed peak at +1 dBTP: only 5 dB fit under -1 dBTP, although
+        // the sample peak alone would allow 6
+        let peaky = WavAnalysis { sample_peak_dbfs: -7.0, true_peak_dbtp: -6.0, ..quiet };
+        let n = normalization_gain(&peaky, -23.0, DEFAULT_PEAK_CEILING_DB).unwrap();
+        assert_eq!(n, Normalization { gain_db: 5.0, peak_limited: true });
+
+        let loud = WavAnalysis { integrated_lufs: Some(-14.0), sample_peak_dbfs: -0.1, true_peak_dbtp: 0.4, ..quiet };
+        assert_eq!(normalization_gain(&loud, -23.0, DEFAULT_PEAK_CEILING_DB).unwrap().gain_db, -9.0);
+        let silent = WavAnalysis { integrated_lufs: None, ..loud };
+        assert_eq!(normalization_gain(&silent, -23.0, DEFAULT_PEAK_CEILING_DB), None);
+    }
+
+    #[test]
+    fn test_analyze_reports_loudness_and_peak() {
+        let path = std::env::temp_dir().join(format!("adaptive_vol_analyze_{}.wav", std::process::id()));
+        let spec = hound::WavSpec { channels: 2, sample_rate: 48000, bits_per_sample: 16, sample_format: SampleFormat::Int };
+        {
+            // 3 s stereo 1 kHz at -23 dBFS: -23 LUFS
+            let mut writer = WavWriter::create(&path, spec).unwrap();
+            let amplitude = 10f32.powf(-23.0 / 20.0) * 32768.0;
+            for i in 0..3 * 48000 {
+                let s = (amplitude * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 48000.0).sin()).round() as i16;
+                writer.write_sample(s).unwrap();
+                writer.write_sample(s).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        let analysis = analyze_wav(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert!((analysis.integrated_lufs.unwrap() + 23.0).abs() < 0.1, "{:?}", analysis);
+        assert!((analysis.sample_peak_dbfs + 23.0).abs() < 0.05, "{:?}", analysis);
+        let (sample_peak, true_peak) = (analysis.sample_peak_dbfs, analysis.true_peak_dbtp);
+        assert!(true_peak >= sample_peak && true_peak < -22.9, "{:?}", analysis);
+        assert!((analysis.duration_s - 3.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::adaptive_gain::NoiseModel;
+
+/// Measured noise curve and mic calibration for one vehicle, loaded from a JSON file such as
+/// `profiles/example_sedan.json`:
+/// `{ "points": [[0,40],[60,63],[120,72]], "calibration_db": 92.5 }`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct VehicleProfile {
+    /// (speed km/h, cabin noise dB) pairs, sorted by speed
+    pub points: Vec<(f32, f32)>,
+    /// Mic dBFS -> dB SPL offset; when absent the binary's own calibration is kept
+    #[serde(default)]
+    pub calibration_db: Option<f32>,
+}
+
+impl VehicleProfile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading vehicle profile {}", path.display()))?;
+        Self::from_json(&text).with_context(|| format!("invalid vehicle profile {}", path.display()))
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        let profile: VehicleProfile = serde_json::from_str(text)?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    /// Load the profile named by `--profile <path>`, if the flag is present
+    pub fn from_args(args: &[String]) -> Result<Option<Self>> {
+        match args.iter().position(|a| a == "--profile") {
+            None => Ok(None),
+            Some(i) => match args.get(i + 1) {
+                Some(path) => Self::load(path).map(Some),
+                None => bail!("--profile needs a path to a JSON vehicle profile"),
+            },
+        }
+    }
+
+    pub fn noise_model(&self) -> NoiseModel {
+        NoiseModel::Table(self.points.clone())
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.points.is_empty() {
+            bail!("profile has no (speed, dB) points");
+        }
+        if let Some(&(speed, db)) = self.points.iter().find(|(s, d)| !s.is_finite() || !d.is_finite()) {
+            bail!("profile point ({}, {}) is not a finite number", speed, db);
+        }
+        for pair in self.points.windows(2) {
+            if pair[1].0 <= pair[0].0 {
+                bail!(
+                    "profile points must be sorted by increasing speed: {} km/h comes after {} km/h",
+                    pair[1].0,
+                    pair[0].0
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_shipped_example_profile() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/profiles/example_sedan.json");
+        let profile = VehicleProfile::load(path).unwrap();
+        assert_eq!(profile.calibration_db, Some(92.5));
+        assert_eq!(profile.noise_model().noise_db(60.0), 63.0);
+    }
+
+    #[test]
+    fn test_unsorted_points_rejected() {
+        let err = VehicleProfile::from_json(r#"{ "points": [[0, 40], [90, 68], [60, 63]] }"#).unwrap_err();
+        assert!(err.to_string().contains("sorted"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_calibration_is_optional() {
+        let profile = VehicleProfile::from_json(r#"{ "points": [[0, 40], [120, 70]] }"#).unwrap();
+        assert_eq!(profile.calibration_db, None);
+    }
+}
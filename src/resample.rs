@@ -0,0 +1,84 @@
+/// Linear-interpolation sample-rate converter for interleaved audio.
+/// Good enough for matching a WAV to the device rate; not a band-limited resampler.
+pub struct LinearResampler {
+    from_rate: u32,
+    to_rate: u32,
+    channels: usize,
+}
+
+impl LinearResampler {
+    pub fn new(from_rate: u32, to_rate: u32, channels: usize) -> Self {
+        Self {
+            from_rate: from_rate.max(1),
+            to_rate: to_rate.max(1),
+            channels: channels.max(1),
+        }
+    }
+
+    /// Output frames per input frame
+    pub fn ratio(&self) -> f64 {
+        self.to_rate as f64 / self.from_rate as f64
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.from_rate == self.to_rate
+    }
+
+    /// Resample a whole interleaved buffer. A trailing partial frame is ignored.
+    pub fn process(&self, input: &[f32]) -> Vec<f32> {
+        let ch = self.channels;
+        let frames_in = input.len() / ch;
+        if self.is_passthrough() || frames_in == 0 {
+            return input[..frames_in * ch].to_vec();
+        }
+
+        let ratio = self.ratio();
+        let frames_out = (frames_in as f64 * ratio).round() as usize;
+        let mut out = Vec::with_capacity(frames_out * ch);
+        for i in 0..frames_out {
+            let pos = i as f64 / ratio;
+            let idx = (pos.floor() as usize).min(frames_in - 1);
+            let next = (idx + 1).min(frames_in - 1);
+            let frac = (pos - idx as f64) as f32;
+            for c in 0..ch {
+                let a = input[idx * ch + c];
+                let b = input[next * ch + c];
+                out.push(a + (b - a) * frac);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resample_44100_to_48000_length() {
+        let from = 44100;
+        let to = 48000;
+        let input: Vec<f32> = (0..from)
+            .map(|n| (2.0 * std::f32::consts::PI * 1000.0 * n as f32 / from as f32).sin())
+            .collect();
+
+        let resampler = LinearResampler::new(from, to, 1);
+        let output = resampler.process(&input);
+
+        let expected = (input.len() as f64 * resampler.ratio()).round() as usize;
+        assert_eq!(output.len(), expected);
+        assert_eq!(output.len(), 48000);
+        assert!(output.iter().all(|s| s.abs() <= 1.0));
+    }
+
+    #[test]
+    fn test_resample_keeps_channels_interleaved() {
+        // left constant 0.5, right constant -0.5
+        let input: Vec<f32> = (0..200).map(|i| if i % 2 == 0 { 0.5 } else { -0.5 }).collect();
+        let output = LinearResampler::new(22050, 44100, 2).process(&input);
+        assert_eq!(output.len(), 400);
+        for frame in output.chunks(2) {
+            assert!((frame[0] - 0.5).abs() < 1e-6 && (frame[1] + 0.5).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Removing our own playback from the cabin noise reading. The mic hears the music as well as the
+//! road, so a louder output raises the measured level, which raises the gain again: a feedback
+//! loop toward the gain ceiling. Given the level we just sent to the speakers and how strongly the
+//! cabin couples it back into the mic, the playback share is subtracted in the power domain.
+
+/// Never take more than this off the measured level (dB): a coupling factor set too high would
+/// otherwise drive the ambient estimate toward silence and the gain to its ceiling
+pub const MAX_SUBTRACTION_DB: f32 = 20.0;
+
+/// `10·log10(10^(a/10) - 10^(b/10))`, the level left after removing `b` from `a`
+fn power_subtract_db(a_db: f32, b_db: f32) -> f32 {
+    10.0 * (10f32.powf(a_db / 10.0) - 10f32.powf(b_db / 10.0)).max(0.0).log10()
+}
+
+/// Estimates the ambient cabin level by subtracting the playback the mic picks up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SelfMaskingCompensator {
+    coupling_db: f32,
+}
+
+impl SelfMaskingCompensator {
+    /// `coupling_db` maps the output level (dBFS, before the speakers) to the level it reads at the
+    /// mic on the controller's scale (mic dBFS plus `MIC_CALIBRATION_DB`). See `calibrate`.
+    pub fn new(coupling_db: f32) -> Self {
+        Self { coupling_db }
+    }
+
+    pub fn coupling_db(&self) -> f32 {
+        self.coupling_db
+    }
+
+    /// Coupling factor from one calibration reading: play a test signal at `output_dbfs` in a
+    /// parked car and measure `measured_db` at the mic; `quiet_db` is the mic level with the
+    /// playback off, removed first so the cabin's own floor doesn't count as coupling.
+    pub fn calibrate(measured_db: f32, quiet_db: f32, output_dbfs: f32) -> Self {
+        let playback_at_mic_db =
+            if measured_db > quiet_db { power_subtract_db(measured_db, quiet_db) } else { measured_db };
+        Self::new(playback_at_mic_db - output_dbfs)
+    }
+
+    /// Level of our own playback at the mic (dB) for an output level of `output_dbfs`
+    pub fn playback_at_mic_db(&self, output_dbfs: f32) -> f32 {
+        output_dbfs + self.coupling_db
+    }
+
+    /// Ambient level (dB) from the mic reading `measured_db` while playing at `output_dbfs`
+    pub fn ambient_db(&self, measured_db: f32, output_dbfs: f32) -> f32 {
+        let floor = measured_db - MAX_SUBTRACTION_DB;
+        power_subtract_db(measured_db, self.playback_at_mic_db(output_dbfs)).max(floor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core_dsp::rms_to_db;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_subtracting_the_playback_recovers_the_ambient_level() {
+        let mut rng = StdRng::seed_from_u64(75);
+        let n = 48_000;
+        let calibration = 94.0;
+        // uncorrelated road noise and music; the cabin carries the music to the mic 12 dB down
+        let road: Vec<f32> = (0..n).map(|_| rng.random_range(-0.05..0.05)).collect();
+        let music: Vec<f32> = (0..n).map(|i| 0.4 * (i as f32 * 0.0311).sin() + rng.random_range(-0.1..0.1)).collect();
+        let coupling = 10f32.powf(-12.0 / 20.0);
+        let mic: Vec<f32> = road.iter().zip(&music).map(|(r, m)| r + coupling * m).collect();
+
+        let ambient_db = rms_to_db(&road, calibration);
+        let output_dbfs = rms_to_db(&music, 0.0);
+        let measured_db = rms_to_db(&mic, calibration);
+        assert!(measured_db - ambient_db > 3.0, "the playback dominates the raw reading");
+
+        // calibrated in a quiet car: only the road floor, then music on top of it
+        let compensator = SelfMaskingCompensator::calibrate(measured_db, ambient_db, output_dbfs);
+        assert!((compensator.coupling_db() - (calibration - 12.0)).abs() < 0.2, "{}", compensator.coupling_db());
+        let recovered = compensator.ambient_db(measured_db, output_dbfs);
+        assert!((recovered - ambient_db).abs() < 0.5, "recovered {} dB, ambient {} dB", recovered, ambient_db);
+
+        // louder playback no longer raises the estimate
+        let louder: Vec<f32> = road.iter().zip(&music).map(|(r, m)| r + 2.0 * coupling * m).collect();
+        let louder_dbfs = output_dbfs + 20.0 * 2f32.log10();
+        let recovered = compensator.ambient_db(rms_to_db(&louder, calibration), louder_dbfs);
+        assert!((recovered - ambient_db).abs() < 1.0, "recovered {} dB at +6 dB playback", recovered);
+
+        // an over-estimated coupling is bounded instead of reading silence
+        let too_strong = SelfMaskingCompensator::new(compensator.coupling_db() + 10.0);
+        assert_eq!(too_strong.ambient_db(measured_db, output_dbfs), measured_db - MAX_SUBTRACTION_DB);
+    }
+}
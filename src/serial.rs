@@ -0,0 +1,73 @@
+//! Raw serial-port access for the OBD-II and GPS speed sources. Uses termios directly through `libc`
+//! (already a dependency for the signal handler) rather than pulling in a serial crate.
+
+use std::fs::File;
+use std::io;
+use std::time::Duration;
+
+/// Open `path` (e.g. `/dev/ttyUSB0`) as a raw 8N1 port at `baud`. Reads return after at most
+/// `read_timeout` (rounded to 0.1 s) with whatever has arrived, possibly nothing.
+#[cfg(unix)]
+pub fn open_serial(path: &str, baud: u32, read_timeout: Duration) -> io::Result<File> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags_noctty()
+        .open(path)?;
+    let speed = baud_constant(baud)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unsupported baud rate {}", baud)))?;
+    let fd = file.as_raw_fd();
+    // SAFETY: fd is a valid open descriptor for the lifetime of `file`; termios is plain data
+    unsafe {
+        let mut tio: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut tio) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut tio);
+        tio.c_cflag |= libc::CLOCAL | libc::CREAD;
+        tio.c_cc[libc::VMIN] = 0;
+        tio.c_cc[libc::VTIME] = (read_timeout.as_millis() / 100).clamp(1, 255) as libc::cc_t;
+        if libc::cfsetispeed(&mut tio, speed) != 0 || libc::cfsetospeed(&mut tio, speed) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::tcsetattr(fd, libc::TCSANOW, &tio) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::tcflush(fd, libc::TCIOFLUSH);
+    }
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+pub fn open_serial(path: &str, _baud: u32, _read_timeout: Duration) -> io::Result<File> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, format!("serial port {} needs a unix host", path)))
+}
+
+#[cfg(unix)]
+trait NoCtty {
+    fn custom_flags_noctty(&mut self) -> &mut Self;
+}
+
+#[cfg(unix)]
+impl NoCtty for std::fs::OpenOptions {
+    // don't let the port become our controlling terminal
+    fn custom_flags_noctty(&mut self) -> &mut Self {
+        use std::os::unix::fs::OpenOptionsExt;
+        self.custom_flags(libc::O_NOCTTY)
+    }
+}
+
+#[cfg(unix)]
+fn baud_constant(baud: u32) -> Option<libc::speed_t> {
+    Some(match baud {
+        4800 => libc::B4800,
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        _ => return None,
+    })
+}
@@ -1,4 +1,3 @@
-mod gain;
 mod audio;
 
 fn main() -> anyhow::Result<()> {
@@ -0,0 +1,131 @@
+//! Band-limited noise estimate from the mic spectrum. Broadband RMS lets engine whine or HVAC
+//! hiss push the gain up; summing only the road-rumble band (100–500 Hz by default) keeps the
+//! controller keyed to the noise that actually masks music.
+//!
+//! The FFT is a small in-place radix-2 transform: one power-of-two size per estimator doesn't
+//! warrant an FFT dependency.
+
+use std::f32::consts::PI;
+
+/// Default road-rumble band (Hz)
+pub const DEFAULT_BAND_HZ: (f32, f32) = (100.0, 500.0);
+
+/// Sums the Hann-windowed power spectrum of a window of mic samples over `[low, high]` Hz
+pub struct SpectralNoiseEstimator {
+    sample_rate: f32,
+    band_hz: (f32, f32),
+    re: Vec<f32>,
+    im: Vec<f32>,
+}
+
+impl SpectralNoiseEstimator {
+    /// `max_window` is the longest window `estimate_db` will see; the FFT size is the next power of two
+    pub fn new(sample_rate: f32, band_hz: (f32, f32), max_window: usize) -> Self {
+        let size = max_window.max(2).next_power_of_two();
+        Self { sample_rate, band_hz, re: vec![0.0; size], im: vec![0.0; size] }
+    }
+
+    pub fn band_hz(&self) -> (f32, f32) {
+        self.band_hz
+    }
+
+    /// Mean-square power of `samples` within the band, in dB, plus `calibration_db` (same scale as
+    /// a broadband RMS level with the same calibration). Longer windows than the FFT size are truncated.
+    pub fn estimate_db(&mut self, samples: &[f32], calibration_db: f32) -> f32 {
+        let n = samples.len().min(self.re.len());
+        if n == 0 {
+            return 20.0 * 1e-9f32.log10() + calibration_db;
+        }
+        let size = self.re.len();
+        let mut window_power = 0.0f32;
+        self.re.fill(0.0);
+        self.im.fill(0.0);
+        for (i, (re, &x)) in self.re.iter_mut().zip(&samples[..n]).enumerate() {
+            let w = if n > 1 { 0.5 - 0.5 * (2.0 * PI * i as f32 / (n - 1) as f32).cos() } else { 1.0 };
+            *re = x * w;
+            window_power += w * w;
+        }
+        fft(&mut self.re, &mut self.im);
+
+        let bin_hz = self.sample_rate / size as f32;
+        let first = ((self.band_hz.0 / bin_hz).ceil() as usize).max(1);
+        let last = ((self.band_hz.1 / bin_hz).floor() as usize).min(size / 2 - 1);
+        let energy: f32 = (first..=last).map(|k| self.re[k] * self.re[k] + self.im[k] * self.im[k]).sum();
+        // one-sided spectrum, normalised by Parseval and the window's power so a sine of amplitude A
+        // inside the band reads A^2/2 like the broadband mean square
+        let power = 2.0 * energy / (size as f32 * window_power.max(1e-12));
+        10.0 * power.max(1e-18).log10() + calibration_db
+    }
+}
+
+/// In-place iterative radix-2 FFT; `re.len()` must be a power of two
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two() && im.len() == n);
+    // bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tones(fs: f32, n: usize, parts: &[(f32, f32)]) -> Vec<f32> {
+        (0..n)
+            .map(|i| parts.iter().map(|&(f, a)| a * (2.0 * PI * f * i as f32 / fs).sin()).sum())
+            .collect()
+    }
+
+    #[test]
+    fn test_band_estimate_tracks_200hz_not_2khz() {
+        let fs = 16_000.0;
+        let mut est = SpectralNoiseEstimator::new(fs, DEFAULT_BAND_HZ, 4096);
+        let both = est.estimate_db(&tones(fs, 4096, &[(200.0, 0.5), (2000.0, 0.5)]), 0.0);
+        // 0.5 amplitude sine: mean square 0.125 -> -9.03 dB
+        assert!((both + 9.03).abs() < 0.3, "band level {}", both);
+
+        let loud_2k = est.estimate_db(&tones(fs, 4096, &[(200.0, 0.5), (2000.0, 1.0)]), 0.0);
+        assert!((loud_2k - both).abs() < 0.1, "2 kHz is outside the band: {} vs {}", loud_2k, both);
+
+        let quiet_200 = est.estimate_db(&tones(fs, 4096, &[(200.0, 0.25), (2000.0, 0.5)]), 0.0);
+        assert!((both - quiet_200 - 6.02).abs() < 0.2, "halving 200 Hz: {} -> {}", both, quiet_200);
+    }
+
+    #[test]
+    fn test_fft_of_impulse_is_flat() {
+        let mut re = vec![0.0; 8];
+        let mut im = vec![0.0; 8];
+        re[0] = 1.0;
+        fft(&mut re, &mut im);
+        assert!(re.iter().all(|&r| (r - 1.0).abs() < 1e-6) && im.iter().all(|&i| i.abs() < 1e-6));
+    }
+}
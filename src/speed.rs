@@ -0,0 +1,202 @@
+use std::str::FromStr;
+
+/// Unit the speed server reports in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpeedUnit {
+    #[default]
+    Kmh,
+    Mph,
+}
+
+impl SpeedUnit {
+    const KMH_PER_MPH: f32 = 1.609_344;
+
+    /// Convert a reading in this unit to km/h (the unit the noise model expects)
+    pub fn to_kmh(self, speed: f32) -> f32 {
+        match self {
+            SpeedUnit::Kmh => speed,
+            SpeedUnit::Mph => speed * Self::KMH_PER_MPH,
+        }
+    }
+}
+
+impl FromStr for SpeedUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "kmh" | "km/h" | "kph" => Ok(SpeedUnit::Kmh),
+            "mph" => Ok(SpeedUnit::Mph),
+            other => Err(format!("unknown speed unit '{}' (expected kmh or mph)", other)),
+        }
+    }
+}
+
+/// Read a speed from a JSON value that is either a number (`72.5`) or a numeric string (`"72.5"`)
+pub fn speed_from_json(value: &serde_json::Value) -> Option<f32> {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64().map(|v| v as f32),
+        serde_json::Value::String(s) => s.trim().parse::<f32>().ok(),
+        _ => None,
+    }
+}
+
+/// Sanity checks for incoming speed readings: values are clamped to `[0, max_speed_kmh]`, and when
+/// `max_jump_kmh` is set a reading that jumps further than that from the last accepted one is
+/// rejected (the previous speed is held). After `HOLD_LIMIT` consecutive rejections the new level is
+/// accepted anyway, so a genuine change the filter mistook for a spike doesn't lock it out.
+#[derive(Clone, Debug)]
+pub struct SpeedValidator {
+    pub max_speed_kmh: f32,
+    pub max_jump_kmh: Option<f32>,
+    last: Option<f32>,
+    held: u32,
+    rejected: usize,
+}
+
+impl SpeedValidator {
+    pub const DEFAULT_MAX_SPEED_KMH: f32 = 250.0;
+    const HOLD_LIMIT: u32 = 3;
+
+    pub fn new(max_speed_kmh: f32, max_jump_kmh: Option<f32>) -> Self {
+        Self { max_speed_kmh: max_speed_kmh.max(0.0), max_jump_kmh, last: None, held: 0, rejected: 0 }
+    }
+
+    /// Read limits from MAX_SPEED_KMH (default 250) and SPEED_MAX_JUMP_KMH (unset disables spike rejection)
+    pub fn from_env() -> Self {
+        let read = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<f32>().ok());
+        Self::new(read("MAX_SPEED_KMH").unwrap_or(Self::DEFAULT_MAX_SPEED_KMH), read("SPEED_MAX_JUMP_KMH"))
+    }
+
+    /// Readings that were out of range, not finite, or rejected as spikes
+    pub fn rejected(&self) -> usize {
+        self.rejected
+    }
+
+    /// The speed to use for this reading, or None to keep the previous one
+    pub fn validate(&mut self, speed_kmh: f32) -> Option<f32> {
+        if !speed_kmh.is_finite() {
+            self.rejected += 1;
+            return None;
+        }
+        let clamped = speed_kmh.clamp(0.0, self.max_speed_kmh);
+        if clamped != speed_kmh {
+            self.rejected += 1;
+        }
+        if let (Some(last), Some(max_jump)) = (self.last, self.max_jump_kmh) {
+            if (clamped - last).abs() > max_jump && self.held < Self::HOLD_LIMIT {
+                if clamped == speed_kmh {
+                    self.rejected += 1;
+                }
+                self.held += 1;
+                return None;
+            }
+        }
+        self.held = 0;
+        self.last = Some(clamped);
+        Some(clamped)
+    }
+}
+
+/// Single-pole low-pass for the speed reading, with its own time constant so GPS/OBD jitter is
+/// removed before the noise model and doesn't interact with the gain smoother's attack/release.
+#[derive(Clone, Debug)]
+pub struct SpeedSmoother {
+    /// Time constant in seconds (0 passes readings straight through)
+    pub tau: f32,
+    value: Option<f32>,
+}
+
+impl SpeedSmoother {
+    pub const DEFAULT_TAU_S: f32 = 1.0;
+
+    pub fn new(tau: f32) -> Self {
+        Self { tau: tau.max(0.0), value: None }
+    }
+
+    /// Read the time constant from SPEED_SMOOTHING_TAU_S (seconds, default 1.0)
+    pub fn from_env() -> Self {
+        let tau = std::env::var("SPEED_SMOOTHING_TAU_S")
+            .ok()
+            .and_then(|v| v.parse::<f32>().ok())
+            .unwrap_or(Self::DEFAULT_TAU_S);
+        Self::new(tau)
+    }
+
+    /// Feed the latest reading, `dt` seconds after the previous step; returns the smoothed speed.
+    /// The first reading initialises the filter.
+    pub fn step(&mut self, speed_kmh: f32, dt: f32) -> f32 {
+        let next = match self.value {
+            Some(v) if self.tau > 0.0 => v + (1.0 - (-dt.max(0.0) / self.tau).exp()) * (speed_kmh - v),
+            _ => speed_kmh,
+        };
+        self.value = Some(next);
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_mph_to_kmh() {
+        assert!((SpeedUnit::Mph.to_kmh(60.0) - 96.56).abs() < 0.01);
+        assert_eq!(SpeedUnit::Kmh.to_kmh(60.0), 60.0);
+        assert_eq!("MPH".parse::<SpeedUnit>(), Ok(SpeedUnit::Mph));
+        assert!("knots".parse::<SpeedUnit>().is_err());
+    }
+
+    #[test]
+    fn test_speed_from_number_or_string() {
+        assert_eq!(speed_from_json(&json!(72.5)), Some(72.5));
+        assert_eq!(speed_from_json(&json!(60)), Some(60.0));
+        assert_eq!(speed_from_json(&json!("72.5")), Some(72.5));
+        assert_eq!(speed_from_json(&json!(" 40 ")), Some(40.0));
+        assert_eq!(speed_from_json(&json!("fast")), None);
+        assert_eq!(speed_from_json(&json!(null)), None);
+    }
+
+    #[test]
+    fn test_validator_clamps_out_of_range() {
+        let mut v = SpeedValidator::new(200.0, None);
+        assert_eq!(v.validate(9999.0), Some(200.0));
+        assert_eq!(v.validate(-5.0), Some(0.0));
+        assert_eq!(v.validate(f32::NAN), None);
+        assert_eq!(v.validate(80.0), Some(80.0));
+        assert_eq!(v.rejected(), 3);
+    }
+
+    #[test]
+    fn test_validator_holds_through_spike() {
+        let mut v = SpeedValidator::new(250.0, Some(20.0));
+        assert_eq!(v.validate(60.0), Some(60.0));
+        assert_eq!(v.validate(180.0), None, "spike held");
+        assert_eq!(v.validate(62.0), Some(62.0));
+        assert_eq!(v.rejected(), 1);
+
+        // a sustained new level is accepted after the hold limit
+        let readings: Vec<Option<f32>> = (0..4).map(|_| v.validate(100.0)).collect();
+        assert_eq!(readings, vec![None, None, None, Some(100.0)]);
+    }
+
+    #[test]
+    fn test_speed_smoother_settles_on_mean_of_jitter() {
+        let mut smoother = SpeedSmoother::new(2.0);
+        let mut smoothed = 0.0;
+        // ~7 Hz readings alternating 50/70 km/h for 30 s
+        for i in 0..210 {
+            let reading = if i % 2 == 0 { 50.0 } else { 70.0 };
+            smoothed = smoother.step(reading, 1.0 / 7.0);
+        }
+        assert!((smoothed - 60.0).abs() < 1.0, "smoothed speed {}", smoothed);
+    }
+
+    #[test]
+    fn test_speed_smoother_zero_tau_passes_through() {
+        let mut smoother = SpeedSmoother::new(0.0);
+        assert_eq!(smoother.step(50.0, 0.1), 50.0);
+        assert_eq!(smoother.step(70.0, 0.1), 70.0);
+    }
+}
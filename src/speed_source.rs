@@ -0,0 +1,342 @@
+//! Where the vehicle speed comes from. Each `SpeedSource` runs on its own thread and pushes readings
+//! through a `SpeedPublisher` (unit conversion, validation, stale tracking) into a `SharedSpeed`
+//! that the controller reads.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+#[cfg(feature = "speed-http")]
+use anyhow::Result;
+#[cfg(feature = "speed-http")]
+use reqwest::blocking::Client;
+
+use crate::speed::{speed_from_json, SpeedUnit, SpeedValidator};
+use log::{info, warn};
+
+use crate::util::{sleep_while_running, spawn_named, AtomicF32};
+use crate::ws::{WsClient, WsMessage};
+
+/// Latest speed and source health, shared lock-free with the controller
+#[derive(Debug, Default)]
+pub struct SharedSpeed {
+    speed_kmh: AtomicF32,
+    stale: AtomicBool,
+    rejected: AtomicUsize,
+    errors: AtomicUsize,
+}
+
+impl SharedSpeed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Last accepted speed (km/h)
+    pub fn speed_kmh(&self) -> f32 {
+        self.speed_kmh.load(Ordering::Relaxed)
+    }
+
+    /// True while the source has failed too many times in a row for the speed to be trusted
+    pub fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::Relaxed)
+    }
+
+    /// Readings the validator clamped or dropped
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Failed polls, lost connections and unusable messages so far
+    pub fn errors(&self) -> usize {
+        self.errors.load(Ordering::Relaxed)
+    }
+}
+
+/// Consecutive-failure tracking for a speed source: retries back off exponentially
+/// (period, 2x, 4x, ... up to `max_delay`) and the speed counts as stale after `stale_after` failures.
+#[derive(Clone, Debug)]
+pub struct PollBackoff {
+    period: Duration,
+    max_delay: Duration,
+    stale_after: u32,
+    failures: u32,
+}
+
+impl PollBackoff {
+    pub fn new(period: Duration, max_delay: Duration, stale_after: u32) -> Self {
+        Self { period, max_delay: max_delay.max(period), stale_after: stale_after.max(1), failures: 0 }
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.failures >= self.stale_after
+    }
+
+    /// Record a good reading; true if this ends a stale period
+    pub fn on_success(&mut self) -> bool {
+        let was_stale = self.is_stale();
+        self.failures = 0;
+        was_stale
+    }
+
+    /// Record a failure; true if this is the failure that makes the speed stale
+    pub fn on_failure(&mut self) -> bool {
+        self.failures = self.failures.saturating_add(1);
+        self.failures == self.stale_after
+    }
+
+    /// How long to wait before the next attempt
+    pub fn delay(&self) -> Duration {
+        let factor = 1u32 << self.failures.min(16);
+        (self.period * factor).min(self.max_delay)
+    }
+}
+
+/// Turns raw readings from a source into the shared speed: converts units, validates,
+/// and tracks failures so the controller can fall back when the source goes quiet.
+pub struct SpeedPublisher {
+    shared: Arc<SharedSpeed>,
+    unit: SpeedUnit,
+    validator: SpeedValidator,
+    backoff: PollBackoff,
+}
+
+impl SpeedPublisher {
+    pub fn new(shared: Arc<SharedSpeed>, unit: SpeedUnit, validator: SpeedValidator, backoff: PollBackoff) -> Self {
+        Self { shared, unit, validator, backoff }
+    }
+
+    /// A reading in the source's unit
+    pub fn publish(&mut self, speed: f32) {
+        // outliers are clamped; spikes keep the previous speed
+        if let Some(kmh) = self.validator.validate(self.unit.to_kmh(speed)) {
+            self.shared.speed_kmh.store(kmh, Ordering::Relaxed);
+        }
+        self.shared.rejected.store(self.validator.rejected(), Ordering::Relaxed);
+        if self.backoff.on_success() {
+            self.shared.stale.store(false, Ordering::Relaxed);
+            info!("[Speed] data is back; adaptive gain resumed");
+        }
+    }
+
+    /// A failed poll, lost connection, or unusable message
+    pub fn fail(&mut self) {
+        self.shared.errors.fetch_add(1, Ordering::Relaxed);
+        if self.backoff.on_failure() {
+            self.shared.stale.store(true, Ordering::Relaxed);
+            warn!("[Speed] no speed after {} attempts; controller holds its gain", self.backoff.stale_after);
+        }
+    }
+
+    /// Wait before the next poll or reconnect
+    pub fn retry_delay(&self) -> Duration {
+        self.backoff.delay()
+    }
+}
+
+/// Speed from a `{"speed": <num>}` JSON payload (number or numeric string)
+pub fn speed_from_payload(json: &serde_json::Value) -> Option<f32> {
+    json.get("speed").and_then(speed_from_json)
+}
+
+/// A producer of vehicle speed readings
+pub trait SpeedSource {
+    /// Start reading on a new thread, publishing every reading until `stop` is set
+    fn spawn(self, publisher: SpeedPublisher, stop: &'static AtomicBool) -> JoinHandle<()>;
+}
+
+/// Polls an HTTP endpoint returning `{"speed": <num>}` (feature `speed-http`)
+#[cfg(feature = "speed-http")]
+pub struct HttpPoller {
+    url: String,
+    client: Client,
+}
+
+#[cfg(feature = "speed-http")]
+impl HttpPoller {
+    /// Per-request limit (connect and total), so a stalled server can't hang the poller
+    pub const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+
+    pub fn new(url: impl Into<String>) -> Result<Self> {
+        let client = Client::builder()
+            .connect_timeout(Self::REQUEST_TIMEOUT)
+            .timeout(Self::REQUEST_TIMEOUT)
+            .build()?;
+        Ok(Self { url: url.into(), client })
+    }
+
+    fn poll(&self) -> Option<f32> {
+        self.client
+            .get(&self.url)
+            .send()
+            .and_then(|resp| resp.error_for_status())
+            .and_then(|resp| resp.json::<serde_json::Value>())
+            .map_err(|e| warn!("Speed poll error: {}", e))
+            .ok()
+            .and_then(|json| speed_from_payload(&json))
+    }
+}
+
+#[cfg(feature = "speed-http")]
+impl SpeedSource for HttpPoller {
+    fn spawn(self, mut publisher: SpeedPublisher, stop: &'static AtomicBool) -> JoinHandle<()> {
+        spawn_named("speed-http", move || {
+            while !stop.load(Ordering::Relaxed) {
+                match self.poll() {
+                    Some(speed) => publisher.publish(speed),
+                    None => publisher.fail(),
+                }
+                sleep_while_running(stop, publisher.retry_delay());
+            }
+        })
+    }
+}
+
+/// Receives `{"speed": <num>}` messages pushed over a `ws://` WebSocket, reconnecting with backoff
+/// whenever the connection drops.
+pub struct WebSocketSource {
+    url: String,
+}
+
+impl WebSocketSource {
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+    /// How often the reader wakes to check for shutdown while the server is quiet
+    const IDLE_POLL: Duration = Duration::from_millis(200);
+
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    /// Read messages until the connection closes or `stop` is set
+    fn run_connection(&self, client: &mut WsClient, publisher: &mut SpeedPublisher, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            match client.poll_message(Self::IDLE_POLL) {
+                Ok(None) => {}
+                Ok(Some(WsMessage::Text(text))) => {
+                    match serde_json::from_str::<serde_json::Value>(&text).ok().as_ref().and_then(speed_from_payload) {
+                        Some(speed) => publisher.publish(speed),
+                        None => warn!("[Speed] ignoring WebSocket message without a speed: {}", text),
+                    }
+                }
+                Ok(Some(WsMessage::Binary(_))) => {}
+                Ok(Some(WsMessage::Close)) => {
+                    warn!("[Speed] WebSocket closed by server");
+                    return;
+                }
+                Err(e) => {
+                    warn!("[Speed] WebSocket error: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl SpeedSource for WebSocketSource {
+    fn spawn(self, mut publisher: SpeedPublisher, stop: &'static AtomicBool) -> JoinHandle<()> {
+        spawn_named("speed-ws", move || {
+            while !stop.load(Ordering::Relaxed) {
+                match WsClient::connect(&self.url, Self::CONNECT_TIMEOUT) {
+                    Ok(mut client) => {
+                        info!("[Speed] WebSocket connected to {}", self.url);
+                        self.run_connection(&mut client, &mut publisher, stop);
+                    }
+                    Err(e) => warn!("[Speed] WebSocket connect to {} failed: {}", self.url, e),
+                }
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                publisher.fail();
+                sleep_while_running(stop, publisher.retry_delay());
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Instant;
+
+    fn publisher(shared: &Arc<SharedSpeed>) -> SpeedPublisher {
+        let backoff = PollBackoff::new(Duration::from_millis(10), Duration::from_millis(50), 3);
+        SpeedPublisher::new(shared.clone(), SpeedUnit::Kmh, SpeedValidator::new(250.0, None), backoff)
+    }
+
+    #[test]
+    fn test_poll_backoff_doubles_to_cap_and_flags_stale() {
+        let mut backoff = PollBackoff::new(Duration::from_millis(150), Duration::from_secs(2), 3);
+        assert_eq!(backoff.delay(), Duration::from_millis(150));
+        assert!(!backoff.on_failure());
+        assert_eq!(backoff.delay(), Duration::from_millis(300));
+        assert!(!backoff.on_failure());
+        assert_eq!(backoff.delay(), Duration::from_millis(600));
+        assert!(backoff.on_failure(), "third failure enters the stale state");
+        assert!(backoff.is_stale());
+        assert!(!backoff.on_failure(), "transition is reported once");
+        for _ in 0..40 {
+            backoff.on_failure();
+        }
+        assert_eq!(backoff.delay(), Duration::from_secs(2), "capped");
+
+        assert!(backoff.on_success(), "recovery leaves the stale state");
+        assert!(!backoff.is_stale());
+        assert_eq!(backoff.delay(), Duration::from_millis(150));
+        assert!(!backoff.on_success());
+    }
+
+    #[test]
+    fn test_publisher_marks_stale_and_recovers() {
+        let shared = Arc::new(SharedSpeed::new());
+        let mut publisher = publisher(&shared);
+        publisher.publish(72.0);
+        assert_eq!(shared.speed_kmh(), 72.0);
+        for _ in 0..3 {
+            publisher.fail();
+        }
+        assert!(shared.is_stale());
+        assert_eq!(shared.errors(), 3);
+        assert_eq!(shared.speed_kmh(), 72.0, "last speed kept while stale");
+        publisher.publish(80.0);
+        assert!(!shared.is_stale());
+        assert_eq!(shared.speed_kmh(), 80.0);
+    }
+
+    #[test]
+    fn test_websocket_source_receives_pushed_speed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/speed", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && !line.ends_with("\r\n\r\n") {}
+            assert!(line.starts_with("GET /speed HTTP/1.1"));
+            let mut stream = stream;
+            stream
+                .write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n")
+                .unwrap();
+            // server frames are unmasked
+            let payload = br#"{"speed": "42.5"}"#;
+            stream.write_all(&[0x81, payload.len() as u8]).unwrap();
+            stream.write_all(payload).unwrap();
+            thread::sleep(Duration::from_millis(200));
+            stream.write_all(&[0x88, 0x00]).unwrap();
+        });
+
+        let shared = Arc::new(SharedSpeed::new());
+        let stop: &'static AtomicBool = Box::leak(Box::new(AtomicBool::new(false)));
+        let handle = WebSocketSource::new(url).spawn(publisher(&shared), stop);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while shared.speed_kmh() != 42.5 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        stop.store(true, Ordering::Relaxed);
+        server.join().unwrap();
+        handle.join().unwrap();
+        assert_eq!(shared.speed_kmh(), 42.5);
+    }
+}
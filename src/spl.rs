@@ -0,0 +1,122 @@
+//! Sound level meter time weighting (IEC 61672-1): the squared signal is averaged by a one-pole
+//! exponential with a standard time constant, so the cabin level reads like a handheld meter set to
+//! Fast, Slow or Impulse instead of depending on the controller's window length.
+
+/// Exponential time weighting applied to the mic power
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Weighting {
+    /// 125 ms
+    #[default]
+    Fast,
+    /// 1 s
+    Slow,
+    /// 35 ms rising, 1.5 s falling: catches short bursts and holds them long enough to read
+    Impulse,
+}
+
+impl Weighting {
+    /// Time constants (s) while the power is rising and falling
+    pub fn time_constants(self) -> (f32, f32) {
+        match self {
+            Weighting::Fast => (0.125, 0.125),
+            Weighting::Slow => (1.0, 1.0),
+            Weighting::Impulse => (0.035, 1.5),
+        }
+    }
+}
+
+impl std::str::FromStr for Weighting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fast" | "f" => Ok(Weighting::Fast),
+            "slow" | "s" => Ok(Weighting::Slow),
+            "impulse" | "i" => Ok(Weighting::Impulse),
+            other => Err(format!("unknown time weighting '{}' (expected fast, slow or impulse)", other)),
+        }
+    }
+}
+
+/// Time-weighted level of a sample stream, in dB plus a calibration offset
+pub struct SplMeter {
+    weighting: Weighting,
+    calibration_db: f32,
+    /// Per-sample smoothing coefficients for rising and falling power
+    alpha_rise: f32,
+    alpha_fall: f32,
+    /// Time-weighted mean square
+    power: f32,
+}
+
+impl SplMeter {
+    /// `calibration_db` maps dBFS to the reported scale (e.g. `MIC_CALIBRATION_DB` for dB SPL)
+    pub fn new(sample_rate: f32, weighting: Weighting, calibration_db: f32) -> Self {
+        let (rise, fall) = weighting.time_constants();
+        let alpha = |tau: f32| 1.0 - (-1.0 / (tau * sample_rate)).exp();
+        Self { weighting, calibration_db, alpha_rise: alpha(rise), alpha_fall: alpha(fall), power: 0.0 }
+    }
+
+    pub fn weighting(&self) -> Weighting {
+        self.weighting
+    }
+
+    /// Feed consecutive samples; returns the level after the last one
+    pub fn process(&mut self, samples: &[f32]) -> f32 {
+        for &x in samples {
+            let p = x * x;
+            let alpha = if p > self.power { self.alpha_rise } else { self.alpha_fall };
+            self.power += alpha * (p - self.power);
+        }
+        self.level_db()
+    }
+
+    /// Current reading (dB); -180 dB plus calibration before any signal
+    pub fn level_db(&self) -> f32 {
+        10.0 * self.power.max(1e-18).log10() + self.calibration_db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f32 = 8000.0;
+
+    // Reading of a meter fed `seconds` of a steady 0.5 amplitude square wave (-6.02 dBFS)
+    fn after_step(weighting: Weighting, seconds: f32) -> f32 {
+        let mut meter = SplMeter::new(RATE, weighting, 94.0);
+        let n = (seconds * RATE).round() as usize;
+        let step: Vec<f32> = (0..n).map(|i| if i % 2 == 0 { 0.5 } else { -0.5 }).collect();
+        meter.process(&step)
+    }
+
+    #[test]
+    fn test_step_settles_per_time_constant() {
+        let target = 94.0 - 6.0206;
+        for weighting in [Weighting::Fast, Weighting::Slow] {
+            let tau = weighting.time_constants().0;
+            // one time constant in, the power is at 1 - 1/e of its final value: 2 dB short
+            let expected = target + 10.0 * (1.0 - (-1.0f32).exp()).log10();
+            let reading = after_step(weighting, tau);
+            assert!((reading - expected).abs() < 0.1, "{:?}: {} dB after tau", weighting, reading);
+            // and inside 1 dB from 1.6 time constants (1 - e^-t/tau >= 10^-0.1)
+            let reading = after_step(weighting, 1.6 * tau);
+            assert!(target - reading < 1.0 && reading <= target, "{:?}: {} dB after 1.6 tau", weighting, reading);
+            assert!((after_step(weighting, 10.0 * tau) - target).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_impulse_rises_fast_and_decays_slowly() {
+        let mut meter = SplMeter::new(RATE, Weighting::Impulse, 0.0);
+        let burst: Vec<f32> = (0..(0.1 * RATE) as usize).map(|i| if i % 2 == 0 { 0.5 } else { -0.5 }).collect();
+        let peak = meter.process(&burst);
+        assert!(peak > -6.5, "a 100 ms burst reads within 0.5 dB of its level: {}", peak);
+        // 1.5 s decay: 10*log10(e) / 1.5 = 2.9 dB per second
+        let after = meter.process(&vec![0.0; RATE as usize]);
+        assert!((peak - after - 2.9).abs() < 0.1, "{} dB/s", peak - after);
+        assert_eq!("slow".parse(), Ok(Weighting::Slow));
+        assert!("medium".parse::<Weighting>().is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Lock-free single-producer/single-consumer ring buffer of f32 samples.
+/// The producer only writes `tail` and the consumer only writes `head`, so neither side ever blocks;
+/// this is what lets the real-time output callback read the playback queue without a mutex.
+struct Ring {
+    buf: Box<[UnsafeCell<f32>]>,
+    // total samples popped / pushed (wrapping); slot index is counter % capacity
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// SAFETY: a slot is written only by the producer while it is outside [head, tail) and read only by the
+// consumer while it is inside it; the Release/Acquire pairs on head/tail order those accesses.
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+}
+
+/// Create a ring holding up to `capacity` samples and split it into its two ends.
+pub fn spsc_ring(capacity: usize) -> (Producer, Consumer) {
+    let buf = (0..capacity.max(1)).map(|_| UnsafeCell::new(0.0)).collect();
+    let ring = Arc::new(Ring {
+        buf,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (Producer { ring: ring.clone() }, Consumer { ring })
+}
+
+/// Writing end (e.g. the WAV loader thread)
+pub struct Producer {
+    ring: Arc<Ring>,
+}
+
+impl Producer {
+    /// Push as many samples from `samples` as fit; returns how many were written.
+    pub fn push_slice(&mut self, samples: &[f32]) -> usize {
+        let cap = self.ring.capacity();
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let head = self.ring.head.load(Ordering::Acquire);
+        let free = cap - tail.wrapping_sub(head);
+        let n = free.min(samples.len());
+        for (i, &s) in samples[..n].iter().enumerate() {
+            let slot = &self.ring.buf[tail.wrapping_add(i) % cap];
+            // SAFETY: slot is outside [head, tail) so the consumer is not reading it
+            unsafe { *slot.get() = s };
+        }
+        self.ring.tail.store(tail.wrapping_add(n), Ordering::Release);
+        n
+    }
+
+    /// Read-only handle for reporting the fill level from another thread
+    pub fn monitor(&self) -> RingMonitor {
+        RingMonitor { ring: self.ring.clone() }
+    }
+}
+
+/// Reading end (e.g. the audio output callback)
+pub struct Consumer {
+    ring: Arc<Ring>,
+}
+
+impl Consumer {
+    pub fn pop(&mut self) -> Option<f32> {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let slot = &self.ring.buf[head % self.ring.capacity()];
+        // SAFETY: slot is inside [head, tail) so the producer has finished writing it
+        let s = unsafe { *slot.get() };
+        self.ring.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(s)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Fill-level view of a ring that can live on any thread
+#[derive(Clone)]
+pub struct RingMonitor {
+    ring: Arc<Ring>,
+}
+
+impl RingMonitor {
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_pop_wraps_around() {
+        let (mut tx, mut rx) = spsc_ring(4);
+        assert_eq!(tx.push_slice(&[1.0, 2.0, 3.0]), 3);
+        assert_eq!(rx.pop(), Some(1.0));
+        assert_eq!(rx.pop(), Some(2.0));
+
+        // only 3 free slots left; the write wraps past the end of the buffer
+        assert_eq!(tx.push_slice(&[4.0, 5.0, 6.0, 7.0]), 3);
+        assert_eq!(tx.push_slice(&[8.0]), 0, "ring is full");
+        let drained: Vec<f32> = std::iter::from_fn(|| rx.pop()).collect();
+        assert_eq!(drained, vec![3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(rx.pop(), None);
+    }
+
+    #[test]
+    fn test_cross_thread_order_preserved() {
+        let (mut tx, mut rx) = spsc_ring(64);
+        let total = 10_000;
+        let producer = std::thread::spawn(move || {
+            let samples: Vec<f32> = (0..total).map(|i| i as f32).collect();
+            let mut pos = 0;
+            while pos < samples.len() {
+                pos += tx.push_slice(&samples[pos..]);
+            }
+        });
+
+        let mut expected = 0;
+        while expected < total {
+            if let Some(s) = rx.pop() {
+                assert_eq!(s, expected as f32);
+                expected += 1;
+            }
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+//! The embedded app's bench link over its debug USART: gain telemetry out, so a desktop can plot
+//! the control loop live, and level commands in, so the target can be tuned without reflashing.
+//! Only `core` is used: the embedded app includes this module directly and the host decodes
+//! frames with it (`adaptive_vol telemetry <tty>`).
+//!
+//! One frame per processing cycle, 11 bytes:
+//!
+//! | offset | size | field                                           |
+//! |--------|------|-------------------------------------------------|
+//! | 0      | 1    | start byte `0xA5`                               |
+//! | 1      | 1    | sequence number, wrapping (gaps = lost frames)  |
+//! | 2      | 4    | smoothed level, ADC counts RMS, f32 LE          |
+//! | 6      | 4    | target gain, linear, f32 LE                     |
+//! | 10     | 1    | CRC-8 (poly 0x07, init 0) over bytes 1..10      |
+
+/// First byte of every frame
+pub const FRAME_START: u8 = 0xA5;
+/// Encoded frame size in bytes
+pub const FRAME_LEN: usize = 11;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TelemetryFrame {
+    pub seq: u8,
+    pub smoothed_level: f32,
+    pub target_gain: f32,
+}
+
+impl TelemetryFrame {
+    pub fn encode(&self) -> [u8; FRAME_LEN] {
+        let mut out = [0u8; FRAME_LEN];
+        out[0] = FRAME_START;
+        out[1] = self.seq;
+        out[2..6].copy_from_slice(&self.smoothed_level.to_le_bytes());
+        out[6..10].copy_from_slice(&self.target_gain.to_le_bytes());
+        out[10] = crc8(&out[1..10]);
+        out
+    }
+
+    /// Decode one complete frame; `None` if the start byte or CRC is wrong
+    pub fn decode(bytes: &[u8; FRAME_LEN]) -> Option<Self> {
+        if bytes[0] != FRAME_START || crc8(&bytes[1..10]) != bytes[10] {
+            return None;
+        }
+        let f32_at = |i: usize| f32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        Some(Self { seq: bytes[1], smoothed_level: f32_at(2), target_gain: f32_at(6) })
+    }
+}
+
+/// CRC-8, polynomial x^8 + x^2 + x + 1 (0x07), initial value 0, no reflection
+pub fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |mut crc, &b| {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// Byte-at-a-time frame decoder for a serial stream. Resynchronises on the start byte after noise,
+/// a partial frame at connect, or a corrupted frame.
+#[derive(Default)]
+pub struct FrameParser {
+    buf: [u8; FRAME_LEN],
+    len: usize,
+}
+
+impl FrameParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one received byte; returns a frame when this byte completes a valid one
+    pub fn push(&mut self, byte: u8) -> Option<TelemetryFrame> {
+        if self.len == 0 && byte != FRAME_START {
+            return None;
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+        if self.len < FRAME_LEN {
+            return None;
+        }
+        if let Some(frame) = TelemetryFrame::decode(&self.buf) {
+            self.len = 0;
+            return Some(frame);
+        }
+        // bad frame: the real start may be inside what we buffered, so restart from the next start byte
+        match self.buf[1..].iter().position(|&b| b == FRAME_START) {
+            Some(i) => {
+                self.buf.copy_within(i + 1.., 0);
+                self.len = FRAME_LEN - (i + 1);
+            }
+            None => self.len = 0,
+        }
+        None
+    }
+}
+
+/// Accepted range for `T` (target playback level, dB), as for the host `L_DESIRED_DB`
+pub const TARGET_DB_RANGE: (f32, f32) = (40.0, 100.0);
+/// Accepted range for `O` (user offset, dB), as for the host `USER_OFFSET_DB`
+pub const OFFSET_DB_RANGE: (f32, f32) = (-24.0, 24.0);
+/// Longest command line accepted; longer lines are discarded whole
+pub const MAX_LINE_LEN: usize = 16;
+
+/// A bench command: one ASCII line, `T75.0` sets the target level, `O-3.0` the user offset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BenchCommand {
+    SetTargetDb(f32),
+    SetOffsetDb(f32),
+}
+
+impl BenchCommand {
+    /// Parse one line (without the newline). Malformed or out-of-range lines give `None`.
+    pub fn parse(line: &[u8]) -> Option<Self> {
+        let text = core::str::from_utf8(line).ok()?.trim();
+        let mut chars = text.chars();
+        let kind = chars.next()?;
+        let value: f32 = chars.as_str().trim().parse().ok()?;
+        let in_range = |(low, high): (f32, f32)| value >= low && value <= high;
+        match kind.to_ascii_uppercase() {
+            'T' if in_range(TARGET_DB_RANGE) => Some(BenchCommand::SetTargetDb(value)),
+            'O' if in_range(OFFSET_DB_RANGE) => Some(BenchCommand::SetOffsetDb(value)),
+            _ => None,
+        }
+    }
+}
+
+/// Collects received bytes into lines and parses each complete one (`\n` or `\r` terminated)
+#[derive(Default)]
+pub struct CommandLineBuffer {
+    buf: [u8; MAX_LINE_LEN],
+    len: usize,
+    overflowed: bool,
+}
+
+impl CommandLineBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one received byte; returns the command when it ends a valid line
+    pub fn push(&mut self, byte: u8) -> Option<BenchCommand> {
+        if byte == b'\n' || byte == b'\r' {
+            let command = if self.overflowed { None } else { BenchCommand::parse(&self.buf[..self.len]) };
+            self.len = 0;
+            self.overflowed = false;
+            return command;
+        }
+        if self.len == MAX_LINE_LEN {
+            self.overflowed = true;
+        } else {
+            self.buf[self.len] = byte;
+            self.len += 1;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_round_trip() {
+        let frame = TelemetryFrame { seq: 200, smoothed_level: 312.5, target_gain: 1.25 };
+        let bytes = frame.encode();
+        assert_eq!(bytes[0], FRAME_START);
+        assert_eq!(TelemetryFrame::decode(&bytes), Some(frame));
+
+        let mut corrupted = bytes;
+        corrupted[7] ^= 0x10;
+        assert_eq!(TelemetryFrame::decode(&corrupted), None, "CRC catches a flipped bit");
+        // CRC-8/SMBUS check value
+        assert_eq!(crc8(b"123456789"), 0xF4);
+    }
+
+    #[test]
+    fn test_parser_resyncs_after_noise_and_bad_frames() {
+        let a = TelemetryFrame { seq: 1, smoothed_level: 10.0, target_gain: 0.5 };
+        let b = TelemetryFrame { seq: 2, smoothed_level: 20.0, target_gain: 2.0 };
+        let mut bad = a.encode();
+        bad[10] ^= 0xFF;
+
+        let mut stream = vec![0x00, 0x13, FRAME_START, 0x42]; // noise, including a false start
+        stream.extend_from_slice(&bad);
+        stream.extend_from_slice(&a.encode());
+        stream.extend_from_slice(&a.encode()[..5]); // truncated frame
+        stream.extend_from_slice(&b.encode());
+
+        let mut parser = FrameParser::new();
+        let frames: Vec<TelemetryFrame> = stream.iter().filter_map(|&byte| parser.push(byte)).collect();
+        assert_eq!(frames, vec![a, b]);
+    }
+
+    #[test]
+    fn test_bench_commands() {
+        assert_eq!(BenchCommand::parse(b"T75.0"), Some(BenchCommand::SetTargetDb(75.0)));
+        assert_eq!(BenchCommand::parse(b"o -3"), Some(BenchCommand::SetOffsetDb(-3.0)));
+        assert_eq!(BenchCommand::parse(b"T150"), None, "out of range");
+        assert_eq!(BenchCommand::parse(b"O-30"), None, "out of range");
+        assert_eq!(BenchCommand::parse(b"X1.0"), None);
+        assert_eq!(BenchCommand::parse(b"Tloud"), None);
+        assert_eq!(BenchCommand::parse(b""), None);
+
+        let mut lines = CommandLineBuffer::new();
+        let input = b"T72.5\r\ngarbage\nO-3.0\nT7777777777777777777777\nO1\n";
+        let commands: Vec<BenchCommand> = input.iter().filter_map(|&b| lines.push(b)).collect();
+        assert_eq!(
+            commands,
+            vec![BenchCommand::SetTargetDb(72.5), BenchCommand::SetOffsetDb(-3.0), BenchCommand::SetOffsetDb(1.0)]
+        );
+    }
+}
@@ -1,61 +1,131 @@
 #![no_main]
 #![no_std]
 
-// HAL-version-matched implementation scaffold for `stm32f4xx-hal = "0.15"` and `rtic = "0.7"`.
+// HAL-version-matched implementation scaffold for `stm32f4xx-hal = "0.15"`, `rtic = "2"` and
+// `rtic-monotonics = "2"` (feature `cortex-m-systick`).
 // This file implements:
-// - ADC circular DMA capture into a static buffer
+// - ADC circular DMA capture into a static buffer, one conversion per TIM2 update at MIC_SAMPLE_RATE
 // - Half/full transfer handling to compute RMS on each half
-// - Simple gain smoother
-// - I2S (SPI3) DMA transmit skeleton for PCM5102 (TX only)
+// - Simple gain smoother, recomputed every 50 ms on a SysTick monotonic
+// - I2S (SPI3) double-buffered DMA transmit to a PCM5102 at 48 kHz, with the gain applied (TX only)
+// - Gain telemetry frames over USART2 (see telemetry.rs for the layout), and `T<dB>` / `O<dB>`
+//   lines received on it to set the target level and user offset
 //
 // IMPORTANT: HAL APIs evolve. This file is intended to compile with the 0.15-era APIs,
 // but you may need to change a few type/constructor names depending on the exact patch
 // release you use. All <ADAPT> comments indicate places you may need to tweak.
+// No target in this workspace builds this file (the firmware dependencies are commented out of
+// Cargo.toml and CI only cross-compiles `core_dsp`), so none of it has been through a compiler.
 
 use core::sync::atomic::{AtomicBool, Ordering};
 use core::cell::RefCell;
 use cortex_m::interrupt::Mutex;
-use libm::{sqrt, powf, log10f};
+use libm::sinf;
 
 // crate::pac;
 // stm32f4xx_hal::pac;
 
 use panic_halt as _;
 use stm32f4xx_hal as hal;
-use hal::{prelude::*, pac, adc::{Adc, config::AdcConfig}, dma::{config::DmaConfig, Stream0, StreamsTuple, Transfer}, gpio::Analog, i2s::{I2sExt, I2s}, serial::Serial};
+use hal::{prelude::*, pac, adc::{Adc, config::{AdcConfig, Continuous, Dma, ExternalTrigger, Scan, TriggerMode}}, dma::{config::DmaConfig, MemoryToPeripheral, PeripheralToMemory, Stream0, Stream5, StreamsTuple, Transfer}, gpio::{Analog, NoPin}, i2s::{I2sExt, I2s}, serial::Serial};
+use hal::i2s::stm32_i2s_v12x::driver::{DataFormat, I2sDriver, I2sDriverConfig, Master, Philips, Transmit};
 
 use rtic::app;
 
+// Gain telemetry frames (decoded on the host by `adaptive_vol telemetry`)
+mod telemetry;
+use telemetry::{BenchCommand, CommandLineBuffer, TelemetryFrame, FRAME_LEN};
+// Q16.16 RMS and dB conversions from the shared `core_dsp` crate (built with its `no_std` feature)
+use core_dsp::fixed::{db_to_lin_q, lin_to_db_q, rms_u16_block_centered, update_dc_estimate, ADC_MIDPOINT, Q_BITS, Q_ONE};
+use rtic_monotonics::systick::prelude::*;
+
+// Timer for the control loop: SysTick at a 1 kHz tick. SysTick is core-local, so it leaves every
+// TIMx free for the board, and 1 ms resolution is plenty for a 50 ms cadence.
+systick_monotonic!(Mono, 1_000);
+
+// Control-loop period (gain recomputed from the newest ADC half each cycle)
+const PROCESS_PERIOD_MS: u32 = 50;
+
 // Buffer length must be even since we treat it as two halves
 pub const ADC_BUF_LEN: usize = 512;
 
+// Mic sample rate, set by the TIM2 update that triggers each ADC conversion. At 4 kHz a half of
+// ADC_BUFFER spans 64 ms, longer than a control period, so process_audio always reads a half before
+// the DMA wraps back onto it. Road noise sits well below the 2 kHz Nyquist limit.
+pub const MIC_SAMPLE_RATE: u32 = 4_000;
+const _: () = assert!((ADC_BUF_LEN as u32 / 2) * 1000 / MIC_SAMPLE_RATE > PROCESS_PERIOD_MS);
+
 // Place ADC buffer in a known memory section and make it mutable static for DMA
 #[link_section = ".axisram.data"]
 static mut ADC_BUFFER: [u16; ADC_BUF_LEN] = [0; ADC_BUF_LEN];
 
+// ADC1 -> ADC_BUFFER on DMA2 Stream0, channel 0 (the ADC1 request line on the F4 parts)
+type AdcTransfer = Transfer<Stream0<pac::DMA2>, 0, Adc<pac::ADC1>, PeripheralToMemory, &'static mut [u16; ADC_BUF_LEN]>;
+
+// Output sample rate for the PCM5102
+pub const I2S_SAMPLE_RATE: u32 = 48_000;
+// Interleaved L/R 16-bit samples per DMA buffer: 240 frames = 5 ms at 48 kHz
+pub const I2S_BUF_LEN: usize = 480;
+
+// Double buffer for I2S TX: the DMA streams one while the DMA1_STREAM5 interrupt refills the other
+static mut I2S_BUF_A: [u16; I2S_BUF_LEN] = [0; I2S_BUF_LEN];
+static mut I2S_BUF_B: [u16; I2S_BUF_LEN] = [0; I2S_BUF_LEN];
+
+// SPI3 TX request: DMA1 Stream5, channel 0 (Stream7 channel 0 also works)
+type I2sTx = I2sDriver<I2s<pac::SPI3>, Master, Transmit, Philips>;
+type I2sTransfer = Transfer<Stream5<pac::DMA1>, 0, I2sTx, MemoryToPeripheral, &'static mut [u16; I2S_BUF_LEN]>;
+
+// Telemetry frame being sent by the USART2 interrupt, one byte per TXE
+pub struct TxFrame {
+    bytes: [u8; FRAME_LEN],
+    pos: usize, // == FRAME_LEN when idle
+}
+
+// Playback level the gain mapping is tuned for (dB), as the host L_DESIRED_DB
+const DEFAULT_TARGET_DB: f32 = 75.0;
+
+// Level knobs set over USART (`T75.0`, `O-3.0`), mirroring the host L_DESIRED_DB / USER_OFFSET_DB
+#[derive(Clone, Copy)]
+pub struct LevelSettings {
+    target_db: f32,
+    offset_db: f32,
+}
+
 // Flag set by DMA half/full transfer callbacks (RTIC interrupt context)
 static HALF_READY: AtomicBool = AtomicBool::new(false);
 static FULL_READY: AtomicBool = AtomicBool::new(false);
 
-#[app(device = stm32f4xx_hal::pac, peripherals = true)]
+#[app(device = stm32f4xx_hal::pac, peripherals = true, dispatchers = [EXTI0])]
 mod app {
+    use super::*;
+
     #[shared]
     struct Shared {
-        // put shared resources here if needed
+        // Linear gain applied to the output, written by process_audio and read by the I2S refill
+        output_gain: f32,
+        // Telemetry frame queued by process_audio and drained by the USART2 interrupt
+        telemetry: TxFrame,
+        // Target level / offset, updated by commands received on USART2
+        levels: LevelSettings,
     }
 
     #[local]
     struct Local {
-        adc: Adc<pac::ADC1>,
-        // The circular DMA transfer owning the buffer (type depends on HAL)
-        // adc_circ: Transfer<...>, // left out here because types vary across hal versions
+        // The circular DMA transfer; owns the ADC and ADC_BUFFER, serviced from the DMA2_STREAM0 interrupt
+        adc_transfer: AdcTransfer,
+        // Double-buffered I2S TX transfer, refilled from the DMA1_STREAM5 interrupt
+        i2s_transfer: I2sTransfer,
 
         // Smoothing / computed values (local to the processing task)
-        smoothed_level: f32,
+        smoothed_level: i32, // ADC counts RMS, Q16.16
+        dc_estimate: f32,    // tracked ADC bias (counts), subtracted before the RMS
         target_gain: f32,
+        telemetry_seq: u8,
 
-        // serial for debug
+        // serial for telemetry and bench commands, both serviced from the USART2 interrupt
         serial: hal::serial::Tx<pac::USART2>,
+        serial_rx: hal::serial::Rx<pac::USART2>,
+        rx_line: CommandLineBuffer,
     }
 
     #[init]
@@ -68,10 +138,13 @@ mod app {
 
         // --- clocks
         let rcc = dp.RCC.constrain();
+        // PLLI2S at 86 MHz: with I2SDIV=3, ODD=1 and 16-bit stereo frames that gives 47.99 kHz,
+        // the closest an 8 MHz HSE gets to 48 kHz without MCLK
         let clocks = rcc.cfgr
             .use_hse(8.mhz())
             .sysclk(168.mhz())
             .pclk1(42.mhz())
+            .i2s_clk(86.mhz())
             .freeze();
 
         // --- GPIO
@@ -81,14 +154,20 @@ mod app {
         // ADC pin: PA0 (adjust as needed)
         let mic_pin = gpioa.pa0.into_analog();
 
-        // Serial TX for debug on PA2 (USART2)
+        // Serial on USART2: TX (PA2) for telemetry, RX (PA3) for bench commands
         let tx_pin = gpioa.pa2.into_alternate_af7();
         let rx_pin = gpioa.pa3.into_alternate_af7();
         let serial = Serial::usart2(dp.USART2, (tx_pin, rx_pin), 115_200.bps(), clocks).unwrap();
-        let (tx, _rx) = serial.split();
-
-        // --- ADC
-        let mut adc = Adc::adc1(dp.ADC1, true, AdcConfig::default());
+        let (tx, mut rx) = serial.split();
+        rx.listen(); // RXNE interrupt: bench commands are buffered byte by byte in usart2
+
+        // --- ADC: one conversion per TIM2 TRGO rising edge, each one raising a DMA request
+        let adc_cfg = AdcConfig::default()
+            .dma(Dma::Continuous)
+            .scan(Scan::Disabled)
+            .continuous(Continuous::Single)
+            .external_trigger(TriggerMode::RisingEdge, ExternalTrigger::Tim_2_trgo);
+        let mut adc = Adc::adc1(dp.ADC1, true, adc_cfg);
         adc.configure_channel(&mic_pin, hal::adc::config::Sequence::One, hal::adc::config::SampleTime::Cycles_15);
 
         // --- DMA setup for ADC -> memory (circular)
@@ -101,115 +180,252 @@ mod app {
             .memory_increment(true)
             .peripheral_increment(false)
             .priority(hal::dma::config::Priority::High)
+            .half_transfer_interrupt(true)
+            .transfer_complete_interrupt(true)
             .circular(true);
 
-        // <ADAPT> The exact Transfer/CircBuffer construction may vary across hal versions.
-        // Here we show the intended pattern: create a circular buffer transfer from ADC data register to ADC_BUFFER.
-
-        // SAFETY: ADC_BUFFER is exclusively used by DMA peripheral + read-only processing in interrupt/task contexts.
-        let circ = unsafe {
-            // Using HAL helper to create a CircBuffer transfer if available
-            // Example (pseudo):
-            // let circ = Transfer::init_peripheral_to_memory(stream0, adc.get_dma_peripheral(), &mut ADC_BUFFER, None, dma_cfg);
-            // If your hal provides `adc.with_dma()` or `adc.read_exact()` helpers, use those helpers instead.
-            core::ptr::null_mut()
+        // <ADAPT> Circular mode: some 0.15 patch releases only set CIRC for double-buffered transfers;
+        // if `.circular` is missing from DmaConfig, set the stream's CIRC bit after `init_peripheral_to_memory`.
+
+        // SAFETY: init runs once with interrupts disabled, so this is the only reference to ADC_BUFFER
+        // handed out; from here on the DMA writes it and the processing task only reads the half the
+        // DMA has just finished with.
+        let adc_buffer: &'static mut [u16; ADC_BUF_LEN] = unsafe { &mut *core::ptr::addr_of_mut!(ADC_BUFFER) };
+        let mut adc_transfer: AdcTransfer =
+            Transfer::init_peripheral_to_memory(stream0, adc, adc_buffer, None, dma_cfg);
+
+        // --- I2S (SPI3) TX to the PCM5102
+        // <ADAPT> Wiring: PA15 WS -> LRCK, PB3 CK -> BCK, PB5 SD -> DIN, no MCLK (tie the PCM5102 SCK
+        // pin low so it derives its system clock from BCK). Other boards may route SPI3 elsewhere.
+        let i2s = I2s::new(dp.SPI3, (gpioa.pa15, gpiob.pb3, NoPin::new(), gpiob.pb5), &clocks);
+        let i2s_cfg = I2sDriverConfig::new_master()
+            .transmit()
+            .standard(Philips)
+            .data_format(DataFormat::Data16Channel16)
+            .request_frequency(I2S_SAMPLE_RATE);
+        let mut i2s_driver: I2sTx = I2sDriver::new(i2s, i2s_cfg);
+        i2s_driver.set_tx_dma(true);
+
+        // Memory -> SPI3 data register; double-buffer mode swaps A/B on every transfer complete
+        let dma1 = StreamsTuple::new(dp.DMA1);
+        let i2s_dma_cfg = DmaConfig::default()
+            .memory_increment(true)
+            .peripheral_increment(false)
+            .priority(hal::dma::config::Priority::VeryHigh)
+            .double_buffer(true)
+            .transfer_complete_interrupt(true);
+        // SAFETY: as for ADC_BUFFER, these are the only references ever made to the I2S buffers; the
+        // DMA reads one while the refill interrupt (the transfer's only user) writes the other.
+        let (buf_a, buf_b): (&'static mut [u16; I2S_BUF_LEN], &'static mut [u16; I2S_BUF_LEN]) = unsafe {
+            (&mut *core::ptr::addr_of_mut!(I2S_BUF_A), &mut *core::ptr::addr_of_mut!(I2S_BUF_B))
         };
-
-        // --- I2S (SPI3) TX setup (skeleton)
-        // Connect to PCM5102: typically SPI3 in I2S mode (PB3 SCK, PB5 SD, PA15 WS etc. Verify with your board)
-        // We'll set up I2S peripheral and DMA transmit similarly to ADC but for memory->peripheral.
-
-        // <ADAPT> Use the HAL i2s ext: `let i2s = dp.SPI3.i2s(...);` patterns differ by hal version.
-
-        // Start the ADC DMA transfer (API depends on HAL). We assume it's started here.
-        // Example: circ.start(|adc_periph| { adc_periph.enable_dma(); });
-
-    // (scheduling disabled) The project originally scheduled the first `process_audio` via a monotonic.
-    // If you add a monotonic to `#[app(...)]`, re-enable the following scheduling call.
-    // cx.schedule.process_audio(cx.start + 84_000_000.cycles()).unwrap(); // 50ms @168MHz ~8.4M cycles
+        let mut i2s_transfer: I2sTransfer =
+            Transfer::init_memory_to_peripheral(dma1.5, i2s_driver, buf_a, Some(buf_b), i2s_dma_cfg);
+        // buffers start as silence; the first transfer complete refills A with program audio
+        i2s_transfer.start(|i2s| i2s.enable());
+
+        // Start the ADC DMA transfer: enable the stream; conversions wait for the trigger timer, and
+        // the DMA wraps around ADC_BUFFER from then on
+        adc_transfer.start(|_adc| {});
+
+        // --- TIM2: update event at MIC_SAMPLE_RATE, routed to TRGO to trigger the ADC. Started after
+        // the DMA so the first conversion already has somewhere to go.
+        let mut adc_timer = dp.TIM2.counter_hz(&clocks);
+        // <ADAPT> 0.15 has no TRGO setter on the timer wrappers; MMS = 0b010 selects the update event
+        // SAFETY: TIM2 is owned by adc_timer and nothing else touches CR2
+        unsafe { (*pac::TIM2::ptr()).cr2.modify(|_, w| w.mms().update()) };
+        adc_timer.start(MIC_SAMPLE_RATE.Hz()).unwrap();
+        // the counter keeps running after the wrapper is dropped (only `release` stops it)
+
+        // Monotonic on SysTick, clocked from the 168 MHz core clock; process_audio then reschedules itself
+        Mono::start(cx.core.SYST, clocks.sysclk().raw());
+        process_audio::spawn().ok();
 
         // return shared and local resources
         (
-            Shared {},
+            Shared {
+                output_gain: 1.0,
+                telemetry: TxFrame { bytes: [0; FRAME_LEN], pos: FRAME_LEN },
+                levels: LevelSettings { target_db: DEFAULT_TARGET_DB, offset_db: 0.0 },
+            },
             Local {
-                adc,
-                smoothed_level: 0.0,
+                adc_transfer,
+                i2s_transfer,
+                smoothed_level: 0,
+                dc_estimate: ADC_MIDPOINT as f32,
                 target_gain: 1.0,
+                telemetry_seq: 0,
                 serial: tx,
+                serial_rx: rx,
+                rx_line: CommandLineBuffer::new(),
             },
         )
     }
 
-    // Periodic processing task: read which half of buffer is ready (via flags set from DMA interrupt), compute RMS and update gain
-    #[task(local = [smoothed_level, target_gain, serial])]
+    // Periodic processing task: read which half of buffer is ready (via flags set from DMA interrupt), compute RMS and update gain.
+    // Runs every PROCESS_PERIOD_MS; the next wake-up is an absolute instant on the monotonic, so the
+    // time spent processing doesn't accumulate as drift.
+    #[task(local = [smoothed_level, dc_estimate, target_gain, telemetry_seq], shared = [output_gain, telemetry, levels])]
     async fn process_audio(mut cx: process_audio::Context) {
-        // Check DMA flags set by interrupts
-        if HALF_READY.swap(false, Ordering::SeqCst) {
-            // compute RMS on first half
-            let half = unsafe { &ADC_BUFFER[0..(ADC_BUF_LEN/2)] };
-            let rms = rms_u16_block(half);
-            // simple smoothing
-            *cx.local.smoothed_level = smooth(*cx.local.smoothed_level, rms, 0.95);
-
-            // compute gain mapping (example: keep target_gain inversely proportional to noise)
-            let noise_db = lin_to_db((*cx.local.smoothed_level).max(1e-6));
-            let desired_db = -0.5 * (noise_db - (-40.0)); // tune constants
-            *cx.local.target_gain = db_to_lin(desired_db);
-
-            // optional: send debug byte (not async-safe; keep minimal)
-            let _ = cx.local.serial.write(b'H');
+        let mut next = Mono::now();
+        loop {
+            // Check DMA flags set by interrupts
+            if HALF_READY.swap(false, Ordering::SeqCst) {
+                // compute RMS on first half
+                let half = unsafe { &ADC_BUFFER[0..(ADC_BUF_LEN/2)] };
+                *cx.local.dc_estimate = update_dc_estimate(*cx.local.dc_estimate, half);
+                let rms = rms_u16_block_centered(half, *cx.local.dc_estimate);
+                // simple smoothing
+                *cx.local.smoothed_level = smooth_q(*cx.local.smoothed_level, rms, SMOOTH_ALPHA_Q16);
+
+                // compute gain mapping (example: keep target_gain inversely proportional to noise)
+                let levels = cx.shared.levels.lock(|l| *l);
+                *cx.local.target_gain = gain_for_level(*cx.local.smoothed_level, levels);
+            }
+
+            if FULL_READY.swap(false, Ordering::SeqCst) {
+                // compute RMS on second half
+                let half = unsafe { &ADC_BUFFER[(ADC_BUF_LEN/2)..ADC_BUF_LEN] };
+                *cx.local.dc_estimate = update_dc_estimate(*cx.local.dc_estimate, half);
+                let rms = rms_u16_block_centered(half, *cx.local.dc_estimate);
+                *cx.local.smoothed_level = smooth_q(*cx.local.smoothed_level, rms, SMOOTH_ALPHA_Q16);
+                let levels = cx.shared.levels.lock(|l| *l);
+                *cx.local.target_gain = gain_for_level(*cx.local.smoothed_level, levels);
+            }
+
+            let gain = *cx.local.target_gain;
+            cx.shared.output_gain.lock(|g| *g = gain);
+
+            // one telemetry frame per cycle; if the previous one is still going out it is skipped,
+            // which shows up as a sequence gap on the host
+            let frame = TelemetryFrame {
+                seq: *cx.local.telemetry_seq,
+                smoothed_level: *cx.local.smoothed_level as f32 / Q_ONE as f32,
+                target_gain: gain,
+            };
+            *cx.local.telemetry_seq = cx.local.telemetry_seq.wrapping_add(1);
+            let queued = cx.shared.telemetry.lock(|tx| {
+                if tx.pos < FRAME_LEN {
+                    return false;
+                }
+                tx.bytes = frame.encode();
+                tx.pos = 0;
+                true
+            });
+            if queued {
+                rtic::pend(pac::Interrupt::USART2);
+            }
+
+            next += PROCESS_PERIOD_MS.millis();
+            Mono::delay_until(next).await;
         }
+    }
 
-        if FULL_READY.swap(false, Ordering::SeqCst) {
-            // compute RMS on second half
-            let half = unsafe { &ADC_BUFFER[(ADC_BUF_LEN/2)..ADC_BUF_LEN] };
-            let rms = rms_u16_block(half);
-            *cx.local.smoothed_level = smooth(*cx.local.smoothed_level, rms, 0.95);
-            let noise_db = lin_to_db((*cx.local.smoothed_level).max(1e-6));
-            let desired_db = -0.5 * (noise_db - (-40.0));
-            *cx.local.target_gain = db_to_lin(desired_db);
-
-            let _ = cx.local.serial.write(b'F');
+    // USART2: bench commands in, telemetry out.
+    // RX: each received byte goes into the line buffer; a complete valid line updates the level
+    // settings, malformed or out-of-range lines are dropped.
+    // TX: write bytes only while the TX data register is empty, never waiting on it. The TXE
+    // interrupt stays enabled until the frame is out, so each byte is sent as soon as there's room.
+    #[task(binds = USART2, local = [serial, serial_rx, rx_line], shared = [telemetry, levels])]
+    fn usart2(mut cx: usart2::Context) {
+        while let Ok(byte) = cx.local.serial_rx.read() {
+            match cx.local.rx_line.push(byte) {
+                Some(BenchCommand::SetTargetDb(db)) => cx.shared.levels.lock(|l| l.target_db = db),
+                Some(BenchCommand::SetOffsetDb(db)) => cx.shared.levels.lock(|l| l.offset_db = db),
+                None => {}
+            }
         }
 
-        // Re-schedule (disabled — needs a monotonic). Re-enable scheduling after adding a monotonic.
+        let serial = cx.local.serial;
+        cx.shared.telemetry.lock(|tx| {
+            while tx.pos < FRAME_LEN && serial.is_tx_empty() {
+                if serial.write(tx.bytes[tx.pos]).is_err() {
+                    break;
+                }
+                tx.pos += 1;
+            }
+            if tx.pos < FRAME_LEN {
+                serial.listen();
+            } else {
+                serial.unlisten();
+            }
+        });
+    }
+
+    // I2S TX: each transfer complete means the DMA has switched to the other buffer, so refill the
+    // one it just finished with the next block of program audio at the current gain
+    #[task(binds = DMA1_STREAM5, local = [i2s_transfer, source_phase: f32 = 0.0], shared = [output_gain], priority = 2)]
+    fn dma1_stream5(mut cx: dma1_stream5::Context) {
+        let transfer = cx.local.i2s_transfer;
+        if !transfer.get_transfer_complete_flag() {
+            return;
+        }
+        transfer.clear_transfer_complete_interrupt();
+        let gain = cx.shared.output_gain.lock(|g| *g);
+        let phase = cx.local.source_phase;
+        // Err only if the refill is late (the DMA already went past this buffer); skip this block then
+        let _ = transfer.next_transfer_with(|buf, _current| {
+            fill_output_block(buf, phase, gain);
+            (buf, ())
+        });
     }
 
     // DMA interrupt handlers (example names -- adapt to your vector table)
     // In many HAL setups, the DMA transfer object registers its own interrupt handler callback. If you use that facility,
     // you can set the HALF_READY/FULL_READY flags there instead of defining interrupts here.
 
-    #[task(binds = DMA2_STREAM0)]
-    fn dma2_stream0(_cx: dma2_stream0::Context) {
-        // Clear interrupt flags on DMA stream and set HALF_READY / FULL_READY accordingly.
-        // This MUST be implemented with the actual peripheral registers or using the HAL helper functions.
-        // Example pseudocode:
-        // if stream.get_half_transfer_flag() { HALF_READY.store(true, Ordering::SeqCst); stream.clear_half_transfer(); }
-        // if stream.get_transfer_complete_flag() { FULL_READY.store(true, Ordering::SeqCst); stream.clear_transfer_complete(); }
+    // Publishes which half of ADC_BUFFER the DMA has just filled. Both flags are checked on every
+    // entry (not else-if): if the ISR is held off long enough for the second event to land too, both
+    // halves are reported rather than one being lost. Flags are cleared only after being read.
+    #[task(binds = DMA2_STREAM0, local = [adc_transfer])]
+    fn dma2_stream0(cx: dma2_stream0::Context) {
+        let transfer = cx.local.adc_transfer;
+        if transfer.get_half_transfer_flag() {
+            transfer.clear_half_transfer_interrupt();
+            HALF_READY.store(true, Ordering::Release);
+        }
+        if transfer.get_transfer_complete_flag() {
+            transfer.clear_transfer_complete_interrupt();
+            FULL_READY.store(true, Ordering::Release);
+        }
     }
-
-    // extern "Rust" {
-    //     fn EXTI0();
-    // }
 }
 
 // ------------------- Utilities -------------------
 
-/// Compute RMS over a block of ADC samples (u16). Expects 12-bit ADC centered ~2048.
-fn rms_u16_block(buf: &[u16]) -> f32 {
-    let mut sum_sq: f64 = 0.0;
-    for &s in buf.iter() {
-        let v = (s as f32) - 2048.0;
-        let vf = v as f64;
-        sum_sq += vf * vf;
+// Test tone played while there is no audio source: 440 Hz at -12 dBFS
+const SOURCE_TONE_HZ: f32 = 440.0;
+const SOURCE_TONE_AMPLITUDE: f32 = 0.25;
+
+/// Fill one interleaved L/R I2S block with the program audio scaled by `gain`, saturating at full scale.
+/// <ADAPT> The source is a test tone; replace it with the real program audio (e.g. an I2S RX from the head unit).
+fn fill_output_block(buf: &mut [u16; I2S_BUF_LEN], phase: &mut f32, gain: f32) {
+    let step = 2.0 * core::f32::consts::PI * SOURCE_TONE_HZ / I2S_SAMPLE_RATE as f32;
+    for frame in buf.chunks_exact_mut(2) {
+        let x = SOURCE_TONE_AMPLITUDE * sinf(*phase) * gain;
+        let s = (x * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        frame[0] = s as u16;
+        frame[1] = s as u16;
+        *phase += step;
+        if *phase >= 2.0 * core::f32::consts::PI {
+            *phase -= 2.0 * core::f32::consts::PI;
+        }
     }
-    let mean_sq = sum_sq / (buf.len() as f64);
-    sqrt(mean_sq) as f32
 }
 
-fn smooth(prev: f32, input: f32, alpha: f32) -> f32 {
-    alpha * prev + (1.0 - alpha) * input
+// Level smoothing coefficient (0.95 per block) in Q16.16
+const SMOOTH_ALPHA_Q16: i32 = 62_259;
+
+// One-pole smoothing in Q16.16: prev + (1 - alpha) * (input - prev)
+fn smooth_q(prev: i32, input: i32, alpha_q16: i32) -> i32 {
+    prev + (((input - prev) as i64 * (Q_ONE - alpha_q16) as i64) >> Q_BITS) as i32
 }
 
-fn db_to_lin(db: f32) -> f32 { powf(10.0_f32, db / 20.0_f32) }
-fn lin_to_db(lin: f32) -> f32 { 20.0 * log10f(lin.abs().max(1e-12)) }
\ No newline at end of file
+// Gain mapping: -0.5 dB of gain per dB of noise above -40 dB (re 1 ADC count); tune constants.
+// The target level and offset shift it 1:1, as on the host.
+fn gain_for_level(level_q16: i32, levels: LevelSettings) -> f32 {
+    let noise_db = lin_to_db_q(level_q16.max(1));
+    let shift_db = ((levels.target_db - DEFAULT_TARGET_DB + levels.offset_db) * Q_ONE as f32) as i32;
+    let desired_db = -((noise_db + (40 << Q_BITS)) / 2) + shift_db;
+    db_to_lin_q(desired_db) as f32 / Q_ONE as f32
+}
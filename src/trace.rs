@@ -0,0 +1,210 @@
+//! Recorded sensor traces: `t,cabin_db,speed_kmh` rows replayed in place of the sine mocks so a
+//! real drive can drive the simulation (or offline processing) deterministically, and the recorder
+//! that captures them from the live controller.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+
+/// Column names accepted for the time column in a CSV header
+const TIME_COLUMNS: [&str; 3] = ["t", "time", "timestamp"];
+
+/// A time-sorted sensor trace, sampled by linear interpolation
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceSource {
+    rows: Vec<(f32 /*t*/, f32 /*cabin_db*/, f32 /*speed_kmh*/)>,
+}
+
+impl TraceSource {
+    /// Sort `rows` by time; at least one row is required
+    pub fn from_rows(mut rows: Vec<(f32, f32, f32)>) -> Result<Self> {
+        if rows.is_empty() {
+            bail!("sensor trace is empty");
+        }
+        if let Some(row) = rows.iter().find(|r| !(r.0.is_finite() && r.1.is_finite() && r.2.is_finite())) {
+            bail!("sensor trace row {:?} is not finite", row);
+        }
+        rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Self { rows })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading trace {}", path.display()))?;
+        Self::from_csv(&text).with_context(|| format!("invalid trace {}", path.display()))
+    }
+
+    /// Parse CSV with an optional header. With a header, the time (`t`, `time` or `timestamp`),
+    /// `cabin_db` and `speed_kmh` columns are found by name, so extra columns (e.g. a recorded drive's
+    /// gain columns) are ignored; without one the first three columns are used in that order.
+    pub fn from_csv(text: &str) -> Result<Self> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(n, l)| (n + 1, l.trim()))
+            .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+            .peekable();
+        let mut columns = [0, 1, 2];
+        if let Some(&(_, first)) = lines.peek() {
+            let fields: Vec<&str> = first.split(',').map(str::trim).collect();
+            if fields.iter().any(|f| f.parse::<f32>().is_err()) {
+                let find = |names: &[&str]| fields.iter().position(|f| names.iter().any(|n| f.eq_ignore_ascii_case(n)));
+                columns = match (find(&TIME_COLUMNS), find(&["cabin_db"]), find(&["speed_kmh"])) {
+                    (Some(t), Some(c), Some(s)) => [t, c, s],
+                    _ => bail!("header needs t, cabin_db and speed_kmh columns: {}", first),
+                };
+                lines.next();
+            }
+        }
+        let mut rows = Vec::new();
+        for (line_no, line) in lines {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let mut row = [0.0f32; 3];
+            for (value, &col) in row.iter_mut().zip(columns.iter()) {
+                let field = fields.get(col).with_context(|| format!("line {}: missing column {}", line_no, col + 1))?;
+                *value = field.parse().with_context(|| format!("line {}: '{}' is not a number", line_no, field))?;
+            }
+            rows.push((row[0], row[1], row[2]));
+        }
+        Self::from_rows(rows)
+    }
+
+    /// `(cabin_db, speed_kmh)` at time `t`, interpolated between the surrounding rows and held at
+    /// the first/last row outside the trace
+    pub fn sample(&self, t: f32) -> (f32, f32) {
+        let rows = &self.rows;
+        let i = rows.partition_point(|row| row.0 <= t);
+        if i == 0 {
+            return (rows[0].1, rows[0].2);
+        }
+        if i == rows.len() {
+            let last = rows[rows.len() - 1];
+            return (last.1, last.2);
+        }
+        let (a, b) = (rows[i - 1], rows[i]);
+        let w = if b.0 > a.0 { (t - a.0) / (b.0 - a.0) } else { 0.0 };
+        (a.1 + (b.1 - a.1) * w, a.2 + (b.2 - a.2) * w)
+    }
+
+    /// Time of the last row (s)
+    pub fn end_time(&self) -> f32 {
+        self.rows[self.rows.len() - 1].0
+    }
+}
+
+/// Header written by `TraceRecorder`. Downstream scripts rely on these columns: only ever append new
+/// ones at the end.
+pub const RECORD_HEADER: &str = "timestamp,cabin_db,speed_kmh,gain_db,gain_lin,underruns,clipped";
+
+/// One controller iteration as recorded by `TraceRecorder`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecordRow {
+    /// Seconds since recording started
+    pub timestamp: f32,
+    pub cabin_db: f32,
+    pub speed_kmh: f32,
+    pub gain_db: f32,
+    pub gain_lin: f32,
+    /// Output underruns so far
+    pub underruns: usize,
+    /// Output samples the limiter had to pull down so far
+    pub clipped: usize,
+}
+
+/// Writes `RecordRow`s as CSV (see `RECORD_HEADER`), flushing at most every `FLUSH_INTERVAL` so a
+/// crash loses little without a syscall per row. Call `finish` on shutdown.
+pub struct TraceRecorder<W: Write> {
+    writer: W,
+    last_flush: Instant,
+}
+
+impl TraceRecorder<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("creating recording {}", path.display()))?;
+        Self::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> TraceRecorder<W> {
+    const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new(mut writer: W) -> Result<Self> {
+        writeln!(writer, "{}", RECORD_HEADER)?;
+        Ok(Self { writer, last_flush: Instant::now() })
+    }
+
+    pub fn record(&mut self, row: &RecordRow) -> Result<()> {
+        writeln!(
+            self.writer,
+            "{:.3},{:.2},{:.2},{:.3},{:.5},{},{}",
+            row.timestamp, row.cabin_db, row.speed_kmh, row.gain_db, row.gain_lin, row.underruns, row.clipped
+        )?;
+        if self.last_flush.elapsed() >= Self::FLUSH_INTERVAL {
+            self.writer.flush()?;
+            self.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Flush everything written so far and hand back the writer
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_midpoint_averages_endpoints() {
+        let trace = TraceSource::from_csv("t,cabin_db,speed_kmh\n0,60,20\n2,70,80\n").unwrap();
+        assert_eq!(trace.sample(1.0), (65.0, 50.0));
+        assert_eq!(trace.sample(0.5), (62.5, 35.0));
+        // held outside the trace
+        assert_eq!(trace.sample(-1.0), (60.0, 20.0));
+        assert_eq!(trace.sample(10.0), (70.0, 80.0));
+    }
+
+    #[test]
+    fn test_csv_sorted_and_columns_by_name() {
+        let trace = TraceSource::from_csv("# drive 3\n4,66,90\n0,60,0\n2,63,45\n").unwrap();
+        assert_eq!(trace.sample(2.0), (63.0, 45.0), "rows sorted by time, no header");
+        assert_eq!(trace.end_time(), 4.0);
+
+        let recorded = "timestamp,cabin_db,speed_kmh,gain_db,gain_lin,underruns\n0.0,61,10,3,1.41,0\n1.0,63,30,2,1.26,0\n";
+        assert_eq!(TraceSource::from_csv(recorded).unwrap().sample(0.5), (62.0, 20.0));
+
+        assert!(TraceSource::from_csv("").is_err());
+        assert!(TraceSource::from_csv("t,speed\n0,1").is_err());
+        assert!(TraceSource::from_csv("0,60\n").is_err(), "missing column");
+    }
+
+    #[test]
+    fn test_recording_replays_as_trace() {
+        let mut recorder = TraceRecorder::new(Vec::new()).unwrap();
+        for (i, speed) in [0.0, 40.0, 80.0].into_iter().enumerate() {
+            let row = RecordRow {
+                timestamp: i as f32 * 0.05,
+                cabin_db: 60.0 + i as f32,
+                speed_kmh: speed,
+                gain_db: 3.0,
+                gain_lin: 1.41254,
+                underruns: i,
+                clipped: 10 * i,
+            };
+            recorder.record(&row).unwrap();
+        }
+        let csv = String::from_utf8(recorder.finish().unwrap()).unwrap();
+        assert_eq!(csv.lines().next(), Some(RECORD_HEADER));
+        assert_eq!(csv.lines().nth(2), Some("0.050,61.00,40.00,3.000,1.41254,1,10"));
+
+        let trace = TraceSource::from_csv(&csv).unwrap();
+        assert_eq!(trace.sample(0.075), (61.5, 60.0));
+    }
+}
@@ -0,0 +1,110 @@
+//! True-peak level per ITU-R BS.1770-4 Annex 2. The largest sample can understate the peak of the
+//! waveform a DAC reconstructs between samples by several dB, so a file that never reaches full
+//! scale sample-wise can still clip the converter. The signal is oversampled 4x with a polyphase
+//! interpolation filter and the largest interpolated magnitude is reported in dBTP.
+
+use std::f64::consts::PI;
+
+/// Oversampling factor
+const FACTOR: usize = 4;
+/// Taps per polyphase branch (48 in total, as in the standard's example filter)
+const TAPS: usize = 12;
+/// Interpolation filter cutoff as a fraction of the input Nyquist frequency
+const CUTOFF: f64 = 0.9;
+
+/// Polyphase branches of a Hann-windowed sinc low-pass at the 4x rate, each normalised to unity
+/// gain at DC. Branch `p` produces the interpolated point `p / 4` of the way between samples.
+fn polyphase_branches() -> [[f32; TAPS]; FACTOR] {
+    let len = FACTOR * TAPS;
+    let centre = (len - 1) as f64 / 2.0;
+    let prototype: Vec<f64> = (0..len)
+        .map(|n| {
+            let t = (n as f64 - centre) / FACTOR as f64 * CUTOFF;
+            let sinc = if t == 0.0 { 1.0 } else { (PI * t).sin() / (PI * t) };
+            let window = 0.5 - 0.5 * (2.0 * PI * (n as f64 + 0.5) / len as f64).cos();
+            sinc * window
+        })
+        .collect();
+    let mut branches = [[0.0f32; TAPS]; FACTOR];
+    for (p, branch) in branches.iter_mut().enumerate() {
+        let taps: Vec<f64> = (0..TAPS).map(|k| prototype[k * FACTOR + p]).collect();
+        let sum: f64 = taps.iter().sum();
+        for (out, tap) in branch.iter_mut().zip(taps) {
+            *out = (tap / sum) as f32;
+        }
+    }
+    branches
+}
+
+/// Running true peak of interleaved audio fed in any chunk sizes
+pub struct TruePeakMeter {
+    channels: usize,
+    branches: [[f32; TAPS]; FACTOR],
+    /// Last `TAPS` samples of each channel, newest last
+    history: Vec<[f32; TAPS]>,
+    peak: f32,
+}
+
+impl TruePeakMeter {
+    pub fn new(channels: usize) -> Self {
+        let channels = channels.max(1);
+        Self { channels, branches: polyphase_branches(), history: vec![[0.0; TAPS]; channels], peak: 0.0 }
+    }
+
+    /// Feed interleaved samples (full scale 1.0); a trailing partial frame is ignored
+    pub fn process_interleaved(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (history, &x) in self.history.iter_mut().zip(frame) {
+                history.rotate_left(1);
+                history[TAPS - 1] = x;
+                // the sample itself counts too, so the true peak is never below the sample peak
+                let mut peak = x.abs();
+                for branch in &self.branches {
+                    let y: f32 = branch.iter().rev().zip(history.iter()).map(|(h, s)| h * s).sum();
+                    peak = peak.max(y.abs());
+                }
+                self.peak = self.peak.max(peak);
+            }
+        }
+    }
+
+    /// Largest reconstructed magnitude so far (linear, full scale 1.0)
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    /// `peak` in dBTP (dB relative to full scale, true peak); -180 before any signal
+    pub fn true_peak_dbtp(&self) -> f32 {
+        20.0 * self.peak.max(1e-9).log10()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_inter_sample_peaks() {
+        // ±0.9 alternating in pairs: a quarter-rate sine sampled 45° off its crests, whose
+        // reconstruction peaks at 0.9·√2 between the samples
+        let signal: Vec<f32> = (0..4800).map(|i| if (i / 2) % 2 == 0 { 0.9 } else { -0.9 }).collect();
+        let mut meter = TruePeakMeter::new(1);
+        meter.process_interleaved(&signal);
+        let sample_peak_db = 20.0 * 0.9f32.log10();
+        assert!(meter.true_peak_dbtp() > sample_peak_db + 2.5, "{} dBTP", meter.true_peak_dbtp());
+        assert!((meter.peak() - 0.9 * 2f32.sqrt()).abs() < 0.03, "{}", meter.peak());
+        assert!(meter.true_peak_dbtp() > 0.0, "clips the DAC although no sample reaches full scale");
+    }
+
+    #[test]
+    fn test_low_frequency_true_peak_matches_sample_peak() {
+        // a 100 Hz sine at 48 kHz has samples right at its crests: nothing between them is higher
+        let signal: Vec<f32> = (0..9600)
+            .flat_map(|i| [0.5 * (2.0 * std::f32::consts::PI * 100.0 * i as f32 / 48000.0).sin(), 0.0])
+            .collect();
+        let mut meter = TruePeakMeter::new(2);
+        meter.process_interleaved(&signal);
+        assert!((meter.peak() - 0.5).abs() < 0.005, "{}", meter.peak());
+        assert_eq!(TruePeakMeter::new(1).true_peak_dbtp(), -180.0);
+    }
+}
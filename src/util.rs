@@ -0,0 +1,138 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+/// An f32 that can be shared between threads without a lock.
+/// Stored as its `to_bits` pattern in an `AtomicU32`, so reads in the audio callback never block.
+#[derive(Debug, Default)]
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+
+    pub fn load(&self, order: Ordering) -> f32 {
+        f32::from_bits(self.0.load(order))
+    }
+
+    pub fn store(&self, value: f32, order: Ordering) {
+        self.0.store(value.to_bits(), order)
+    }
+
+    pub fn swap(&self, value: f32, order: Ordering) -> f32 {
+        f32::from_bits(self.0.swap(value.to_bits(), order))
+    }
+
+    /// Compares bit patterns, so it only succeeds for exactly the value last loaded
+    pub fn compare_exchange(&self, current: f32, new: f32, success: Ordering, failure: Ordering) -> Result<f32, f32> {
+        self.0
+            .compare_exchange(current.to_bits(), new.to_bits(), success, failure)
+            .map(f32::from_bits)
+            .map_err(f32::from_bits)
+    }
+}
+
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Install a Ctrl-C (SIGINT) / SIGTERM handler that sets a process-wide stop flag.
+/// Returns the flag so worker threads can poll it and leave their loops.
+pub fn install_ctrlc_handler() -> &'static AtomicBool {
+    #[cfg(unix)]
+    {
+        extern "C" fn on_signal(_signum: libc::c_int) {
+            // only an atomic store: async-signal-safe
+            STOP_REQUESTED.store(true, Ordering::SeqCst);
+        }
+        let handler = on_signal as *const () as libc::sighandler_t;
+        // SAFETY: the handler only touches a static atomic
+        unsafe {
+            libc::signal(libc::SIGINT, handler);
+            libc::signal(libc::SIGTERM, handler);
+        }
+    }
+    &STOP_REQUESTED
+}
+
+/// `thread::spawn` with a name, which log lines carry so each one can be traced to its thread
+pub fn spawn_named<F, T>(name: &str, f: F) -> std::thread::JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    std::thread::Builder::new().name(name.to_string()).spawn(f).expect("failed to spawn thread")
+}
+
+/// Sleep for `duration`, waking early once `stop` is set so shutdown isn't held up by a long wait
+pub fn sleep_while_running(stop: &AtomicBool, duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while !stop.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        std::thread::sleep((deadline - now).min(Duration::from_millis(50)));
+    }
+}
+
+/// Paces a loop at a fixed period against absolute deadlines (`start + n * period`) instead of
+/// sleeping a fixed `dt` after each iteration, so work time and oversleeping don't accumulate as drift.
+pub struct FrameClock {
+    start: Instant,
+    period: Duration,
+    frames: u32,
+}
+
+impl FrameClock {
+    /// Start counting frames from now
+    pub fn new(period: Duration) -> Self {
+        Self { start: Instant::now(), period, frames: 0 }
+    }
+
+    /// Sleep until the end of the current frame. Returns immediately when already behind; the next
+    /// deadline is unaffected, so a late frame is caught up rather than pushing everything back.
+    pub fn tick(&mut self) {
+        self.frames += 1;
+        let deadline = self.start + self.period * self.frames;
+        let now = Instant::now();
+        if deadline > now {
+            std::thread::sleep(deadline - now);
+        }
+    }
+
+    /// Frames completed so far
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_f32_roundtrip() {
+        let a = AtomicF32::new(1.5);
+        assert_eq!(a.load(Ordering::Relaxed), 1.5);
+        a.store(-0.25, Ordering::Relaxed);
+        assert_eq!(a.load(Ordering::Relaxed), -0.25);
+    }
+
+    #[test]
+    fn test_frame_clock_does_not_drift() {
+        let period = Duration::from_millis(10);
+        let frames = 30;
+        let mut clock = FrameClock::new(period);
+        let start = Instant::now();
+        for i in 0..frames {
+            // uneven per-frame work, including one frame that overruns its period
+            std::thread::sleep(Duration::from_millis(if i == 5 { 15 } else { 3 }));
+            clock.tick();
+        }
+        let elapsed = start.elapsed();
+        assert_eq!(clock.frames(), frames);
+        assert!(elapsed >= period * frames, "finished early: {:?}", elapsed);
+        // a clock sleeping a full period after each frame's work would be ~90 ms late; a few periods of
+        // slack absorb scheduler jitter on a loaded machine
+        assert!(elapsed < period * (frames + 5), "drifted: {:?} for {} frames", elapsed, frames);
+    }
+}
@@ -0,0 +1,158 @@
+//! Voice-activity detection on the mic signal. Passengers talking raise the measured cabin level,
+//! and following it would turn the music up over the conversation; while `Vad::is_speech` reports
+//! speech the controller holds the gain instead (`GainController::hold`).
+//!
+//! Speech is taken to be a burst of energy in the voice band that both stands well above that
+//! band's recent floor and carries a real share of the block's energy; road and engine noise sit
+//! mostly below the band, so a broadband bump raises the band level without raising that share.
+//! A short hangover bridges the gaps between words.
+
+use crate::filters::Biquad;
+
+/// Voice band (Hz) the detector measures
+pub const SPEECH_BAND_HZ: (f32, f32) = (300.0, 3400.0);
+
+/// Detection thresholds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VadThresholds {
+    /// Voice-band level above its tracked floor (dB) that counts as a burst
+    pub onset_db: f32,
+    /// Minimum share of the block's energy inside the voice band (0..1)
+    pub min_band_ratio: f32,
+    /// Blocks that still report speech after the last detected one
+    pub hangover_blocks: u32,
+    /// How fast the floor rises toward a louder band level (dB per block); it falls immediately
+    pub floor_rise_db: f32,
+}
+
+impl Default for VadThresholds {
+    /// Tuned for 50 ms blocks: 6 dB onset, 5% of the energy in band (road rumble alone puts
+    /// around 1% there), 300 ms hangover
+    fn default() -> Self {
+        Self { onset_db: 6.0, min_band_ratio: 0.05, hangover_blocks: 6, floor_rise_db: 0.1 }
+    }
+}
+
+pub struct Vad {
+    thresholds: VadThresholds,
+    highpass: Biquad,
+    lowpass: Biquad,
+    /// Tracked voice-band level with no speech present (dB, uncalibrated)
+    floor_db: Option<f32>,
+    hangover: u32,
+}
+
+impl Vad {
+    pub fn new(sample_rate: f32) -> Self {
+        Self::with_thresholds(sample_rate, VadThresholds::default())
+    }
+
+    pub fn with_thresholds(sample_rate: f32, thresholds: VadThresholds) -> Self {
+        Self {
+            thresholds,
+            highpass: Biquad::highpass(sample_rate, SPEECH_BAND_HZ.0, 0.707),
+            lowpass: Biquad::lowpass(sample_rate, SPEECH_BAND_HZ.1, 0.707),
+            floor_db: None,
+            hangover: 0,
+        }
+    }
+
+    pub fn thresholds(&self) -> VadThresholds {
+        self.thresholds
+    }
+
+    pub fn set_thresholds(&mut self, thresholds: VadThresholds) {
+        self.thresholds = thresholds;
+    }
+
+    /// Feed the next block of mic samples (consecutive blocks, so the band filters stay continuous);
+    /// true while speech is present or within the hangover after it
+    pub fn is_speech(&mut self, block: &[f32]) -> bool {
+        if block.is_empty() {
+            return self.hangover > 0;
+        }
+        let mut total = 0.0f64;
+        let mut band = 0.0f64;
+        for &x in block {
+            let y = self.lowpass.process(self.highpass.process(x));
+            total += (x as f64) * (x as f64);
+            band += (y as f64) * (y as f64);
+        }
+        let band_db = 10.0 * ((band / block.len() as f64).max(1e-18)).log10() as f32;
+        let ratio = if total > 0.0 { (band / total) as f32 } else { 0.0 };
+
+        let floor_db = *self.floor_db.get_or_insert(band_db);
+        let burst = band_db - floor_db >= self.thresholds.onset_db && ratio >= self.thresholds.min_band_ratio;
+        if burst {
+            self.hangover = self.thresholds.hangover_blocks + 1;
+        } else {
+            // only learn the floor from non-speech blocks
+            let rise = (band_db - floor_db).min(self.thresholds.floor_rise_db);
+            self.floor_db = Some(if band_db < floor_db { band_db } else { floor_db + rise });
+        }
+        if self.hangover > 0 {
+            self.hangover -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core_dsp::rms_to_db;
+    use crate::gain::AdaptiveGain;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::f32::consts::PI;
+
+    const RATE: f32 = 16_000.0;
+    const BLOCK: usize = 800; // 50 ms
+
+    // Road rumble: low tones plus a little broadband hiss
+    fn road(rng: &mut StdRng, t: f32) -> f32 {
+        0.2 * (2.0 * PI * 70.0 * t).sin() + 0.1 * (2.0 * PI * 140.0 * t).sin() + rng.random_range(-0.005..0.005)
+    }
+
+    // Voiced speech: harmonics of a 150 Hz pitch through the voice band, syllable-modulated at 4 Hz
+    fn voice(t: f32) -> f32 {
+        let envelope = 0.6 + 0.4 * (2.0 * PI * 4.0 * t).sin();
+        let tone: f32 = (2..=20).map(|h| (2.0 * PI * 150.0 * h as f32 * t).sin() / h as f32).sum();
+        0.3 * envelope * tone
+    }
+
+    #[test]
+    fn test_speech_burst_holds_the_gain() {
+        let mut rng = StdRng::seed_from_u64(74);
+        let mut vad = Vad::new(RATE);
+        let mut ag = AdaptiveGain::default();
+        // 3 s of road noise, 1 s with a passenger talking over it, 2 s of road noise again
+        let (speech_start, speech_end) = (60, 80);
+        let mut gains = Vec::new();
+        let mut flags = Vec::new();
+        for b in 0..120 {
+            let block: Vec<f32> = (0..BLOCK)
+                .map(|i| {
+                    let t = (b * BLOCK + i) as f32 / RATE;
+                    road(&mut rng, t) + if (speech_start..speech_end).contains(&b) { voice(t) } else { 0.0 }
+                })
+                .collect();
+            let speech = vad.is_speech(&block);
+            let (gain_db, _) =
+                if speech { ag.hold() } else { ag.compute_gain_dt(rms_to_db(&block, 94.0), 0.0, 0.05) };
+            flags.push(speech);
+            gains.push(gain_db);
+        }
+
+        assert!(!flags[..speech_start].iter().any(|&s| s), "road noise alone is not speech");
+        assert!(flags[speech_start..speech_end].iter().all(|&s| s), "{:?}", &flags[speech_start..speech_end]);
+        let hangover = VadThresholds::default().hangover_blocks as usize;
+        assert!(flags[speech_end..speech_end + hangover].iter().all(|&s| s), "hangover runs past the burst");
+        assert!(!flags[speech_end + hangover + 1..].iter().any(|&s| s));
+        // the gain stays where the road noise put it for the whole burst
+        let held = gains[speech_start - 1];
+        assert!(gains[speech_start..speech_end + hangover].iter().all(|&g| g == held));
+    }
+}
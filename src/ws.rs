@@ -0,0 +1,256 @@
+//! Minimal WebSocket (RFC 6455) client: enough to receive text messages from a speed server over
+//! plain `ws://`. Written against std so it builds without extra crates; no TLS and no extensions.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// Largest message accepted; speed updates are a few dozen bytes
+const MAX_MESSAGE_LEN: u64 = 64 * 1024;
+/// Once a message has started, each read must complete within this
+const FRAME_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// `ws://host[:port][/path]` split into (host, port, path)
+pub(crate) fn parse_ws_url(url: &str) -> io::Result<(String, u16, String)> {
+    let rest = url
+        .strip_prefix("ws://")
+        .ok_or_else(|| invalid(format!("unsupported WebSocket URL '{}' (only ws:// is supported)", url)))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((h, p)) => (h, p.parse().map_err(|_| invalid(format!("bad port in '{}'", url)))?),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(invalid(format!("missing host in '{}'", url)));
+    }
+    Ok((host.to_string(), port, path.to_string()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// A connected client. Messages are read with `read_message`; pings are answered internally.
+pub(crate) struct WsClient {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+/// One complete message from the server
+#[derive(Debug, PartialEq)]
+pub(crate) enum WsMessage {
+    Text(String),
+    Binary(Vec<u8>),
+    /// The server closed the connection
+    Close,
+}
+
+impl WsClient {
+    /// Open the TCP connection and perform the HTTP upgrade handshake
+    pub(crate) fn connect(url: &str, timeout: Duration) -> io::Result<Self> {
+        let (host, port, path) = parse_ws_url(url)?;
+        let addr = (host.as_str(), port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| invalid(format!("could not resolve {}", host)))?;
+        let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_nodelay(true)?;
+
+        let key = base64(&rand::random::<[u8; 16]>());
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: {}:{}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path, host, port, key
+        )?;
+
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut status = String::new();
+        reader.read_line(&mut status)?;
+        if status.split_whitespace().nth(1) != Some("101") {
+            return Err(invalid(format!("WebSocket upgrade refused: {}", status.trim())));
+        }
+        // skip the remaining response headers
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+                break;
+            }
+        }
+        Ok(Self { stream, reader })
+    }
+
+    /// Wait up to `idle` for the next message. Ok(None) means nothing arrived in time; once a
+    /// message has started, the rest of it must arrive within `FRAME_TIMEOUT`.
+    pub(crate) fn poll_message(&mut self, idle: Duration) -> io::Result<Option<WsMessage>> {
+        if self.reader.buffer().is_empty() {
+            self.stream.set_read_timeout(Some(idle))?;
+            match self.reader.fill_buf().map(|buf| buf.is_empty()) {
+                Ok(true) => return Ok(Some(WsMessage::Close)), // EOF
+                Ok(false) => {}
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.stream.set_read_timeout(Some(FRAME_TIMEOUT))?;
+        self.read_message().map(Some)
+    }
+
+    /// Read until a full text/binary message arrives (answering pings on the way)
+    fn read_message(&mut self) -> io::Result<WsMessage> {
+        let mut message = Vec::new();
+        let mut message_op = None;
+        loop {
+            let (fin, opcode, payload) = read_frame(&mut self.reader)?;
+            match opcode {
+                OP_PING => write_frame(&mut self.stream, OP_PONG, &payload)?,
+                OP_PONG => {}
+                OP_CLOSE => {
+                    let _ = write_frame(&mut self.stream, OP_CLOSE, &[]);
+                    return Ok(WsMessage::Close);
+                }
+                OP_TEXT | OP_BINARY | OP_CONTINUATION => {
+                    if opcode != OP_CONTINUATION {
+                        message_op = Some(opcode);
+                        message.clear();
+                    }
+                    message.extend_from_slice(&payload);
+                    if message.len() as u64 > MAX_MESSAGE_LEN {
+                        return Err(invalid("WebSocket message too large"));
+                    }
+                    if fin {
+                        return match message_op {
+                            Some(OP_TEXT) => String::from_utf8(message)
+                                .map(WsMessage::Text)
+                                .map_err(|_| invalid("WebSocket text message is not UTF-8")),
+                            Some(_) => Ok(WsMessage::Binary(message)),
+                            None => Err(invalid("continuation frame without a message")),
+                        };
+                    }
+                }
+                other => return Err(invalid(format!("unknown WebSocket opcode {:#x}", other))),
+            }
+        }
+    }
+}
+
+/// Read one frame: (fin, opcode, unmasked payload)
+fn read_frame(r: &mut impl Read) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    r.read_exact(&mut head)?;
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0f;
+    let masked = head[1] & 0x80 != 0;
+    let len = match head[1] & 0x7f {
+        126 => {
+            let mut ext = [0u8; 2];
+            r.read_exact(&mut ext)?;
+            u16::from_be_bytes(ext) as u64
+        }
+        127 => {
+            let mut ext = [0u8; 8];
+            r.read_exact(&mut ext)?;
+            u64::from_be_bytes(ext)
+        }
+        n => n as u64,
+    };
+    if len > MAX_MESSAGE_LEN {
+        return Err(invalid("WebSocket frame too large"));
+    }
+    let mut mask = [0u8; 4];
+    if masked {
+        r.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    r.read_exact(&mut payload)?;
+    if masked {
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+    }
+    Ok((fin, opcode, payload))
+}
+
+/// Write one final frame; client-to-server frames must be masked
+fn write_frame(w: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        n if n < 126 => frame.push(0x80 | n as u8),
+        n if n <= u16::MAX as usize => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    let mask = rand::random::<[u8; 4]>();
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    w.write_all(&frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_ws_url() {
+        assert_eq!(parse_ws_url("ws://car.local:5005/speed").unwrap(), ("car.local".into(), 5005, "/speed".into()));
+        assert_eq!(parse_ws_url("ws://10.0.0.2").unwrap(), ("10.0.0.2".into(), 80, "/".into()));
+        assert!(parse_ws_url("wss://secure/speed").is_err());
+        assert!(parse_ws_url("http://host/speed").is_err());
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b"hello"), "aGVsbG8=");
+        assert_eq!(base64(b"hi"), "aGk=");
+        assert_eq!(base64(b"abc"), "YWJj");
+    }
+
+    #[test]
+    fn test_masked_frame_roundtrip() {
+        let mut buf = Vec::new();
+        let payload = vec![b'x'; 300]; // needs the 16-bit length form
+        write_frame(&mut buf, OP_TEXT, &payload).unwrap();
+        assert_eq!(buf[1] & 0x80, 0x80, "client frames are masked");
+        let (fin, opcode, decoded) = read_frame(&mut Cursor::new(buf)).unwrap();
+        assert!(fin);
+        assert_eq!(opcode, OP_TEXT);
+        assert_eq!(decoded, payload);
+    }
+}
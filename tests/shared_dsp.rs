@@ -0,0 +1,24 @@
+// Exercises the library's public API the way an external consumer would.
+use adaptive_vol::{apply_gain_and_limit, db_to_lin, soft_limit, speed_to_noise, AdaptiveGain, Smoother};
+
+#[test]
+fn adaptive_gain_respects_configured_bounds() {
+    let mut ag = AdaptiveGain::builder().tau_attack(0.01).min_gain_db(-6.0).max_gain_db(6.0).build();
+    for speed in [0.0, 60.0, 120.0] {
+        assert!(speed_to_noise(speed) >= 40.0);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let (gain_db, gain_lin) = ag.compute_gain(30.0, speed);
+        assert!((-6.0..=6.0).contains(&gain_db), "gain {} dB outside bounds", gain_db);
+        assert!((gain_lin - db_to_lin(gain_db)).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn smoothed_gain_applied_to_pcm_stays_in_range() {
+    let mut smoother = Smoother::new(0.0, 0.1, 1.0);
+    let gain_db = smoother.step_dt(12.0, 1.0);
+    let input: Vec<i16> = (0..480).map(|n| ((n as f32 * 0.1).sin() * 30000.0) as i16).collect();
+    let out = apply_gain_and_limit(&input, db_to_lin(gain_db));
+    assert_eq!(out.len(), input.len());
+    assert!(soft_limit(2.0, 0.9) < 2.0);
+}